@@ -0,0 +1,132 @@
+import fs from 'fs'
+import os from 'os'
+import path from 'path'
+import { isDeepStrictEqual } from 'util'
+import { app } from 'electron'
+
+/**
+ * Checks docs/wire-vectors.json against the app's own decoding: every frame goes through
+ * FrameDecoder (which reassembles fragments) and readFrame (which decrypts), MessagePack
+ * payloads through decodeBinary, and stream headers through parseStreamHeader. It runs
+ * under Electron, as the modules it loads expect, with a throwaway profile, and exits
+ * non-zero on any mismatch. Run it with `npm run check:wire-vectors`.
+ */
+
+interface Vectors {
+  sessionKey: string
+  senderId: string
+  frames: { name: string; wire: string; decoded: unknown }[]
+  msgpack: { payload: string; decoded: unknown }[]
+  streamHeaders: {
+    wire: string
+    invalid?: boolean
+    fileId?: string
+    start?: number
+    end?: number
+    compression?: 'zstd'
+    delta?: boolean
+  }[]
+}
+
+const VECTORS_PATH = path.resolve('docs/wire-vectors.json')
+
+// Vectors are JSON, so values are compared as JSON would have them (no undefined fields)
+const normalize = (value: unknown): unknown => JSON.parse(JSON.stringify(value))
+
+async function check(): Promise<string[]> {
+  const { FrameDecoder, readFrame } = await import('../src/main/wire')
+  const { storeSession } = await import('../src/main/crypto/sessionKey')
+  const { decodeBinary } = await import('../src/main/binaryCodec')
+  const { parseStreamHeader } = await import('../src/main/streamHeader')
+
+  const vectors = JSON.parse(fs.readFileSync(VECTORS_PATH, 'utf8')) as Vectors
+  const failures: string[] = []
+  // The encrypted frames were sealed under this key, by a peer on the legacy protocol
+  storeSession(vectors.senderId, {
+    sessionKey: Buffer.from(vectors.sessionKey, 'hex'),
+    deviceId: vectors.senderId
+  })
+
+  for (const vector of vectors.frames) {
+    const decoder = new FrameDecoder('Vectors')
+    const frames: unknown[] = []
+    decoder.on('data', (frame: unknown) => frames.push(frame))
+    decoder.on('invalid', (err: Error) => failures.push(`${vector.name}: ${err.message}`))
+    await new Promise<void>((resolve, reject) => {
+      decoder.on('end', resolve)
+      decoder.on('error', reject)
+      decoder.end(Buffer.from(vector.wire, 'hex'))
+    })
+    if (frames.length !== 1) {
+      failures.push(`${vector.name}: ${frames.length} frames instead of one`)
+      continue
+    }
+    try {
+      const frame = readFrame(frames[0], vectors.senderId, 'Vectors')
+      if (!frame) {
+        failures.push(`${vector.name}: could not be read`)
+      } else if (!isDeepStrictEqual(normalize(frame.message), vector.decoded)) {
+        failures.push(`${vector.name}: decoded to ${JSON.stringify(frame.message)}`)
+      }
+    } catch (err) {
+      failures.push(`${vector.name}: ${(err as Error).message}`)
+    }
+  }
+
+  for (const vector of vectors.msgpack) {
+    try {
+      const decoded = decodeBinary(Buffer.from(vector.payload, 'hex'))
+      if (!isDeepStrictEqual(normalize(decoded), vector.decoded)) {
+        failures.push(`msgpack ${vector.payload}: decoded to ${JSON.stringify(decoded)}`)
+      }
+    } catch (err) {
+      failures.push(`msgpack ${vector.payload}: ${(err as Error).message}`)
+    }
+  }
+
+  for (const vector of vectors.streamHeaders) {
+    const line = Buffer.from(vector.wire, 'hex').toString()
+    const header = parseStreamHeader(line)
+    const name = `stream header ${line.trim()}`
+    if (vector.invalid) {
+      if (header) failures.push(`${name}: accepted, though it is invalid`)
+      continue
+    }
+    const expected = {
+      fileId: vector.fileId,
+      start: vector.start ?? 0,
+      end: vector.end,
+      compression: vector.compression,
+      delta: vector.delta ?? false
+    }
+    const actual = header && {
+      fileId: header.fileId,
+      start: header.start,
+      end: header.end,
+      compression: header.compression,
+      delta: header.delta
+    }
+    if (!isDeepStrictEqual(actual, expected)) {
+      failures.push(`${name}: parsed to ${JSON.stringify(actual)}`)
+    }
+  }
+  return failures
+}
+
+// A throwaway profile, so nothing here touches the user's own
+app.setPath('userData', fs.mkdtempSync(path.join(os.tmpdir(), 'hyperconnect-vectors-')))
+check().then(
+  (failures) => {
+    for (const failure of failures) console.error(`FAIL ${failure}`)
+    console.log(
+      failures.length > 0
+        ? `${failures.length} wire vector check(s) failed`
+        : 'All wire vectors decode as expected'
+    )
+    app.exit(failures.length > 0 ? 1 : 0)
+  },
+  (err) => {
+    console.error('Wire vector check could not run:', err)
+    app.exit(1)
+  }
+)
@@ -0,0 +1,21 @@
+import { resolve } from 'path'
+import { defineConfig } from 'electron-vite'
+
+// Builds the wire vector check (see checkWireVectors.ts) on its own, apart from the app
+export default defineConfig({
+  main: {
+    resolve: {
+      alias: {
+        '@shared': resolve('src/shared')
+      }
+    },
+    build: {
+      outDir: 'out/check',
+      rollupOptions: {
+        input: {
+          checkWireVectors: resolve('scripts/checkWireVectors.ts')
+        }
+      }
+    }
+  }
+})
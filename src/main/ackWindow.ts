@@ -0,0 +1,79 @@
+import { Transform, TransformCallback } from 'stream'
+
+// The window is sized to about this much of the receiver's measured disk throughput
+const WINDOW_DURATION = 1000
+const MIN_WINDOW = 1024 * 1024
+const MAX_WINDOW = 64 * 1024 * 1024
+const INITIAL_WINDOW = 8 * 1024 * 1024
+// Throughput is smoothed over acks so one slow flush doesn't collapse the window
+const SMOOTHING = 0.25
+// With no ack for this long the window is dropped and TCP's flow control takes over
+const ACK_TIMEOUT = 15 * 1000
+
+/**
+ * Sits between the file and the rest of an outgoing stream and holds back data once
+ * `window` bytes are unacknowledged, so a fast sender can't run far ahead of a receiver
+ * whose disk is slower than the network. The receiver acks the offset it has written
+ * (FILE_ACK) and the window follows how fast those offsets advance.
+ */
+export class AckWindow extends Transform {
+  private sent: number
+  private acked: number
+  private window = INITIAL_WINDOW
+  private throughput = 0 // bytes/s, smoothed
+  private lastAck: { time: number; offset: number }
+  private held?: TransformCallback
+  private timer?: NodeJS.Timeout
+
+  // `start` is the file offset the stream begins at; acks are file offsets too
+  constructor(start: number) {
+    super()
+    this.sent = start
+    this.acked = start
+    this.lastAck = { time: Date.now(), offset: start }
+  }
+
+  _transform(chunk: Buffer, _encoding: BufferEncoding, callback: TransformCallback): void {
+    this.sent += chunk.length
+    this.push(chunk)
+    if (this.sent - this.acked >= this.window) {
+      this.held = callback
+      this.timer = setTimeout(() => {
+        this.window = Infinity
+        this.release()
+      }, ACK_TIMEOUT)
+    } else {
+      callback()
+    }
+  }
+
+  _destroy(error: Error | null, callback: (error?: Error | null) => void): void {
+    clearTimeout(this.timer)
+    callback(error)
+  }
+
+  ack(offset: number): void {
+    if (offset <= this.acked) return
+    const now = Date.now()
+    const elapsed = now - this.lastAck.time
+    if (elapsed > 0) {
+      const rate = ((offset - this.lastAck.offset) / elapsed) * 1000
+      this.throughput += this.throughput ? SMOOTHING * (rate - this.throughput) : rate
+      const target = (this.throughput * WINDOW_DURATION) / 1000
+      // A timed-out window stays off
+      if (this.window !== Infinity) {
+        this.window = Math.min(MAX_WINDOW, Math.max(MIN_WINDOW, target))
+      }
+    }
+    this.acked = Math.min(offset, this.sent)
+    this.lastAck = { time: now, offset: this.acked }
+    if (this.sent - this.acked < this.window) this.release()
+  }
+
+  private release(): void {
+    clearTimeout(this.timer)
+    const callback = this.held
+    this.held = undefined
+    callback?.()
+  }
+}
@@ -0,0 +1,78 @@
+import net from 'net'
+import os from 'os'
+import { isOverlayAddress } from './overlay'
+
+/**
+ * Choosing which of a peer's announced addresses to connect to. IPv6 link-local addresses
+ * (fe80::/10) need care: every interface has one, so an address means nothing without the
+ * interface it is on, given as a zone ID (`fe80::1%en0`, or `%12` by index on Windows).
+ * mDNS answers don't carry one, so on IPv6-only networks we have to add it ourselves.
+ */
+
+// How long a candidate link-local address gets to answer (ms)
+const PROBE_TIMEOUT = 1000
+
+// A dual-stack server sees IPv4 peers as ::ffff:a.b.c.d; this gives back a.b.c.d
+export const unmapAddress = (address: string | undefined): string | undefined =>
+  address?.replace(/^::ffff:(?=\d+\.\d+\.\d+\.\d+$)/i, '')
+
+// Whether `socket` goes out from one of `addresses`, e.g. those of an interface that went away
+export const isBoundTo = (socket: net.Socket, addresses: string[]): boolean => {
+  const local = unmapAddress(socket.localAddress)?.split('%')[0]
+  return !!local && addresses.includes(local)
+}
+
+export const isLinkLocal = (address: string): boolean => /^fe[89ab][0-9a-f]:/i.test(address)
+
+// Zone IDs for the local interfaces that have a link-local address, in the form this OS takes
+function getLinkLocalZones(): string[] {
+  const zones: string[] = []
+  for (const [name, addresses] of Object.entries(os.networkInterfaces())) {
+    const linkLocal = addresses?.find(
+      (addr) => addr.family === 'IPv6' && !addr.internal && isLinkLocal(addr.address)
+    )
+    if (!linkLocal) continue
+    zones.push(process.platform === 'win32' ? String(linkLocal.scopeid) : name)
+  }
+  return zones
+}
+
+// Whether something accepts connections on `port` at `address`
+const probe = (address: string, port: number): Promise<boolean> =>
+  new Promise((resolve) => {
+    const socket = net.connect({ host: address, port })
+    const done = (reachable: boolean): void => {
+      socket.destroy()
+      resolve(reachable)
+    }
+    socket.setTimeout(PROBE_TIMEOUT, () => done(false))
+    socket.once('connect', () => done(true))
+    socket.once('error', () => done(false))
+  })
+
+/**
+ * Picks the address to reach a peer at: IPv4 if it has one, then a global IPv6 address, then
+ * a link-local one scoped to the interface it answers on. When several interfaces could
+ * lead to it, each is tried on `port`. Overlay addresses (Tailscale's) come last, as the
+ * LAN is the shorter way to a peer that announced itself there.
+ */
+export async function pickAddress(addresses: string[], port: number): Promise<string> {
+  const local = addresses.filter((addr) => !addr.startsWith('127.') && addr !== '::1')
+  const usable = [
+    ...local.filter((addr) => !isOverlayAddress(addr)),
+    ...local.filter((addr) => isOverlayAddress(addr))
+  ]
+  const ipv4 = usable.find((addr) => net.isIPv4(addr))
+  if (ipv4) return ipv4
+  const global = usable.find((addr) => net.isIPv6(addr) && !isLinkLocal(addr))
+  if (global) return global
+
+  const linkLocal = usable.find((addr) => isLinkLocal(addr))
+  if (!linkLocal) return addresses[0] || ''
+  if (linkLocal.includes('%')) return linkLocal
+
+  const candidates = getLinkLocalZones().map((zone) => `${linkLocal}%${zone}`)
+  if (candidates.length <= 1) return candidates[0] ?? linkLocal
+  const reachable = await Promise.all(candidates.map((candidate) => probe(candidate, port)))
+  return candidates[reachable.indexOf(true)] ?? candidates[0]
+}
@@ -0,0 +1,62 @@
+import path from 'path'
+import { v4 as uuidv4 } from 'uuid'
+import { AutoAcceptRule, FileMetadata } from '@shared/messageTypes'
+import { getStoreOptions } from './profiles'
+import { isTrustedDevice } from './trustedDevices'
+
+export async function getAutoAcceptRules(): Promise<AutoAcceptRule[]> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  return store.get('autoAcceptRules', []) as AutoAcceptRule[]
+}
+
+/**
+ * Replaces the whole rule list. Order matters: the first matching rule wins.
+ */
+export async function setAutoAcceptRules(rules: AutoAcceptRule[]): Promise<AutoAcceptRule[]> {
+  const normalized = rules.map((rule) => ({
+    ...rule,
+    id: rule.id || uuidv4(),
+    extensions: rule.extensions
+      ?.map((ext) => ext.trim().toLowerCase().replace(/^\.?/, '.'))
+      .filter((ext) => ext.length > 1)
+  }))
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('autoAcceptRules', normalized)
+  return normalized
+}
+
+const matches = (
+  rule: AutoAcceptRule,
+  deviceId: string,
+  metadata: FileMetadata,
+  trusted: boolean
+): boolean => {
+  if (rule.enabled === false) return false
+  if (rule.deviceId && rule.deviceId !== deviceId) return false
+  if (rule.peer === 'trusted' && !trusted) return false
+  if (rule.peer === 'untrusted' && trusted) return false
+  if (rule.maxSize !== undefined && metadata.size > rule.maxSize) return false
+  if (rule.extensions?.length) {
+    // Folders have no single type, so they only match rules without an extension filter
+    if (metadata.isFolder) return false
+    if (!rule.extensions.includes(path.extname(metadata.name).toLowerCase())) return false
+  }
+  return true
+}
+
+/**
+ * Returns what the first matching rule says to do with an incoming file, or null to fall
+ * back to the auto-accept toggle / asking the user.
+ */
+export async function evaluateAutoAcceptRules(
+  deviceId: string,
+  metadata: FileMetadata
+): Promise<AutoAcceptRule['action'] | null> {
+  const rules = await getAutoAcceptRules()
+  if (rules.length === 0) return null
+  const trusted = await isTrustedDevice(deviceId)
+  const rule = rules.find((r) => matches(r, deviceId, metadata, trusted))
+  return rule?.action ?? null
+}
@@ -0,0 +1,93 @@
+import fs from 'fs'
+import path from 'path'
+import { getProfileDataPath } from './profiles'
+
+// Peers' avatars are per profile, like everything else we know about them
+const getAvatarDir = (): string => path.join(getProfileDataPath(), 'avatars')
+const DATA_URL_PATTERN = /^data:image\/(png|jpe?g|webp|gif);base64,([A-Za-z0-9+/=]+)$/
+const MAX_AVATAR_BYTES = 256 * 1024
+
+// The image has to be what its data URL says it is, as the renderer only shows these
+const looksLike = (type: string, image: Buffer): boolean => {
+  switch (type) {
+    case 'png':
+      return image.subarray(0, 4).equals(Buffer.from([0x89, 0x50, 0x4e, 0x47]))
+    case 'jpg':
+      return image.subarray(0, 3).equals(Buffer.from([0xff, 0xd8, 0xff]))
+    case 'gif':
+      return image.subarray(0, 4).toString('latin1') === 'GIF8'
+    case 'webp':
+      return (
+        image.subarray(0, 4).toString('latin1') === 'RIFF' &&
+        image.subarray(8, 12).toString('latin1') === 'WEBP'
+      )
+    default:
+      return false
+  }
+}
+
+function ensureDir(): void {
+  if (!fs.existsSync(getAvatarDir())) {
+    fs.mkdirSync(getAvatarDir(), { recursive: true })
+  }
+}
+
+function findCachedFile(deviceId: string): string | undefined {
+  const dir = getAvatarDir()
+  if (!fs.existsSync(dir)) return undefined
+  const file = fs.readdirSync(dir).find((f) => path.parse(f).name === deviceId)
+  return file ? path.join(dir, file) : undefined
+}
+
+/**
+ * Writes a peer's avatar (a base64 data URL) to disk and returns the cached file path.
+ * Only PNG, JPEG, WebP and GIF images up to MAX_AVATAR_BYTES are taken; for anything else
+ * it returns undefined and keeps what was cached before.
+ */
+export function cacheAvatar(deviceId: string, dataUrl: string): string | undefined {
+  // Checked before decoding, so an oversized one is never held in memory twice
+  if (dataUrl.length > Math.ceil((MAX_AVATAR_BYTES * 4) / 3) + 32) return undefined
+  const match = dataUrl.match(DATA_URL_PATTERN)
+  // Device IDs are UUIDs; anything else could escape the avatar directory
+  if (!match || !/^[a-zA-Z0-9-]+$/.test(deviceId)) return undefined
+  const ext = match[1] === 'jpeg' ? 'jpg' : match[1]
+  const image = Buffer.from(match[2], 'base64')
+  if (image.length > MAX_AVATAR_BYTES || !looksLike(ext, image)) {
+    console.warn(`[Avatar] Ignoring an avatar from ${deviceId}: not an image we show`)
+    return undefined
+  }
+
+  try {
+    ensureDir()
+    const existing = findCachedFile(deviceId)
+    if (existing) fs.unlinkSync(existing)
+
+    const filePath = path.join(getAvatarDir(), `${deviceId}.${ext}`)
+    fs.writeFileSync(filePath, image)
+    return filePath
+  } catch (e) {
+    console.error(`[Avatar] Failed to cache avatar for ${deviceId}:`, e)
+    return undefined
+  }
+}
+
+/**
+ * Loads a previously cached avatar as a data URL so it can be shown before the peer handshakes.
+ */
+export function getCachedAvatar(deviceId: string): string | undefined {
+  const filePath = findCachedFile(deviceId)
+  if (!filePath) return undefined
+
+  try {
+    const ext = path.extname(filePath).slice(1)
+    const mime = ext === 'jpg' ? 'jpeg' : ext
+    return `data:image/${mime};base64,${fs.readFileSync(filePath).toString('base64')}`
+  } catch (e) {
+    console.error(`[Avatar] Failed to read cached avatar for ${deviceId}:`, e)
+    return undefined
+  }
+}
+
+export function getCachedAvatarPath(deviceId: string): string | undefined {
+  return findCachedFile(deviceId)
+}
@@ -0,0 +1,241 @@
+/**
+ * Compact binary encoding for frame payloads: the subset of MessagePack that JSON values
+ * need (nil, booleans, numbers, strings, arrays and maps), so the result reads back as
+ * exactly what JSON.parse(JSON.stringify(value)) would give.
+ */
+
+const textEncoder = new TextEncoder()
+const textDecoder = new TextDecoder()
+
+class Writer {
+  private buffer = Buffer.alloc(256)
+  private length = 0
+
+  private reserve(size: number): void {
+    if (this.length + size <= this.buffer.length) return
+    const next = Buffer.alloc(Math.max(this.buffer.length * 2, this.length + size))
+    this.buffer.copy(next, 0, 0, this.length)
+    this.buffer = next
+  }
+
+  byte(value: number): void {
+    this.reserve(1)
+    this.buffer[this.length++] = value
+  }
+
+  // A type byte followed by a big-endian unsigned integer of `size` bytes
+  header(type: number, value: number, size: 1 | 2 | 4): void {
+    this.reserve(1 + size)
+    this.buffer[this.length] = type
+    this.buffer.writeUIntBE(value, this.length + 1, size)
+    this.length += 1 + size
+  }
+
+  int(type: number, value: number, size: 1 | 2 | 4): void {
+    this.reserve(1 + size)
+    this.buffer[this.length] = type
+    this.buffer.writeIntBE(value, this.length + 1, size)
+    this.length += 1 + size
+  }
+
+  double(value: number): void {
+    this.reserve(9)
+    this.buffer[this.length] = 0xcb
+    this.buffer.writeDoubleBE(value, this.length + 1)
+    this.length += 9
+  }
+
+  bytes(data: Uint8Array): void {
+    this.reserve(data.length)
+    this.buffer.set(data, this.length)
+    this.length += data.length
+  }
+
+  result(): Buffer {
+    return this.buffer.subarray(0, this.length)
+  }
+}
+
+const writeLength = (
+  writer: Writer,
+  length: number,
+  fixed: number,
+  fixedMax: number,
+  types: [number | null, number, number]
+): void => {
+  const [type8, type16, type32] = types
+  if (length <= fixedMax) writer.byte(fixed | length)
+  else if (type8 !== null && length <= 0xff) writer.header(type8, length, 1)
+  else if (length <= 0xffff) writer.header(type16, length, 2)
+  else writer.header(type32, length, 4)
+}
+
+const writeNumber = (writer: Writer, value: number): void => {
+  if (!Number.isFinite(value)) {
+    writer.byte(0xc0) // JSON has no NaN or Infinity either
+  } else if (!Number.isInteger(value) || value > 0xffffffff || value < -0x80000000) {
+    writer.double(value)
+  } else if (value >= 0) {
+    if (value <= 0x7f) writer.byte(value)
+    else if (value <= 0xff) writer.header(0xcc, value, 1)
+    else if (value <= 0xffff) writer.header(0xcd, value, 2)
+    else writer.header(0xce, value, 4)
+  } else if (value >= -32) {
+    writer.byte(value & 0xff)
+  } else if (value >= -0x80) {
+    writer.int(0xd0, value, 1)
+  } else if (value >= -0x8000) {
+    writer.int(0xd1, value, 2)
+  } else {
+    writer.int(0xd2, value, 4)
+  }
+}
+
+const writeValue = (writer: Writer, value: unknown): void => {
+  if (value && typeof (value as { toJSON?: unknown }).toJSON === 'function') {
+    value = (value as { toJSON: () => unknown }).toJSON()
+  }
+
+  if (value === null || value === undefined || typeof value === 'function') {
+    writer.byte(0xc0)
+  } else if (typeof value === 'boolean') {
+    writer.byte(value ? 0xc3 : 0xc2)
+  } else if (typeof value === 'number') {
+    writeNumber(writer, value)
+  } else if (typeof value === 'string') {
+    const data = textEncoder.encode(value)
+    writeLength(writer, data.length, 0xa0, 31, [0xd9, 0xda, 0xdb])
+    writer.bytes(data)
+  } else if (Array.isArray(value)) {
+    writeLength(writer, value.length, 0x90, 15, [null, 0xdc, 0xdd])
+    for (const item of value) writeValue(writer, item)
+  } else if (typeof value === 'object') {
+    // Like JSON, keys holding undefined or functions are left out
+    const entries = Object.entries(value).filter(
+      ([, v]) => v !== undefined && typeof v !== 'function'
+    )
+    writeLength(writer, entries.length, 0x80, 15, [null, 0xde, 0xdf])
+    for (const [key, item] of entries) {
+      writeValue(writer, key)
+      writeValue(writer, item)
+    }
+  } else {
+    throw new TypeError(`Cannot encode ${typeof value}`)
+  }
+}
+
+export function encodeBinary(value: unknown): Buffer {
+  const writer = new Writer()
+  writeValue(writer, value)
+  return writer.result()
+}
+
+class Reader {
+  private buffer: Buffer
+  private offset = 0
+
+  constructor(buffer: Buffer) {
+    this.buffer = buffer
+  }
+
+  private take(size: number): number {
+    if (this.offset + size > this.buffer.length) throw new RangeError('Truncated payload')
+    const start = this.offset
+    this.offset += size
+    return start
+  }
+
+  uint(size: number): number {
+    return this.buffer.readUIntBE(this.take(size), size)
+  }
+
+  int(size: number): number {
+    return this.buffer.readIntBE(this.take(size), size)
+  }
+
+  string(length: number): string {
+    const start = this.take(length)
+    return textDecoder.decode(this.buffer.subarray(start, start + length))
+  }
+
+  array(length: number): unknown[] {
+    const items: unknown[] = []
+    for (let i = 0; i < length; i++) items.push(this.value())
+    return items
+  }
+
+  map(length: number): Record<string, unknown> {
+    const object: Record<string, unknown> = {}
+    for (let i = 0; i < length; i++) {
+      const key = this.value()
+      if (typeof key !== 'string') throw new TypeError('Map keys must be strings')
+      // Defined rather than assigned, so a "__proto__" key stays an ordinary property
+      Object.defineProperty(object, key, {
+        value: this.value(),
+        enumerable: true,
+        writable: true,
+        configurable: true
+      })
+    }
+    return object
+  }
+
+  value(): unknown {
+    const type = this.uint(1)
+    if (type <= 0x7f) return type
+    if (type >= 0xe0) return type - 0x100
+    if ((type & 0xe0) === 0xa0) return this.string(type & 0x1f)
+    if ((type & 0xf0) === 0x90) return this.array(type & 0x0f)
+    if ((type & 0xf0) === 0x80) return this.map(type & 0x0f)
+
+    switch (type) {
+      case 0xc0:
+        return null
+      case 0xc2:
+        return false
+      case 0xc3:
+        return true
+      case 0xcb:
+        return this.buffer.readDoubleBE(this.take(8))
+      case 0xcc:
+        return this.uint(1)
+      case 0xcd:
+        return this.uint(2)
+      case 0xce:
+        return this.uint(4)
+      case 0xd0:
+        return this.int(1)
+      case 0xd1:
+        return this.int(2)
+      case 0xd2:
+        return this.int(4)
+      case 0xd9:
+        return this.string(this.uint(1))
+      case 0xda:
+        return this.string(this.uint(2))
+      case 0xdb:
+        return this.string(this.uint(4))
+      case 0xdc:
+        return this.array(this.uint(2))
+      case 0xdd:
+        return this.array(this.uint(4))
+      case 0xde:
+        return this.map(this.uint(2))
+      case 0xdf:
+        return this.map(this.uint(4))
+      default:
+        throw new TypeError(`Unsupported type 0x${type.toString(16)}`)
+    }
+  }
+
+  done(): boolean {
+    return this.offset === this.buffer.length
+  }
+}
+
+export function decodeBinary(buffer: Buffer): unknown {
+  const reader = new Reader(buffer)
+  const value = reader.value()
+  if (!reader.done()) throw new RangeError('Trailing bytes after payload')
+  return value
+}
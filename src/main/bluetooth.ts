@@ -0,0 +1,418 @@
+import fs from 'fs'
+import net from 'net'
+import tty from 'tty'
+import { Duplex, Readable, Writable } from 'stream'
+import { promisify } from 'util'
+import { randomBytes } from 'crypto'
+import { Device } from '@shared/messageTypes'
+import { discoveryManager } from './discovery'
+import { getDeviceInfo } from './identity'
+import { getStoreOptions } from './profiles'
+import { tcpServer } from './tcpServer'
+
+/**
+ * Bluetooth as a last resort, for devices with no IP network in common. Node has no
+ * Bluetooth sockets, so this goes through the serial port each OS makes for an RFCOMM
+ * (serial port profile) link to a paired device: /dev/rfcommN on Linux (`rfcomm bind` or
+ * `rfcomm watch`), /dev/tty.<name> on macOS, and the COM port Windows assigns in its
+ * Bluetooth settings. The user lists which ports lead to other devices.
+ *
+ * A link is a single byte stream, while a peer is reached over several connections (the
+ * control one and one per file stream), so the link carries numbered streams. Each frame
+ * is a type byte, the stream's id (u32) and the payload's length (u16), then the payload:
+ * - HELLO (stream 0): JSON saying who we are, as mDNS would announce it
+ * - OPEN, DATA, END: a stream opening, its bytes, and the end of the sender's half
+ * - RESET: the stream is gone
+ * Streams are handed to TCPServer or dialed like sockets, so the frame protocol above them
+ * is unchanged. RFCOMM carries tens to hundreds of KB/s, so only small files are sent.
+ */
+
+const FrameType = {
+  HELLO: 1,
+  OPEN: 2,
+  DATA: 3,
+  END: 4,
+  RESET: 5
+} as const
+
+const HEADER_SIZE = 7
+const MAX_PAYLOAD = 4096
+// Larger files wait for a faster route rather than taking hours over Bluetooth
+export const MAX_BLUETOOTH_FILE_SIZE = 20 * 1024 * 1024
+// How often a port that couldn't be opened (peer out of range, say) is tried again (ms)
+const REOPEN_INTERVAL = 10 * 1000
+// How often peers on a link are announced again, so they take over when the LAN goes (ms)
+const ANNOUNCE_INTERVAL = 15 * 1000
+
+const open = promisify(fs.open)
+
+export interface BluetoothStatus {
+  enabled: boolean
+  ports: string[]
+  peers: string[] // display names of the devices on open links
+}
+
+const socketError = (message: string, code: string): Error =>
+  Object.assign(new Error(message), { code })
+
+const frame = (type: number, id: number, payload?: Buffer): Buffer => {
+  const header = Buffer.alloc(HEADER_SIZE)
+  header[0] = type
+  header.writeUInt32BE(id, 1)
+  header.writeUInt16BE(payload?.length ?? 0, 5)
+  return payload ? Buffer.concat([header, payload]) : header
+}
+
+/**
+ * One stream on a link, with the parts of net.Socket's interface the connection and
+ * transfer code use.
+ */
+class BluetoothStream extends Duplex {
+  private link?: BluetoothLink
+  private timeout = 0
+  private idleTimer?: NodeJS.Timeout
+  private endSent = false
+  private endReceived = false
+
+  constructor(public readonly id: number) {
+    super({ allowHalfOpen: false })
+  }
+
+  get remoteAddress(): string | undefined {
+    return this.link && `bluetooth:${this.link.path}`
+  }
+
+  get localAddress(): undefined {
+    return undefined
+  }
+
+  setNoDelay(): this {
+    return this
+  }
+
+  setKeepAlive(): this {
+    return this
+  }
+
+  // Emits 'timeout' after `ms` without traffic, as net.Socket does; 0 turns it off
+  setTimeout(ms: number, callback?: () => void): this {
+    this.timeout = ms
+    if (callback) this.once('timeout', callback)
+    this.touch()
+    return this
+  }
+
+  private touch(): void {
+    clearTimeout(this.idleTimer)
+    if (this.timeout > 0 && !this.destroyed) {
+      this.idleTimer = setTimeout(() => this.emit('timeout'), this.timeout)
+    }
+  }
+
+  // Opens the stream on the link at `path` (the "host"); there is no port
+  connect(_port: number, path: string, onConnect?: () => void): this {
+    const link = bluetoothTransport.getLink(path)
+    if (!link) {
+      process.nextTick(() => this.destroy(socketError(`No Bluetooth link on ${path}`, 'ENOTCONN')))
+      return this
+    }
+    this.attach(link)
+    link.send(FrameType.OPEN, this.id)
+    // The link delivers in order, so data may follow the OPEN straight away
+    if (onConnect) this.once('connect', onConnect)
+    process.nextTick(() => this.emit('connect'))
+    return this
+  }
+
+  attach(link: BluetoothLink): void {
+    this.link = link
+    link.register(this)
+  }
+
+  receive(type: number, payload: Buffer): void {
+    this.touch()
+    if (type === FrameType.DATA) {
+      if (!this.push(payload)) this.link?.stall(this)
+    } else if (type === FrameType.END) {
+      this.endReceived = true
+      this.push(null)
+    } else if (type === FrameType.RESET) {
+      this.endSent = this.endReceived = true // nothing to tell the other side
+      this.destroy(socketError('Bluetooth stream reset by peer', 'ECONNRESET'))
+    }
+  }
+
+  _read(): void {
+    this.link?.resumeFor(this)
+  }
+
+  _write(chunk: Buffer, _encoding: BufferEncoding, callback: (error?: Error | null) => void): void {
+    const link = this.link
+    if (!link) return callback(socketError('Bluetooth stream is not connected', 'ENOTCONN'))
+    this.touch()
+    let flushed = true
+    for (let offset = 0; offset < chunk.length; offset += MAX_PAYLOAD) {
+      flushed = link.send(FrameType.DATA, this.id, chunk.subarray(offset, offset + MAX_PAYLOAD))
+    }
+    if (flushed) callback()
+    else link.onDrain(() => callback())
+  }
+
+  _final(callback: (error?: Error | null) => void): void {
+    this.endSent = true
+    this.link?.send(FrameType.END, this.id)
+    callback()
+  }
+
+  _destroy(error: Error | null, callback: (error: Error | null) => void): void {
+    clearTimeout(this.idleTimer)
+    if (this.link && !(this.endSent && this.endReceived)) {
+      this.link.send(FrameType.RESET, this.id)
+    }
+    this.link?.unregister(this)
+    callback(error)
+  }
+}
+
+/**
+ * An open serial port to one paired device, carrying any number of streams.
+ */
+class BluetoothLink {
+  private input!: Readable
+  private output!: Writable
+  private buffer = Buffer.alloc(0)
+  private streams: Map<number, BluetoothStream> = new Map()
+  private stalled?: BluetoothStream // the input waits until this one reads again
+  private closed = false
+  public peer?: Device
+
+  constructor(
+    public readonly path: string,
+    private onClose: (link: BluetoothLink) => void
+  ) {}
+
+  // A tty (Linux, macOS) is read without blocking and in raw mode, so no byte is translated
+  async open(): Promise<void> {
+    const fd = await open(this.path, 'r+')
+    if (tty.isatty(fd)) {
+      const input = new tty.ReadStream(fd)
+      input.setRawMode(true)
+      this.input = input
+    } else {
+      this.input = fs.createReadStream('', { fd, autoClose: false })
+      this.input.once('close', () => fs.close(fd, () => {}))
+    }
+    this.output = fs.createWriteStream('', { fd, autoClose: false })
+    this.output.setMaxListeners(0)
+    this.input.on('data', (chunk: Buffer) => this.onData(chunk))
+    this.input.on('error', (err) => this.close(err))
+    this.input.on('close', () => this.close())
+    this.output.on('error', (err) => this.close(err))
+
+    const { deviceId, displayName, platform, appVersion } = getDeviceInfo()
+    const hello = JSON.stringify({ deviceId, displayName, platform, appVersion })
+    this.send(FrameType.HELLO, 0, Buffer.from(hello))
+  }
+
+  send(type: number, id: number, payload?: Buffer): boolean {
+    if (this.closed) return true
+    return this.output.write(frame(type, id, payload))
+  }
+
+  onDrain(callback: () => void): void {
+    if (this.closed) callback()
+    else this.output.once('drain', callback)
+  }
+
+  register(stream: BluetoothStream): void {
+    this.streams.set(stream.id, stream)
+  }
+
+  unregister(stream: BluetoothStream): void {
+    if (this.streams.get(stream.id) === stream) this.streams.delete(stream.id)
+    this.resumeFor(stream)
+  }
+
+  // A stream's reader fell behind; nothing more is read off the port until it catches up
+  stall(stream: BluetoothStream): void {
+    this.stalled = stream
+    this.input.pause()
+  }
+
+  resumeFor(stream: BluetoothStream): void {
+    if (this.stalled !== stream) return
+    this.stalled = undefined
+    if (!this.closed) this.input.resume()
+  }
+
+  private onData(chunk: Buffer): void {
+    this.buffer = Buffer.concat([this.buffer, chunk])
+    while (this.buffer.length >= HEADER_SIZE) {
+      const length = this.buffer.readUInt16BE(5)
+      if (this.buffer.length < HEADER_SIZE + length) break
+      const type = this.buffer[0]
+      const id = this.buffer.readUInt32BE(1)
+      const payload = this.buffer.subarray(HEADER_SIZE, HEADER_SIZE + length)
+      this.buffer = this.buffer.subarray(HEADER_SIZE + length)
+      this.receive(type, id, payload)
+    }
+  }
+
+  private receive(type: number, id: number, payload: Buffer): void {
+    if (type === FrameType.HELLO) {
+      this.receiveHello(payload)
+      return
+    }
+    const stream = this.streams.get(id)
+    if (stream) {
+      stream.receive(type, payload)
+    } else if (type === FrameType.OPEN) {
+      const accepted = new BluetoothStream(id)
+      accepted.attach(this)
+      tcpServer.handleConnection(accepted as unknown as net.Socket)
+    } else if (type !== FrameType.RESET) {
+      this.send(FrameType.RESET, id)
+    }
+  }
+
+  private receiveHello(payload: Buffer): void {
+    let info: Partial<Device>
+    try {
+      info = JSON.parse(payload.toString('utf-8'))
+    } catch {
+      return
+    }
+    if (typeof info.deviceId !== 'string' || info.deviceId === getDeviceInfo().deviceId) return
+    console.log(`[Bluetooth] ${info.displayName || info.deviceId} is on ${this.path}`)
+    this.peer = {
+      deviceId: info.deviceId,
+      displayName: info.displayName || info.deviceId,
+      platform: info.platform || 'unknown',
+      appVersion: info.appVersion || '0.0.0',
+      address: this.path,
+      port: 0,
+      bluetooth: true,
+      lastSeen: Date.now(),
+      isOnline: true
+    }
+    this.announce()
+  }
+
+  announce(): void {
+    if (this.peer) discoveryManager.addRemoteDevice({ ...this.peer, lastSeen: Date.now() })
+  }
+
+  close(error?: Error): void {
+    if (this.closed) return
+    this.closed = true
+    if (error) console.warn(`[Bluetooth] Link on ${this.path} failed:`, error.message)
+    for (const stream of this.streams.values()) {
+      stream.destroy(socketError(`Bluetooth link on ${this.path} closed`, 'ECONNRESET'))
+    }
+    this.input?.destroy()
+    this.output?.destroy()
+    if (this.peer) discoveryManager.removeRemoteDevice(this.peer.deviceId, 'bluetooth')
+    this.onClose(this)
+  }
+}
+
+class BluetoothTransport {
+  private enabled = false
+  private ports: string[] = []
+  private links: Map<string, BluetoothLink> = new Map()
+  private reopenTimers: Map<string, NodeJS.Timeout> = new Map()
+  private announceTimer?: NodeJS.Timeout
+
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    this.ports = store.get('bluetoothPorts', []) as string[]
+    this.configure(store.get('bluetoothFallback', false) as boolean)
+  }
+
+  getStatus(): BluetoothStatus {
+    const peers = [...this.links.values()]
+      .filter((link) => link.peer)
+      .map((link) => link.peer!.displayName)
+    return { enabled: this.enabled, ports: this.ports, peers }
+  }
+
+  async setEnabled(enabled: boolean): Promise<BluetoothStatus> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('bluetoothFallback', enabled)
+    this.configure(enabled)
+    return this.getStatus()
+  }
+
+  async setPorts(ports: string[]): Promise<BluetoothStatus> {
+    const cleaned = ports.map((port) => port.trim()).filter(Boolean)
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('bluetoothPorts', cleaned)
+    this.ports = cleaned
+    this.configure(this.enabled)
+    return this.getStatus()
+  }
+
+  getLink(path: string): BluetoothLink | undefined {
+    return this.links.get(path)
+  }
+
+  // A stream to be opened with connect(), as a net.Socket would be
+  createStream(): net.Socket {
+    return new BluetoothStream(randomBytes(4).readUInt32BE()) as unknown as net.Socket
+  }
+
+  // Opens the ports on the list and closes the rest
+  private configure(enabled: boolean): void {
+    this.enabled = enabled
+    const wanted = enabled ? this.ports : []
+    for (const [path, link] of this.links) {
+      if (!wanted.includes(path)) link.close()
+    }
+    for (const [path, timer] of this.reopenTimers) {
+      if (wanted.includes(path)) continue
+      clearTimeout(timer)
+      this.reopenTimers.delete(path)
+    }
+    for (const path of wanted) {
+      if (!this.links.has(path) && !this.reopenTimers.has(path)) this.openLink(path)
+    }
+    clearInterval(this.announceTimer)
+    this.announceTimer = enabled
+      ? setInterval(() => this.links.forEach((link) => link.announce()), ANNOUNCE_INTERVAL)
+      : undefined
+  }
+
+  private openLink(path: string): void {
+    const link = new BluetoothLink(path, (closed) => {
+      if (this.links.get(path) === closed) this.links.delete(path)
+      this.scheduleReopen(path)
+    })
+    this.links.set(path, link)
+    link.open().catch((err) => {
+      console.warn(`[Bluetooth] Could not open ${path}:`, err.message)
+      link.close()
+    })
+  }
+
+  private scheduleReopen(path: string): void {
+    if (!this.enabled || !this.ports.includes(path) || this.reopenTimers.has(path)) return
+    const timer = setTimeout(() => {
+      this.reopenTimers.delete(path)
+      if (this.enabled && this.ports.includes(path) && !this.links.has(path)) this.openLink(path)
+    }, REOPEN_INTERVAL)
+    this.reopenTimers.set(path, timer)
+  }
+
+  stop(): void {
+    this.enabled = false
+    clearInterval(this.announceTimer)
+    for (const timer of this.reopenTimers.values()) clearTimeout(timer)
+    this.reopenTimers.clear()
+    for (const link of this.links.values()) link.close()
+  }
+}
+
+export const bluetoothTransport = new BluetoothTransport()
@@ -0,0 +1,64 @@
+import path from 'path'
+import zlib from 'zlib'
+import { Transform } from 'stream'
+
+export type FileCompression = 'zstd'
+
+// zstd landed in Node's zlib recently and is missing from older typings
+const zstd = zlib as typeof zlib & {
+  createZstdCompress?: () => Transform
+  createZstdDecompress?: () => Transform
+}
+
+// Formats that are already compressed; running zstd over them costs CPU for no gain
+const COMPRESSED_EXTENSIONS = new Set([
+  '.zip',
+  '.gz',
+  '.tgz',
+  '.bz2',
+  '.xz',
+  '.7z',
+  '.rar',
+  '.zst',
+  '.jpg',
+  '.jpeg',
+  '.png',
+  '.gif',
+  '.webp',
+  '.heic',
+  '.avif',
+  '.mp3',
+  '.aac',
+  '.m4a',
+  '.ogg',
+  '.opus',
+  '.flac',
+  '.mp4',
+  '.m4v',
+  '.mkv',
+  '.mov',
+  '.webm',
+  '.docx',
+  '.xlsx',
+  '.pptx',
+  '.apk',
+  '.dmg'
+])
+
+export function isZstdSupported(): boolean {
+  return typeof zstd.createZstdCompress === 'function'
+}
+
+export function shouldCompress(fileName: string): boolean {
+  return !COMPRESSED_EXTENSIONS.has(path.extname(fileName).toLowerCase())
+}
+
+export function createCompressionStream(): Transform {
+  if (!zstd.createZstdCompress) throw new Error('zstd is not available in this runtime')
+  return zstd.createZstdCompress()
+}
+
+export function createDecompressionStream(): Transform {
+  if (!zstd.createZstdDecompress) throw new Error('zstd is not available in this runtime')
+  return zstd.createZstdDecompress()
+}
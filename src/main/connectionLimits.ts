@@ -0,0 +1,125 @@
+import { recordSecurityEvent } from './securityLog'
+
+// Enough for a control connection, a full set of parallel file streams and a few more
+const MAX_CONNECTIONS_PER_ADDRESS = 32
+// Handshakes one address may start at once, and how fast it earns more (per second)
+const HANDSHAKE_BURST = 10
+const HANDSHAKE_RATE = 0.5
+// Frames one connection may send at once, and per second after that
+const FRAME_BURST = 1000
+const FRAME_RATE = 500
+// A limit hit again and again is logged once per this long for each address or device
+const REPORT_INTERVAL = 60 * 1000
+// Addresses tracked before those with nothing left worth remembering are dropped
+const MAX_TRACKED_ADDRESSES = 1000
+
+class TokenBucket {
+  private tokens: number
+  private updated = Date.now()
+
+  constructor(
+    private burst: number,
+    private rate: number
+  ) {
+    this.tokens = burst
+  }
+
+  take(): boolean {
+    this.refill()
+    if (this.tokens < 1) return false
+    this.tokens--
+    return true
+  }
+
+  // A full bucket is no different from a new one
+  isFull(): boolean {
+    this.refill()
+    return this.tokens >= this.burst
+  }
+
+  private refill(): void {
+    const now = Date.now()
+    this.tokens = Math.min(this.burst, this.tokens + ((now - this.updated) / 1000) * this.rate)
+    this.updated = now
+  }
+}
+
+/**
+ * Limits on the accept path, so a device on the LAN can't wear us down: how many
+ * connections one address may hold open at once, how fast it may start handshakes, and
+ * how many frames a connection may send per second. Hitting one is logged as a security
+ * event. Connections that come in through the relay all share its address, so they share
+ * its limits too.
+ */
+class ConnectionLimits {
+  private open = new Map<string, number>() // address → open connections
+  private handshakes = new Map<string, TokenBucket>() // address → handshake allowance
+  private reported = new Map<string, number>() // address or device → last logged
+
+  /**
+   * Counts a new connection from `address`, or returns false if it already has as many
+   * as it may. One that was counted must be released when it closes.
+   */
+  acquire(address: string | undefined): boolean {
+    if (!address) return true
+    const count = this.open.get(address) ?? 0
+    if (count >= MAX_CONNECTIONS_PER_ADDRESS) {
+      this.report(address, `More than ${MAX_CONNECTIONS_PER_ADDRESS} connections at once`)
+      return false
+    }
+    this.open.set(address, count + 1)
+    return true
+  }
+
+  release(address: string | undefined): void {
+    if (!address) return
+    const count = (this.open.get(address) ?? 0) - 1
+    if (count > 0) this.open.set(address, count)
+    else this.open.delete(address)
+  }
+
+  // Whether `address` may start another handshake now
+  allowHandshake(address: string | undefined): boolean {
+    if (!address) return true
+    let bucket = this.handshakes.get(address)
+    if (!bucket) {
+      if (this.handshakes.size >= MAX_TRACKED_ADDRESSES) {
+        for (const [key, old] of this.handshakes) if (old.isFull()) this.handshakes.delete(key)
+      }
+      bucket = new TokenBucket(HANDSHAKE_BURST, HANDSHAKE_RATE)
+      this.handshakes.set(address, bucket)
+    }
+    if (bucket.take()) return true
+    this.report(address, 'Too many handshakes')
+    return false
+  }
+
+  /**
+   * A frame allowance for one connection: the function it returns says whether the next
+   * frame may be handled. `key` names the peer in the log, as it is known at the time.
+   */
+  frameLimiter(key: () => string | undefined): () => boolean {
+    const bucket = new TokenBucket(FRAME_BURST, FRAME_RATE)
+    return () => {
+      if (bucket.take()) return true
+      this.report(key(), `More than ${FRAME_RATE} frames a second`)
+      return false
+    }
+  }
+
+  private report(key: string | undefined, details: string): void {
+    const now = Date.now()
+    const id = key ?? 'unknown peer'
+    if (now - (this.reported.get(id) ?? 0) < REPORT_INTERVAL) return
+    if (this.reported.size >= MAX_TRACKED_ADDRESSES) {
+      for (const [old, at] of this.reported) {
+        if (now - at >= REPORT_INTERVAL) this.reported.delete(old)
+      }
+    }
+    this.reported.set(id, now)
+    console.warn(`[Limits] ${id}: ${details}`)
+    recordSecurityEvent('rate-limited', key, details)
+  }
+}
+
+export const connectionLimits = new ConnectionLimits()
@@ -0,0 +1,139 @@
+import {
+  KeyObject,
+  createECDH,
+  createPrivateKey,
+  createPublicKey,
+  hkdfSync,
+  randomBytes,
+  sign
+} from 'crypto'
+import { wipe } from './sessionKey'
+
+/**
+ * Minimal X.509 for the TLS transport: a self-signed ECDSA P-256 certificate whose subject
+ * is the device ID, with a key derived from the device's identity key (see
+ * deriveCertificateKey). Nothing ever validates it against a CA; peers pin its fingerprint
+ * on first use instead, so only the fields TLS libraries insist on are filled in.
+ */
+
+// DER: tag, length, contents
+const der = (tag: number, contents: Buffer): Buffer => {
+  const length = contents.length
+  let header: Buffer
+  if (length < 0x80) {
+    header = Buffer.from([tag, length])
+  } else {
+    const bytes: number[] = []
+    for (let n = length; n > 0; n = Math.floor(n / 256)) bytes.unshift(n & 0xff)
+    header = Buffer.from([tag, 0x80 | bytes.length, ...bytes])
+  }
+  return Buffer.concat([header, contents])
+}
+
+const sequence = (...items: Buffer[]): Buffer => der(0x30, Buffer.concat(items))
+const set = (...items: Buffer[]): Buffer => der(0x31, Buffer.concat(items))
+
+const oid = (dotted: string): Buffer => {
+  const [first, second, ...rest] = dotted.split('.').map(Number)
+  const bytes = [first * 40 + second]
+  for (const value of rest) {
+    const encoded = [value & 0x7f]
+    for (let n = value >>> 7; n > 0; n >>>= 7) encoded.unshift(0x80 | (n & 0x7f))
+    bytes.push(...encoded)
+  }
+  return der(0x06, Buffer.from(bytes))
+}
+
+// A positive INTEGER from big-endian bytes
+const integer = (bytes: Buffer): Buffer =>
+  der(0x02, bytes[0] & 0x80 ? Buffer.concat([Buffer.from([0]), bytes]) : bytes)
+
+// UTCTime up to 2049, GeneralizedTime after, as RFC 5280 requires
+const time = (date: Date): Buffer => {
+  const iso = date.toISOString().replace(/[-:T]/g, '').slice(0, 14) + 'Z'
+  return date.getUTCFullYear() < 2050
+    ? der(0x17, Buffer.from(iso.slice(2)))
+    : der(0x18, Buffer.from(iso))
+}
+
+// Order of the P-256 group; a private key must be below it
+const P256_ORDER = BigInt('0xffffffff00000000ffffffffffffffffbce6faada7179e84f3b9cac2fc632551')
+
+/**
+ * The certificate's P-256 private key, derived from `secret` (which comes from the identity
+ * key), so the certificate belongs to the identity rather than being a key of its own.
+ * The rare candidate outside the group order is skipped for the next.
+ */
+export function deriveCertificateKey(secret: Buffer): KeyObject {
+  for (let counter = 0; ; counter++) {
+    const info = `hyperconnect-tls-certificate:${counter}`
+    const d = Buffer.from(hkdfSync('sha256', secret, Buffer.alloc(0), info, 32))
+    const scalar = BigInt('0x' + d.toString('hex'))
+    if (scalar === 0n || scalar >= P256_ORDER) {
+      wipe(d)
+      continue
+    }
+    const ecdh = createECDH('prime256v1')
+    ecdh.setPrivateKey(d)
+    const point = ecdh.getPublicKey() // 0x04, x, y
+    const key = createPrivateKey({
+      key: {
+        kty: 'EC',
+        crv: 'P-256',
+        d: d.toString('base64url'),
+        x: point.subarray(1, 33).toString('base64url'),
+        y: point.subarray(33).toString('base64url')
+      },
+      format: 'jwk'
+    })
+    wipe(d)
+    return key
+  }
+}
+
+const ECDSA_WITH_SHA256 = '1.2.840.10045.4.3.2'
+const COMMON_NAME = '2.5.4.3'
+const VALIDITY_YEARS = 20
+
+export interface Certificate {
+  key: string // PEM, PKCS#8
+  cert: string // PEM
+}
+
+const toPem = (label: string, data: Buffer): string => {
+  const lines = data.toString('base64').match(/.{1,64}/g) ?? []
+  return `-----BEGIN ${label}-----\n${lines.join('\n')}\n-----END ${label}-----\n`
+}
+
+export function createSelfSignedCertificate(
+  commonName: string,
+  privateKey: KeyObject
+): Certificate {
+  const publicKey = createPublicKey(privateKey)
+  const name = sequence(set(sequence(oid(COMMON_NAME), der(0x0c, Buffer.from(commonName)))))
+  const algorithm = sequence(oid(ECDSA_WITH_SHA256))
+  const notBefore = new Date(Date.now() - 24 * 60 * 60 * 1000) // tolerate clock skew
+  const notAfter = new Date(notBefore)
+  notAfter.setUTCFullYear(notAfter.getUTCFullYear() + VALIDITY_YEARS)
+
+  const tbs = sequence(
+    der(0xa0, der(0x02, Buffer.from([2]))), // version 3
+    integer(randomBytes(16)),
+    algorithm,
+    name, // issuer: it signs itself
+    sequence(time(notBefore), time(notAfter)),
+    name,
+    publicKey.export({ type: 'spki', format: 'der' })
+  )
+  const signature = sign('sha256', tbs, privateKey)
+  const certificate = sequence(
+    tbs,
+    algorithm,
+    der(0x03, Buffer.concat([Buffer.from([0]), signature]))
+  )
+
+  return {
+    key: privateKey.export({ type: 'pkcs8', format: 'pem' }) as string,
+    cert: toPem('CERTIFICATE', certificate)
+  }
+}
@@ -0,0 +1,97 @@
+import { Cipher, Decipher, createCipheriv, createDecipheriv, randomBytes } from 'crypto'
+
+/**
+ * The AEAD that encrypts frames and sealed file streams. AES-256-GCM is fast wherever the
+ * CPU has AES instructions, but slow (and harder to make constant-time) where it doesn't,
+ * as on older ARM phones and SBCs; ChaCha20-Poly1305 is quick everywhere. Both take a
+ * 32-byte key and a 12-byte nonce and give a 16-byte tag, so one can stand in for the other.
+ *
+ * Each end lists the suites it has in HELLO_SECURE, its preferred one first. ChaCha20 is
+ * used if both have it and either end prefers it, so the slower device gets its way;
+ * otherwise, and with peers that list nothing, it's AES-256-GCM.
+ */
+
+export type CipherSuite = 'aes-256-gcm' | 'chacha20-poly1305'
+
+export const DEFAULT_CIPHER_SUITE: CipherSuite = 'aes-256-gcm'
+const CIPHER_SUITES: CipherSuite[] = ['aes-256-gcm', 'chacha20-poly1305']
+
+const TAG_SIZE = 16
+
+// What the two have in common, which the typings only give each separately
+export type AeadCipher = Cipher & { setAAD(aad: Buffer): AeadCipher; getAuthTag(): Buffer }
+export type AeadDecipher = Decipher & {
+  setAAD(aad: Buffer): AeadDecipher
+  setAuthTag(tag: Buffer): AeadDecipher
+}
+
+export function createAeadCipher(suite: CipherSuite, key: Buffer, nonce: Buffer): AeadCipher {
+  return (
+    suite === 'chacha20-poly1305'
+      ? createCipheriv(suite, key, nonce, { authTagLength: TAG_SIZE })
+      : createCipheriv(suite, key, nonce)
+  ) as AeadCipher
+}
+
+export function createAeadDecipher(suite: CipherSuite, key: Buffer, nonce: Buffer): AeadDecipher {
+  return (
+    suite === 'chacha20-poly1305'
+      ? createDecipheriv(suite, key, nonce, { authTagLength: TAG_SIZE })
+      : createDecipheriv(suite, key, nonce)
+  ) as AeadDecipher
+}
+
+const BENCHMARK_SIZE = 256 * 1024
+// With AES instructions the two are about even; without, AES-GCM is several times slower
+const CHACHA_ADVANTAGE = 2
+
+const timeSuite = (suite: CipherSuite, data: Buffer): number => {
+  const start = process.hrtime.bigint()
+  const cipher = createAeadCipher(suite, randomBytes(32), randomBytes(12))
+  cipher.update(data)
+  cipher.final()
+  return Number(process.hrtime.bigint() - start)
+}
+
+let preferred: CipherSuite | undefined
+
+/**
+ * Times both suites over `size` bytes (each after a warm-up run) and returns their
+ * throughput in MB/s. Node can't tell whether the CPU has AES instructions, so this is
+ * what the preferred suite is based on: ChaCha20 if it is clearly the faster one here,
+ * else AES-256-GCM. The answer is kept until the crypto self-test times them again.
+ */
+export function benchmarkCipherSuites(size = BENCHMARK_SIZE): Record<CipherSuite, number> {
+  const data = randomBytes(size)
+  const [aes, chacha] = CIPHER_SUITES.map((suite) => {
+    timeSuite(suite, data)
+    return timeSuite(suite, data)
+  })
+  preferred = chacha * CHACHA_ADVANTAGE < aes ? 'chacha20-poly1305' : 'aes-256-gcm'
+  console.log(`[Crypto] Preferring ${preferred} (AES-GCM ${aes} ns, ChaCha20 ${chacha} ns)`)
+  // Bytes per nanosecond are GB/s
+  return { 'aes-256-gcm': (size / aes) * 1000, 'chacha20-poly1305': (size / chacha) * 1000 }
+}
+
+function getPreferredSuite(): CipherSuite {
+  if (!preferred) benchmarkCipherSuites()
+  return preferred!
+}
+
+// The suites we offer in HELLO_SECURE, preferred first
+export function getCipherSuites(): CipherSuite[] {
+  const first = getPreferredSuite()
+  return [first, ...CIPHER_SUITES.filter((suite) => suite !== first)]
+}
+
+/**
+ * The suite for a session, given the list the peer offered. Gives the same answer on both
+ * ends, whichever of them dialed.
+ */
+export function negotiateCipherSuite(remote: unknown): CipherSuite {
+  if (!Array.isArray(remote) || remote.length === 0) return DEFAULT_CIPHER_SUITE
+  const local = getCipherSuites()
+  const chacha = 'chacha20-poly1305'
+  const shared = local.includes(chacha) && remote.includes(chacha)
+  return shared && (local[0] === chacha || remote[0] === chacha) ? chacha : DEFAULT_CIPHER_SUITE
+}
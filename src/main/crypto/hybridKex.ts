@@ -0,0 +1,80 @@
+import * as crypto from 'node:crypto'
+import { wipe } from './sessionKey'
+
+/**
+ * ML-KEM-768 (Kyber) alongside X25519, so a session stays secret even if a quantum
+ * computer later breaks the elliptic-curve exchange recorded today. The dialer adds an
+ * ML-KEM public key to its HELLO_SECURE; a peer that can use it answers with a ciphertext,
+ * and both feed the KEM secret into the session key along with the X25519 (or Noise) one.
+ * Either secret alone is enough to keep the key safe.
+ *
+ * ML-KEM arrived in Node recently (and needs a TLS library that has it), so this is only
+ * offered where the runtime can do it; HYBRID_KEX is left out of our capabilities otherwise.
+ */
+
+// Missing from older typings
+type KemCrypto = typeof crypto & {
+  encapsulate?: (key: crypto.KeyObject) => { sharedKey: Buffer; ciphertext: Buffer }
+  decapsulate?: (key: crypto.KeyObject, ciphertext: Buffer) => Buffer
+}
+const kem = crypto as KemCrypto
+const generateKeyPair = crypto.generateKeyPairSync as unknown as (
+  type: string
+) => crypto.KeyPairKeyObjectResult
+
+const ALGORITHM = 'ml-kem-768'
+
+export interface KemKeyPair {
+  publicKey: string // Base64, SPKI DER
+  privateKey: crypto.KeyObject
+}
+
+let supported: boolean | undefined
+
+export function isHybridKexSupported(): boolean {
+  if (supported === undefined) {
+    try {
+      const { publicKey, privateKey } = generateKeyPair(ALGORITHM)
+      const { sharedKey, ciphertext } = kem.encapsulate!(publicKey)
+      supported = kem.decapsulate!(privateKey, ciphertext).equals(sharedKey)
+    } catch {
+      supported = false
+    }
+  }
+  return supported
+}
+
+export function generateKemKeyPair(): KemKeyPair {
+  const { publicKey, privateKey } = generateKeyPair(ALGORITHM)
+  return {
+    publicKey: publicKey.export({ type: 'spki', format: 'der' }).toString('base64'),
+    privateKey
+  }
+}
+
+/**
+ * The answering side: a secret for the peer's public key, and the ciphertext that lets
+ * the peer recover it.
+ */
+export function encapsulate(publicKey: string): { secret: Buffer; ciphertext: string } {
+  const key = crypto.createPublicKey({
+    key: Buffer.from(publicKey, 'base64'),
+    format: 'der',
+    type: 'spki'
+  })
+  if (key.asymmetricKeyType !== ALGORITHM) throw new Error('Not an ML-KEM-768 public key')
+  const { sharedKey, ciphertext } = kem.encapsulate!(key)
+  return { secret: sharedKey, ciphertext: ciphertext.toString('base64') }
+}
+
+export function decapsulate(privateKey: crypto.KeyObject, ciphertext: string): Buffer {
+  return kem.decapsulate!(privateKey, Buffer.from(ciphertext, 'base64'))
+}
+
+// The session key from the classical key and the KEM secret together; both are wiped
+export function combineSecrets(sessionKey: Buffer, kemSecret: Buffer): Buffer {
+  const input = Buffer.concat([sessionKey, kemSecret])
+  const combined = Buffer.from(crypto.hkdfSync('sha256', input, '', 'hyperconnect-hybrid', 32))
+  wipe(input, sessionKey, kemSecret)
+  return combined
+}
@@ -1,48 +1,166 @@
-import { randomBytes, createCipheriv, createDecipheriv } from 'crypto'
+import { randomBytes } from 'crypto'
+import zlib from 'zlib'
+import { recordSecurityEvent } from '../securityLog'
+import { decodeBinary, encodeBinary } from '../binaryCodec'
+import type { RatchetHeader } from './ratchet'
+import {
+  CipherSuite,
+  DEFAULT_CIPHER_SUITE,
+  createAeadCipher,
+  createAeadDecipher
+} from './cipherSuite'
+
+// How the plaintext inside an encrypted frame is serialized; JSON unless negotiated
+export type PayloadEncoding = 'json' | 'msgpack'
+
+// Bits of EncryptedMessage.flags; the others are reserved, and a frame using one is refused
+export const FrameFlag = {
+  DEFLATE: 1 << 0, // the plaintext was deflated before encryption
+  PADDED: 1 << 1 // the plaintext was padded; its last 4 bytes give the unpadded length
+} as const
+const KNOWN_FLAGS = FrameFlag.DEFLATE | FrameFlag.PADDED
+
+// Padded plaintexts are rounded up to a multiple of this, so small frames of different
+// kinds look the same size on the wire
+const PAD_BLOCK = 256
+
+// Only payloads this large are worth compressing: manifests, batches, long messages
+const COMPRESS_THRESHOLD = 4 * 1024
+// A compressed payload may not inflate past this, so a small frame can't exhaust memory
+const MAX_INFLATED_SIZE = 64 * 1024 * 1024
 
 export interface EncryptedMessage {
   type: 'ENCRYPTED_MESSAGE'
   iv: string // Base64
   tag: string // Base64
   payload: string // Base64 (of the encrypted buffer)
+  encoding?: 'msgpack' // absent for JSON, which is all older peers understand
+  flags?: number // FrameFlag bits; absent when none are set
+  seq?: number // sender's sequence number in this session, when replay protection is on
+  ratchet?: RatchetHeader // which Double Ratchet key encrypted it, when the session has one
+}
+
+export interface EncryptOptions {
+  encoding?: PayloadEncoding
+  compress?: boolean // deflate a large payload, if that makes it smaller
+  pad?: boolean // pad the payload to a multiple of PAD_BLOCK bytes
+  // Numbers the frame; authenticated along with the sender, so it can't be altered or
+  // reflected back at the sender
+  seq?: { value: number; senderId: string }
+  ratchet?: RatchetHeader // sent along with the frame, and authenticated too
+  cipherSuite?: CipherSuite // as agreed for the session; AES-256-GCM if not given
+}
+
+const padPlaintext = (plaintext: Buffer): Buffer => {
+  const length = Buffer.alloc(4)
+  length.writeUInt32BE(plaintext.length)
+  const size = Math.ceil((plaintext.length + 4) / PAD_BLOCK) * PAD_BLOCK
+  return Buffer.concat([plaintext, Buffer.alloc(size - plaintext.length - 4), length])
+}
+
+const unpadPlaintext = (padded: Buffer): Buffer => {
+  if (padded.length < 4) throw new RangeError('Padded payload too short')
+  const length = padded.readUInt32BE(padded.length - 4)
+  if (length > padded.length - 4) throw new RangeError('Invalid padding')
+  return padded.subarray(0, length)
+}
+
+// Additional authenticated data binding a frame to its sender and position in the session
+const sequenceAad = (senderId: string, seq: number): Buffer => Buffer.from(`${senderId}:${seq}`)
+
+// The sequence AAD, if any, followed by the ratchet header, if any
+const frameAad = (senderId: string, seq?: number, ratchet?: RatchetHeader): Buffer | undefined => {
+  const sequence = seq === undefined ? undefined : sequenceAad(senderId, seq)
+  if (!ratchet) return sequence
+  const header = Buffer.from(`|${ratchet.dh}:${ratchet.n}:${ratchet.pn}`)
+  return sequence ? Buffer.concat([sequence, header]) : header
 }
 
 /**
- * Encrypts a JSON-serializable object using AES-256-GCM.
+ * Encrypts a JSON-serializable object using AES-256-GCM, or ChaCha20-Poly1305 if that was
+ * negotiated.
  */
-export function encryptMessage(data: unknown, sessionKey: Buffer): EncryptedMessage {
+export function encryptMessage(
+  data: unknown,
+  sessionKey: Buffer,
+  {
+    encoding = 'json',
+    compress = false,
+    pad = false,
+    seq,
+    ratchet,
+    cipherSuite = DEFAULT_CIPHER_SUITE
+  }: EncryptOptions = {}
+): EncryptedMessage {
   const iv = randomBytes(12)
-  const cipher = createCipheriv('aes-256-gcm', sessionKey, iv)
+  const cipher = createAeadCipher(cipherSuite, sessionKey, iv)
+  const aad = frameAad(seq?.senderId ?? '', seq?.value, ratchet)
+  if (aad) cipher.setAAD(aad)
 
-  const json = JSON.stringify(data)
-  const ciphertext = Buffer.concat([cipher.update(json, 'utf8'), cipher.final()])
+  let plaintext = encoding === 'msgpack' ? encodeBinary(data) : Buffer.from(JSON.stringify(data))
+  let flags = 0
+  if (compress && plaintext.length >= COMPRESS_THRESHOLD) {
+    const deflated = zlib.deflateRawSync(plaintext)
+    if (deflated.length < plaintext.length) {
+      plaintext = deflated
+      flags |= FrameFlag.DEFLATE
+    }
+  }
+  if (pad) {
+    plaintext = padPlaintext(plaintext)
+    flags |= FrameFlag.PADDED
+  }
+  const ciphertext = Buffer.concat([cipher.update(plaintext), cipher.final()])
   const tag = cipher.getAuthTag()
 
   return {
     type: 'ENCRYPTED_MESSAGE',
     iv: iv.toString('base64'),
     tag: tag.toString('base64'),
-    payload: ciphertext.toString('base64')
+    payload: ciphertext.toString('base64'),
+    ...(encoding === 'msgpack' && { encoding }),
+    ...(flags && { flags }),
+    ...(seq && { seq: seq.value }),
+    ...(ratchet && { ratchet })
   }
 }
 
 /**
- * Decrypts an encrypted message using AES-256-GCM.
- * Throws an error if decryption or parsing fails.
+ * Decrypts an encrypted message with the session's cipher suite. A numbered frame is
+ * authenticated against `deviceId` as its sender; whether the number is fresh is the
+ * caller's check. A ratcheted frame is decrypted with the key the ratchet found for its
+ * header. Throws an error if decryption or parsing fails. Failures are recorded in the
+ * security log.
  */
-export function decryptMessage(msg: EncryptedMessage, sessionKey: Buffer): unknown {
+export function decryptMessage(
+  msg: EncryptedMessage,
+  sessionKey: Buffer,
+  deviceId?: string,
+  cipherSuite: CipherSuite = DEFAULT_CIPHER_SUITE
+): unknown {
   try {
     const iv = Buffer.from(msg.iv, 'base64')
     const tag = Buffer.from(msg.tag, 'base64')
     const ciphertext = Buffer.from(msg.payload, 'base64')
 
-    const decipher = createDecipheriv('aes-256-gcm', sessionKey, iv)
+    const flags = msg.flags ?? 0
+    if (flags & ~KNOWN_FLAGS) throw new Error(`Unknown frame flags 0x${flags.toString(16)}`)
+
+    const decipher = createAeadDecipher(cipherSuite, sessionKey, iv)
     decipher.setAuthTag(tag)
+    const aad = frameAad(deviceId ?? '', msg.seq, msg.ratchet)
+    if (aad) decipher.setAAD(aad)
 
-    const decrypted = Buffer.concat([decipher.update(ciphertext), decipher.final()])
+    let decrypted = Buffer.concat([decipher.update(ciphertext), decipher.final()])
+    if (flags & FrameFlag.PADDED) decrypted = unpadPlaintext(decrypted)
+    if (flags & FrameFlag.DEFLATE) {
+      decrypted = zlib.inflateRawSync(decrypted, { maxOutputLength: MAX_INFLATED_SIZE })
+    }
+    if (msg.encoding === 'msgpack') return decodeBinary(decrypted)
     return JSON.parse(decrypted.toString('utf8'))
   } catch (e) {
     console.error('[Crypto] Decryption failed:', e)
+    recordSecurityEvent('decryption-failed', deviceId, (e as Error).message)
     throw new Error('Failed to decrypt message: potentially invalid session key or corrupted data')
   }
 }
@@ -60,6 +178,31 @@ export function isEncryptedMessage(msg: unknown): msg is EncryptedMessage {
  * Checks if a message type contains sensitive user data that MUST be encrypted.
  */
 export function isSensitiveMessageType(type: string): boolean {
-  const sensitiveTypes = ['MESSAGE', 'FILE_META', 'FILE_ACCEPT', 'FILE_REJECT', 'MESSAGE_DELETE']
+  const sensitiveTypes = [
+    'MESSAGE',
+    'FILE_META',
+    'FILE_ACCEPT',
+    'FILE_REJECT',
+    'FILE_PAUSE',
+    'FILE_RESUME',
+    'FILE_CANCEL',
+    'FILE_ACK',
+    'PING', // liveness patterns are nobody else's business either
+    'PONG',
+    'GOODBYE',
+    'ERROR',
+    'TRANSFER_STATUS_QUERY',
+    'TRANSFER_STATUS',
+    'SESSION_TICKET',
+    'MESSAGE_DELETE',
+    'MESSAGE_EDIT',
+    'GROUP_MESSAGE',
+    'GROUP_UPDATE',
+    'SENDER_KEY',
+    'BATCH',
+    'PROFILE_UPDATE', // or anyone could rename any device they claim to be
+    'MESSAGE_ACK',
+    'READ_RECEIPT'
+  ]
   return sensitiveTypes.includes(type)
 }
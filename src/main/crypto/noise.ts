@@ -0,0 +1,260 @@
+import {
+  KeyObject,
+  createCipheriv,
+  createDecipheriv,
+  createHash,
+  createHmac,
+  createPrivateKey,
+  createPublicKey,
+  diffieHellman,
+  generateKeyPairSync
+} from 'crypto'
+import type { KeyPair } from './ecdh'
+import { wipe } from './sessionKey'
+
+/**
+ * Noise_XX_25519_AESGCM_SHA256 (noiseprotocol.org, revision 34), the handshake pattern for
+ * two parties that learn each other's static keys during the handshake:
+ *
+ *   -> e
+ *   <- e, ee, s, es
+ *   -> s, se
+ *
+ * Only what XX needs is here: no PSKs, no other patterns, and handshake payloads are
+ * always empty. Keys on the wire are raw 32-byte X25519 public keys.
+ */
+
+const PROTOCOL_NAME = 'Noise_XX_25519_AESGCM_SHA256'
+const DHLEN = 32
+const TAGLEN = 16
+// DER prefix of an X25519 SubjectPublicKeyInfo, followed by the raw key
+const SPKI_PREFIX = Buffer.from('302a300506032b656e032100', 'hex')
+
+export interface StaticKey {
+  privateKey: KeyObject
+  publicKey: Buffer // raw
+}
+
+const sha256 = (...parts: Buffer[]): Buffer => {
+  const hash = createHash('sha256')
+  for (const part of parts) hash.update(part)
+  return hash.digest()
+}
+
+const hmac = (key: Buffer, ...parts: Buffer[]): Buffer => {
+  const mac = createHmac('sha256', key)
+  for (const part of parts) mac.update(part)
+  return mac.digest()
+}
+
+// HKDF as Noise defines it, with two outputs
+const hkdf = (chainingKey: Buffer, input: Buffer): [Buffer, Buffer] => {
+  const tempKey = hmac(chainingKey, input)
+  const first = hmac(tempKey, Buffer.from([1]))
+  return [first, hmac(tempKey, first, Buffer.from([2]))]
+}
+
+const rawPublicKey = (key: KeyObject): Buffer =>
+  key.export({ type: 'spki', format: 'der' }).subarray(SPKI_PREFIX.length)
+
+const toPublicKey = (raw: Buffer): KeyObject =>
+  createPublicKey({ key: Buffer.concat([SPKI_PREFIX, raw]), format: 'der', type: 'spki' })
+
+const dh = (privateKey: KeyObject, publicKey: Buffer): Buffer =>
+  diffieHellman({ privateKey, publicKey: toPublicKey(publicKey) })
+
+export function generateStaticKey(): StaticKey {
+  const { privateKey, publicKey } = generateKeyPairSync('x25519')
+  return { privateKey, publicKey: rawPublicKey(publicKey) }
+}
+
+// For keys kept as PKCS#8 DER, like the handshake keys in ecdh.ts
+export function importStaticKey(pkcs8: Buffer): StaticKey {
+  const privateKey = createPrivateKey({ key: pkcs8, format: 'der', type: 'pkcs8' })
+  return { privateKey, publicKey: rawPublicKey(createPublicKey(privateKey)) }
+}
+
+class CipherState {
+  private n = 0n
+
+  constructor(private key?: Buffer) {}
+
+  destroy(): void {
+    wipe(this.key)
+  }
+
+  private nonce(): Buffer {
+    const nonce = Buffer.alloc(12)
+    nonce.writeBigUInt64BE(this.n, 4)
+    return nonce
+  }
+
+  encrypt(ad: Buffer, plaintext: Buffer): Buffer {
+    if (!this.key) return plaintext
+    const cipher = createCipheriv('aes-256-gcm', this.key, this.nonce())
+    cipher.setAAD(ad)
+    const ciphertext = Buffer.concat([cipher.update(plaintext), cipher.final()])
+    this.n++
+    return Buffer.concat([ciphertext, cipher.getAuthTag()])
+  }
+
+  decrypt(ad: Buffer, ciphertext: Buffer): Buffer {
+    if (!this.key) return ciphertext
+    if (ciphertext.length < TAGLEN) throw new Error('Noise message too short')
+    const decipher = createDecipheriv('aes-256-gcm', this.key, this.nonce())
+    decipher.setAAD(ad)
+    decipher.setAuthTag(ciphertext.subarray(ciphertext.length - TAGLEN))
+    const body = ciphertext.subarray(0, ciphertext.length - TAGLEN)
+    const plaintext = Buffer.concat([decipher.update(body), decipher.final()])
+    this.n++
+    return plaintext
+  }
+}
+
+/**
+ * One side of an XX handshake. The initiator writes the first and third messages and the
+ * responder the second; each side reads the others. Once complete, split() gives the
+ * transport keys and remoteStatic the peer's static key, which the caller must check.
+ */
+export class NoiseHandshake {
+  private h: Buffer
+  private ck: Buffer
+  private cipher = new CipherState()
+  private ephemeral?: { privateKey: KeyObject; publicKey: Buffer }
+  private remoteEphemeral?: Buffer
+  remoteStatic?: Buffer
+  private step = 0
+
+  constructor(
+    private initiator: boolean,
+    private local: StaticKey,
+    prologue: Buffer
+  ) {
+    // The name is shorter than a hash, so it is padded rather than hashed
+    const name = Buffer.from(PROTOCOL_NAME)
+    this.h = Buffer.concat([name, Buffer.alloc(32 - name.length)])
+    this.ck = this.h
+    this.mixHash(prologue)
+  }
+
+  private mixHash(data: Buffer): void {
+    this.h = sha256(this.h, data)
+  }
+
+  private mixKey(input: Buffer): void {
+    const [ck, key] = hkdf(this.ck, input)
+    wipe(input)
+    if (this.ck !== this.h) wipe(this.ck)
+    this.cipher.destroy()
+    this.ck = ck
+    this.cipher = new CipherState(key)
+  }
+
+  private encryptAndHash(plaintext: Buffer): Buffer {
+    const ciphertext = this.cipher.encrypt(this.h, plaintext)
+    this.mixHash(ciphertext)
+    return ciphertext
+  }
+
+  private decryptAndHash(ciphertext: Buffer): Buffer {
+    const plaintext = this.cipher.decrypt(this.h, ciphertext)
+    this.mixHash(ciphertext)
+    return plaintext
+  }
+
+  private writeEphemeral(): Buffer {
+    const { privateKey, publicKey } = generateKeyPairSync('x25519')
+    this.ephemeral = { privateKey, publicKey: rawPublicKey(publicKey) }
+    this.mixHash(this.ephemeral.publicKey)
+    return this.ephemeral.publicKey
+  }
+
+  private readEphemeral(message: Buffer, offset: number): number {
+    if (message.length < offset + DHLEN) throw new Error('Noise message too short')
+    this.remoteEphemeral = message.subarray(offset, offset + DHLEN)
+    this.mixHash(this.remoteEphemeral)
+    return offset + DHLEN
+  }
+
+  private readStatic(message: Buffer, offset: number): number {
+    const length = DHLEN + TAGLEN
+    if (message.length < offset + length) throw new Error('Noise message too short')
+    this.remoteStatic = this.decryptAndHash(message.subarray(offset, offset + length))
+    return offset + length
+  }
+
+  // The next handshake message from us
+  writeMessage(): Buffer {
+    const parts: Buffer[] = []
+    if (this.step === 0 && this.initiator) {
+      parts.push(this.writeEphemeral())
+    } else if (this.step === 1 && !this.initiator) {
+      parts.push(this.writeEphemeral())
+      this.mixKey(dh(this.ephemeral!.privateKey, this.remoteEphemeral!)) // ee
+      parts.push(this.encryptAndHash(this.local.publicKey)) // s
+      this.mixKey(dh(this.local.privateKey, this.remoteEphemeral!)) // es
+    } else if (this.step === 2 && this.initiator) {
+      parts.push(this.encryptAndHash(this.local.publicKey)) // s
+      this.mixKey(dh(this.local.privateKey, this.remoteEphemeral!)) // se
+    } else {
+      throw new Error('Not our turn in the Noise handshake')
+    }
+    parts.push(this.encryptAndHash(Buffer.alloc(0)))
+    this.step++
+    return Buffer.concat(parts)
+  }
+
+  // The next handshake message from the peer; throws if it doesn't authenticate
+  readMessage(message: Buffer): void {
+    let offset = 0
+    if (this.step === 0 && !this.initiator) {
+      offset = this.readEphemeral(message, offset)
+    } else if (this.step === 1 && this.initiator) {
+      offset = this.readEphemeral(message, offset)
+      this.mixKey(dh(this.ephemeral!.privateKey, this.remoteEphemeral!)) // ee
+      offset = this.readStatic(message, offset) // s
+      this.mixKey(dh(this.ephemeral!.privateKey, this.remoteStatic!)) // es
+    } else if (this.step === 2 && !this.initiator) {
+      offset = this.readStatic(message, offset) // s
+      this.mixKey(dh(this.ephemeral!.privateKey, this.remoteStatic!)) // se
+    } else {
+      throw new Error("Not the peer's turn in the Noise handshake")
+    }
+    this.decryptAndHash(message.subarray(offset))
+    this.step++
+  }
+
+  isComplete(): boolean {
+    return this.step === 3
+  }
+
+  // The hash of the whole handshake, for binding anything else to it
+  getHandshakeHash(): Buffer {
+    return this.h
+  }
+
+  /**
+   * Transport keys: initiator to responder, then responder to initiator. The handshake's
+   * own keys are wiped, so this can only be done once.
+   */
+  split(): [Buffer, Buffer] {
+    if (!this.isComplete()) throw new Error('Noise handshake is not complete')
+    const keys = hkdf(this.ck, Buffer.alloc(0))
+    wipe(this.ck)
+    this.cipher.destroy()
+    return keys
+  }
+
+  /**
+   * The ephemeral keys, in the form ecdh.ts uses, for whatever starts from them next
+   * (the Double Ratchet).
+   */
+  getEphemeralKeys(): { ours: KeyPair; theirs: string } {
+    const theirs = Buffer.concat([SPKI_PREFIX, this.remoteEphemeral!]).toString('base64')
+    const ours = {
+      publicKey: Buffer.concat([SPKI_PREFIX, this.ephemeral!.publicKey]).toString('base64'),
+      privateKey: this.ephemeral!.privateKey.export({ type: 'pkcs8', format: 'der' })
+    }
+    return { ours, theirs }
+  }
+}
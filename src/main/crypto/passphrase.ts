@@ -0,0 +1,115 @@
+import * as crypto from 'node:crypto'
+import { PassphraseProtection } from '@shared/messageTypes'
+import { wipe } from './sessionKey'
+
+/**
+ * Transfers protected by a passphrase the two people agreed on elsewhere, so a file can't
+ * be opened by whoever happens to be at the receiving device (or holds its session keys)
+ * without also knowing the phrase. A key stretched from the passphrase with Argon2id is
+ * mixed into the key of each sealed file stream; the receiver derives it again from what
+ * the user types when accepting.
+ *
+ * Argon2 is only in recent Node versions, so where either end lacks it scrypt stands in,
+ * which is also memory-hard. FILE_META names the function and carries the salt, plus a
+ * short check value so a mistyped phrase is caught at accept instead of as a corrupt stream.
+ */
+
+export type PassphraseKdf = PassphraseProtection['kdf']
+
+// Missing from older typings
+type Argon2Crypto = typeof crypto & {
+  argon2?: (
+    algorithm: 'argon2id',
+    parameters: {
+      message: string
+      nonce: Buffer
+      parallelism: number
+      tagLength: number
+      memory: number // KiB
+      passes: number
+    },
+    callback: (err: Error | null, key: Buffer) => void
+  ) => void
+}
+const argon2 = (crypto as Argon2Crypto).argon2
+
+// RFC 9106's second recommended option, for memory-constrained use
+const ARGON2_PARAMETERS = { parallelism: 4, memory: 64 * 1024, passes: 3 }
+// 64 MiB like the Argon2 parameters; maxmem must leave room over 128 * N * r bytes
+const SCRYPT_PARAMETERS = { N: 1 << 16, r: 8, p: 1, maxmem: 128 * 1024 * 1024 }
+
+const KEY_SIZE = 32
+const SALT_SIZE = 16
+const CHECK_SIZE = 16
+
+export function isArgon2Supported(): boolean {
+  return typeof argon2 === 'function'
+}
+
+const stretch = (passphrase: string, kdf: PassphraseKdf, salt: Buffer): Promise<Buffer> => {
+  // The same phrase typed on another platform may come out in another Unicode form
+  const message = passphrase.normalize('NFKC')
+  return new Promise((resolve, reject) => {
+    const done = (err: Error | null, key: Buffer): void => (err ? reject(err) : resolve(key))
+    if (kdf === 'argon2id') {
+      if (!argon2) return reject(new Error('Argon2 is not available in this runtime'))
+      argon2('argon2id', { message, nonce: salt, tagLength: KEY_SIZE, ...ARGON2_PARAMETERS }, done)
+    } else {
+      crypto.scrypt(message, salt, KEY_SIZE, SCRYPT_PARAMETERS, done)
+    }
+  })
+}
+
+const checkValue = (key: Buffer): Buffer =>
+  crypto
+    .createHmac('sha256', key)
+    .update('hyperconnect-passphrase-check')
+    .digest()
+    .subarray(0, CHECK_SIZE)
+
+/**
+ * The sender's side: a key from `passphrase`, and what FILE_META needs for the receiver to
+ * derive it again.
+ */
+export async function protectWithPassphrase(
+  passphrase: string,
+  kdf: PassphraseKdf
+): Promise<{ key: Buffer; protection: PassphraseProtection }> {
+  const salt = crypto.randomBytes(SALT_SIZE)
+  const key = await stretch(passphrase, kdf, salt)
+  const protection = {
+    kdf,
+    salt: salt.toString('base64'),
+    check: checkValue(key).toString('base64')
+  }
+  return { key, protection }
+}
+
+/**
+ * The receiver's side: the key for `passphrase`, or null if it isn't the sender's phrase
+ * (or was stretched with a function we don't have).
+ */
+export async function unlockWithPassphrase(
+  passphrase: string,
+  protection: PassphraseProtection
+): Promise<Buffer | null> {
+  if (protection.kdf === 'argon2id' ? !isArgon2Supported() : protection.kdf !== 'scrypt') {
+    return null
+  }
+  const key = await stretch(passphrase, protection.kdf, Buffer.from(protection.salt, 'base64'))
+  const expected = Buffer.from(protection.check, 'base64')
+  const actual = checkValue(key)
+  if (expected.length === actual.length && crypto.timingSafeEqual(expected, actual)) return key
+  wipe(key)
+  return null
+}
+
+// What a protected file stream is keyed with, in place of the session key
+export function mixPassphraseKey(sessionKey: Buffer, passphraseKey: Buffer): Buffer {
+  const input = Buffer.concat([sessionKey, passphraseKey])
+  const mixed = Buffer.from(
+    crypto.hkdfSync('sha256', input, Buffer.alloc(0), 'hyperconnect-passphrase-stream', 32)
+  )
+  wipe(input)
+  return mixed
+}
@@ -0,0 +1,221 @@
+import { createHmac, hkdfSync } from 'crypto'
+import { KeyPair, computeSharedSecret, generateKeyPair } from './ecdh'
+import { wipe } from './sessionKey'
+import { recordSecurityEvent } from '../securityLog'
+
+/**
+ * The Double Ratchet (as in Signal) for encrypted frames, so each frame has a key of its
+ * own and losing one key exposes neither earlier frames nor, once the ratchet has turned,
+ * later ones. Every frame's key comes off a chain (HMAC) that is deleted as it advances;
+ * every time the conversation changes direction, the side that just received sends a new
+ * X25519 ratchet key, and a DH with it reseeds both chains from the root key.
+ *
+ * The handshake already gives both sides each other's ephemeral key and the session key,
+ * which become the first ratchet keys and root key. The device with the lower id turns
+ * the ratchet once straight away; the other can send before hearing from it, on a chain
+ * seeded from the session key alone, so those first frames are only as safe as the
+ * session key. Frames arrive out of order a little (they travel over both of a peer's
+ * connections), so keys for frames that were passed over are kept for a while. Every key
+ * is wiped as soon as the ratchet has moved past it.
+ */
+
+export interface RatchetHeader {
+  dh: string // sender's current ratchet public key (base64 DER)
+  n: number // frame number in the sender's current chain
+  pn: number // length of the sender's previous chain
+}
+
+// Frames a single step may pass over, and how many of their keys are kept in all
+const MAX_SKIP = 1000
+const MAX_SKIPPED_KEYS = 2000
+// Earlier ratchet keys of the peer's that are remembered, so a stale frame isn't taken
+// for a new step
+const MAX_RETIRED_KEYS = 100
+
+interface RatchetState {
+  rootKey: Buffer
+  ours: KeyPair
+  theirs: string
+  sendChain: Buffer
+  receiveChain: Buffer
+  sent: number
+  received: number
+  previousSent: number
+  skipped: Map<string, Buffer> // `${dh}:${n}` -> key of a frame not yet received
+  retired: Set<string>
+}
+
+// New root key and chain key from the old root key and a DH output
+const kdfRoot = (rootKey: Buffer, dhOutput: Buffer): [Buffer, Buffer] => {
+  const okm = Buffer.from(hkdfSync('sha256', dhOutput, rootKey, 'hyperconnect-ratchet', 64))
+  return [okm.subarray(0, 32), okm.subarray(32)]
+}
+
+// Next chain key, and the key for one frame (or group message, see senderKey.ts)
+export const kdfChain = (chainKey: Buffer): [Buffer, Buffer] => [
+  createHmac('sha256', chainKey).update(Buffer.from([2])).digest(),
+  createHmac('sha256', chainKey).update(Buffer.from([1])).digest()
+]
+
+// The chain a device sends on before the first ratchet step
+const seedChain = (sessionKey: Buffer, deviceId: string): Buffer =>
+  createHmac('sha256', sessionKey).update(`hyperconnect-chain:${deviceId}`).digest()
+
+const fail = (deviceId: string, reason: string): never => {
+  recordSecurityEvent('decryption-failed', deviceId, reason)
+  throw new Error(reason)
+}
+
+export class DoubleRatchet {
+  private state: RatchetState
+
+  /**
+   * `ours` and `theirs` are the ephemeral keys of the handshake that produced `sessionKey`.
+   */
+  constructor(
+    sessionKey: Buffer,
+    ours: KeyPair,
+    theirs: string,
+    localId: string,
+    private remoteId: string
+  ) {
+    // Copies, so the ratchet can wipe its keys without the caller's being wiped, or the
+    // other way round
+    ours = { ...ours, privateKey: Buffer.from(ours.privateKey) }
+    this.state = {
+      rootKey: Buffer.from(sessionKey),
+      ours,
+      theirs,
+      sendChain: seedChain(sessionKey, localId),
+      receiveChain: seedChain(sessionKey, remoteId),
+      sent: 0,
+      received: 0,
+      previousSent: 0,
+      skipped: new Map(),
+      retired: new Set()
+    }
+    if (localId < remoteId) {
+      const state = this.state
+      state.ours = generateKeyPair()
+      const dhOutput = computeSharedSecret(state.ours.privateKey, theirs)
+      const [rootKey, sendChain] = [state.rootKey, state.sendChain]
+      ;[state.rootKey, state.sendChain] = kdfRoot(state.rootKey, dhOutput)
+      wipe(dhOutput, rootKey, sendChain, ours.privateKey)
+    }
+  }
+
+  // The key for the next frame we send, and the header that goes with it; the caller
+  // wipes the key once the frame is encrypted
+  next(): { key: Buffer; header: RatchetHeader } {
+    const state = this.state
+    const [chain, key] = kdfChain(state.sendChain)
+    wipe(state.sendChain)
+    state.sendChain = chain
+    const header = { dh: state.ours.publicKey, n: state.sent++, pn: state.previousSent }
+    return { key, header }
+  }
+
+  /**
+   * Finds the key for a received frame and passes it to `decrypt`. The ratchet only moves
+   * on once `decrypt` returns, so a forged or corrupted frame leaves it as it was.
+   */
+  open<T>(header: RatchetHeader, decrypt: (key: Buffer) => T): T {
+    if (
+      typeof header?.dh !== 'string' ||
+      !Number.isSafeInteger(header.n) ||
+      !Number.isSafeInteger(header.pn)
+    ) {
+      return fail(this.remoteId, 'Malformed ratchet header')
+    }
+    const { dh, n, pn } = header
+    const id = `${dh}:${n}`
+    const skippedKey = this.state.skipped.get(id)
+    if (skippedKey) {
+      const result = decrypt(skippedKey)
+      this.state.skipped.delete(id)
+      wipe(skippedKey)
+      return result
+    }
+    if (dh !== this.state.theirs && this.state.retired.has(dh)) {
+      return fail(this.remoteId, `Frame ${n} of an earlier ratchet step was already received`)
+    }
+
+    const next: RatchetState = {
+      ...this.state,
+      skipped: new Map(this.state.skipped),
+      retired: new Set(this.state.retired)
+    }
+    // Keys the new state no longer needs, wiped only if it replaces the current one
+    const discarded: Buffer[] = []
+    if (dh !== next.theirs) {
+      this.skip(next, pn, discarded)
+      this.step(next, dh, discarded)
+    }
+    this.skip(next, n, discarded)
+    const [chain, key] = kdfChain(next.receiveChain)
+    discarded.push(next.receiveChain)
+    next.receiveChain = chain
+    next.received++
+
+    try {
+      const result = decrypt(key)
+      this.state = next
+      wipe(...discarded)
+      return result
+    } finally {
+      wipe(key)
+    }
+  }
+
+  // Wipes every key, once the session is over
+  destroy(): void {
+    const state = this.state
+    wipe(state.rootKey, state.sendChain, state.receiveChain, state.ours.privateKey)
+    wipe(...state.skipped.values())
+    state.skipped.clear()
+  }
+
+  // Keeps the keys of the frames in the current receiving chain before `until`
+  private skip(state: RatchetState, until: number, discarded: Buffer[]): void {
+    if (until < state.received) {
+      fail(this.remoteId, `Frame ${until} of the current chain was already received`)
+    }
+    if (until - state.received > MAX_SKIP) {
+      fail(this.remoteId, `Frame ${until} is too far ahead of ${state.received}`)
+    }
+    while (state.received < until) {
+      const [chain, key] = kdfChain(state.receiveChain)
+      state.skipped.set(`${state.theirs}:${state.received}`, key)
+      discarded.push(state.receiveChain)
+      state.receiveChain = chain
+      state.received++
+    }
+    // Maps keep insertion order, so the oldest keys go first
+    for (const [old, key] of state.skipped) {
+      if (state.skipped.size <= MAX_SKIPPED_KEYS) break
+      state.skipped.delete(old)
+      discarded.push(key)
+    }
+  }
+
+  // The peer has a new ratchet key: a chain to receive on, then a new key and chain of ours
+  private step(state: RatchetState, theirs: string, discarded: Buffer[]): void {
+    state.retired.add(state.theirs)
+    for (const old of state.retired) {
+      if (state.retired.size <= MAX_RETIRED_KEYS) break
+      state.retired.delete(old)
+    }
+    state.previousSent = state.sent
+    state.sent = 0
+    state.received = 0
+    state.theirs = theirs
+    discarded.push(state.rootKey, state.receiveChain, state.sendChain, state.ours.privateKey)
+    const received = computeSharedSecret(state.ours.privateKey, theirs)
+    ;[state.rootKey, state.receiveChain] = kdfRoot(state.rootKey, received)
+    discarded.push(state.rootKey)
+    state.ours = generateKeyPair()
+    const sent = computeSharedSecret(state.ours.privateKey, theirs)
+    ;[state.rootKey, state.sendChain] = kdfRoot(state.rootKey, sent)
+    wipe(received, sent)
+  }
+}
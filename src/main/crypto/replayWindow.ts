@@ -0,0 +1,29 @@
+// Frames of one session travel over both of a peer's connections and can overtake each
+// other a little, so this many sequence numbers behind the newest may still arrive late
+const WINDOW_SIZE = 32
+
+/**
+ * Tracks which recent sequence numbers a session has received, so a captured frame can't
+ * be played back into it. Only frames that have already authenticated may be passed in,
+ * or a forged number could move the window.
+ */
+export class ReplayWindow {
+  private highest = 0
+  private seen = 0 // bit i set: `highest - i` has been received
+
+  accept(seq: number): boolean {
+    if (!Number.isSafeInteger(seq) || seq <= 0) return false
+
+    if (seq > this.highest) {
+      const shift = seq - this.highest
+      this.seen = shift >= WINDOW_SIZE ? 1 : ((this.seen << shift) | 1) >>> 0
+      this.highest = seq
+      return true
+    }
+
+    const offset = this.highest - seq
+    if (offset >= WINDOW_SIZE || (this.seen >>> offset) & 1) return false
+    this.seen = (this.seen | (1 << offset)) >>> 0
+    return true
+  }
+}
@@ -0,0 +1,175 @@
+import { createCipheriv, createDecipheriv, hkdfSync, randomBytes, randomUUID } from 'crypto'
+import { SessionTicketPayload } from '@shared/messageTypes'
+import { getSession, wipe } from './sessionKey'
+import { recordSecurityEvent } from '../securityLog'
+
+/**
+ * Session resumption, so a peer that dropped off for a moment (flaky Wi-Fi, a laptop lid)
+ * can pick up again without a new key exchange or approval prompt. After a handshake the
+ * accepting side sends a SESSION_TICKET: a secret derived from the session key, sealed
+ * with a key only it knows (and only in memory, so tickets die with the app). The dialing
+ * side keeps the ticket and the same secret, and offers them back in its next HELLO_SECURE
+ * along with a nonce; the new session key comes from the secret and both ends' nonces.
+ *
+ * Its HELLO_SECURE still carries a public key, so a peer that can't or won't resume (ticket
+ * expired, app restarted, approval needed) answers with a full handshake in the same round
+ * trip. Each ticket works once. A resumed session has no Double Ratchet, since that starts
+ * from the key exchange it skipped.
+ */
+
+// Offered in the dialer's HELLO_SECURE
+export interface ResumeRequest {
+  ticket: string // Base64, as received in SESSION_TICKET
+  nonce: string // Base64, fresh for this attempt
+}
+
+// The dialer's side of a resumption in progress
+export interface PendingResumption {
+  request: ResumeRequest
+  secret: Buffer
+}
+
+const TICKET_LIFETIME = 30 * 60 * 1000
+const NONCE_SIZE = 16
+
+// Seals the tickets we issue
+const ticketKey = randomBytes(32)
+// Tickets already resumed, until they would have expired anyway (ticket id → expiry)
+const redeemed = new Map<string, number>()
+// Tickets peers have issued us, with their secrets
+const held = new Map<string, { ticket: string; secret: Buffer; expires: number }>()
+
+interface TicketContents {
+  id: string
+  deviceId: string
+  secret: string
+  expires: number
+}
+
+const resumptionSecret = (sessionKey: Buffer): Buffer =>
+  Buffer.from(hkdfSync('sha256', sessionKey, Buffer.alloc(0), 'hyperconnect-resumption', 32))
+
+const resumedSessionKey = (secret: Buffer, clientNonce: Buffer, serverNonce: Buffer): Buffer =>
+  Buffer.from(
+    hkdfSync(
+      'sha256',
+      secret,
+      Buffer.concat([clientNonce, serverNonce]),
+      'hyperconnect-resumed-session',
+      32
+    )
+  )
+
+const sealTicket = (contents: TicketContents): string => {
+  const iv = randomBytes(12)
+  const cipher = createCipheriv('aes-256-gcm', ticketKey, iv)
+  const sealed = Buffer.concat([cipher.update(JSON.stringify(contents)), cipher.final()])
+  return Buffer.concat([iv, cipher.getAuthTag(), sealed]).toString('base64')
+}
+
+// Throws if the ticket wasn't sealed by us
+const openTicket = (ticket: string): TicketContents => {
+  const raw = Buffer.from(ticket, 'base64')
+  const decipher = createDecipheriv('aes-256-gcm', ticketKey, raw.subarray(0, 12))
+  decipher.setAuthTag(raw.subarray(12, 28))
+  const opened = Buffer.concat([decipher.update(raw.subarray(28)), decipher.final()])
+  return JSON.parse(opened.toString('utf8'))
+}
+
+/**
+ * A ticket for `deviceId` to resume the session keyed by `sessionKey` later.
+ */
+export function issueTicket(deviceId: string, sessionKey: Buffer): SessionTicketPayload {
+  const now = Date.now()
+  for (const [id, expires] of redeemed) {
+    if (expires <= now) redeemed.delete(id)
+  }
+  const secret = resumptionSecret(sessionKey)
+  const ticket = sealTicket({
+    id: randomUUID(),
+    deviceId,
+    secret: secret.toString('base64'),
+    expires: now + TICKET_LIFETIME
+  })
+  wipe(secret)
+  return { ticket, lifetime: TICKET_LIFETIME }
+}
+
+/**
+ * Keeps a ticket `deviceId` sent us for our next connection to it. Must be called while the
+ * session the ticket was issued in is still the current one.
+ */
+export function storeTicket(deviceId: string, payload: unknown): void {
+  const session = getSession(deviceId)
+  const { ticket, lifetime } = (payload ?? {}) as Partial<SessionTicketPayload>
+  if (!session || typeof ticket !== 'string' || typeof lifetime !== 'number') return
+  wipe(held.get(deviceId)?.secret)
+  held.set(deviceId, {
+    ticket,
+    secret: resumptionSecret(session.sessionKey),
+    expires: Date.now() + Math.min(lifetime, TICKET_LIFETIME)
+  })
+}
+
+/**
+ * Takes the ticket held for `deviceId`, if it hasn't expired, for a HELLO_SECURE about to
+ * be sent. It is gone afterwards either way, as it can only be used once.
+ */
+export function takeResumption(deviceId: string): PendingResumption | undefined {
+  const entry = held.get(deviceId)
+  held.delete(deviceId)
+  if (!entry) return undefined
+  if (entry.expires <= Date.now()) {
+    wipe(entry.secret)
+    return undefined
+  }
+  const request = { ticket: entry.ticket, nonce: randomBytes(NONCE_SIZE).toString('base64') }
+  return { request, secret: entry.secret }
+}
+
+/**
+ * The dialer's key for a session the peer agreed to resume, given the peer's nonce.
+ */
+export function completeResumption(pending: PendingResumption, serverNonce: string): Buffer {
+  const clientNonce = Buffer.from(pending.request.nonce, 'base64')
+  return resumedSessionKey(pending.secret, clientNonce, Buffer.from(serverNonce, 'base64'))
+}
+
+/**
+ * Checks a resumption `deviceId` asked for. Returns the new session key and the nonce to
+ * answer with, or undefined to fall back to a full handshake.
+ */
+export function acceptResumption(
+  deviceId: string,
+  request: unknown
+): { sessionKey: Buffer; nonce: string } | undefined {
+  const { ticket, nonce } = (request ?? {}) as Partial<ResumeRequest>
+  if (typeof ticket !== 'string' || typeof nonce !== 'string') return undefined
+  let contents: TicketContents
+  try {
+    contents = openTicket(ticket)
+  } catch {
+    // Most likely issued before a restart, but could be forged
+    console.warn(`[Resumption] Ignoring a ticket from ${deviceId} that doesn't open`)
+    return undefined
+  }
+  if (contents.deviceId !== deviceId) {
+    const reason = `Offered a ticket issued to ${contents.deviceId}`
+    recordSecurityEvent('handshake-rejected', deviceId, reason)
+    return undefined
+  }
+  if (contents.expires <= Date.now()) return undefined
+  if (redeemed.has(contents.id)) {
+    recordSecurityEvent('replay-rejected', deviceId, 'Offered a ticket that was already used')
+    return undefined
+  }
+  const clientNonce = Buffer.from(nonce, 'base64')
+  if (clientNonce.length !== NONCE_SIZE) return undefined
+  redeemed.set(contents.id, contents.expires)
+
+  const serverNonce = randomBytes(NONCE_SIZE)
+  const secret = Buffer.from(contents.secret, 'base64')
+  const sessionKey = resumedSessionKey(secret, clientNonce, serverNonce)
+  wipe(secret)
+  return { sessionKey, nonce: serverNonce.toString('base64') }
+}
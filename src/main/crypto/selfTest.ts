@@ -0,0 +1,132 @@
+import { createCipheriv, hkdfSync } from 'crypto'
+import { CryptoSelfTestResult } from '@shared/messageTypes'
+import {
+  CipherSuite,
+  benchmarkCipherSuites,
+  createAeadCipher,
+  createAeadDecipher,
+  getCipherSuites
+} from './cipherSuite'
+
+/**
+ * Known-answer tests for the primitives everything else is built on, and a benchmark of
+ * the cipher suites on this hardware, run on demand from settings. A wrong answer means
+ * the runtime's crypto is broken (or not what it claims to be), so nothing it encrypts
+ * can be trusted. The benchmark runs over more data than the quick one at startup, and
+ * its result becomes the suite we prefer in new handshakes.
+ */
+
+const BENCHMARK_SIZE = 8 * 1024 * 1024
+
+// Long vectors are split over several lines
+const hex = (...parts: string[]): Buffer => Buffer.from(parts.join(''), 'hex')
+
+interface AeadVector {
+  key: Buffer
+  nonce: Buffer
+  aad: Buffer
+  plaintext: Buffer
+  ciphertext: Buffer
+  tag: Buffer
+}
+
+// Seals the plaintext and opens the ciphertext, both of which must give the known answer
+const aeadMatches = (suite: CipherSuite, v: AeadVector): boolean => {
+  const cipher = createAeadCipher(suite, v.key, v.nonce).setAAD(v.aad)
+  const sealed = Buffer.concat([cipher.update(v.plaintext), cipher.final()])
+  if (!sealed.equals(v.ciphertext) || !cipher.getAuthTag().equals(v.tag)) return false
+  const decipher = createAeadDecipher(suite, v.key, v.nonce).setAAD(v.aad).setAuthTag(v.tag)
+  return Buffer.concat([decipher.update(v.ciphertext), decipher.final()]).equals(v.plaintext)
+}
+
+const KNOWN_ANSWERS: { name: string; passes: () => boolean }[] = [
+  {
+    // The GCM specification (McGrew and Viega), test case 16
+    name: 'AES-256-GCM',
+    passes: () =>
+      aeadMatches('aes-256-gcm', {
+        key: hex('feffe9928665731c6d6a8f9467308308feffe9928665731c6d6a8f9467308308'),
+        nonce: hex('cafebabefacedbaddecaf888'),
+        aad: hex('feedfacedeadbeeffeedfacedeadbeefabaddad2'),
+        plaintext: hex(
+          'd9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a72',
+          '1c3c0c95956809532fcf0e2449a6b525b16aedf5aa0de657ba637b39'
+        ),
+        ciphertext: hex(
+          '522dc1f099567d07f47f37a32a84427d643a8cdcbfe5c0c97598a2bd2555d1aa',
+          '8cb08e48590dbb3da7b08b1056828838c5f61e6393ba7a0abcc9f662'
+        ),
+        tag: hex('76fc6ece0f4e1768cddf8853bb2d551b')
+      })
+  },
+  {
+    // RFC 8439, section 2.8.2
+    name: 'ChaCha20-Poly1305',
+    passes: () =>
+      aeadMatches('chacha20-poly1305', {
+        key: hex('808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f'),
+        nonce: hex('070000004041424344454647'),
+        aad: hex('50515253c0c1c2c3c4c5c6c7'),
+        plaintext: Buffer.from(
+          "Ladies and Gentlemen of the class of '99: If I could offer you only one tip for " +
+            'the future, sunscreen would be it.'
+        ),
+        ciphertext: hex(
+          'd31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d6',
+          '3dbea45e8ca9671282fafb69da92728b1a71de0a9e060b2905d6a5b67ecd3b36',
+          '92ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc',
+          '3ff4def08e4b7a9de576d26586cec64b6116'
+        ),
+        tag: hex('1ae10b594f09e26a7e902ecbd0600691')
+      })
+  },
+  {
+    // NIST SP 800-38A, F.5.5 (the first two blocks); legacy file streams use it
+    name: 'AES-256-CTR',
+    passes: () => {
+      const cipher = createCipheriv(
+        'aes-256-ctr',
+        hex('603deb1015ca71be2b73aef0857d77811f352c073b6108d72d9810a30914dff4'),
+        hex('f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff')
+      )
+      const plaintext = hex('6bc1bee22e409f96e93d7e117393172aae2d8a571e03ac9c9eb76fac45af8e51')
+      return cipher
+        .update(plaintext)
+        .equals(hex('601ec313775789a5b7a7f504bbf3d228f443e3ca4d62b59aca84e990cacaf5c5'))
+    }
+  },
+  {
+    // RFC 5869, test case 1
+    name: 'HKDF-SHA256',
+    passes: () => {
+      const okm = hkdfSync(
+        'sha256',
+        Buffer.alloc(22, 0x0b),
+        hex('000102030405060708090a0b0c'),
+        hex('f0f1f2f3f4f5f6f7f8f9'),
+        42
+      )
+      return Buffer.from(okm).equals(
+        hex(
+          '3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c',
+          '5db02d56ecc4c5bf34007208d5b887185865'
+        )
+      )
+    }
+  }
+]
+
+export function runCryptoSelfTest(): CryptoSelfTestResult {
+  const checks = KNOWN_ANSWERS.map(({ name, passes }) => {
+    let passed = false
+    try {
+      passed = passes()
+    } catch (err) {
+      console.error(`[Crypto] ${name} self-test threw:`, err)
+    }
+    if (!passed) console.error(`[Crypto] ${name} failed its known-answer test`)
+    return { name, passed }
+  })
+  const throughput = benchmarkCipherSuites(BENCHMARK_SIZE)
+  return { checks, throughput, preferredSuite: getCipherSuites()[0] }
+}
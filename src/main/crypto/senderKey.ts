@@ -0,0 +1,159 @@
+import { createCipheriv, createDecipheriv, randomBytes, randomUUID } from 'crypto'
+import { SealedGroupPayload, SenderKeyPayload } from '@shared/messageTypes'
+import { kdfChain } from './ratchet'
+import { wipe } from './sessionKey'
+import { recordSecurityEvent } from '../securityLog'
+
+/**
+ * Sender keys (as in Signal's groups) for GROUP_MESSAGE: each member encrypts a message
+ * once, with a key off a hash chain of its own, and the same ciphertext goes to every
+ * member. The chain's current state reaches each member in a SENDER_KEY frame over their
+ * pairwise session; the chain only moves forward, so a member given it can't read what
+ * was sent before. Group messages still travel over those pairwise sessions, which is
+ * what tells a receiver who sent one, so sender keys carry no signatures.
+ *
+ * Whenever the members change, everyone starts a new chain before sending again and hands
+ * it only to the current members: someone removed can't read on, and someone added can't
+ * read back.
+ */
+
+// Messages a receiver may pass over, and how many of their keys are kept in all
+const MAX_SKIP = 1000
+const MAX_SKIPPED_KEYS = 2000
+
+const groupAad = (groupId: string, keyId: string, iteration: number): Buffer =>
+  Buffer.from(`${groupId}:${keyId}:${iteration}`)
+
+export class SenderKey {
+  private skipped = new Map<number, Buffer>() // iteration -> key of a message not yet received
+
+  private constructor(
+    readonly groupId: string,
+    readonly keyId: string,
+    private chainKey: Buffer,
+    private iteration: number
+  ) {}
+
+  // A new chain of our own for `groupId`
+  static generate(groupId: string): SenderKey {
+    return new SenderKey(groupId, randomUUID(), randomBytes(32), 0)
+  }
+
+  // A member's chain as it arrived in SENDER_KEY; null if the payload is malformed
+  static fromPayload(payload: unknown): SenderKey | null {
+    const { groupId, keyId, chainKey, iteration } = (payload ?? {}) as Partial<SenderKeyPayload>
+    if (typeof groupId !== 'string' || typeof keyId !== 'string') return null
+    if (typeof chainKey !== 'string' || !Number.isSafeInteger(iteration)) return null
+    const key = Buffer.from(chainKey, 'base64')
+    if (key.length !== 32 || iteration! < 0) return null
+    return new SenderKey(groupId, keyId, key, iteration!)
+  }
+
+  // The chain as it stands, for a member to read what we send from now on
+  toPayload(): SenderKeyPayload {
+    return {
+      groupId: this.groupId,
+      keyId: this.keyId,
+      chainKey: this.chainKey.toString('base64'),
+      iteration: this.iteration
+    }
+  }
+
+  // Encrypts the next message on our chain
+  seal(plaintext: string): SealedGroupPayload {
+    const [chain, key] = kdfChain(this.chainKey)
+    wipe(this.chainKey)
+    this.chainKey = chain
+    const iteration = this.iteration++
+    try {
+      const iv = randomBytes(12)
+      const cipher = createCipheriv('aes-256-gcm', key, iv)
+      cipher.setAAD(groupAad(this.groupId, this.keyId, iteration))
+      const ciphertext = Buffer.concat([cipher.update(plaintext, 'utf8'), cipher.final()])
+      return {
+        keyId: this.keyId,
+        iteration,
+        iv: iv.toString('base64'),
+        tag: cipher.getAuthTag().toString('base64'),
+        ciphertext: ciphertext.toString('base64')
+      }
+    } finally {
+      wipe(key)
+    }
+  }
+
+  /**
+   * Decrypts a message `senderId` sent on this chain. The chain only moves on once the
+   * message has authenticated, so a forged one leaves it as it was. Throws (and logs a
+   * security event) if it can't be decrypted.
+   */
+  open(sealed: SealedGroupPayload, senderId: string): string {
+    const { iteration } = sealed
+    const fail = (reason: string): never => {
+      recordSecurityEvent('decryption-failed', senderId, reason)
+      throw new Error(reason)
+    }
+    if (!Number.isSafeInteger(iteration)) return fail('Malformed group message')
+
+    const skippedKey = this.skipped.get(iteration)
+    if (skippedKey) {
+      const plaintext = this.decrypt(sealed, skippedKey)
+      this.skipped.delete(iteration)
+      wipe(skippedKey)
+      return plaintext
+    }
+    if (iteration < this.iteration) {
+      return fail(`Group message ${iteration} was already received`)
+    }
+    if (iteration - this.iteration > MAX_SKIP) {
+      return fail(`Group message ${iteration} is too far ahead of ${this.iteration}`)
+    }
+
+    // Worked out on the side and kept only if the message authenticates
+    const passed = new Map<number, Buffer>()
+    const discarded: Buffer[] = []
+    let chainKey = this.chainKey
+    for (let i = this.iteration; i < iteration; i++) {
+      const [chain, key] = kdfChain(chainKey)
+      passed.set(i, key)
+      if (chainKey !== this.chainKey) discarded.push(chainKey)
+      chainKey = chain
+    }
+    const [chain, key] = kdfChain(chainKey)
+    if (chainKey !== this.chainKey) discarded.push(chainKey)
+
+    let plaintext: string
+    try {
+      plaintext = this.decrypt(sealed, key)
+    } catch (e) {
+      wipe(chain, ...discarded, ...passed.values())
+      return fail((e as Error).message)
+    } finally {
+      wipe(key)
+    }
+    wipe(this.chainKey, ...discarded)
+    this.chainKey = chain
+    this.iteration = iteration + 1
+    for (const [i, passedKey] of passed) this.skipped.set(i, passedKey)
+    // Maps keep insertion order, so the oldest keys go first
+    for (const [old, oldKey] of this.skipped) {
+      if (this.skipped.size <= MAX_SKIPPED_KEYS) break
+      this.skipped.delete(old)
+      wipe(oldKey)
+    }
+    return plaintext
+  }
+
+  destroy(): void {
+    wipe(this.chainKey, ...this.skipped.values())
+    this.skipped.clear()
+  }
+
+  private decrypt(sealed: SealedGroupPayload, key: Buffer): string {
+    const decipher = createDecipheriv('aes-256-gcm', key, Buffer.from(sealed.iv, 'base64'))
+    decipher.setAAD(groupAad(this.groupId, this.keyId, sealed.iteration))
+    decipher.setAuthTag(Buffer.from(sealed.tag, 'base64'))
+    const ciphertext = Buffer.from(sealed.ciphertext, 'base64')
+    return Buffer.concat([decipher.update(ciphertext), decipher.final()]).toString('utf8')
+  }
+}
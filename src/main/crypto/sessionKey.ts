@@ -1,8 +1,19 @@
 import { createHash } from 'crypto'
+import type { Socket } from 'net'
+import { recordSecurityEvent } from '../securityLog'
+import type { PeerProtocol } from '../protocolVersion'
+import type { ReplayWindow } from './replayWindow'
+import type { DoubleRatchet } from './ratchet'
 
 export interface SessionData {
   sessionKey: Buffer
   deviceId: string
+  protocol?: PeerProtocol // agreed in the handshake that created this session
+  sentSeq?: number // last sequence number we sent in this session
+  received?: ReplayWindow // sequence numbers received, with replay protection on
+  extensions?: Set<string> // what the peer listed in EXTENSIONS, once it has
+  ratchet?: DoubleRatchet // per-frame keys, when both ends support RATCHET
+  socket?: Socket // the connection whose handshake created it
 }
 
 const activeSessions: Map<string, SessionData> = new Map()
@@ -20,9 +31,30 @@ export function deriveSessionKey(sharedSecret: Buffer): Buffer {
  * Stores a session key for a given connection/device.
  */
 export function storeSession(socketId: string, data: SessionData): void {
+  const existing = activeSessions.get(socketId)
+  // A re-handshake while a session is still live replaces the key mid-conversation
+  if (existing && !existing.sessionKey.equals(data.sessionKey)) {
+    recordSecurityEvent('key-changed', data.deviceId, 'Session key replaced by a new handshake')
+  }
+  if (existing && existing.sessionKey !== data.sessionKey) wipeSession(existing)
   activeSessions.set(socketId, data)
 }
 
+/**
+ * Overwrites key material once it is no longer needed. JavaScript can't promise a secret
+ * leaves no other copy (OpenSSL keeps its own inside a KeyObject or cipher, and strings
+ * can't be cleared at all), but Buffers live outside the garbage-collected heap, so these
+ * at least don't linger until their memory happens to be reused.
+ */
+export function wipe(...keys: (Buffer | undefined)[]): void {
+  for (const key of keys) key?.fill(0)
+}
+
+const wipeSession = (session: SessionData): void => {
+  wipe(session.sessionKey)
+  session.ratchet?.destroy()
+}
+
 /**
  * Retrieves a session key for a given connection/device.
  */
@@ -31,8 +63,13 @@ export function getSession(socketId: string): SessionData | undefined {
 }
 
 /**
- * Discards a session key when a connection is closed.
+ * Discards a session key when a connection is closed, wiping it. Given the `socket` that
+ * closed, only a session that connection created is discarded: a device can have another
+ * connection (one it opened to us, say) still using the session it agreed there.
  */
-export function discardSession(socketId: string): void {
+export function discardSession(socketId: string, socket?: Socket): void {
+  const session = activeSessions.get(socketId)
+  if (!session || (socket && session.socket && session.socket !== socket)) return
   activeSessions.delete(socketId)
+  wipeSession(session)
 }
@@ -1,5 +1,11 @@
-import { createCipheriv, createDecipheriv } from 'crypto'
+import { createCipheriv, createDecipheriv, hkdfSync } from 'crypto'
 import { Transform } from 'stream'
+import {
+  CipherSuite,
+  DEFAULT_CIPHER_SUITE,
+  createAeadCipher,
+  createAeadDecipher
+} from './cipherSuite'
 
 /**
  * Creates an encryption transform stream using AES-256-CTR.
@@ -18,3 +24,111 @@ export function createDecryptionStream(sessionKey: Buffer, iv: Buffer): Transfor
   if (iv.length !== 16) throw new Error('IV must be 16 bytes for AES-256-CTR')
   return createDecipheriv('aes-256-ctr', sessionKey, iv)
 }
+
+/**
+ * File streams sealed in chunks with AES-256-GCM (or the session's cipher suite, if
+ * ChaCha20-Poly1305 was negotiated), so a flipped bit is caught at the chunk
+ * it lands in rather than (maybe) by the checksum at the end. Each chunk is a u32 length,
+ * the ciphertext and its 16-byte tag; the top bit of the length marks the last chunk, which
+ * is authenticated too, so a stream cut short is caught as well. Every chunk's nonce is its
+ * index, under a key derived from the session key and the stream's random 16-byte salt
+ * (sent where the CTR IV is), so no two streams share a key and nonce.
+ */
+
+const SEAL_CHUNK_SIZE = 64 * 1024
+const SEAL_TAG_SIZE = 16
+const SEAL_FINAL = 0x80000000
+
+const streamKey = (sessionKey: Buffer, salt: Buffer): Buffer => {
+  if (salt.length !== 16) throw new Error('Salt must be 16 bytes for sealed streams')
+  return Buffer.from(hkdfSync('sha256', sessionKey, salt, 'hyperconnect-file-stream', 32))
+}
+
+const chunkNonce = (index: number): Buffer => {
+  const nonce = Buffer.alloc(12)
+  nonce.writeUIntBE(index, 6, 6)
+  return nonce
+}
+
+// Whether a chunk is the last, bound into its tag (its position is in the nonce)
+const chunkAad = (final: boolean): Buffer => Buffer.from([final ? 1 : 0])
+
+export function createSealingStream(
+  sessionKey: Buffer,
+  salt: Buffer,
+  suite: CipherSuite = DEFAULT_CIPHER_SUITE
+): Transform {
+  const key = streamKey(sessionKey, salt)
+  let pending = Buffer.alloc(0)
+  let index = 0
+
+  const seal = (plaintext: Buffer, final: boolean): Buffer => {
+    const cipher = createAeadCipher(suite, key, chunkNonce(index++))
+    cipher.setAAD(chunkAad(final))
+    const ciphertext = Buffer.concat([cipher.update(plaintext), cipher.final()])
+    const header = Buffer.alloc(4)
+    header.writeUInt32BE((final ? SEAL_FINAL : 0) + plaintext.length)
+    return Buffer.concat([header, ciphertext, cipher.getAuthTag()])
+  }
+
+  return new Transform({
+    transform(chunk: Buffer, _encoding, callback): void {
+      pending = Buffer.concat([pending, chunk])
+      // The last full chunk is held back; it may turn out to be the final one
+      while (pending.length > SEAL_CHUNK_SIZE) {
+        this.push(seal(pending.subarray(0, SEAL_CHUNK_SIZE), false))
+        pending = pending.subarray(SEAL_CHUNK_SIZE)
+      }
+      callback()
+    },
+    flush(callback): void {
+      this.push(seal(pending, true))
+      callback()
+    }
+  })
+}
+
+// Fails with an error on the first chunk that doesn't authenticate, or a missing end
+export function createOpeningStream(
+  sessionKey: Buffer,
+  salt: Buffer,
+  suite: CipherSuite = DEFAULT_CIPHER_SUITE
+): Transform {
+  const key = streamKey(sessionKey, salt)
+  let pending = Buffer.alloc(0)
+  let index = 0
+  let finished = false
+
+  return new Transform({
+    transform(chunk: Buffer, _encoding, callback): void {
+      if (finished) return callback(new Error('Data after the end of a sealed stream'))
+      pending = Buffer.concat([pending, chunk])
+      try {
+        while (pending.length >= 4) {
+          const header = pending.readUInt32BE(0)
+          const final = header >= SEAL_FINAL
+          const length = header - (final ? SEAL_FINAL : 0)
+          if (length > SEAL_CHUNK_SIZE) throw new Error(`Sealed chunk too large: ${length}`)
+          const size = 4 + length + SEAL_TAG_SIZE
+          if (pending.length < size) break
+          const decipher = createAeadDecipher(suite, key, chunkNonce(index++))
+          decipher.setAAD(chunkAad(final))
+          decipher.setAuthTag(pending.subarray(4 + length, size))
+          const ciphertext = pending.subarray(4, 4 + length)
+          this.push(Buffer.concat([decipher.update(ciphertext), decipher.final()]))
+          pending = pending.subarray(size)
+          finished = final
+          if (finished && pending.length > 0) {
+            throw new Error('Data after the end of a sealed stream')
+          }
+        }
+        callback()
+      } catch (err) {
+        callback(err as Error)
+      }
+    },
+    flush(callback): void {
+      callback(finished ? null : new Error('Sealed stream ended early'))
+    }
+  })
+}
@@ -0,0 +1,51 @@
+import { createHash, createHmac, hkdfSync, timingSafeEqual } from 'crypto'
+import { wipe } from './sessionKey'
+
+/**
+ * A MAC over both HELLO_SECUREs, keyed from the session key they led to. The hellos go in
+ * the clear, so someone in the path could take capabilities or cipher suites out of either
+ * and leave both ends agreeing on something weaker. Whichever end has the session key first
+ * sends the MAC (the answerer with its HELLO_SECURE, or the dialer with HANDSHAKE_FINISH
+ * after Noise) and the other checks it, so a hello changed on the way fails the handshake.
+ *
+ * Each hello is hashed as its JSON, less the MAC itself. A parsed object keeps its key
+ * order, so the receiver gets back the exact text the sender wrote.
+ */
+
+export interface Transcript {
+  dialerId: string
+  answererId: string
+  dialerHello: unknown
+  answererHello: unknown
+}
+
+const digest = (hello: unknown): Buffer => {
+  const fields = { ...(hello as Record<string, unknown>) }
+  delete fields.transcriptMac
+  return createHash('sha256').update(JSON.stringify(fields)).digest()
+}
+
+export function transcriptMac(sessionKey: Buffer, transcript: Transcript): string {
+  const key = Buffer.from(
+    hkdfSync('sha256', sessionKey, Buffer.alloc(0), 'hyperconnect-transcript', 32)
+  )
+  const mac = createHmac('sha256', key)
+    .update(`${transcript.dialerId}:${transcript.answererId}:`)
+    .update(digest(transcript.dialerHello))
+    .update(digest(transcript.answererHello))
+    .digest('base64')
+  wipe(key)
+  return mac
+}
+
+// Whether `mac`, as the peer sent it, is the one for `transcript`
+export function checkTranscriptMac(
+  sessionKey: Buffer,
+  transcript: Transcript,
+  mac: unknown
+): boolean {
+  if (typeof mac !== 'string') return false
+  const expected = Buffer.from(transcriptMac(sessionKey, transcript), 'base64')
+  const actual = Buffer.from(mac, 'base64')
+  return actual.length === expected.length && timingSafeEqual(actual, expected)
+}
@@ -0,0 +1,217 @@
+import fs from 'fs'
+import crypto from 'crypto'
+import { Transform, TransformCallback } from 'stream'
+import { DeltaSignature } from '@shared/messageTypes'
+
+// Instruction stream: [op u8][value u32 BE], followed by `value` raw bytes for LITERAL
+const OP_COPY = 1
+const OP_LITERAL = 2
+const HEADER_SIZE = 5
+
+const MIN_BLOCK_SIZE = 8 * 1024
+const MAX_BLOCKS = 16 * 1024
+// Literal runs are flushed at this size so the encoder never buffers much of the file
+const MAX_LITERAL = 1024 * 1024
+
+const strongHash = (block: Buffer): string =>
+  crypto.createHash('sha256').update(block).digest('hex').slice(0, 32)
+
+// rsync-style weak checksum; both halves are kept mod 2^16 so it can roll one byte at a time
+const weakChecksum = (buf: Buffer, start: number, end: number): [number, number] => {
+  const length = end - start
+  let a = 0
+  let b = 0
+  for (let i = start; i < end; i++) {
+    a = (a + buf[i]) & 0xffff
+    b = (b + (length - (i - start)) * buf[i]) & 0xffff
+  }
+  return [a, b]
+}
+
+const combine = (a: number, b: number): number => ((b << 16) | a) >>> 0
+
+/**
+ * Block signatures of the receiver's existing copy. The sender uses them to send only
+ * the parts of the new file that the receiver doesn't already have.
+ */
+export async function computeSignature(filePath: string): Promise<DeltaSignature> {
+  const { size } = await fs.promises.stat(filePath)
+  const blockSize = Math.max(MIN_BLOCK_SIZE, Math.ceil(size / MAX_BLOCKS))
+  const weak: number[] = []
+  const strong: string[] = []
+
+  let pending = Buffer.alloc(0)
+  const addBlock = (block: Buffer): void => {
+    const [a, b] = weakChecksum(block, 0, block.length)
+    weak.push(combine(a, b))
+    strong.push(strongHash(block))
+  }
+
+  for await (const chunk of fs.createReadStream(filePath, { highWaterMark: blockSize })) {
+    pending = Buffer.concat([pending, chunk as Buffer])
+    while (pending.length >= blockSize) {
+      addBlock(pending.subarray(0, blockSize))
+      pending = pending.subarray(blockSize)
+    }
+  }
+  if (pending.length > 0) addBlock(pending)
+
+  return { blockSize, weak, strong }
+}
+
+/**
+ * Turns the new file into COPY (block already on the receiver) and LITERAL (new bytes)
+ * instructions, matching blocks at any offset with a rolling checksum.
+ */
+export class DeltaEncoder extends Transform {
+  private index = new Map<number, number[]>()
+  private buf = Buffer.alloc(0)
+  private pos = 0
+  private literalStart = 0
+  private rolling: [number, number] | null = null
+
+  constructor(private signature: DeltaSignature) {
+    super()
+    signature.weak.forEach((weak, i) => {
+      const blocks = this.index.get(weak)
+      if (blocks) blocks.push(i)
+      else this.index.set(weak, [i])
+    })
+  }
+
+  _transform(chunk: Buffer, _encoding: BufferEncoding, callback: TransformCallback): void {
+    this.buf = Buffer.concat([this.buf.subarray(this.literalStart), chunk])
+    this.pos -= this.literalStart
+    this.literalStart = 0
+    this.scan()
+    callback()
+  }
+
+  _flush(callback: TransformCallback): void {
+    this.scan()
+    this.emitLiteral(this.buf.length)
+    callback()
+  }
+
+  private scan(): void {
+    const size = this.signature.blockSize
+    while (this.buf.length - this.pos >= size) {
+      if (!this.rolling) this.rolling = weakChecksum(this.buf, this.pos, this.pos + size)
+      const [a, b] = this.rolling
+
+      const candidates = this.index.get(combine(a, b))
+      if (candidates) {
+        const hash = strongHash(this.buf.subarray(this.pos, this.pos + size))
+        const match = candidates.find((i) => this.signature.strong[i] === hash)
+        if (match !== undefined) {
+          this.emitLiteral(this.pos)
+          this.emitOp(OP_COPY, match)
+          this.pos += size
+          this.literalStart = this.pos
+          this.rolling = null
+          continue
+        }
+      }
+
+      // Need the byte after the window to roll; wait for more input
+      if (this.pos + size >= this.buf.length) break
+      const out = this.buf[this.pos]
+      const next = this.buf[this.pos + size]
+      const nextA = (a - out + next) & 0xffff
+      this.rolling = [nextA, (b - size * out + nextA) & 0xffff]
+      this.pos++
+
+      if (this.pos - this.literalStart >= MAX_LITERAL) this.emitLiteral(this.pos)
+    }
+  }
+
+  private emitLiteral(end: number): void {
+    if (end <= this.literalStart) return
+    this.emitOp(OP_LITERAL, end - this.literalStart)
+    this.push(Buffer.from(this.buf.subarray(this.literalStart, end)))
+    this.literalStart = end
+  }
+
+  private emitOp(op: number, value: number): void {
+    const header = Buffer.alloc(HEADER_SIZE)
+    header.writeUInt8(op, 0)
+    header.writeUInt32BE(value, 1)
+    this.push(header)
+  }
+}
+
+/**
+ * Rebuilds the new file from the instruction stream, reading COPY blocks from the
+ * receiver's existing copy (`basisPath`).
+ */
+export class DeltaDecoder extends Transform {
+  private fd: number
+  private buf = Buffer.alloc(0)
+  private literalRemaining = 0
+
+  constructor(
+    basisPath: string,
+    private blockSize: number
+  ) {
+    super()
+    this.fd = fs.openSync(basisPath, 'r')
+  }
+
+  _transform(chunk: Buffer, _encoding: BufferEncoding, callback: TransformCallback): void {
+    this.buf = Buffer.concat([this.buf, chunk])
+    try {
+      this.parse()
+      callback()
+    } catch (e) {
+      callback(e as Error)
+    }
+  }
+
+  _flush(callback: TransformCallback): void {
+    this.closeBasis()
+    if (this.literalRemaining > 0 || this.buf.length > 0) {
+      callback(new Error('Delta stream ended mid-instruction'))
+    } else {
+      callback()
+    }
+  }
+
+  _destroy(error: Error | null, callback: (error: Error | null) => void): void {
+    this.closeBasis()
+    callback(error)
+  }
+
+  private closeBasis(): void {
+    if (this.fd < 0) return
+    fs.closeSync(this.fd)
+    this.fd = -1
+  }
+
+  private parse(): void {
+    for (;;) {
+      if (this.literalRemaining > 0) {
+        if (this.buf.length === 0) return
+        const n = Math.min(this.literalRemaining, this.buf.length)
+        this.push(this.buf.subarray(0, n))
+        this.buf = this.buf.subarray(n)
+        this.literalRemaining -= n
+        continue
+      }
+
+      if (this.buf.length < HEADER_SIZE) return
+      const op = this.buf.readUInt8(0)
+      const value = this.buf.readUInt32BE(1)
+      this.buf = this.buf.subarray(HEADER_SIZE)
+
+      if (op === OP_COPY) {
+        const block = Buffer.alloc(this.blockSize)
+        const bytesRead = fs.readSync(this.fd, block, 0, this.blockSize, value * this.blockSize)
+        this.push(block.subarray(0, bytesRead))
+      } else if (op === OP_LITERAL) {
+        this.literalRemaining = value
+      } else {
+        throw new Error(`Unknown delta instruction ${op}`)
+      }
+    }
+  }
+}
@@ -1,7 +1,15 @@
 import { Bonjour, Browser, Service } from 'bonjour-service'
-import { Device, DeviceInfo } from '@shared/messageTypes'
+import { Device, DeviceInfo, ProfileUpdatePayload } from '@shared/messageTypes'
 import EventEmitter from 'events'
 import { connectionManager } from './protocol'
+import { cacheAvatar, getCachedAvatar, getCachedAvatarPath } from './avatarCache'
+import { pickAddress } from './addresses'
+import { parseKeyStorage } from './keychain'
+
+// How good a route to a peer is: the LAN beats an overlay network, which beats the relay,
+// and Bluetooth is the last resort
+const routeRank = (device: Device): number =>
+  device.bluetooth ? 0 : device.relay ? 1 : device.overlay ? 2 : 3
 
 export class DiscoveryManager extends EventEmitter {
   private bonjour: Bonjour
@@ -9,65 +17,90 @@ export class DiscoveryManager extends EventEmitter {
   private browser?: Browser
   private discoveredDevices: Map<string, Device> = new Map()
   private localDeviceId?: string
+  private port: number = 0
+  private tlsPort?: number
+  private udpPort?: number
 
   constructor() {
     super()
     this.bonjour = new Bonjour()
   }
 
-  startDiscovery(deviceInfo: DeviceInfo, port: number): void {
+  startDiscovery(deviceInfo: DeviceInfo, port: number, tlsPort?: number): void {
     this.localDeviceId = deviceInfo.deviceId
+    this.port = port
+    this.tlsPort = tlsPort
     console.log(`Starting discovery for ${deviceInfo.displayName} on port ${port}...`)
     // 1. Advertise this device
-    const publish = (name: string): void => {
-      this.service = this.bonjour.publish({
-        name,
-        type: 'hyperconnect',
-        protocol: 'tcp',
-        port: port,
-        txt: {
-          deviceId: deviceInfo.deviceId,
-          displayName: deviceInfo.displayName,
-          platform: deviceInfo.platform,
-          appVersion: deviceInfo.appVersion
-        }
-      })
-
-      this.service.on('up', () => {
-        console.log(`Discovery service published: ${name} (_hyperconnect._tcp)`)
-      })
-
-      this.service.on('error', (err) => {
-        console.error('Discovery service error:', err)
-        if (err.message.includes('already in use')) {
-          const freshName = `${name}-${Math.floor(Math.random() * 1000)}`
-          console.log(`Service name conflict, retrying with: ${freshName}`)
-          this.service?.stop?.()
-          publish(freshName)
-        }
-      })
-    }
-
-    publish(deviceInfo.displayName)
+    this.publish(deviceInfo.displayName, deviceInfo)
 
     // 2. Discover other devices
     this.browser = this.bonjour.find({ type: 'hyperconnect', protocol: 'tcp' })
     this.setupBrowserListeners(deviceInfo.deviceId)
   }
 
+  /**
+   * Re-advertises this device after its identity changed (e.g. switching to guest mode).
+   */
+  updateAdvertisement(deviceInfo: DeviceInfo): void {
+    if (!this.service) return
+    console.log(`[Discovery] Re-publishing service as ${deviceInfo.displayName}`)
+    this.service.stop?.()
+    this.localDeviceId = deviceInfo.deviceId
+    this.publish(deviceInfo.displayName, deviceInfo)
+    this.rescan()
+  }
+
+  // Both take effect at the next (re-)advertisement
+  setPort(port: number): void {
+    this.port = port
+  }
+
+  setUdpPort(port?: number): void {
+    this.udpPort = port
+  }
+
+  private publish(name: string, deviceInfo: DeviceInfo): void {
+    this.service = this.bonjour.publish({
+      name,
+      type: 'hyperconnect',
+      protocol: 'tcp',
+      port: this.port,
+      txt: {
+        deviceId: deviceInfo.deviceId,
+        displayName: deviceInfo.displayName,
+        platform: deviceInfo.platform,
+        appVersion: deviceInfo.appVersion,
+        ...(this.tlsPort ? { tlsPort: String(this.tlsPort) } : {}),
+        ...(this.udpPort ? { udpPort: String(this.udpPort) } : {})
+      }
+    })
+
+    this.service.on('up', () => {
+      console.log(`Discovery service published: ${name} (_hyperconnect._tcp)`)
+    })
+
+    this.service.on('error', (err) => {
+      console.error('Discovery service error:', err)
+      if (err.message.includes('already in use')) {
+        const freshName = `${name}-${Math.floor(Math.random() * 1000)}`
+        console.log(`Service name conflict, retrying with: ${freshName}`)
+        this.service?.stop?.()
+        this.publish(freshName, deviceInfo)
+      }
+    })
+  }
+
   private setupBrowserListeners(localDeviceId: string): void {
     if (!this.browser) return
 
-    this.browser.on('up', (service: Service) => {
+    this.browser.on('up', async (service: Service) => {
       const deviceId = service.txt?.deviceId
       if (!deviceId || deviceId === localDeviceId) return
 
       console.log(`Found peer: ${service.name} (${service.addresses?.join(', ')})`)
 
-      const address =
-        service.addresses?.find((addr) => addr.includes('.') && !addr.startsWith('127.')) ||
-        service.addresses?.[0] ||
-        ''
+      const address = await pickAddress(service.addresses ?? [], service.port)
 
       const device: Device = {
         deviceId,
@@ -76,8 +109,13 @@ export class DiscoveryManager extends EventEmitter {
         appVersion: service.txt?.appVersion || '0.0.0',
         address,
         port: service.port,
+        tlsPort: Number(service.txt?.tlsPort) || undefined,
+        udpPort: Number(service.txt?.udpPort) || undefined,
         lastSeen: Date.now(),
-        isOnline: true
+        isOnline: true,
+        // Avatars are too large for TXT records; show the cached one until the handshake
+        profileImage: getCachedAvatar(deviceId),
+        avatarPath: getCachedAvatarPath(deviceId)
       }
 
       this.discoveredDevices.set(deviceId, device)
@@ -131,6 +169,54 @@ export class DiscoveryManager extends EventEmitter {
     }
   }
 
+  // For a peer that said goodbye; it shows up again on its next announcement or message
+  markDeviceOffline(deviceId: string): void {
+    const device = this.discoveredDevices.get(deviceId)
+    if (device?.isOnline) {
+      device.isOnline = false
+      console.log(`[Discovery] Device ${device.displayName} disconnected`)
+      this.emit('deviceLost', deviceId)
+    }
+  }
+
+  // For peers the relay reports (see relay.ts) or found on an overlay network (overlay.ts);
+  // one that is also reachable a more direct way stays on that route
+  addRemoteDevice(device: Device): void {
+    const existing = this.discoveredDevices.get(device.deviceId)
+    if (existing?.isOnline && routeRank(existing) > routeRank(device)) return
+    device.profileImage = existing?.profileImage ?? getCachedAvatar(device.deviceId)
+    device.avatarPath = existing?.avatarPath ?? getCachedAvatarPath(device.deviceId)
+    device.statusMessage = existing?.statusMessage
+    this.discoveredDevices.set(device.deviceId, device)
+    if (!existing?.isOnline) this.emit('deviceFound', device)
+  }
+
+  removeRemoteDevice(deviceId: string, route: 'relay' | 'overlay' | 'bluetooth'): void {
+    if (this.discoveredDevices.get(deviceId)?.[route]) this.markDeviceOffline(deviceId)
+  }
+
+  /**
+   * Applies a profile a peer sent about itself. It comes off the wire, so only fields of
+   * the right type are taken, and none at all without a display name.
+   */
+  updateDeviceProfile(deviceId: string, profile: ProfileUpdatePayload): void {
+    const device = this.discoveredDevices.get(deviceId)
+    if (!device || typeof profile?.displayName !== 'string' || !profile.displayName) return
+    device.displayName = profile.displayName
+    if (typeof profile.statusMessage === 'string') device.statusMessage = profile.statusMessage
+    const keyStorage = parseKeyStorage(profile.keyStorage)
+    if (keyStorage) device.keyStorage = keyStorage
+    // An image that's too big or not one we show is ignored, and the old one kept
+    const avatarPath =
+      typeof profile.profileImage === 'string' && cacheAvatar(deviceId, profile.profileImage)
+    if (avatarPath) {
+      device.profileImage = profile.profileImage
+      device.avatarPath = avatarPath
+    }
+    console.log(`[Discovery] Profile updated for ${deviceId}: ${profile.displayName}`)
+    this.emit('deviceUpdated', device)
+  }
+
   rescan(): void {
     console.log('[Discovery] Manual rescan triggered')
     if (this.browser && this.localDeviceId) {
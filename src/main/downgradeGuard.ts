@@ -0,0 +1,67 @@
+import { getStoreOptions } from './profiles'
+import { Capability, PeerProtocol } from './protocolVersion'
+
+/**
+ * Remembers which peers have had an encrypted session with us, so an attacker can't talk
+ * one down to less the next time. A handshake with a peer that sent a transcript MAC
+ * before (see crypto/transcript.ts) must have one again, so the capability can't quietly
+ * be edited out of a hello. With the policy on (the default), nothing goes to or is
+ * taken from such a peer in plaintext either, so dropping HELLO_SECURE altogether doesn't
+ * leave the two talking in the clear.
+ *
+ * The first handshake with a peer is taken on trust, as with pinned keys.
+ */
+class DowngradeGuard {
+  private peers: Record<string, boolean> = {} // deviceId → whether it sent a transcript MAC
+  private refusePlaintext = true
+
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    this.peers = store.get('encryptedPeers', {}) as Record<string, boolean>
+    this.refusePlaintext = store.get('refusePlaintext', true) as boolean
+  }
+
+  isPlaintextRefused(): boolean {
+    return this.refusePlaintext
+  }
+
+  async setPlaintextRefused(enabled: boolean): Promise<boolean> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('refusePlaintext', enabled)
+    this.refusePlaintext = enabled
+    return enabled
+  }
+
+  // Whether plaintext to or from `deviceId` is refused under the policy
+  refusesPlaintext(deviceId: string | undefined): boolean {
+    return this.refusePlaintext && !!deviceId && deviceId in this.peers
+  }
+
+  /**
+   * Throws if a handshake with `deviceId` agreed on `protocol` without the transcript MAC
+   * it had before.
+   */
+  check(deviceId: string, protocol: PeerProtocol): void {
+    if (this.peers[deviceId] && !(protocol.capabilities & Capability.TRANSCRIPT_MAC)) {
+      throw new Error(`${deviceId} left out the transcript MAC it used before`)
+    }
+  }
+
+  // Records a finished handshake with `deviceId`
+  remember(deviceId: string, protocol: PeerProtocol): void {
+    const bound = (protocol.capabilities & Capability.TRANSCRIPT_MAC) !== 0
+    if (this.peers[deviceId] === bound) return
+    this.save(deviceId, bound)
+  }
+
+  private async save(deviceId: string, bound: boolean): Promise<void> {
+    this.peers[deviceId] = bound
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('encryptedPeers', this.peers)
+  }
+}
+
+export const downgradeGuard = new DowngradeGuard()
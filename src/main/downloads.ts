@@ -0,0 +1,158 @@
+import fs from 'fs'
+import path from 'path'
+import { app, dialog, BrowserWindow } from 'electron'
+import { ConflictPolicy } from '@shared/messageTypes'
+import { getStoreOptions } from './profiles'
+import { discoveryManager } from './discovery'
+
+export async function getDownloadDirectory(): Promise<string> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  const customPath = store.get('downloadPath') as string | undefined
+  return customPath || app.getPath('downloads')
+}
+
+/**
+ * Validates and saves the download directory, creating it if needed. Throws if the
+ * path can't be written to, so the old setting stays in place.
+ */
+export async function setDownloadDirectory(dir: string): Promise<string> {
+  const resolved = path.resolve(dir)
+  await fs.promises.mkdir(resolved, { recursive: true })
+  await fs.promises.access(resolved, fs.constants.W_OK)
+
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('downloadPath', resolved)
+  return resolved
+}
+
+export async function isPerPeerFoldersEnabled(): Promise<boolean> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  return store.get('perPeerDownloadFolders', false) as boolean
+}
+
+export async function setPerPeerFolders(enabled: boolean): Promise<boolean> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('perPeerDownloadFolders', enabled)
+  return enabled
+}
+
+export async function getConflictPolicy(): Promise<ConflictPolicy> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  return store.get('conflictPolicy', 'rename') as ConflictPolicy
+}
+
+export async function setConflictPolicy(policy: ConflictPolicy): Promise<ConflictPolicy> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('conflictPolicy', policy)
+  return policy
+}
+
+// Bytes available to this user on the volume holding `dir`
+export async function getFreeSpace(dir: string): Promise<number> {
+  const { bavail, bsize } = await fs.promises.statfs(dir)
+  return bavail * bsize
+}
+
+// Largest file or folder accepted from a peer, in bytes; 0 means no limit
+export async function getMaxIncomingSize(): Promise<number> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  return store.get('maxIncomingFileSize', 0) as number
+}
+
+export async function setMaxIncomingSize(bytes: number): Promise<number> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  const value = Math.max(0, Math.floor(bytes))
+  store.set('maxIncomingFileSize', value)
+  return value
+}
+
+const nextFreePath = (dir: string, fileName: string): string => {
+  const ext = path.extname(fileName)
+  const baseName = path.basename(fileName, ext)
+  let filePath = path.join(dir, fileName)
+  let counter = 1
+  while (fs.existsSync(filePath)) {
+    filePath = path.join(dir, `${baseName} (${counter})${ext}`)
+    counter++
+  }
+  return filePath
+}
+
+// Peer names come off the network; keep them to a single safe path segment
+const toFolderName = (name: string): string =>
+  name
+    .replace(/[<>:"/\\|?*\x00-\x1f]/g, '_')
+    .replace(/^\.+/, '_')
+    .trim()
+    .slice(0, 64) || 'Unknown device'
+
+// The download directory, or a subfolder named after the peer when per-peer folders are on
+const getPeerDirectory = async (deviceId: string): Promise<string> => {
+  const dir = await getDownloadDirectory()
+  if (!(await isPerPeerFoldersEnabled())) return dir
+  const device = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
+  return path.join(dir, toFolderName(device?.displayName || deviceId))
+}
+
+/**
+ * Returns an earlier copy of `name` from this peer (a previous version or an interrupted
+ * download), which delta sync can use so only changed blocks are sent again.
+ */
+export async function findExistingDownload(
+  deviceId: string,
+  name: string
+): Promise<string | null> {
+  const filePath = path.join(await getPeerDirectory(deviceId), path.basename(name))
+  try {
+    const stats = await fs.promises.stat(filePath)
+    return stats.isFile() && stats.size > 0 ? filePath : null
+  } catch {
+    return null
+  }
+}
+
+/**
+ * Picks the destination for a received file or folder: the download directory, or a
+ * subfolder named after the sending peer when per-peer folders are on. Name clashes are
+ * handled by the conflict policy; returns null if the user cancels the "ask" prompt.
+ */
+export async function resolveDownloadPath(
+  deviceId: string,
+  name: string
+): Promise<string | null> {
+  const dir = await getPeerDirectory(deviceId)
+  await fs.promises.mkdir(dir, { recursive: true })
+
+  const fileName = path.basename(name)
+  const filePath = path.join(dir, fileName)
+  if (!fs.existsSync(filePath)) return filePath
+
+  const policy = await getConflictPolicy()
+  if (policy === 'overwrite') return filePath
+  if (policy === 'rename') return nextFreePath(dir, fileName)
+
+  const options = {
+    type: 'question' as const,
+    title: 'File already exists',
+    message: `"${fileName}" already exists in ${dir}.`,
+    detail: 'Do you want to replace it or keep both copies?',
+    buttons: ['Keep Both', 'Replace', 'Cancel'],
+    defaultId: 0,
+    cancelId: 2
+  }
+  const window = BrowserWindow.getFocusedWindow() || BrowserWindow.getAllWindows()[0]
+  const { response } = window
+    ? await dialog.showMessageBox(window, options)
+    : await dialog.showMessageBox(options)
+  if (response === 1) return filePath
+  if (response === 0) return nextFreePath(dir, fileName)
+  return null
+}
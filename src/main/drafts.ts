@@ -0,0 +1,39 @@
+import { getStoreOptions } from './profiles'
+import { isGuestMode } from './identity'
+
+// A guest's drafts are kept only in memory, like the rest of its conversations
+let guestDrafts: Record<string, string> = {}
+
+// Forgets a guest's drafts, whenever guest mode starts or ends
+export function clearGuestDrafts(): void {
+  guestDrafts = {}
+}
+
+export async function getDraft(conversationId: string): Promise<string> {
+  if (isGuestMode()) return guestDrafts[conversationId] || ''
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  const drafts = store.get('drafts', {}) as Record<string, string>
+  return drafts[conversationId] || ''
+}
+
+/**
+ * Saves the unsent text for a conversation. Empty text removes the draft.
+ */
+export async function saveDraft(conversationId: string, text: string): Promise<void> {
+  const update = (drafts: Record<string, string>): Record<string, string> => {
+    if (text.trim()) {
+      drafts[conversationId] = text
+    } else {
+      delete drafts[conversationId]
+    }
+    return drafts
+  }
+  if (isGuestMode()) {
+    update(guestDrafts)
+    return
+  }
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('drafts', update({ ...(store.get('drafts', {}) as Record<string, string>) }))
+}
@@ -0,0 +1,113 @@
+import net from 'net'
+import os from 'os'
+import { getStoreOptions } from './profiles'
+import { tcpServer } from './tcpServer'
+import { createWebSocketServer } from './webSocketBridge'
+
+/**
+ * Listeners besides the main TCP server, e.g. a WebSocket on localhost for a browser
+ * companion or plain TCP on an overlay interface only. Each is written as a URL:
+ * - tcp://host:port for the app's own protocol
+ * - ws://host:port for WebSocket peers
+ * where host is an address, "localhost", an interface name such as "tailscale0", or "*"
+ * for every address. Whatever connects is handed to TCPServer, so they all reach the same
+ * handshake and routing as the main listener.
+ */
+
+export interface ListenerEndpoint {
+  url: string
+  port: number // the port it listens on, 0 when it couldn't start
+  error?: string
+}
+
+interface Endpoint {
+  protocol: 'tcp' | 'ws'
+  host: string // '' for every address
+  port: number
+}
+
+// Returns the endpoint `url` describes, or throws saying what's wrong with it
+const parseEndpoint = (url: string): Endpoint => {
+  const match = /^(tcp|ws):\/\/(\*|\[[^\]]+\]|[^:/[\]]+):(\d{1,5})\/?$/.exec(url.trim())
+  const port = match ? Number(match[3]) : 0
+  if (!match || port < 1 || port > 65535) {
+    throw new Error(`Invalid endpoint: ${url} (use tcp://host:port or ws://host:port)`)
+  }
+  const host = match[2] === '*' ? '' : match[2].replace(/^\[|\]$/g, '')
+  return { protocol: match[1] as Endpoint['protocol'], host, port }
+}
+
+// The address to bind for `host`; an interface name means its first address, IPv4 first
+const resolveHost = (host: string): string => {
+  if (host === 'localhost') return '127.0.0.1'
+  const entries = os.networkInterfaces()[host]
+  if (!entries) return host
+  const entry = entries.find((e) => e.family === 'IPv4') ?? entries[0]
+  if (!entry) throw new Error(`Interface ${host} has no address`)
+  return entry.family === 'IPv6' && entry.scopeid ? `${entry.address}%${host}` : entry.address
+}
+
+function listen(server: net.Server, host: string, port: number): Promise<number> {
+  return new Promise((resolve, reject) => {
+    server.once('error', reject)
+    server.listen(port, host || undefined, () => {
+      server.removeListener('error', reject)
+      resolve((server.address() as net.AddressInfo).port)
+    })
+  })
+}
+
+class ListenerEndpoints {
+  private servers: net.Server[] = []
+  private status: ListenerEndpoint[] = []
+
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    await this.start(store.get('listenEndpoints', []) as string[])
+  }
+
+  getEndpoints(): ListenerEndpoint[] {
+    return this.status
+  }
+
+  async setEndpoints(urls: string[]): Promise<ListenerEndpoint[]> {
+    const cleaned = urls.map((url) => url.trim()).filter(Boolean)
+    cleaned.forEach(parseEndpoint)
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('listenEndpoints', cleaned)
+    await this.start(cleaned)
+    return this.status
+  }
+
+  // One endpoint failing (port taken, interface gone) doesn't stop the others
+  private async start(urls: string[]): Promise<void> {
+    this.stop()
+    this.status = await Promise.all(
+      urls.map(async (url) => {
+        try {
+          const { protocol, host, port } = parseEndpoint(url)
+          const server =
+            protocol === 'ws'
+              ? createWebSocketServer()
+              : net.createServer((socket) => tcpServer.handleConnection(socket))
+          this.servers.push(server)
+          const bound = await listen(server, resolveHost(host), port)
+          console.log(`[Endpoints] Listening on ${url}`)
+          return { url, port: bound }
+        } catch (err) {
+          console.error(`[Endpoints] Could not listen on ${url}:`, err)
+          return { url, port: 0, error: (err as Error).message }
+        }
+      })
+    )
+  }
+
+  stop(): void {
+    for (const server of this.servers) server.close()
+    this.servers = []
+  }
+}
+
+export const listenerEndpoints = new ListenerEndpoints()
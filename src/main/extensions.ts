@@ -0,0 +1,63 @@
+import net from 'net'
+import { ExtensionsPayload, NetworkMessage } from '@shared/messageTypes'
+import { getSession } from './crypto/sessionKey'
+import { isZstdSupported } from './compression'
+import { isArgon2Supported } from './crypto/passphrase'
+import { getDeviceInfo } from './identity'
+import { Capability, CapabilityFlag, peerSupports } from './protocolVersion'
+import { writeFrame } from './wire'
+
+/**
+ * Optional features, named rather than numbered like capabilities so new ones don't need
+ * a protocol change. Each end lists the ones it can use in an EXTENSIONS frame right after
+ * the handshake; unlike capabilities, the list can depend on this machine (zstd may not
+ * be available) and not just on the version.
+ */
+export type Extension = 'compression' | 'multiplexing' | 'delta-sync' | 'passphrase' | 'argon2'
+
+// How each extension was signalled before EXTENSIONS; peers that haven't sent the frame
+// (yet) are judged by these. Extensions newer than EXTENSIONS itself have none.
+const CAPABILITY_FALLBACK: Partial<Record<Extension, CapabilityFlag>> = {
+  compression: Capability.ZSTD,
+  multiplexing: Capability.PARALLEL_STREAMS,
+  'delta-sync': Capability.DELTA
+}
+
+export function getLocalExtensions(): Extension[] {
+  const extensions: Extension[] = ['multiplexing', 'delta-sync', 'passphrase']
+  if (isZstdSupported()) extensions.unshift('compression')
+  if (isArgon2Supported()) extensions.push('argon2')
+  return extensions
+}
+
+// Announces our extensions on a freshly established connection
+export function sendExtensions(socket: net.Socket, deviceId: string, tag: string): void {
+  if (!peerSupports(deviceId, Capability.EXTENSIONS)) return
+  const message: NetworkMessage = {
+    type: 'EXTENSIONS',
+    deviceId: getDeviceInfo().deviceId,
+    timestamp: Date.now(),
+    payload: { extensions: getLocalExtensions() } as ExtensionsPayload
+  }
+  writeFrame(socket, deviceId, message, tag)
+}
+
+/**
+ * Stores the extensions a peer listed, for as long as its session lasts. Names we don't
+ * know are kept too, so nothing is lost if a later version looks them up.
+ */
+export function setPeerExtensions(deviceId: string, payload: unknown): void {
+  const session = getSession(deviceId)
+  const extensions = (payload as ExtensionsPayload | undefined)?.extensions
+  if (!session || !Array.isArray(extensions)) return
+  session.extensions = new Set(extensions.filter((name) => typeof name === 'string'))
+}
+
+// Whether we and `deviceId` can both use an extension
+export function peerHasExtension(deviceId: string, extension: Extension): boolean {
+  if (!getLocalExtensions().includes(extension)) return false
+  const extensions = getSession(deviceId)?.extensions
+  if (extensions) return extensions.has(extension)
+  const fallback = CAPABILITY_FALLBACK[extension]
+  return fallback !== undefined && peerSupports(deviceId, fallback)
+}
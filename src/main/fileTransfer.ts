@@ -3,62 +3,479 @@ import path from 'path'
 import net from 'net'
 import stream from 'stream'
 import { v4 as uuidv4 } from 'uuid'
-import { BrowserWindow, ipcMain, dialog, app, shell } from 'electron'
+import { BrowserWindow, ipcMain, dialog, shell } from 'electron'
 import { connectionManager } from './protocol'
 import { discoveryManager } from './discovery'
 import { tcpServer } from './tcpServer'
-import { FileMetadata, NetworkMessage, FileTransferProgress } from '@shared/messageTypes'
+import { frameGuard } from './frameGuard'
+import { peerHasExtension } from './extensions'
+import {
+  FileMetadata,
+  FileControlPayload,
+  FolderEntry,
+  NetworkMessage,
+  FileTransferProgress,
+  ForwardedFrom,
+  DeltaSignature,
+  QueuedTransfer,
+  TransferPriority,
+  FanOutStatus,
+  FanOutTarget,
+  FileRejectPayload,
+  FileRejection,
+  SpeedSample,
+  TransferStats,
+  ErrorCode,
+  ErrorPayload,
+  TransferStatusPayload,
+  TransferStatusQuery,
+  Device
+} from '@shared/messageTypes'
 import { getDeviceInfo } from './identity'
-import { createDecryptionStream, createEncryptionStream } from './crypto/streamCrypto'
-import { getSession } from './crypto/sessionKey'
+import { getStoreOptions } from './profiles'
+import { messageStore } from './messageStore'
+import {
+  findExistingDownload,
+  getDownloadDirectory,
+  getFreeSpace,
+  getMaxIncomingSize,
+  resolveDownloadPath
+} from './downloads'
+import { evaluateAutoAcceptRules } from './autoAcceptRules'
+import { createPreview, createThumbnail, isPreviewable } from './thumbnails'
+import {
+  STARTUP_CLEANUP_AGE,
+  cleanIncompleteTransfers,
+  trackIncomplete,
+  untrackIncomplete
+} from './transferCleanup'
+import {
+  createDecryptionStream,
+  createEncryptionStream,
+  createOpeningStream,
+  createSealingStream
+} from './crypto/streamCrypto'
+import {
+  FileCompression,
+  createCompressionStream,
+  createDecompressionStream,
+  isZstdSupported,
+  shouldCompress
+} from './compression'
+import { DeltaDecoder, DeltaEncoder, computeSignature } from './delta'
+import { AckWindow } from './ackWindow'
+import { ZipEntry, canZip, createZipStream, getZipSize, listZipEntries } from './zipStream'
+import { getSession, wipe } from './crypto/sessionKey'
+import type { CipherSuite } from './crypto/cipherSuite'
+import {
+  mixPassphraseKey,
+  protectWithPassphrase,
+  unlockWithPassphrase
+} from './crypto/passphrase'
+import { recordSecurityEvent } from './securityLog'
+import { Capability, getPeerProtocol, peerSupports } from './protocolVersion'
+import { createErrorFrame } from './peerErrors'
+import { relayClient } from './relay'
+import { udpChannel } from './udpChannel'
+import { MAX_BLUETOOTH_FILE_SIZE, bluetoothTransport } from './bluetooth'
+import { isBoundTo, unmapAddress } from './addresses'
+import { timeoutSettings } from './timeouts'
+import { getSocketTuning } from './listen'
+import { parseStreamHeader } from './streamHeader'
 import crypto from 'node:crypto'
 
+// Files at least this large are split across connections when parallel streams are enabled
+const PARALLEL_MIN_SIZE = 64 * 1024 * 1024
+
+// Accepted outgoing transfers beyond this wait in the queue; a folder counts as one
+const MAX_CONCURRENT_TRANSFERS = 3
+
+const PRIORITY_RANK: Record<TransferPriority, number> = { high: 0, normal: 1, low: 2 }
+
+// One dedicated FILE_STREAM connection carrying the bytes [start, end) of a file
+interface TransferStream {
+  socket: net.Socket
+  start: number
+  end: number
+  written: number // bytes that came out of the decrypt/decompress pipeline (or were read)
+  ackWindow?: AckWindow // sender: limits unacknowledged data on a single, unranged stream
+  ackTimer?: NodeJS.Timeout // receiver: reports how far the file has been written
+  writeStream?: fs.WriteStream
+  decipherStream?: stream.Transform
+}
+
+type ActiveTransfer = FileTransferProgress & {
+  filePath?: string
+  metadata?: FileMetadata
+  streams?: TransferStream[] // open connections; several when a file is sent in parallel
+  ranged?: boolean // receiver: the current streams each fill one range of a pre-created file
+  accepted?: boolean // FILE_ACCEPT was sent (receiver) or received (sender); no data before it
+  bytesDone?: number
+  wireBytes?: number
+  sessionStart?: { time: number; bytes: number } // for speed since the last (re)start
+  compression?: FileCompression // agreed in FILE_ACCEPT; folder children use the folder's
+  deltaSignature?: DeltaSignature // sender: the receiver's blocks, from FILE_ACCEPT
+  deltaBasis?: { path: string; blockSize: number } // receiver: the copy COPY blocks come from
+  finalPath?: string // receiver: where a delta-built file is moved once complete
+  preallocated?: boolean // receiver: the file was created at its full size before any data
+  retries?: number // sender: automatic retries used so far
+  peerError?: ErrorCode // sender: the receiver failed the transfer for good
+  resumes?: number
+  samples?: SpeedSample[] // kept on the top-level transfer, so a folder has one history
+  lastSample?: { time: number; bytes: number }
+  zipEntries?: ZipEntry[] // sender: a folder sent as one zip archive generated on the fly
+  folderId?: string // set on the per-file children of a folder transfer
+  children?: string[] // set on the folder transfer itself
+  passphraseKey?: Buffer // mixed into the stream key of a passphrase-protected file
+}
+
+/**
+ * A sealed stream for `transfer`, keyed by the session key, mixed with the passphrase key
+ * if the file is protected with one.
+ */
+const sealedStream = (
+  create: typeof createSealingStream,
+  transfer: ActiveTransfer,
+  sessionKey: Buffer,
+  salt: Buffer,
+  suite: CipherSuite
+): stream.Transform => {
+  if (!transfer.passphraseKey) return create(sessionKey, salt, suite)
+  const key = mixPassphraseKey(sessionKey, transfer.passphraseKey)
+  const sealed = create(key, salt, suite)
+  wipe(key)
+  return sealed
+}
+
+// Sensitive files are logged by id so their names stay out of log files
+const describeFile = (metadata?: FileMetadata): string =>
+  metadata?.sensitive ? `sensitive file ${metadata.fileId}` : `${metadata?.name}`
+
+// Over Bluetooth a large file would take hours; it waits for a faster route instead
+const checkRoute = (device: Device, size: number): void => {
+  if (device.bluetooth && size > MAX_BLUETOOTH_FILE_SIZE) {
+    throw new Error(`${device.displayName} is only reachable over Bluetooth, too slow for this`)
+  }
+}
+
+// A queued start; `resumeFrom` is set when the receiver asked to resume a paused transfer
+interface QueueEntry {
+  fileId: string
+  resumeFrom?: FileControlPayload
+}
+
+const hashFile = async (
+  filePath: string,
+  onProgress?: (bytesHashed: number) => void
+): Promise<string> => {
+  const hash = crypto.createHash('sha256')
+  let bytesHashed = 0
+  for await (const chunk of fs.createReadStream(filePath)) {
+    hash.update(chunk as Buffer)
+    bytesHashed += (chunk as Buffer).length
+    onProgress?.(bytesHashed)
+  }
+  return hash.digest('hex')
+}
+
+// Socket errors worth retrying; anything else (e.g. the source file vanished) fails at once
+const TRANSIENT_ERRORS = [
+  'ECONNRESET',
+  'ECONNREFUSED',
+  'ECONNABORTED',
+  'ETIMEDOUT',
+  'EPIPE',
+  'EHOSTUNREACH',
+  'ENETUNREACH',
+  'ENETDOWN'
+]
+const RETRY_BASE_DELAY = 1000
+// After the network changes, how long to give the new interface before reconnecting (ms)
+const MIGRATION_DELAY = 2000
+const RETRY_MAX_DELAY = 30 * 1000
+
+// Failures a receiver reports in an ERROR frame; none of them goes away by retrying
+const PEER_ERROR_REASONS: Partial<Record<ErrorCode, string>> = {
+  UNAUTHORIZED: 'The receiver does not accept files from this device',
+  DISK_FULL: 'The receiver ran out of disk space',
+  CHECKSUM_MISMATCH: 'The receiver got a corrupt copy of the file'
+}
+
+// Minimum gap between verification-progress events for one file
+const VERIFY_PROGRESS_INTERVAL = 250
+
+// How often a receiver acks the offset it has written (FILE_ACK)
+const ACK_INTERVAL = 250
+
+// Speed history: one sample a second, the last ten minutes of it
+const SPEED_SAMPLE_INTERVAL = 1000
+const MAX_SPEED_SAMPLES = 600
+
+const TERMINAL_STATUSES: FileTransferProgress['status'][] = [
+  'completed',
+  'failed',
+  'rejected',
+  'cancelled'
+]
+
+// Transfers that were interrupted and are checked against the peer's view on reconnect
+const RECONCILED_STATUSES: FileTransferProgress['status'][] = ['failed', 'retrying', 'paused']
+
+// Paused or cancelled: open streams were closed on purpose and nothing more should be sent
+const isStopped = (transfer: FileTransferProgress): boolean =>
+  transfer.status === 'paused' || transfer.status === 'cancelled'
+
+// Manifest paths come from the peer; only plain '/'-separated names are accepted
+const isSafeRelativePath = (relativePath: string): boolean =>
+  relativePath
+    .split('/')
+    .every((segment) => !['', '.', '..'].includes(segment) && !/[\\:]/.test(segment))
+
+// Resolves a manifest path under `root`, refusing anything that would escape it
+const resolveInside = (root: string, relativePath: string): string | null => {
+  const target = path.resolve(root, ...relativePath.split('/'))
+  const relative = path.relative(root, target)
+  if (!relative || relative.startsWith('..') || path.isAbsolute(relative)) return null
+  return target
+}
+
 class FileTransferManager {
-  private activeTransfers: Map<
-    string,
-    FileTransferProgress & {
-      filePath?: string
-      metadata?: FileMetadata
-      writeStream?: fs.WriteStream
-    }
-  > = new Map()
+  private activeTransfers: Map<string, ActiveTransfer> = new Map()
   private mainWindow?: BrowserWindow
+  private queue: QueueEntry[] = []
+  private running: Set<string> = new Set()
+  private batches: Map<string, FanOutStatus> = new Map()
+  private batchOf: Map<string, string> = new Map() // fileId -> batchId
+  private expiryTimers: Map<string, NodeJS.Timeout> = new Map()
+
+  /**
+   * Pushes the serializable part of a transfer to the renderer. The chat message that
+   * carried the FILE_META (`messageId`) lets the UI tie the bubble to this lifecycle.
+   */
+  private emitProgress(transfer: ActiveTransfer): void {
+    // Files inside a folder are reported as one aggregate transfer
+    if (transfer.folderId) {
+      const folder = this.activeTransfers.get(transfer.folderId)
+      if (folder) this.updateFolderProgress(folder)
+      return
+    }
+
+    const batch = this.batches.get(this.batchOf.get(transfer.fileId) || '')
+    if (batch) this.updateBatch(batch)
+
+    this.mainWindow?.webContents.send('file-transfer-progress', {
+      fileId: transfer.fileId,
+      deviceId: transfer.deviceId,
+      messageId: transfer.messageId,
+      progress: transfer.progress,
+      speed: transfer.speed,
+      eta: transfer.eta,
+      status: transfer.status,
+      // The chat bubble still names a sensitive file; transfer lists and toasts don't
+      name: transfer.metadata?.sensitive ? 'Sensitive file' : transfer.metadata?.name,
+      path: transfer.filePath,
+      size: transfer.metadata?.size,
+      direction: transfer.direction,
+      fileCount: transfer.children?.length,
+      filesCompleted: transfer.filesCompleted,
+      compressionRatio: transfer.compressionRatio,
+      priority: transfer.priority,
+      queuePosition: this.getQueuePosition(transfer.fileId),
+      error: transfer.error,
+      rejection: transfer.rejection,
+      thumbnail: transfer.thumbnail,
+      verifyProgress: transfer.verifyProgress
+    } as FileTransferProgress)
+  }
+
+  private updateFolderProgress(folder: ActiveTransfer): void {
+    const children = (folder.children || [])
+      .map((id) => this.activeTransfers.get(id))
+      .filter((t): t is ActiveTransfer => !!t)
+    const total = folder.metadata?.size || 0
+    const done = children.reduce((sum, t) => sum + t.progress * (t.metadata?.size || 0), 0)
+    const current = children.find((t) => t.status === 'active')
+
+    folder.filesCompleted = children.filter((t) => t.status === 'completed').length
+    folder.progress = total > 0 ? done / total : folder.filesCompleted / (children.length || 1)
+    folder.speed = current?.speed ?? folder.speed
+    folder.compressionRatio = current?.compressionRatio ?? folder.compressionRatio
+    folder.eta = folder.speed > 0 ? (total - done) / folder.speed : 0
+
+    if (children.some((t) => t.status === 'cancelled')) {
+      folder.status = 'cancelled'
+    } else if (children.some((t) => t.status === 'retrying')) {
+      folder.status = 'retrying'
+    } else if (children.some((t) => t.status === 'failed')) {
+      folder.status = 'failed'
+    } else if (children.some((t) => t.status === 'paused')) {
+      folder.status = 'paused'
+    } else if (folder.filesCompleted === children.length) {
+      folder.status = 'completed'
+      folder.progress = 1
+      folder.eta = 0
+    } else if (current) {
+      folder.status = 'active'
+    }
+    this.emitProgress(folder)
+  }
+
+  private updateBatch(batch: FanOutStatus): void {
+    for (const target of batch.targets) {
+      const transfer = target.fileId ? this.activeTransfers.get(target.fileId) : undefined
+      if (!transfer) continue
+      target.status = transfer.status
+      target.progress = transfer.status === 'completed' ? 1 : transfer.progress
+    }
+
+    const done = batch.targets.filter((t) => TERMINAL_STATUSES.includes(t.status))
+    const succeeded = done.filter((t) => t.status === 'completed').length
+    if (done.length < batch.targets.length) {
+      batch.status = 'active'
+    } else if (succeeded === batch.targets.length) {
+      batch.status = 'completed'
+    } else {
+      batch.status = succeeded > 0 ? 'partial' : 'failed'
+    }
+    const total = batch.targets.reduce((sum, t) => sum + t.progress, 0)
+    batch.progress = batch.targets.length > 0 ? total / batch.targets.length : 1
+
+    this.mainWindow?.webContents.send('file-fanout-progress', batch)
+  }
+
+  /**
+   * Sends each path to each device as its own transfer. Every file is hashed once and the
+   * checksum is shared by all of its transfers; progress is reported for the batch as a
+   * whole. Devices that can't be reached are marked failed without stopping the rest.
+   */
+  async sendToDevices(paths: string[], deviceIds: string[]): Promise<FanOutStatus> {
+    const batch: FanOutStatus = { batchId: uuidv4(), status: 'active', progress: 0, targets: [] }
+    this.batches.set(batch.batchId, batch)
+
+    for (const filePath of paths) {
+      let checksum: string | undefined
+      let isFolder = false
+      let error: string | undefined
+      try {
+        isFolder = (await fs.promises.stat(filePath)).isDirectory()
+        if (!isFolder) checksum = await hashFile(filePath)
+      } catch (err) {
+        error = (err as Error).message
+      }
+
+      for (const deviceId of deviceIds) {
+        const target: FanOutTarget = { deviceId, filePath, status: 'pending', progress: 0 }
+        batch.targets.push(target)
+        if (error) {
+          target.status = 'failed'
+          target.error = error
+          continue
+        }
+        try {
+          const message = isFolder
+            ? await this.initiateFolderSend(deviceId, filePath)
+            : await this.initiateSend(deviceId, filePath, undefined, undefined, checksum)
+          target.fileId = (message.payload as FileMetadata).fileId
+          target.messageId = message.id
+          this.batchOf.set(target.fileId, batch.batchId)
+        } catch (err) {
+          target.status = 'failed'
+          target.error = (err as Error).message
+        }
+      }
+    }
+
+    this.updateBatch(batch)
+    return batch
+  }
 
   setup(mainWindow: BrowserWindow): void {
     this.mainWindow = mainWindow
 
-    ipcMain.handle('send-file', async (_, deviceId: string, filePath: string, replyTo?: string) => {
-      return this.initiateSend(deviceId, filePath, replyTo)
+    ipcMain.handle(
+      'send-file',
+      async (
+        _,
+        deviceId: string,
+        filePath: string,
+        replyTo?: string,
+        sensitive?: boolean,
+        passphrase?: string
+      ) => {
+        return this.initiateSend(
+          deviceId,
+          filePath,
+          replyTo,
+          undefined,
+          undefined,
+          sensitive,
+          passphrase
+        )
+      }
+    )
+
+    ipcMain.handle('send-file-to-devices', (_, paths: string[], deviceIds: string[]) =>
+      this.sendToDevices(paths, deviceIds)
+    )
+    ipcMain.handle('clean-incomplete-transfers', () =>
+      cleanIncompleteTransfers(0, this.getLiveIncomingIds())
+    )
+    // Nothing is in flight yet, so only the age threshold protects recent partial files
+    cleanIncompleteTransfers(STARTUP_CLEANUP_AGE).catch((e) => {
+      console.error('[FileTransfer] Failed to clean up incomplete transfers:', e)
     })
 
-    ipcMain.handle('accept-file', async (_, fileId: string) => {
-      const transfer = this.activeTransfers.get(fileId)
-      if (!transfer || !transfer.metadata) return
-
-      // Get custom download path or use default Downloads folder
-      const Store = (await import('electron-store')).default
-      const store = new Store()
-      const customPath = store.get('downloadPath') as string | undefined
-      const downloadsPath = customPath || app.getPath('downloads')
-      let filePath = path.join(downloadsPath, transfer.metadata.name)
+    ipcMain.handle('get-fanout-status', (_, batchId: string) => this.batches.get(batchId) ?? null)
 
-      // Handle file name conflicts by appending a number
-      let counter = 1
-      const ext = path.extname(transfer.metadata.name)
-      const baseName = path.basename(transfer.metadata.name, ext)
+    ipcMain.handle(
+      'send-folder',
+      async (_, deviceId: string, folderPath: string, replyTo?: string, asZip?: boolean) => {
+        return this.initiateFolderSend(deviceId, folderPath, replyTo, asZip)
+      }
+    )
 
-      while (fs.existsSync(filePath)) {
-        filePath = path.join(downloadsPath, `${baseName} (${counter})${ext}`)
-        counter++
+    // Resolves to false if the file is protected and `passphrase` isn't the sender's
+    ipcMain.handle('accept-file', async (_, fileId: string, passphrase?: string) => {
+      const transfer = this.activeTransfers.get(fileId)
+      if (!transfer || !transfer.metadata) return false
+
+      const protection = transfer.metadata.passphrase
+      if (protection) {
+        const key = passphrase ? await unlockWithPassphrase(passphrase, protection) : null
+        if (!key) return false
+        wipe(transfer.passphraseKey)
+        transfer.passphraseKey = key
       }
 
-      this.sendAccept(fileId, filePath)
+      const filePath = await resolveDownloadPath(transfer.deviceId, transfer.metadata.name)
+      if (filePath) {
+        this.sendAccept(fileId, filePath)
+      } else {
+        this.sendReject(fileId)
+      }
+      return true
     })
 
     ipcMain.handle('reject-file', (_, fileId: string) => {
       this.sendReject(fileId)
     })
 
+    connectionManager.on('connected', (deviceId: string) => {
+      this.reconcileTransfers(deviceId).catch((e) => {
+        console.error(`[FileTransfer] Failed to reconcile transfers with ${deviceId}:`, e)
+      })
+    })
+
+    ipcMain.handle('pause-file', (_, fileId: string) => this.pause(fileId))
+    ipcMain.handle('resume-file', (_, fileId: string) => this.resume(fileId))
+    ipcMain.handle('cancel-file', (_, fileId: string) => this.cancel(fileId))
+    ipcMain.handle('get-transfer-queue', () => this.getQueue())
+    ipcMain.handle('get-transfer-stats', (_, fileId: string) => this.getTransferStats(fileId))
+    ipcMain.handle('reorder-transfer-queue', (_, fileIds: string[]) => this.reorderQueue(fileIds))
+    ipcMain.handle('set-transfer-priority', (_, fileId: string, priority: TransferPriority) =>
+      this.setPriority(fileId, priority)
+    )
+
     ipcMain.handle('select-file', async () => {
       if (!this.mainWindow) return null
       const { canceled, filePaths } = await dialog.showOpenDialog(this.mainWindow, {
@@ -68,6 +485,15 @@ class FileTransferManager {
       return filePaths[0]
     })
 
+    ipcMain.handle('select-folder', async () => {
+      if (!this.mainWindow) return null
+      const { canceled, filePaths } = await dialog.showOpenDialog(this.mainWindow, {
+        properties: ['openDirectory']
+      })
+      if (canceled || filePaths.length === 0) return null
+      return filePaths[0]
+    })
+
     ipcMain.handle('open-file-location', async (_, filePath: string) => {
       if (filePath && fs.existsSync(filePath)) {
         shell.showItemInFolder(filePath)
@@ -76,279 +502,1508 @@ class FileTransferManager {
 
     // Handle raw connections from TCPServer
     tcpServer.on('raw-connection', (socket: net.Socket, initialBuffer: Buffer) => {
-      // Header format: "FILE_STREAM:<fileId>\n"
-      const str = initialBuffer.toString()
-      const match = str.match(/^FILE_STREAM:([a-zA-Z0-9-]+)\n/)
-      if (match) {
-        const fileId = match[1]
-        const transfer = this.activeTransfers.get(fileId)
-        if (transfer && transfer.filePath) {
-          transfer.writeStream = fs.createWriteStream(transfer.filePath)
-          transfer.status = 'active'
-          let receivedBytes = 0
-          const startTime = Date.now()
-
-          // Process remaining bytes in initialBuffer after header
-          const headerLength = match[0].length
-          const bufferedData = initialBuffer.slice(headerLength)
-
-          const session = getSession(transfer.deviceId)
-
-          if (!session) {
-            console.error(`[FileTransfer] No session key for device ${transfer.deviceId}`)
-            socket.destroy()
-            return
-          }
+      const header = parseStreamHeader(initialBuffer.toString())
+      if (!header) {
+        const reason = 'malformed stream header'
+        const address = unmapAddress(socket.remoteAddress)
+        frameGuard.reportMalformed(socket, address, reason, 'FileTransfer', true)
+        return
+      }
+      const transfer = this.activeTransfers.get(header.fileId)
+      if (!transfer || !transfer.filePath) {
+        socket.destroy()
+        return
+      }
+      // Only files we are receiving and have accepted may be written to
+      const owner = transfer.folderId ? this.activeTransfers.get(transfer.folderId) : transfer
+      if (transfer.direction !== 'incoming' || !owner?.accepted) {
+        console.warn(`[FileTransfer] Refused stream for unaccepted transfer ${transfer.fileId}`)
+        socket.destroy()
+        return
+      }
 
-          let decipherStream: stream.Transform | null = null
-          let ivBuffer = Buffer.alloc(0)
-
-          const processChunk = (chunk: Buffer): void => {
-            if (!decipherStream) {
-              ivBuffer = Buffer.concat([ivBuffer, chunk])
-              if (ivBuffer.length >= 16) {
-                const iv = ivBuffer.slice(0, 16)
-                const remaining = ivBuffer.slice(16)
-
-                decipherStream = createDecryptionStream(session.sessionKey, iv)
-                decipherStream.pipe(transfer.writeStream!)
-
-                if (remaining.length > 0) {
-                  decipherStream.write(remaining)
-                  receivedBytes += remaining.length
-                }
-              }
-            } else {
-              decipherStream.write(chunk)
-              receivedBytes += chunk.length
-            }
+      const session = getSession(transfer.deviceId)
+      if (!session) {
+        console.error(`[FileTransfer] No session key for device ${transfer.deviceId}`)
+        socket.destroy()
+        return
+      }
 
-            // Update progress
-            const now = Date.now()
-            const duration = (now - startTime) / 1000
-            const speed = duration > 0 ? receivedBytes / duration : 0
-            const progress = receivedBytes / (transfer.metadata?.size || 1)
-            const eta = speed > 0 ? ((transfer.metadata?.size || 0) - receivedBytes) / speed : 0
-
-            transfer.progress = progress
-            transfer.speed = speed
-            transfer.eta = eta
-            this.mainWindow?.webContents.send('file-transfer-progress', {
-              fileId: transfer.fileId,
-              deviceId: transfer.deviceId,
-              progress: transfer.progress,
-              speed: transfer.speed,
-              eta: transfer.eta,
-              status: transfer.status,
-              name: transfer.metadata?.name,
-              path: transfer.filePath,
-              size: transfer.metadata?.size,
-              direction: 'incoming'
-            })
-          }
+      const { start, end } = header
+      const isCompressed = header.compression === 'zstd'
+      const isDelta = header.delta && !!transfer.deltaBasis
+      if (header.delta && !isDelta) {
+        socket.destroy()
+        return
+      }
+      // A peer that seals its streams always does, so an unsealed one has been tampered with
+      const isSealed = header.sealed
+      const { cipherSuite } = getPeerProtocol(transfer.deviceId)
+      if (!isSealed && peerSupports(transfer.deviceId, Capability.SEALED_STREAMS)) {
+        recordSecurityEvent('decryption-failed', transfer.deviceId, 'unsealed file stream')
+        socket.destroy()
+        return
+      }
+      const size = transfer.metadata?.size || 0
+      // A range has to lie within the file that was announced
+      if (start > size || (end !== undefined && (end < start || end > size))) {
+        const reason = `stream range ${start}-${end ?? ''} outside ${size} bytes`
+        frameGuard.reportMalformed(socket, transfer.deviceId, reason, 'FileTransfer', true)
+        return
+      }
 
-          if (bufferedData.length > 0) {
-            processChunk(bufferedData)
-          }
+      if (end === undefined) {
+        // A plain (or resumed) stream carries everything from `start` to the end of the file;
+        // a resumed one overwrites from the offset we reported in FILE_RESUME
+        this.startSession(transfer, start)
+        transfer.ranged = false
+      } else if (!transfer.ranged) {
+        // First of a set of parallel ranges: each range fills its part of one file
+        this.startSession(transfer, 0)
+        transfer.ranged = true
+        if (!transfer.preallocated) fs.writeFileSync(transfer.filePath, '')
+      }
 
-          socket.on('data', (data) => {
-            processChunk(data)
-          })
+      // A sender that stops sending has stalled; closing the stream fails it like a drop would
+      socket.setTimeout(timeoutSettings.ms('bulk'), () => {
+        console.warn(`[FileTransfer] Stream for ${transfer.fileId} stalled, closing it`)
+        socket.destroy()
+      })
+
+      const entry: TransferStream = { socket, start, end: end ?? size, written: 0 }
+      entry.writeStream = fs.createWriteStream(transfer.filePath, {
+        flags: start > 0 || end !== undefined || transfer.preallocated ? 'r+' : 'w',
+        start
+      })
+      entry.writeStream.on('error', (err) => {
+        console.error('[FileTransfer] Failed to write received file:', err)
+        socket.destroy()
+        transfer.status = 'failed'
+        this.emitProgress(transfer)
+        if ((err as NodeJS.ErrnoException).code === 'ENOSPC') {
+          this.sendError(transfer, 'DISK_FULL').catch(() => {})
+        }
+      })
+      transfer.streams!.push(entry)
+      transfer.status = 'active'
+
+      // Acks tell the sender what has reached the disk, so it can pace itself to our writes
+      if (end === undefined && peerSupports(transfer.deviceId, Capability.FILE_ACK)) {
+        let acked = start
+        entry.ackTimer = setInterval(() => {
+          const offset = start + (entry.writeStream?.bytesWritten || 0)
+          if (offset <= acked) return
+          acked = offset
+          this.sendControl(transfer, 'FILE_ACK', offset).catch(() => {})
+        }, ACK_INTERVAL)
+        socket.once('close', () => clearInterval(entry.ackTimer))
+      }
 
-          socket.on('end', () => {
-            if (decipherStream) decipherStream.end()
-            if (transfer.writeStream) {
-              transfer.writeStream.end()
-              transfer.status = 'completed'
-              this.mainWindow?.webContents.send('file-transfer-progress', {
-                fileId: transfer.fileId,
-                deviceId: transfer.deviceId,
-                progress: 1,
-                speed: transfer.speed,
-                eta: 0,
-                status: 'completed',
-                name: transfer.metadata?.name,
-                path: transfer.filePath,
-                size: transfer.metadata?.size,
-                direction: 'incoming'
+      let ivBuffer = Buffer.alloc(0)
+
+      const processChunk = (chunk: Buffer): void => {
+        if (isStopped(transfer)) return
+        if (!entry.decipherStream) {
+          ivBuffer = Buffer.concat([ivBuffer, chunk])
+          if (ivBuffer.length >= 16) {
+            const iv = ivBuffer.slice(0, 16)
+            const remaining = ivBuffer.slice(16)
+
+            const decipherStream = isSealed
+              ? sealedStream(createOpeningStream, transfer, session.sessionKey, iv, cipherSuite)
+              : createDecryptionStream(session.sessionKey, iv)
+            // A sealed chunk that doesn't authenticate stops the stream there and then
+            decipherStream.on('error', (err) => {
+              console.error('[FileTransfer] File stream failed authentication:', err.message)
+              recordSecurityEvent('decryption-failed', transfer.deviceId, err.message)
+              socket.destroy()
+              transfer.status = 'failed'
+              this.emitProgress(transfer)
+            })
+            let output: stream.Readable = isCompressed
+              ? decipherStream.pipe(createDecompressionStream())
+              : decipherStream
+            if (isDelta) {
+              const { path: basisPath, blockSize } = transfer.deltaBasis!
+              const decoder = new DeltaDecoder(basisPath, blockSize)
+              decoder.on('error', (err) => {
+                console.error('[FileTransfer] Failed to apply delta:', err)
+                socket.destroy()
+                transfer.status = 'failed'
+                this.emitProgress(transfer)
               })
+              output = output.pipe(decoder)
             }
-          })
+            // Progress counts bytes written to disk, which differ from wire bytes when compressed
+            output.on('data', (data: Buffer) => {
+              entry.written += data.length
+              transfer.bytesDone = (transfer.bytesDone || 0) + data.length
+              this.updateStreamProgress(transfer, isCompressed)
+            })
+            output.pipe(entry.writeStream!)
+            entry.decipherStream = decipherStream
+
+            if (remaining.length > 0) {
+              transfer.wireBytes = (transfer.wireBytes || 0) + remaining.length
+              decipherStream.write(remaining)
+            }
+          }
+        } else {
+          transfer.wireBytes = (transfer.wireBytes || 0) + chunk.length
+          entry.decipherStream.write(chunk)
         }
       }
+
+      // Process remaining bytes in initialBuffer after header
+      const bufferedData = initialBuffer.slice(header.length)
+      if (bufferedData.length > 0) {
+        processChunk(bufferedData)
+      }
+
+      socket.on('data', (data) => {
+        processChunk(data)
+      })
+
+      socket.on('end', () => {
+        if (isStopped(transfer)) return
+        const writeStream = entry.writeStream
+        if (!writeStream) return
+
+        // Judge completeness only once the decrypt/decompress pipeline has flushed
+        writeStream.once('finish', () => {
+          if (isStopped(transfer)) return
+          const complete = transfer.ranged
+            ? this.getContiguousBytes(transfer) >= size
+            : (transfer.bytesDone || 0) >= size
+          if (complete) {
+            this.completeIncoming(transfer).catch((err) => {
+              console.error('[FileTransfer] Failed to finish received file:', err)
+              transfer.status = 'failed'
+              this.emitProgress(transfer)
+            })
+          } else if (entry.written < entry.end - entry.start) {
+            // A stream cut short is resumable, so it fails rather than completing
+            transfer.status = 'failed'
+            this.emitProgress(transfer)
+          }
+          // Otherwise this range is done and others are still arriving
+        })
+        if (entry.decipherStream) {
+          entry.decipherStream.end()
+        } else {
+          writeStream.end()
+        }
+      })
     })
   }
 
+  // Moves a delta-built file into place and checks it against the sender's checksum, if any
+  private async completeIncoming(transfer: ActiveTransfer): Promise<void> {
+    // The last parallel ranges can finish together; only the first one completes the file
+    if (!transfer.streams) return
+    transfer.streams = undefined
+
+    if (transfer.finalPath) {
+      fs.renameSync(transfer.filePath!, transfer.finalPath)
+      transfer.filePath = transfer.finalPath
+      transfer.finalPath = undefined
+    }
+
+    const expected = transfer.metadata?.checksum
+    const actual = expected ? await this.verifyChecksum(transfer) : undefined
+    // Cancelled while verifying
+    if (expected && transfer.status !== 'verifying') return
+    if (actual !== expected) {
+      console.error(`[FileTransfer] Checksum mismatch for ${describeFile(transfer.metadata)}`)
+      transfer.status = 'failed'
+      transfer.error = 'Checksum mismatch: the received file is corrupt'
+      this.sendError(transfer, 'CHECKSUM_MISMATCH').catch(() => {})
+    } else {
+      transfer.status = 'completed'
+      transfer.progress = 1
+      transfer.eta = 0
+      wipe(transfer.passphraseKey)
+      transfer.passphraseKey = undefined
+      await untrackIncomplete(transfer.fileId)
+    }
+    this.emitProgress(transfer)
+
+    // Folder children are reported as the folder, so only top-level files get one
+    const filePath = transfer.filePath!
+    const previewable = !transfer.metadata?.sensitive && isPreviewable(filePath)
+    if (transfer.status === 'completed' && !transfer.folderId && previewable) {
+      transfer.thumbnail = (await createThumbnail(transfer.fileId, filePath)) ?? undefined
+      if (transfer.thumbnail) this.emitProgress(transfer)
+    }
+  }
+
+  /**
+   * Hashes a received file, reporting how far along it is with `file-verification-progress`
+   * events since this can take a while for very large files. Resolves to null if the file
+   * can't be read.
+   */
+  private async verifyChecksum(transfer: ActiveTransfer): Promise<string | null> {
+    transfer.status = 'verifying'
+    transfer.verifyProgress = 0
+    this.emitProgress(transfer)
+
+    const size = transfer.metadata?.size || 0
+    let lastEmit = 0
+    const actual = await hashFile(transfer.filePath!, (bytesHashed) => {
+      const now = Date.now()
+      if (now - lastEmit < VERIFY_PROGRESS_INTERVAL) return
+      lastEmit = now
+      transfer.verifyProgress = size > 0 ? bytesHashed / size : 1
+      this.mainWindow?.webContents.send('file-verification-progress', {
+        fileId: transfer.fileId,
+        deviceId: transfer.deviceId,
+        progress: transfer.verifyProgress
+      })
+    }).catch(() => null)
+    transfer.verifyProgress = undefined
+    return actual
+  }
+
+  private startSession(transfer: ActiveTransfer, bytes: number): void {
+    transfer.streams = []
+    transfer.bytesDone = bytes
+    transfer.wireBytes = 0
+    transfer.sessionStart = { time: Date.now(), bytes }
+    // Time spent paused or waiting isn't part of the next sample
+    const owner = transfer.folderId ? this.activeTransfers.get(transfer.folderId) : transfer
+    if (owner) owner.lastSample = undefined
+  }
+
+  // Records the top-level transfer's throughput once per interval
+  private sampleSpeed(transfer: ActiveTransfer): void {
+    const now = Date.now()
+    const bytes = transfer.progress * (transfer.metadata?.size || 0)
+    const last = transfer.lastSample
+    if (last && now - last.time < SPEED_SAMPLE_INTERVAL) return
+    if (last) {
+      const speed = (bytes - last.bytes) / ((now - last.time) / 1000)
+      if (!transfer.samples) transfer.samples = []
+      transfer.samples.push({ time: now, speed: Math.max(0, speed) })
+      if (transfer.samples.length > MAX_SPEED_SAMPLES) transfer.samples.shift()
+    }
+    transfer.lastSample = { time: now, bytes }
+  }
+
+  private updateStreamProgress(transfer: ActiveTransfer, compressed: boolean): void {
+    const size = transfer.metadata?.size || 0
+    const done = transfer.bytesDone || 0
+    const base = transfer.sessionStart?.bytes || 0
+    const duration = transfer.sessionStart ? (Date.now() - transfer.sessionStart.time) / 1000 : 0
+    const speed = duration > 0 ? (done - base) / duration : 0
+
+    transfer.progress = done / (size || 1)
+    transfer.speed = speed
+    transfer.eta = speed > 0 ? (size - done) / speed : 0
+    if (compressed && transfer.wireBytes) {
+      transfer.compressionRatio = (done - base) / transfer.wireBytes
+    }
+    this.emitProgress(transfer)
+
+    // A folder's progress is brought up to date by emitProgress above
+    const owner = transfer.folderId ? this.activeTransfers.get(transfer.folderId) : transfer
+    if (owner) this.sampleSpeed(owner)
+  }
+
+  // How we see a transfer, to answer a peer's TRANSFER_STATUS_QUERY
+  public getTransferStatus(deviceId: string, fileId: string): TransferStatusPayload {
+    const transfer = this.activeTransfers.get(fileId)
+    if (!transfer || transfer.deviceId !== deviceId) return { fileId, status: 'unknown', bytes: 0 }
+    const bytes = transfer.ranged ? this.getContiguousBytes(transfer) : transfer.bytesDone || 0
+    return { fileId, status: transfer.status, bytes }
+  }
+
+  /**
+   * After reconnecting, asks the peer how it sees each transfer that was cut off or paused,
+   * and settles ours where the peer has moved on: a transfer it already finished, cancelled
+   * or no longer knows about isn't resumed.
+   */
+  private async reconcileTransfers(deviceId: string): Promise<void> {
+    if (!peerSupports(deviceId, Capability.TRANSFER_STATUS)) return
+    const device = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
+    if (!device) return
+
+    const interrupted = [...this.activeTransfers.values()].filter(
+      (t) =>
+        t.deviceId === deviceId &&
+        !t.folderId &&
+        !t.peerError &&
+        RECONCILED_STATUSES.includes(t.status)
+    )
+    for (const transfer of interrupted) {
+      const query: NetworkMessage = {
+        type: 'TRANSFER_STATUS_QUERY',
+        deviceId: getDeviceInfo().deviceId,
+        payload: { fileId: transfer.fileId, status: transfer.status } as TransferStatusQuery,
+        id: uuidv4(),
+        timestamp: Date.now()
+      }
+      try {
+        const response = await connectionManager.request(device, query)
+        if (response.type === 'TRANSFER_STATUS') {
+          await this.reconcile(transfer, response.payload as TransferStatusPayload)
+        }
+      } catch (e) {
+        console.warn(`[FileTransfer] No status for ${transfer.fileId} from ${deviceId}:`, e)
+      }
+    }
+  }
+
+  /**
+   * Checks a peer's TRANSFER_STATUS_QUERY against our side. A receiver that lost its
+   * streams (say, its network changed) asks while we may still be sending into sockets
+   * that will never deliver; those are failed now, so the retry resumes from what the
+   * receiver actually has instead of waiting out TCP timeouts.
+   */
+  public handleStatusQuery(deviceId: string, query: TransferStatusQuery): void {
+    const transfer = this.activeTransfers.get(query.fileId)
+    if (!transfer || transfer.deviceId !== deviceId || transfer.direction !== 'outgoing') return
+    if (query.status !== 'failed' && query.status !== 'retrying') return
+    const children = (transfer.children || []).map((id) => this.activeTransfers.get(id))
+    for (const t of [transfer, ...children]) {
+      if (t?.status !== 'active') continue
+      console.log(`[FileTransfer] ${deviceId} lost ${t.fileId}, restarting our streams`)
+      this.failStreams(t, 'ECONNRESET', (entry) => !entry.socket.destroyed)
+    }
+  }
+
+  /**
+   * Moves transfers off an interface the machine no longer has. Outgoing streams bound to
+   * it fail with ENETDOWN, which retries them like any dropped connection; incoming ones
+   * are closed and marked failed. Once the new interface has had a moment to come up, we
+   * reconnect to each peer involved, which reconciles the transfers with it.
+   */
+  migrate(lostAddresses: string[]): void {
+    const affected = new Set<string>()
+    for (const transfer of this.activeTransfers.values()) {
+      if (transfer.status !== 'active') continue
+      const onLost = (entry: TransferStream): boolean => isBoundTo(entry.socket, lostAddresses)
+      if (!transfer.streams?.some(onLost)) continue
+      affected.add(transfer.deviceId)
+      if (transfer.direction === 'outgoing') {
+        this.failStreams(transfer, 'ENETDOWN', onLost)
+      } else {
+        for (const entry of transfer.streams.filter(onLost)) entry.socket.destroy()
+        transfer.status = 'failed'
+        this.emitProgress(transfer)
+      }
+    }
+    if (affected.size === 0) return
+
+    setTimeout(() => {
+      for (const deviceId of affected) {
+        const device = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
+        if (!device) continue
+        // A new connection reconciles on 'connected'; an existing one has to be asked to
+        const reconciled = connectionManager.isConnected(deviceId)
+          ? this.reconcileTransfers(deviceId)
+          : connectionManager.getConnection(device)
+        reconciled.catch((e) => {
+          console.warn(`[FileTransfer] Could not reach ${deviceId} after network change:`, e)
+        })
+      }
+    }, MIGRATION_DELAY)
+  }
+
+  // Destroys a transfer's outgoing streams with an error, so they fail and are retried
+  private failStreams(
+    transfer: ActiveTransfer,
+    code: string,
+    filter: (entry: TransferStream) => boolean
+  ): void {
+    for (const entry of (transfer.streams || []).filter(filter)) {
+      entry.socket.destroy(Object.assign(new Error('Network path lost'), { code }))
+    }
+  }
+
+  private async reconcile(transfer: ActiveTransfer, remote: TransferStatusPayload): Promise<void> {
+    // Resumed, cancelled or finished while the query was out
+    if (!RECONCILED_STATUSES.includes(transfer.status)) return
+
+    if (remote.status === 'cancelled') {
+      await this.stopTransfer(transfer)
+    } else if (remote.status === 'unknown' || remote.status === 'rejected') {
+      transfer.status = 'failed'
+      transfer.error = 'The other device no longer has this transfer'
+      this.emitProgress(transfer)
+    } else if (remote.status === 'completed' && transfer.direction === 'outgoing') {
+      // The receiver has everything; only the end of our stream was lost
+      const children = (transfer.children || []).map((id) => this.activeTransfers.get(id))
+      for (const t of [transfer, ...children]) {
+        if (!t) continue
+        t.status = 'completed'
+        t.progress = 1
+        t.eta = 0
+      }
+      this.emitProgress(transfer)
+    }
+  }
+
+  // Length of the gap-free prefix covered by ranged streams
+  private getContiguousBytes(transfer: ActiveTransfer): number {
+    let prefix = 0
+    const streams = [...(transfer.streams || [])].sort((a, b) => a.start - b.start)
+    for (const s of streams) {
+      if (s.start > prefix) break
+      prefix = Math.max(prefix, s.start + s.written)
+    }
+    return prefix
+  }
+
   async initiateSend(
     deviceId: string,
     filePath: string,
-    replyTo?: string
+    replyTo?: string,
+    forwardedFrom?: ForwardedFrom,
+    checksum?: string,
+    sensitive?: boolean,
+    passphrase?: string
   ): Promise<NetworkMessage> {
     const stats = fs.statSync(filePath)
     const fileId = uuidv4()
+    const device = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
+    if (!device) throw new Error('Device not found')
+    checkRoute(device, stats.size)
+
+    let protection: Awaited<ReturnType<typeof protectWithPassphrase>> | undefined
+    if (passphrase) {
+      // Whether the peer can take it is only known once connected
+      await connectionManager.getConnection(device)
+      const sealed = peerSupports(deviceId, Capability.SEALED_STREAMS)
+      if (!sealed || !peerHasExtension(deviceId, 'passphrase')) {
+        throw new Error(`${device.displayName} can't receive passphrase-protected files`)
+      }
+      const kdf = peerHasExtension(deviceId, 'argon2') ? 'argon2id' : 'scrypt'
+      protection = await protectWithPassphrase(passphrase, kdf)
+    }
+
     const metadata: FileMetadata = {
       fileId,
       name: path.basename(filePath),
       size: stats.size,
-      path: filePath
+      path: filePath,
+      compression: peerHasExtension(deviceId, 'compression') ? 'zstd' : undefined,
+      delta: peerHasExtension(deviceId, 'delta-sync') || undefined,
+      checksum,
+      sensitive: sensitive || undefined,
+      // A preview would give away what the passphrase protects
+      preview: sensitive || protection ? undefined : await createPreview(filePath),
+      passphrase: protection?.protection
     }
 
-    const device = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
-    if (!device) throw new Error('Device not found')
-
     const message: NetworkMessage = {
       type: 'FILE_META',
       deviceId: getDeviceInfo().deviceId,
       payload: metadata,
       id: uuidv4(),
       timestamp: Date.now(),
-      replyTo
+      replyTo,
+      forwardedFrom
     }
 
-    this.activeTransfers.set(fileId, {
+    const transfer: ActiveTransfer = {
       fileId,
       deviceId,
+      messageId: message.id,
       progress: 0,
       speed: 0,
       eta: 0,
       status: 'pending',
       filePath,
       metadata,
-      direction: 'outgoing'
-    })
+      direction: 'outgoing',
+      priority: 'normal',
+      passphraseKey: protection?.key
+    }
+    this.activeTransfers.set(fileId, transfer)
 
     // Notify renderer of new pending transfer
-    this.mainWindow?.webContents.send('file-transfer-progress', {
+    this.emitProgress(transfer)
+
+    await connectionManager.getConnection(device)
+    connectionManager.sendMessage(deviceId, message)
+    messageStore.add(deviceId, message)
+
+    return message
+  }
+
+  // The archive is announced as an ordinary file, so the receiver needs nothing new
+  private async initiateZipSend(
+    deviceId: string,
+    folderPath: string,
+    zipEntries: ZipEntry[],
+    replyTo?: string
+  ): Promise<NetworkMessage> {
+    const device = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
+    if (!device) throw new Error('Device not found')
+
+    const fileId = uuidv4()
+    const metadata: FileMetadata = {
+      fileId,
+      name: `${path.basename(folderPath)}.zip`,
+      size: getZipSize(zipEntries),
+      path: folderPath,
+      compression: peerHasExtension(deviceId, 'compression') ? 'zstd' : undefined
+    }
+    checkRoute(device, metadata.size)
+
+    const message: NetworkMessage = {
+      type: 'FILE_META',
+      deviceId: getDeviceInfo().deviceId,
+      payload: metadata,
+      id: uuidv4(),
+      timestamp: Date.now(),
+      replyTo
+    }
+
+    const transfer: ActiveTransfer = {
       fileId,
       deviceId,
+      messageId: message.id,
       progress: 0,
       speed: 0,
       eta: 0,
       status: 'pending',
-      name: metadata.name,
-      path: filePath,
-      size: metadata.size,
-      direction: 'outgoing'
-    })
+      filePath: folderPath,
+      metadata,
+      zipEntries,
+      direction: 'outgoing',
+      priority: 'normal'
+    }
+    this.activeTransfers.set(fileId, transfer)
+    this.emitProgress(transfer)
 
     await connectionManager.getConnection(device)
     connectionManager.sendMessage(deviceId, message)
+    messageStore.add(deviceId, message)
 
     return message
   }
 
-  public async handleIncomingMeta(message: NetworkMessage): Promise<void> {
-    const metadata: FileMetadata = message.payload as FileMetadata
-    this.activeTransfers.set(metadata.fileId, {
-      fileId: metadata.fileId,
-      deviceId: message.deviceId,
+  /**
+   * Sends a directory as a single FILE_META carrying a manifest of every file (relative
+   * path + size). Once accepted, the files are streamed one after another over the normal
+   * FILE_STREAM path and progress is reported for the folder as a whole.
+   *
+   * With `asZip` (by default the "send folders as zip" setting) the folder goes out as a
+   * single zip archive instead, which peers that predate folder manifests can receive.
+   */
+  async initiateFolderSend(
+    deviceId: string,
+    folderPath: string,
+    replyTo?: string,
+    asZip?: boolean
+  ): Promise<NetworkMessage> {
+    const device = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
+    if (!device) throw new Error('Device not found')
+
+    // Peers from before folder manifests can only take the folder as an archive
+    await connectionManager.getConnection(device)
+    if (!peerSupports(deviceId, Capability.FOLDERS)) {
+      asZip = true
+    } else if (asZip === undefined) {
+      const Store = (await import('electron-store')).default
+      const store = new Store(getStoreOptions())
+      asZip = store.get('sendFoldersAsZip', false) as boolean
+    }
+    if (asZip) {
+      const zipEntries = await listZipEntries(folderPath)
+      if (canZip(zipEntries)) return this.initiateZipSend(deviceId, folderPath, zipEntries, replyTo)
+      // Archives past 4 GB or 65535 entries would need ZIP64, which isn't implemented
+      console.warn(`[FileTransfer] ${folderPath} is too large to zip; sending it as a folder`)
+    }
+
+    const folderId = uuidv4()
+    const entries: FolderEntry[] = []
+    const directories: string[] = []
+    const children: string[] = []
+
+    const walk = async (dir: string, prefix: string): Promise<void> => {
+      const items = await fs.promises.readdir(dir, { withFileTypes: true })
+      for (const item of items) {
+        const relativePath = prefix ? `${prefix}/${item.name}` : item.name
+        const fullPath = path.join(dir, item.name)
+        if (item.isDirectory()) {
+          directories.push(relativePath)
+          await walk(fullPath, relativePath)
+        } else if (item.isFile()) {
+          // Symlinks and special files are skipped
+          const { size } = await fs.promises.stat(fullPath)
+          const fileId = uuidv4()
+          entries.push({ fileId, relativePath, size })
+          children.push(fileId)
+          this.activeTransfers.set(fileId, {
+            fileId,
+            deviceId,
+            folderId,
+            progress: 0,
+            speed: 0,
+            eta: 0,
+            status: 'pending',
+            filePath: fullPath,
+            metadata: { fileId, name: relativePath, size },
+            direction: 'outgoing'
+          })
+        }
+      }
+    }
+    await walk(folderPath, '')
+
+    const metadata: FileMetadata = {
+      fileId: folderId,
+      name: path.basename(folderPath),
+      size: entries.reduce((sum, e) => sum + e.size, 0),
+      path: folderPath,
+      isFolder: true,
+      compression: peerHasExtension(deviceId, 'compression') ? 'zstd' : undefined,
+      entries,
+      directories
+    }
+    try {
+      checkRoute(device, metadata.size)
+    } catch (err) {
+      for (const child of children) this.activeTransfers.delete(child)
+      throw err
+    }
+
+    const message: NetworkMessage = {
+      type: 'FILE_META',
+      deviceId: getDeviceInfo().deviceId,
+      payload: metadata,
+      id: uuidv4(),
+      timestamp: Date.now(),
+      replyTo
+    }
+
+    const folder: ActiveTransfer = {
+      fileId: folderId,
+      deviceId,
+      messageId: message.id,
       progress: 0,
       speed: 0,
       eta: 0,
       status: 'pending',
+      filePath: folderPath,
       metadata,
-      direction: 'incoming'
-    })
+      children,
+      direction: 'outgoing',
+      priority: 'normal'
+    }
+    this.activeTransfers.set(folderId, folder)
+    this.emitProgress(folder)
 
-    // Notify renderer of new incoming file request
-    this.mainWindow?.webContents.send('file-transfer-progress', {
+    await connectionManager.getConnection(device)
+    connectionManager.sendMessage(deviceId, message)
+    messageStore.add(deviceId, message)
+
+    return message
+  }
+
+  private async streamFolder(
+    folder: ActiveTransfer,
+    resumeFrom?: FileControlPayload
+  ): Promise<void> {
+    const children = folder.children || []
+    if (children.length === 0) {
+      this.updateFolderProgress(folder)
+      return
+    }
+    for (const fileId of children) {
+      const child = this.activeTransfers.get(fileId)
+      if (!child?.filePath || child.status === 'completed') continue
+      if (isStopped(child)) return
+      const offset = resumeFrom?.fileId === fileId ? resumeFrom.offset || 0 : 0
+      // A failed child stops the folder here; the error decides whether it is retried
+      await this.startStreaming(fileId, child.filePath, folder.deviceId, offset)
+      if (isStopped(child)) return
+    }
+  }
+
+  public async handleIncomingMeta(message: NetworkMessage): Promise<void> {
+    const metadata: FileMetadata = message.payload as FileMetadata
+    if (metadata.isFolder) {
+      const paths = [
+        ...(metadata.entries || []).map((e) => e.relativePath),
+        ...(metadata.directories || [])
+      ]
+      if (!paths.every(isSafeRelativePath)) {
+        console.warn(`[FileTransfer] Ignoring folder ${metadata.name}: unsafe path in manifest`)
+        return
+      }
+    }
+    // An id already in use (ours, or another device's) or repeated in the manifest would
+    // overwrite that transfer
+    const ids = [
+      metadata.fileId,
+      ...(metadata.isFolder ? (metadata.entries || []).map((e) => e.fileId) : [])
+    ]
+    const reused =
+      new Set(ids).size !== ids.length ||
+      ids.some((id) => typeof id !== 'string' || this.activeTransfers.has(id))
+    if (reused) {
+      console.warn(`[FileTransfer] Ignoring ${describeFile(metadata)}: its id is already in use`)
+      return
+    }
+
+    const transfer: ActiveTransfer = {
       fileId: metadata.fileId,
       deviceId: message.deviceId,
+      messageId: message.id,
       progress: 0,
       speed: 0,
       eta: 0,
       status: 'pending',
-      name: metadata.name,
-      size: metadata.size,
-      direction: 'incoming'
-    })
+      metadata,
+      direction: 'incoming',
+      children: metadata.isFolder ? (metadata.entries || []).map((e) => e.fileId) : undefined
+    }
+    this.activeTransfers.set(metadata.fileId, transfer)
+
+    for (const entry of metadata.isFolder ? metadata.entries || [] : []) {
+      this.activeTransfers.set(entry.fileId, {
+        fileId: entry.fileId,
+        deviceId: message.deviceId,
+        folderId: metadata.fileId,
+        progress: 0,
+        speed: 0,
+        eta: 0,
+        status: 'pending',
+        metadata: { fileId: entry.fileId, name: entry.relativePath, size: entry.size },
+        direction: 'incoming'
+      })
+    }
+
+    // Notify renderer of new incoming file request
+    this.emitProgress(transfer)
 
     this.mainWindow?.webContents.send('file-received', message)
 
-    // Handle Auto-accept
+    // Refuse what can't or shouldn't be stored before anyone is asked to accept it
+    const refusal = await this.preflight(transfer, await getDownloadDirectory())
+    if (refusal) {
+      console.log(`[FileTransfer] Refusing ${describeFile(metadata)}: ${refusal.reason}`)
+      transfer.status = 'rejected'
+      transfer.rejection = refusal
+      this.emitProgress(transfer)
+      await this.sendReject(metadata.fileId, refusal)
+      return
+    }
+
+    // Rules decide first; the plain auto-accept toggle only applies when none match
+    const decision = await evaluateAutoAcceptRules(message.deviceId, metadata)
+    if (decision === 'reject') {
+      console.log(`[FileTransfer] Auto-rejecting by rule: ${describeFile(metadata)}`)
+      transfer.status = 'rejected'
+      this.emitProgress(transfer)
+      await this.sendReject(metadata.fileId)
+      return
+    }
+
     const Store = (await import('electron-store')).default
-    const store = new Store()
+    const store = new Store(getStoreOptions())
     const autoAccept = store.get('autoAccept', false) as boolean
 
-    if (autoAccept) {
+    // A sensitive file always waits for the user, even from a trusted device, and one
+    // protected with a passphrase can't be accepted without the user typing it
+    const needsUser = metadata.sensitive || metadata.passphrase
+    if (!needsUser && (decision === 'accept' || autoAccept)) {
       console.log(`[FileTransfer] Auto-accepting file: ${metadata.name}`)
-      const customPath = store.get('downloadPath') as string | undefined
-      const downloadsPath = customPath || app.getPath('downloads')
-      let filePath = path.join(downloadsPath, metadata.name)
+      const filePath = await resolveDownloadPath(message.deviceId, metadata.name)
+      if (filePath) {
+        await this.sendAccept(metadata.fileId, filePath)
+      } else {
+        await this.sendReject(metadata.fileId)
+      }
+      return
+    }
 
-      // Handle file name conflicts
-      let counter = 1
-      const ext = path.extname(metadata.name)
-      const baseName = path.basename(metadata.name, ext)
+    const expiryMinutes = store.get('fileRequestExpiry', 60) as number
+    if (expiryMinutes > 0) this.scheduleExpiry(metadata.fileId, expiryMinutes * 60 * 1000)
+  }
 
-      while (fs.existsSync(filePath)) {
-        filePath = path.join(downloadsPath, `${baseName} (${counter})${ext}`)
-        counter++
-      }
+  /**
+   * Checks an incoming file or folder against the size limit and the free space in `dir`.
+   * Runs when the request arrives and again on accept, since space may have run out since.
+   */
+  private async preflight(transfer: ActiveTransfer, dir: string): Promise<FileRejection | null> {
+    const size = transfer.metadata?.size || 0
+    const maxSize = await getMaxIncomingSize()
+    if (maxSize > 0 && size > maxSize) return { reason: 'too-large', maxSize }
+
+    const available = await getFreeSpace(dir).catch(() => Infinity)
+    if (available < size) return { reason: 'no-space', available }
+    return null
+  }
 
-      await this.sendAccept(metadata.fileId, filePath)
-    }
+  // Rejects a request nobody answered, so unaccepted transfers don't pile up forever
+  private scheduleExpiry(fileId: string, delay: number): void {
+    const timer = setTimeout(() => {
+      this.expiryTimers.delete(fileId)
+      const transfer = this.activeTransfers.get(fileId)
+      if (transfer?.status !== 'pending') return
+      console.log(`[FileTransfer] File request expired: ${describeFile(transfer.metadata)}`)
+      transfer.status = 'rejected'
+      transfer.rejection = { reason: 'timeout' }
+      this.emitProgress(transfer)
+      this.sendReject(fileId, transfer.rejection)
+    }, delay)
+    this.expiryTimers.set(fileId, timer)
+  }
+
+  private clearExpiry(fileId: string): void {
+    clearTimeout(this.expiryTimers.get(fileId))
+    this.expiryTimers.delete(fileId)
   }
 
   public async handleAccept(message: NetworkMessage): Promise<void> {
-    const { fileId } = message.payload as { fileId: string }
+    const { fileId, compression, signature } = message.payload as {
+      fileId: string
+      compression?: FileCompression
+      signature?: DeltaSignature
+    }
     const transfer = this.activeTransfers.get(fileId)
-    if (!transfer || !transfer.filePath) return
+    if (!transfer || !transfer.filePath || transfer.direction !== 'outgoing') return
+    // Only the receiver we offered the file to can accept it, and only once
+    if (message.deviceId !== transfer.deviceId || transfer.status !== 'pending') return
+    if (transfer.accepted) return
+    transfer.accepted = true
+
+    // Only compress if we offered it and the receiver agreed
+    transfer.compression =
+      compression === 'zstd' && transfer.metadata?.compression === 'zstd' ? 'zstd' : undefined
+    transfer.deltaSignature = transfer.metadata?.delta ? signature : undefined
+    this.enqueue({ fileId })
+  }
 
+  // Slots are taken by whole transfers: a folder, or a file with all of its parallel ranges
+  private async runTransfer(
+    transfer: ActiveTransfer,
+    resumeFrom?: FileControlPayload
+  ): Promise<void> {
     transfer.status = 'active'
-    this.mainWindow?.webContents.send('file-transfer-progress', {
+    if (resumeFrom) {
+      transfer.resumes = (transfer.resumes || 0) + 1
+      // For a folder this is the paused child, which streamFolder would otherwise skip past
+      const target = this.activeTransfers.get(resumeFrom.fileId)
+      if (target) target.status = 'active'
+    }
+    this.emitProgress(transfer)
+    if (transfer.children) return this.streamFolder(transfer, resumeFrom)
+    if (resumeFrom) {
+      const offset = resumeFrom.offset || 0
+      return this.startStreaming(transfer.fileId, transfer.filePath!, transfer.deviceId, offset)
+    }
+
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    const parallelStreams = store.get('parallelStreams', 1) as number
+    // A delta is a single instruction stream and a zip is generated in order, so neither
+    // goes over parallel ranges
+    const size = transfer.metadata?.size || 0
+    const sequential = transfer.deltaSignature || transfer.zipEntries
+    const parallel = !sequential && peerHasExtension(transfer.deviceId, 'multiplexing')
+    if (parallel && parallelStreams > 1 && size >= PARALLEL_MIN_SIZE) {
+      return this.startParallelStreaming(transfer, parallelStreams)
+    }
+    return this.startStreaming(transfer.fileId, transfer.filePath!, transfer.deviceId)
+  }
+
+  /**
+   * Queues an accepted outgoing transfer behind others of the same or higher priority and
+   * starts it as soon as a slot is free.
+   */
+  private enqueue(entry: QueueEntry): void {
+    const transfer = this.activeTransfers.get(entry.fileId)
+    if (!transfer) return
+    this.queue = this.queue.filter((e) => e.fileId !== entry.fileId)
+
+    const rank = PRIORITY_RANK[transfer.priority || 'normal']
+    const index = this.queue.findIndex((e) => {
+      const queued = this.activeTransfers.get(e.fileId)
+      return PRIORITY_RANK[queued?.priority || 'normal'] > rank
+    })
+    this.queue.splice(index === -1 ? this.queue.length : index, 0, entry)
+    transfer.status = 'queued'
+    this.emitQueue()
+    this.pumpQueue()
+  }
+
+  private dequeue(fileId: string): void {
+    const length = this.queue.length
+    this.queue = this.queue.filter((e) => e.fileId !== fileId)
+    if (this.queue.length !== length) this.emitQueue()
+  }
+
+  private pumpQueue(): void {
+    while (this.running.size < MAX_CONCURRENT_TRANSFERS && this.queue.length > 0) {
+      const entry = this.queue.shift()!
+      const transfer = this.activeTransfers.get(entry.fileId)
+      if (!transfer || transfer.status !== 'queued') continue
+
+      this.running.add(entry.fileId)
+      this.runTransfer(transfer, entry.resumeFrom)
+        .catch((err) => this.scheduleRetry(transfer, err))
+        .finally(() => {
+          this.running.delete(entry.fileId)
+          this.pumpQueue()
+        })
+      this.emitQueue()
+    }
+  }
+
+  /**
+   * Called when a running transfer fails (it is already marked failed). Network errors are
+   * retried with exponential backoff, up to the configured limit, by asking the receiver
+   * to resume from the offset it has confirmed.
+   */
+  private async scheduleRetry(transfer: ActiveTransfer, err: unknown): Promise<void> {
+    const code = (err as NodeJS.ErrnoException | undefined)?.code
+    if (!code || !TRANSIENT_ERRORS.includes(code) || transfer.peerError) return
+
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    const limit = store.get('transferRetryLimit', 5) as number
+    const attempt = (transfer.retries || 0) + 1
+    if (attempt > limit) {
+      transfer.error = `Gave up after ${limit} retries`
+      this.emitProgress(transfer)
+      return
+    }
+
+    // For a folder, the child that failed is the one to resume
+    const target = this.getControlTarget(transfer.fileId) ?? transfer
+    const delay = Math.min(RETRY_MAX_DELAY, RETRY_BASE_DELAY * 2 ** (attempt - 1))
+    transfer.retries = attempt
+    transfer.status = 'retrying'
+    target.status = 'retrying'
+    transfer.error = `Connection lost, retry ${attempt} of ${limit}`
+    // A folder child reports through the folder, so this covers both cases
+    this.emitProgress(target)
+
+    const retryLater = (): void => {
+      transfer.status = 'failed'
+      target.status = 'failed'
+      this.scheduleRetry(transfer, { code: 'EHOSTUNREACH' })
+    }
+    setTimeout(() => {
+      // Paused or cancelled while waiting
+      if (target.status !== 'retrying') return
+      const device = discoveryManager
+        .getDiscoveredDevices()
+        .find((d) => d.deviceId === transfer.deviceId)
+      if (!device) {
+        retryLater()
+        return
+      }
+      transfer.error = undefined
+      this.sendControl(target, 'FILE_RESUME').catch(retryLater)
+    }, delay)
+  }
+
+  // Positions shift whenever the queue changes, so every waiting transfer is re-reported
+  private emitQueue(): void {
+    for (const entry of this.queue) {
+      const transfer = this.activeTransfers.get(entry.fileId)
+      if (transfer) this.emitProgress(transfer)
+    }
+  }
+
+  private getQueuePosition(fileId: string): number | undefined {
+    const index = this.queue.findIndex((e) => e.fileId === fileId)
+    return index === -1 ? undefined : index + 1
+  }
+
+  getQueue(): QueuedTransfer[] {
+    return this.queue
+      .map((e) => this.activeTransfers.get(e.fileId))
+      .filter((t): t is ActiveTransfer => !!t)
+      .map((t) => ({
+        fileId: t.fileId,
+        deviceId: t.deviceId,
+        name: t.metadata?.name,
+        size: t.metadata?.size,
+        priority: t.priority || 'normal'
+      }))
+  }
+
+  getTransferStats(fileId: string): TransferStats | null {
+    const transfer = this.activeTransfers.get(fileId)
+    if (!transfer) return null
+    return {
       fileId,
-      deviceId: transfer.deviceId,
+      status: transfer.status,
       progress: transfer.progress,
       speed: transfer.speed,
       eta: transfer.eta,
-      status: 'active',
-      name: transfer.metadata?.name,
-      size: transfer.metadata?.size,
-      direction: 'outgoing'
-    })
-    this.startStreaming(fileId, transfer.filePath, transfer.deviceId)
+      samples: transfer.samples || [],
+      retries: transfer.retries || 0,
+      resumes: transfer.resumes || 0
+    }
+  }
+
+  /**
+   * Puts the queue in the given order. Ids not listed keep their relative order after the
+   * listed ones; priorities are left as they are.
+   */
+  reorderQueue(fileIds: string[]): QueuedTransfer[] {
+    const listed = fileIds
+      .map((id) => this.queue.find((e) => e.fileId === id))
+      .filter((e): e is QueueEntry => !!e)
+    this.queue = [...listed, ...this.queue.filter((e) => !fileIds.includes(e.fileId))]
+    this.emitQueue()
+    return this.getQueue()
+  }
+
+  setPriority(fileId: string, priority: TransferPriority): boolean {
+    const transfer = this.activeTransfers.get(fileId)
+    if (!transfer || transfer.direction !== 'outgoing' || !(priority in PRIORITY_RANK)) {
+      return false
+    }
+    transfer.priority = priority
+    const entry = this.queue.find((e) => e.fileId === fileId)
+    if (entry) {
+      this.enqueue(entry)
+    } else {
+      this.emitProgress(transfer)
+    }
+    return true
+  }
+
+  /**
+   * Splits a large file into `count` byte ranges and sends them over separate connections
+   * at once; the receiver writes each range at its offset. Resuming afterwards uses a
+   * single stream from the receiver's gap-free prefix.
+   */
+  private async startParallelStreaming(transfer: ActiveTransfer, count: number): Promise<void> {
+    const size = transfer.metadata?.size || 0
+    const rangeSize = Math.ceil(size / count)
+    const ranges: [number, number][] = []
+    for (let start = 0; start < size; start += rangeSize) {
+      ranges.push([start, Math.min(size, start + rangeSize)])
+    }
+
+    this.startSession(transfer, 0)
+    try {
+      await Promise.all(
+        ranges.map(([start, end]) =>
+          this.startStreaming(transfer.fileId, transfer.filePath!, transfer.deviceId, start, end)
+        )
+      )
+    } catch (err) {
+      // One failed range fails the transfer; stop the others so it can be resumed cleanly
+      this.closeOutgoingStreams(transfer)
+      throw err
+    }
+    if (isStopped(transfer)) return
+
+    transfer.status = 'completed'
+    transfer.progress = 1
+    transfer.eta = 0
+    this.emitProgress(transfer)
   }
 
   public handleReject(message: NetworkMessage): void {
-    const { fileId } = message.payload as { fileId: string }
+    const { fileId, ...rejection } = message.payload as FileRejectPayload
     const transfer = this.activeTransfers.get(fileId)
-    if (transfer) {
+    // Once accepted, a transfer can only be cancelled
+    if (transfer && message.deviceId === transfer.deviceId && !transfer.accepted) {
+      this.dequeue(fileId)
       transfer.status = 'rejected'
-      this.mainWindow?.webContents.send('file-transfer-progress', {
-        ...transfer,
-        name: transfer.metadata?.name,
-        size: transfer.metadata?.size
-      })
+      transfer.rejection = rejection.reason ? rejection : undefined
+      this.emitProgress(transfer)
     }
   }
 
+  // Folders are paused and resumed through the child file that is currently in flight
+  private getControlTarget(fileId: string): ActiveTransfer | undefined {
+    const transfer = this.activeTransfers.get(fileId)
+    if (!transfer?.children) return transfer
+    const children = transfer.children.map((id) => this.activeTransfers.get(id))
+    return (
+      children.find((t) => t?.status === 'active' || t?.status === 'paused') ||
+      children.find((t) => t && t.status !== 'completed')
+    )
+  }
+
+  private async closeIncomingStream(entry: TransferStream): Promise<void> {
+    entry.socket.destroy()
+    const writeStream = entry.writeStream
+    if (!writeStream || writeStream.closed) return
+    await new Promise<void>((resolve) => {
+      writeStream.once('close', () => resolve())
+      // The decipher stream is piped into the file, so ending it flushes and closes both
+      if (entry.decipherStream) {
+        entry.decipherStream.end()
+      } else {
+        writeStream.end()
+      }
+    })
+  }
+
+  private closeOutgoingStreams(transfer: ActiveTransfer): void {
+    for (const entry of transfer.streams || []) entry.socket.destroy()
+  }
+
+  // Stops writing and reports how many bytes actually reached the disk
+  private async getVerifiedOffset(transfer: ActiveTransfer): Promise<number> {
+    await Promise.all((transfer.streams || []).map((entry) => this.closeIncomingStream(entry)))
+    if (!transfer.filePath || !fs.existsSync(transfer.filePath)) return 0
+
+    if (transfer.ranged) {
+      // Later ranges may have landed past a gap; only the gap-free prefix counts
+      transfer.bytesDone = this.getContiguousBytes(transfer)
+      transfer.ranged = false
+    }
+    transfer.streams = undefined
+    // A preallocated file is full-size from the start, so its length says nothing
+    if (transfer.preallocated) return transfer.bytesDone || 0
+
+    if (transfer.bytesDone !== undefined) fs.truncateSync(transfer.filePath, transfer.bytesDone)
+    return fs.statSync(transfer.filePath).size
+  }
+
+  async pause(fileId: string): Promise<boolean> {
+    const queued = this.activeTransfers.get(fileId)
+    if (queued?.status === 'queued') {
+      // Not started yet: leave the queue; resuming asks the receiver for an offset as usual
+      this.dequeue(fileId)
+      const target = this.getControlTarget(fileId)
+      queued.status = 'paused'
+      if (target) target.status = 'paused'
+      this.emitProgress(queued)
+      if (target) await this.sendControl(target, 'FILE_PAUSE')
+      return true
+    }
+
+    const transfer = this.getControlTarget(fileId)
+    if (!transfer || (transfer.status !== 'active' && transfer.status !== 'pending')) return false
+
+    transfer.status = 'paused'
+    let offset: number | undefined
+    if (transfer.direction === 'incoming') {
+      offset = await this.getVerifiedOffset(transfer)
+    } else {
+      this.closeOutgoingStreams(transfer)
+    }
+    this.emitProgress(transfer)
+    await this.sendControl(transfer, 'FILE_PAUSE', offset)
+    return true
+  }
+
+  /**
+   * The receiver always decides where a resumed stream starts. A sender asking to resume
+   * sends FILE_RESUME without an offset and waits for the receiver's reply.
+   */
+  async resume(fileId: string): Promise<boolean> {
+    const transfer = this.getControlTarget(fileId)
+    if (!transfer || (transfer.status !== 'paused' && transfer.status !== 'failed')) return false
+
+    if (transfer.direction === 'incoming') {
+      const offset = await this.getVerifiedOffset(transfer)
+      transfer.status = 'active'
+      this.emitProgress(transfer)
+      await this.sendControl(transfer, 'FILE_RESUME', offset)
+    } else {
+      await this.sendControl(transfer, 'FILE_RESUME')
+    }
+    return true
+  }
+
+  public async handlePause(message: NetworkMessage): Promise<void> {
+    const { fileId } = message.payload as FileControlPayload
+    const transfer = this.activeTransfers.get(fileId)
+    if (!transfer || transfer.status === 'completed' || transfer.status === 'rejected') return
+
+    transfer.status = 'paused'
+    if (transfer.direction === 'incoming') {
+      await this.getVerifiedOffset(transfer)
+    } else {
+      this.closeOutgoingStreams(transfer)
+    }
+    this.emitProgress(transfer)
+  }
+
+  public async handleResume(message: NetworkMessage): Promise<void> {
+    const { fileId, offset } = message.payload as FileControlPayload
+    const transfer = this.activeTransfers.get(fileId)
+    if (!transfer || transfer.status === 'completed' || transfer.status === 'rejected') return
+
+    if (transfer.direction === 'incoming') {
+      // The sender wants to continue; tell it where our copy ends
+      const verified = await this.getVerifiedOffset(transfer)
+      const owner = transfer.folderId ? this.activeTransfers.get(transfer.folderId) : transfer
+      if (owner) owner.resumes = (owner.resumes || 0) + 1
+      transfer.status = 'active'
+      this.emitProgress(transfer)
+      await this.sendControl(transfer, 'FILE_RESUME', verified)
+      return
+    }
+
+    if (offset === undefined || !transfer.filePath) return
+    // Resuming is only for data the receiver already agreed to take
+    const owner = transfer.folderId ? this.activeTransfers.get(transfer.folderId) : transfer
+    if (!owner?.accepted) return
+    // A resumed transfer waits for a slot like a new one
+    this.enqueue({ fileId: transfer.folderId ?? fileId, resumeFrom: { fileId, offset } })
+  }
+
+  /**
+   * Cancels a transfer from either end and tells the peer with FILE_CANCEL, so a sender
+   * stops streaming and a receiver stops waiting. Incomplete received files are removed.
+   */
+  async cancel(fileId: string): Promise<boolean> {
+    const transfer = this.activeTransfers.get(fileId)
+    if (!transfer || TERMINAL_STATUSES.includes(transfer.status)) return false
+
+    // Tell the peer first so a sender sees the cancel rather than a broken stream
+    await this.sendControl(transfer, 'FILE_CANCEL')
+    await this.stopTransfer(transfer)
+    return true
+  }
+
+  // The receiver has written the file up to `offset`; lets more of our stream through
+  public handleAck(message: NetworkMessage): void {
+    const { fileId, offset } = message.payload as FileControlPayload
+    const transfer = this.activeTransfers.get(fileId)
+    if (!transfer || transfer.direction !== 'outgoing' || offset === undefined) return
+    if (message.deviceId !== transfer.deviceId) return
+    for (const stream of transfer.streams || []) stream.ackWindow?.ack(offset)
+  }
+
+  /**
+   * The receiver gave up on one of our transfers for a reason retrying won't fix, so the
+   * transfer fails for good. A folder fails along with the child the error names.
+   */
+  public handlePeerError(deviceId: string, error: ErrorPayload): void {
+    const transfer = error.ref ? this.activeTransfers.get(error.ref) : undefined
+    if (!transfer || transfer.direction !== 'outgoing' || transfer.deviceId !== deviceId) return
+    if (transfer.status === 'cancelled' || transfer.status === 'rejected') return
+
+    const reason = PEER_ERROR_REASONS[error.code] ?? `The receiver reported ${error.code}`
+    const owner = transfer.folderId ? this.activeTransfers.get(transfer.folderId) : undefined
+    for (const t of owner ? [transfer, owner] : [transfer]) {
+      this.dequeue(t.fileId)
+      t.peerError = error.code
+      t.status = 'failed'
+      t.error = reason
+    }
+    // Destroyed with an error so a stream in progress fails instead of waiting to resume
+    for (const entry of transfer.streams || []) entry.socket.destroy(new Error(reason))
+    this.emitProgress(transfer)
+  }
+
+  public async handleCancel(message: NetworkMessage): Promise<void> {
+    const { fileId } = message.payload as FileControlPayload
+    const transfer = this.activeTransfers.get(fileId)
+    // A sender may already have marked the transfer failed when the stream was cut
+    if (!transfer || ['completed', 'rejected', 'cancelled'].includes(transfer.status)) return
+    await this.stopTransfer(transfer)
+  }
+
+  private async stopTransfer(transfer: ActiveTransfer): Promise<void> {
+    const children = (transfer.children || [])
+      .map((id) => this.activeTransfers.get(id))
+      .filter((t): t is ActiveTransfer => !!t && t.status !== 'completed')
+    const stopping = [transfer, ...children]
+
+    this.dequeue(transfer.fileId)
+    for (const t of stopping) t.status = 'cancelled'
+    for (const t of stopping) {
+      if (t.direction === 'outgoing') {
+        this.closeOutgoingStreams(t)
+        continue
+      }
+      await Promise.all((t.streams || []).map((entry) => this.closeIncomingStream(entry)))
+      t.streams = undefined
+      // The folder's own path is the destination directory, which may hold finished files
+      if (t.filePath && !t.children) {
+        await fs.promises.rm(t.filePath, { force: true })
+        await untrackIncomplete(t.fileId)
+      }
+    }
+    this.emitProgress(transfer)
+  }
+
+  private async sendControl(
+    transfer: ActiveTransfer,
+    type: 'FILE_PAUSE' | 'FILE_RESUME' | 'FILE_CANCEL' | 'FILE_ACK',
+    offset?: number
+  ): Promise<void> {
+    const device = discoveryManager
+      .getDiscoveredDevices()
+      .find((d) => d.deviceId === transfer.deviceId)
+    if (!device) return
+    await connectionManager.getConnection(device)
+    // Older peers have no transfer controls; closing the stream is all they understand
+    if (!peerSupports(transfer.deviceId, Capability.FILE_CONTROL)) return
+
+    const message: NetworkMessage = {
+      type,
+      deviceId: getDeviceInfo().deviceId,
+      payload: { fileId: transfer.fileId, offset } as FileControlPayload,
+      id: uuidv4(),
+      timestamp: Date.now()
+    }
+
+    connectionManager.sendMessage(transfer.deviceId, message)
+  }
+
+  // Tells the sender why an incoming transfer failed, if it understands ERROR frames
+  private async sendError(transfer: ActiveTransfer, code: ErrorCode): Promise<void> {
+    const device = discoveryManager
+      .getDiscoveredDevices()
+      .find((d) => d.deviceId === transfer.deviceId)
+    if (!device) return
+    await connectionManager.getConnection(device)
+    const message = createErrorFrame(transfer.deviceId, code, transfer.fileId)
+    if (message) connectionManager.sendMessage(transfer.deviceId, message)
+  }
+
   private async sendAccept(fileId: string, savePath: string): Promise<void> {
     const transfer = this.activeTransfers.get(fileId)
     if (!transfer) return
+    this.clearExpiry(fileId)
 
     const device = discoveryManager
       .getDiscoveredDevices()
       .find((d) => d.deviceId === transfer.deviceId)
     if (!device) return
 
+    const refusal = await this.preflight(transfer, path.dirname(savePath))
+    if (refusal) {
+      transfer.status = 'rejected'
+      transfer.rejection = refusal
+      this.emitProgress(transfer)
+      await this.sendReject(fileId, refusal)
+      return
+    }
+
     transfer.filePath = savePath
+    if (transfer.children) this.prepareFolder(transfer, savePath)
+    if (transfer.metadata?.compression === 'zstd' && isZstdSupported()) {
+      transfer.compression = 'zstd'
+    }
+    const signature = await this.prepareDelta(transfer, savePath)
+    try {
+      await this.preallocate(transfer)
+    } catch (err) {
+      console.error('[FileTransfer] Cannot store incoming file:', err)
+      transfer.status = 'failed'
+      transfer.error = (err as Error).message
+      this.emitProgress(transfer)
+      const outOfSpace = (err as NodeJS.ErrnoException).code === 'ENOSPC'
+      await this.sendReject(fileId, outOfSpace ? { reason: 'no-space' } : undefined)
+      return
+    }
 
     const message: NetworkMessage = {
       type: 'FILE_ACCEPT',
       deviceId: getDeviceInfo().deviceId,
-      payload: { fileId },
+      payload: { fileId, compression: transfer.compression, signature },
       id: uuidv4(),
+      correlationId: transfer.messageId,
       timestamp: Date.now()
     }
+    transfer.accepted = true
 
     await connectionManager.getConnection(device)
     connectionManager.sendMessage(transfer.deviceId, message)
   }
 
-  private async sendReject(fileId: string): Promise<void> {
+  // Incoming transfers that could still write to their files, including resumable failures
+  private getLiveIncomingIds(): Set<string> {
+    const live = [...this.activeTransfers.values()].filter(
+      (t) =>
+        t.direction === 'incoming' && !['completed', 'rejected', 'cancelled'].includes(t.status)
+    )
+    return new Set(live.map((t) => t.fileId))
+  }
+
+  /**
+   * Creates every destination file at its declared size before any data arrives. Where the
+   * filesystem reserves the space, a full disk fails here instead of far into the transfer,
+   * and growing the file in one step lets it be laid out contiguously.
+   */
+  private async preallocate(transfer: ActiveTransfer): Promise<void> {
+    const files = transfer.children
+      ? transfer.children
+          .map((id) => this.activeTransfers.get(id))
+          .filter((t): t is ActiveTransfer => !!t?.filePath)
+      : [transfer]
+    try {
+      for (const file of files) {
+        const handle = await fs.promises.open(file.filePath!, 'w')
+        try {
+          await handle.truncate(file.metadata?.size || 0)
+        } finally {
+          await handle.close()
+        }
+        file.preallocated = true
+        await trackIncomplete(file.fileId, file.filePath!, file.deviceId)
+      }
+    } catch (err) {
+      for (const file of files) {
+        if (file.preallocated) await fs.promises.rm(file.filePath!, { force: true })
+        await untrackIncomplete(file.fileId)
+        file.preallocated = false
+      }
+      throw err
+    }
+  }
+
+  /**
+   * If the sender offered a delta and we already hold a copy of the file, signs that copy
+   * so only changed blocks come over the wire. The new file is built next to the
+   * destination and moved into place when complete, so the copy can be the destination.
+   */
+  private async prepareDelta(
+    transfer: ActiveTransfer,
+    savePath: string
+  ): Promise<DeltaSignature | undefined> {
+    if (!transfer.metadata?.delta || transfer.children) return undefined
+    const basisPath = await findExistingDownload(transfer.deviceId, transfer.metadata.name)
+    if (!basisPath) return undefined
+
+    try {
+      const signature = await computeSignature(basisPath)
+      transfer.deltaBasis = { path: basisPath, blockSize: signature.blockSize }
+      transfer.finalPath = savePath
+      transfer.filePath = `${savePath}.delta-${transfer.fileId}`
+      return signature
+    } catch (err) {
+      console.error('[FileTransfer] Failed to sign existing copy for delta sync:', err)
+      return undefined
+    }
+  }
+
+  // Recreates the directory tree and points each child transfer at its destination
+  private prepareFolder(folder: ActiveTransfer, root: string): void {
+    fs.mkdirSync(root, { recursive: true })
+    for (const dir of folder.metadata?.directories || []) {
+      const target = resolveInside(root, dir)
+      if (target) fs.mkdirSync(target, { recursive: true })
+    }
+    for (const fileId of folder.children || []) {
+      const child = this.activeTransfers.get(fileId)
+      const target = child?.metadata && resolveInside(root, child.metadata.name)
+      if (!child || !target) continue
+      fs.mkdirSync(path.dirname(target), { recursive: true })
+      child.filePath = target
+    }
+    if (!folder.children?.length) this.updateFolderProgress(folder)
+  }
+
+  // Local state is dropped even if the sender is unreachable; it can't be accepted later
+  private async sendReject(fileId: string, rejection?: FileRejection): Promise<void> {
     const transfer = this.activeTransfers.get(fileId)
     if (!transfer) return
+    this.clearExpiry(fileId)
+    for (const childId of transfer.children || []) this.activeTransfers.delete(childId)
+    this.activeTransfers.delete(fileId)
 
     const device = discoveryManager
       .getDiscoveredDevices()
@@ -358,18 +2013,28 @@ class FileTransferManager {
     const message: NetworkMessage = {
       type: 'FILE_REJECT',
       deviceId: getDeviceInfo().deviceId,
-      payload: { fileId },
+      payload: { fileId, ...rejection } as FileRejectPayload,
       id: uuidv4(),
+      correlationId: transfer.messageId,
       timestamp: Date.now()
     }
 
     await connectionManager.getConnection(device)
     connectionManager.sendMessage(transfer.deviceId, message)
-
-    this.activeTransfers.delete(fileId)
   }
 
-  private async startStreaming(fileId: string, filePath: string, deviceId: string): Promise<void> {
+  /**
+   * Streams one file (or, with `end`, the byte range [offset, end) of it) over a dedicated
+   * connection. Only whole-file streams mark the transfer completed; ranged callers do that
+   * once every range is done.
+   */
+  private async startStreaming(
+    fileId: string,
+    filePath: string,
+    deviceId: string,
+    offset = 0,
+    end?: number
+  ): Promise<void> {
     const transfer = this.activeTransfers.get(fileId)
     if (!transfer) return
 
@@ -383,94 +2048,143 @@ class FileTransferManager {
       return
     }
 
-    // Open DEDICATED connection for file stream
-    const socket = new net.Socket({
-      // @ts-expect-error - writableHighWaterMark is missing in some node typings but valid
-      writableHighWaterMark: 4 * 1024 * 1024 // 4MB
-    })
+    if (end === undefined) this.startSession(transfer, offset)
+    const { bufferSize } = await getSocketTuning()
+
+    return new Promise((resolve, reject) => {
+      // Open DEDICATED connection for file stream
+      const udp = udpChannel.isUsable(device)
+      let connected = false
+      const socket = udp
+        ? udpChannel.createStream()
+        : device.bluetooth
+          ? bluetoothTransport.createStream()
+          : new net.Socket({
+              // @ts-expect-error - writableHighWaterMark is missing in some node typings but valid
+              writableHighWaterMark: bufferSize
+            })
+      const entry: TransferStream = {
+        socket,
+        start: offset,
+        end: end ?? (transfer.metadata?.size || 0),
+        written: 0
+      }
+      transfer.streams?.push(entry)
+
+      const folder = transfer.folderId ? this.activeTransfers.get(transfer.folderId) : undefined
+      const compress = (folder ?? transfer).compression === 'zstd' && shouldCompress(filePath)
+      // Resumed and ranged streams carry plain bytes at an offset; only a fresh one is a delta
+      const delta = offset === 0 && end === undefined ? transfer.deltaSignature : undefined
+      // Acks name a single offset, so only an unranged stream is paced by them
+      if (end === undefined && peerSupports(deviceId, Capability.FILE_ACK)) {
+        entry.ackWindow = new AckWindow(offset)
+      }
 
-    socket.connect(device.port, device.address, () => {
-      socket.setNoDelay(true)
-
-      // 1. Send header
-      socket.write(`FILE_STREAM:${fileId}\n`)
-
-      // 2. Generate and send random 16-byte IV
-      const iv = crypto.randomBytes(16)
-      socket.write(iv)
-
-      // 3. Setup encryption stream
-      const encryptionStream = createEncryptionStream(session.sessionKey, iv)
-      const readStream = fs.createReadStream(filePath, { highWaterMark: 256 * 1024 })
-
-      let uploaded = 0
-      const startTime = Date.now()
-
-      encryptionStream.pipe(socket)
-
-      readStream.on('data', (chunk) => {
-        uploaded += chunk.length
-        const now = Date.now()
-        const duration = (now - startTime) / 1000
-        const speed = duration > 0 ? uploaded / duration : 0
-        const progress = uploaded / (transfer.metadata?.size || 1)
-        const eta = speed > 0 ? ((transfer.metadata?.size || 0) - uploaded) / speed : 0
-
-        transfer.progress = progress
-        transfer.speed = speed
-        transfer.eta = eta
-
-        this.mainWindow?.webContents.send('file-transfer-progress', {
-          fileId: transfer.fileId,
-          deviceId: transfer.deviceId,
-          progress: transfer.progress,
-          speed: transfer.speed,
-          eta: transfer.eta,
-          status: transfer.status,
-          name: transfer.metadata?.name,
-          path: transfer.filePath,
-          size: transfer.metadata?.size,
-          direction: 'outgoing'
+      const connect = (onConnect: () => void): void => {
+        if (device.relay) relayClient.connect(socket, device.deviceId, onConnect)
+        else socket.connect(udp ? device.udpPort! : device.port, device.address, onConnect)
+      }
+      connect(() => {
+        connected = true
+        socket.setNoDelay(true)
+        socket.setTimeout(timeoutSettings.ms('bulk'), () => {
+          socket.destroy(new Error(`File stream for ${fileId} stalled`))
         })
-      })
+        transfer.status = 'active'
 
-      readStream.pipe(encryptionStream)
+        // 1. Send header (with the receiver's verified offset when resuming, or the range)
+        let header = `FILE_STREAM:${fileId}`
+        const sealed = peerSupports(deviceId, Capability.SEALED_STREAMS)
+        if (offset > 0 || end !== undefined || compress || delta || sealed) {
+          header += `:${offset}`
+        }
+        if (end !== undefined) header += `-${end}`
+        if (compress) header += ':zstd'
+        if (delta) header += ':delta'
+        if (sealed) header += ':sealed'
+        socket.write(`${header}\n`)
+
+        // 2. Generate and send random 16-byte IV (the key salt for a sealed stream)
+        const iv = crypto.randomBytes(16)
+        socket.write(iv)
+
+        // 3. Setup encryption stream
+        const encryptionStream = sealed
+          ? sealedStream(
+              createSealingStream,
+              transfer,
+              session.sessionKey,
+              iv,
+              getPeerProtocol(deviceId).cipherSuite
+            )
+          : createEncryptionStream(session.sessionKey, iv)
+        // A zipped folder is regenerated from the start and skipped ahead on resume
+        const readStream = transfer.zipEntries
+          ? createZipStream(transfer.zipEntries, offset)
+          : fs.createReadStream(filePath, {
+              highWaterMark: 256 * 1024,
+              start: offset,
+              end: end !== undefined ? end - 1 : undefined // inclusive
+            })
+        // e.g. a file in a zipped folder changed size mid-send; fail the transfer with it
+        readStream.once('error', (err) => socket.destroy(err))
 
-      readStream.on('end', () => {
-        // No need to call encryptionStream.end() if we just piped it
-      })
+        encryptionStream.pipe(socket)
+        encryptionStream.on('data', (chunk: Buffer) => {
+          transfer.wireBytes = (transfer.wireBytes || 0) + chunk.length
+        })
 
-      socket.on('finish', () => {
-        transfer.status = 'completed'
-        this.mainWindow?.webContents.send('file-transfer-progress', {
-          fileId: transfer.fileId,
-          deviceId: transfer.deviceId,
-          progress: 1,
-          speed: transfer.speed,
-          eta: 0,
-          status: 'completed',
-          name: transfer.metadata?.name,
-          path: transfer.filePath,
-          size: transfer.metadata?.size,
-          direction: 'outgoing'
+        readStream.on('data', (chunk) => {
+          entry.written += chunk.length
+          transfer.bytesDone = (transfer.bytesDone || 0) + chunk.length
+          this.updateStreamProgress(transfer, compress)
+        })
+
+        const paced = entry.ackWindow ? readStream.pipe(entry.ackWindow) : readStream
+        const source = delta ? paced.pipe(new DeltaEncoder(delta)) : paced
+        if (compress) {
+          source.pipe(createCompressionStream()).pipe(encryptionStream)
+        } else {
+          source.pipe(encryptionStream)
+        }
+        socket.once('close', () => {
+          readStream.destroy()
+          entry.ackWindow?.destroy()
+        })
+
+        readStream.on('end', () => {
+          // No need to call encryptionStream.end() if we just piped it
+        })
+
+        socket.on('finish', () => {
+          if (end === undefined) {
+            transfer.status = 'completed'
+            transfer.progress = 1
+            transfer.eta = 0
+            transfer.streams = undefined
+            this.emitProgress(transfer)
+          }
+          resolve()
         })
       })
-    })
 
-    socket.on('error', (err) => {
-      console.error('File stream socket error:', err)
-      transfer.status = 'failed'
-      this.mainWindow?.webContents.send('file-transfer-progress', {
-        fileId: transfer.fileId,
-        deviceId: transfer.deviceId,
-        progress: transfer.progress,
-        speed: transfer.speed,
-        eta: transfer.eta,
-        status: 'failed',
-        name: transfer.metadata?.name,
-        path: transfer.filePath,
-        size: transfer.metadata?.size,
-        direction: 'outgoing'
+      // Pausing or cancelling destroys the socket; that ends this stream without a failure
+      socket.on('close', () => {
+        if (isStopped(transfer)) resolve()
+      })
+
+      socket.on('error', (err) => {
+        if (isStopped(transfer)) return
+        // Nothing answered over UDP (e.g. a firewall drops it); the channel now says TCP
+        if (udp && !connected) {
+          transfer.streams = transfer.streams?.filter((s) => s !== entry)
+          this.startStreaming(fileId, filePath, deviceId, offset, end).then(resolve, reject)
+          return
+        }
+        console.error('File stream socket error:', err)
+        transfer.status = 'failed'
+        this.emitProgress(transfer)
+        reject(err)
       })
     })
   }
@@ -0,0 +1,126 @@
+import net from 'net'
+import { getStoreOptions } from './profiles'
+import { unmapAddress } from './addresses'
+
+/**
+ * Where the app listens and who may connect to it, for users who want it tighter than
+ * every address and every peer:
+ * - bindAddress: the address the listeners bind, '' for all of them (used from the next start)
+ * - allowedSubnets: connections only from these, e.g. "192.168.1.0/24"; empty allows any
+ * - lanOnly: connections only from private, link-local and loopback addresses, so peers
+ *   arriving through a relay or overlay network elsewhere are turned away too
+ * Every inbound connection passes through TCPServer, which asks isAllowed() first.
+ */
+
+export interface FirewallSettings {
+  bindAddress: string
+  allowedSubnets: string[]
+  lanOnly: boolean
+}
+
+type Family = 'ipv4' | 'ipv6'
+
+interface Subnet {
+  address: string
+  prefix: number
+  family: Family
+}
+
+const LAN_SUBNETS: [string, number, Family][] = [
+  ['10.0.0.0', 8, 'ipv4'],
+  ['172.16.0.0', 12, 'ipv4'],
+  ['192.168.0.0', 16, 'ipv4'],
+  ['169.254.0.0', 16, 'ipv4'],
+  ['127.0.0.0', 8, 'ipv4'],
+  ['fc00::', 7, 'ipv6'],
+  ['fe80::', 10, 'ipv6'],
+  ['::1', 128, 'ipv6']
+]
+
+const lan = new net.BlockList()
+for (const [address, prefix, family] of LAN_SUBNETS) lan.addSubnet(address, prefix, family)
+
+// "a.b.c.d/n", "v6::/n", or a bare address for just that one
+const parseSubnet = (subnet: string): Subnet => {
+  const [address, prefix] = subnet.trim().split('/')
+  const family = net.isIP(address)
+  const bits = family === 4 ? 32 : 128
+  const length = prefix === undefined ? bits : Number(prefix)
+  if (!family || !Number.isInteger(length) || length < 0 || length > bits) {
+    throw new Error(`Invalid subnet: ${subnet}`)
+  }
+  return { address, prefix: length, family: family === 4 ? 'ipv4' : 'ipv6' }
+}
+
+const buildList = (subnets: string[]): net.BlockList => {
+  const list = new net.BlockList()
+  for (const subnet of subnets) {
+    const { address, prefix, family } = parseSubnet(subnet)
+    list.addSubnet(address, prefix, family)
+  }
+  return list
+}
+
+class Firewall {
+  private settings: FirewallSettings = { bindAddress: '', allowedSubnets: [], lanOnly: false }
+  private allowed = new net.BlockList()
+
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    this.apply({
+      bindAddress: store.get('bindAddress', '') as string,
+      allowedSubnets: store.get('allowedSubnets', []) as string[],
+      lanOnly: store.get('lanOnly', false) as boolean
+    })
+  }
+
+  getSettings(): FirewallSettings {
+    return this.settings
+  }
+
+  getBindAddress(): string | undefined {
+    return this.settings.bindAddress || undefined
+  }
+
+  async setSettings(settings: FirewallSettings): Promise<FirewallSettings> {
+    const bindAddress = settings.bindAddress.trim()
+    if (bindAddress && !net.isIP(bindAddress)) {
+      throw new Error(`Invalid bind address: ${bindAddress}`)
+    }
+    const cleaned = {
+      bindAddress,
+      allowedSubnets: settings.allowedSubnets.map((subnet) => subnet.trim()).filter(Boolean),
+      lanOnly: settings.lanOnly
+    }
+    this.apply(cleaned)
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('bindAddress', cleaned.bindAddress)
+    store.set('allowedSubnets', cleaned.allowedSubnets)
+    store.set('lanOnly', cleaned.lanOnly)
+    return this.settings
+  }
+
+  // Throws on a malformed subnet before anything changes
+  private apply(settings: FirewallSettings): void {
+    this.allowed = buildList(settings.allowedSubnets)
+    this.settings = settings
+  }
+
+  // Whether a connection from `remoteAddress` may go on
+  isAllowed(remoteAddress: string | undefined): boolean {
+    const address = unmapAddress(remoteAddress)?.split('%')[0]
+    const { allowedSubnets, lanOnly } = this.settings
+    if (!lanOnly && allowedSubnets.length === 0) return true
+    // A Bluetooth link only reaches a paired device in radio range
+    if (address?.startsWith('bluetooth:')) return true
+    const version = net.isIP(address || '')
+    if (!address || !version) return false
+    const family: Family = version === 4 ? 'ipv4' : 'ipv6'
+    if (lanOnly && !lan.check(address, family)) return false
+    return allowedSubnets.length === 0 || this.allowed.check(address, family)
+  }
+}
+
+export const firewall = new Firewall()
@@ -0,0 +1,104 @@
+import { randomUUID } from 'crypto'
+import { NetworkMessage } from '@shared/messageTypes'
+import { getDeviceInfo } from './identity'
+import { Capability, peerSupports } from './protocolVersion'
+
+/**
+ * A line longer than MAX_FRAME_SIZE goes out as a run of FRAGMENT frames instead, each
+ * carrying a slice of it, and is put back together before it is decoded. The slices are
+ * of the finished (already encrypted) line, so fragments need no encryption of their own
+ * and the reassembled line is handled like any other.
+ */
+export const MAX_FRAME_SIZE = 1024 * 1024
+// Largest line accepted at all: from peers that can't fragment, or after reassembly
+export const MAX_MESSAGE_SIZE = 64 * 1024 * 1024
+// Characters of the original line per fragment; even fully escaped they stay under the cap
+const FRAGMENT_LENGTH = 128 * 1024
+const MAX_FRAGMENTS = Math.ceil(MAX_MESSAGE_SIZE / FRAGMENT_LENGTH)
+
+interface FragmentPayload {
+  index: number
+  count: number
+  data: string
+}
+
+const isFragment = (raw: unknown): raw is NetworkMessage & { payload: FragmentPayload } =>
+  (raw as NetworkMessage)?.type === 'FRAGMENT'
+
+// Longest line we'll buffer from `deviceId`
+export function getFrameLimit(deviceId: string | null | undefined): number {
+  return deviceId && peerSupports(deviceId, Capability.FRAGMENTS)
+    ? MAX_FRAME_SIZE
+    : MAX_MESSAGE_SIZE
+}
+
+// The lines to write for `line`: just itself unless it is too long and the peer can reassemble
+export function splitFrame(line: string, deviceId: string): string[] {
+  if (Buffer.byteLength(line) <= MAX_FRAME_SIZE || !peerSupports(deviceId, Capability.FRAGMENTS)) {
+    return [line]
+  }
+  const id = randomUUID()
+  const count = Math.ceil(line.length / FRAGMENT_LENGTH)
+  const fragments: string[] = []
+  for (let index = 0; index < count; index++) {
+    const fragment: NetworkMessage = {
+      type: 'FRAGMENT',
+      deviceId: getDeviceInfo().deviceId,
+      id,
+      payload: {
+        index,
+        count,
+        data: line.slice(index * FRAGMENT_LENGTH, (index + 1) * FRAGMENT_LENGTH)
+      }
+    }
+    fragments.push(JSON.stringify(fragment))
+  }
+  return fragments
+}
+
+/**
+ * Reassembles fragmented lines on one connection. Fragments of a line are written back to
+ * back, so they arrive in order and never interleave; anything else is a broken stream.
+ */
+export class FrameAssembler {
+  private pending?: { id: string; count: number; parts: string[]; length: number }
+
+  /**
+   * Returns the frame `raw` stands for: `raw` itself when it isn't a fragment, the
+   * reassembled frame once the last fragment is in, and null until then.
+   */
+  push(raw: unknown): unknown | null {
+    if (!isFragment(raw)) return raw
+
+    const { index, count, data } = raw.payload ?? {}
+    if (index === 0 && typeof raw.id === 'string') {
+      this.pending = { id: raw.id, count, parts: [], length: 0 }
+    }
+    const pending = this.pending
+    if (
+      !pending ||
+      pending.id !== raw.id ||
+      pending.count !== count ||
+      pending.parts.length !== index ||
+      typeof data !== 'string' ||
+      !Number.isInteger(count) ||
+      count > MAX_FRAGMENTS
+    ) {
+      this.pending = undefined
+      throw new Error('Unexpected fragment')
+    }
+
+    pending.parts.push(data)
+    pending.length += data.length
+    if (pending.length > MAX_MESSAGE_SIZE) {
+      this.pending = undefined
+      throw new RangeError('Fragmented frame is too large')
+    }
+    if (pending.parts.length < count) return null
+
+    this.pending = undefined
+    const frame = JSON.parse(pending.parts.join(''))
+    if (isFragment(frame)) throw new Error('Nested fragment')
+    return frame
+  }
+}
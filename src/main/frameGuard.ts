@@ -0,0 +1,129 @@
+import net from 'net'
+import { NetworkMessage } from '@shared/messageTypes'
+import { getStoreOptions } from './profiles'
+import { recordSecurityEvent } from './securityLog'
+
+// A peer may send this many malformed frames within MALFORMED_WINDOW before it is
+// disconnected, and is refused for BAN_DURATION once that has happened BAN_AFTER times
+const MAX_MALFORMED = 10
+const MALFORMED_WINDOW = 60 * 1000
+const BAN_AFTER = 3
+const BAN_DURATION = 10 * 60 * 1000
+
+// Fields that, when present, must be strings for the rest of the app to handle the frame
+const STRING_FIELDS = ['id', 'ackId', 'groupId', 'correlationId'] as const
+
+interface Offences {
+  count: number
+  windowStart: number
+  drops: number
+}
+
+/**
+ * Keeps count of malformed frames per peer, keyed by device ID once a connection has
+ * authenticated and by remote address before that. In strict mode (the default) frames
+ * that are structurally wrong are dropped instead of dispatched, and peers that keep
+ * sending them are disconnected and then banned for a while.
+ */
+class FrameGuard {
+  private strict = true
+  private offences = new Map<string, Offences>()
+  private bans = new Map<string, number>() // key → banned until
+
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    this.strict = store.get('strictFrameParsing', true) as boolean
+  }
+
+  isStrict(): boolean {
+    return this.strict
+  }
+
+  async setStrict(enabled: boolean): Promise<boolean> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('strictFrameParsing', enabled)
+    this.strict = enabled
+    return enabled
+  }
+
+  isBanned(...keys: (string | null | undefined)[]): boolean {
+    const now = Date.now()
+    return keys.some((key) => !!key && (this.bans.get(key) ?? 0) > now)
+  }
+
+  /**
+   * Returns why a decoded frame is malformed, or null if it is fine (always, outside strict
+   * mode). `deviceId` is who the connection authenticated as; a frame may only speak for
+   * that device.
+   */
+  check(message: NetworkMessage, deviceId: string | null): string | null {
+    if (!this.strict) return null
+    if (typeof message !== 'object' || message === null || Array.isArray(message)) {
+      return 'frame is not an object'
+    }
+    if (typeof message.type !== 'string' || !message.type) return 'missing type'
+    if (typeof message.deviceId !== 'string' || !message.deviceId) return 'missing deviceId'
+    if (deviceId && message.deviceId !== deviceId) {
+      return `${message.type} claims to be from ${message.deviceId}`
+    }
+    if (message.timestamp !== undefined && !Number.isFinite(message.timestamp)) {
+      return `${message.type} has an invalid timestamp`
+    }
+    for (const field of STRING_FIELDS) {
+      if (message[field] !== undefined && typeof message[field] !== 'string') {
+        return `${message.type} has an invalid ${field}`
+      }
+    }
+    return null
+  }
+
+  /**
+   * Counts a malformed frame from `key` and, in strict mode, closes `socket` once the peer
+   * has sent too many. A `fatal` problem (one the stream can't recover from) closes it
+   * regardless, and counts as a disconnect straight away. `tag` prefixes log lines.
+   */
+  reportMalformed(
+    socket: net.Socket,
+    key: string | null | undefined,
+    reason: string,
+    tag: string,
+    fatal = false
+  ): void {
+    console.warn(`[${tag}] Malformed frame from ${key ?? 'unknown peer'}: ${reason}`)
+    if (!key) {
+      if (fatal) socket.destroy()
+      return
+    }
+
+    const now = Date.now()
+    let offences = this.offences.get(key)
+    if (!offences) {
+      offences = { count: 0, windowStart: now, drops: 0 }
+      this.offences.set(key, offences)
+    }
+    if (now - offences.windowStart > MALFORMED_WINDOW) {
+      offences.count = 0
+      offences.windowStart = now
+    }
+    offences.count++
+    if (!fatal && (!this.strict || offences.count < MAX_MALFORMED)) return
+
+    console.warn(`[${tag}] Disconnecting ${key} after malformed frames`)
+    socket.destroy()
+    offences.count = 0
+    offences.drops++
+    if (this.strict && offences.drops >= BAN_AFTER) {
+      this.bans.set(key, now + BAN_DURATION)
+      this.offences.delete(key)
+      recordSecurityEvent(
+        'peer-blocked',
+        key,
+        `Banned for ${BAN_DURATION / 60000} minutes after repeated malformed frames`
+      )
+    }
+  }
+}
+
+export const frameGuard = new FrameGuard()
@@ -0,0 +1,327 @@
+import EventEmitter from 'events'
+import { v4 as uuidv4 } from 'uuid'
+import {
+  Group,
+  GroupDeliveryState,
+  NetworkMessage,
+  SealedGroupPayload,
+  SenderKeyPayload
+} from '@shared/messageTypes'
+import { connectionManager } from './protocol'
+import { discoveryManager } from './discovery'
+import { getDeviceInfo } from './identity'
+import { getStoreOptions } from './profiles'
+import { messageStore } from './messageStore'
+import { SessionData, getSession } from './crypto/sessionKey'
+import { SenderKey } from './crypto/senderKey'
+import { Capability, peerSupports } from './protocolVersion'
+
+// Our sender key for one group, and the pairwise session each member was given it over
+interface OwnSenderKey {
+  key: SenderKey
+  sharedWith: Map<string, SessionData>
+}
+
+class GroupManager extends EventEmitter {
+  private groups: Map<string, Group> = new Map()
+  // messageId -> per-member delivery state for group messages we sent
+  private deliveries: Map<string, { groupId: string; state: GroupDeliveryState }> = new Map()
+  // Sender keys (see crypto/senderKey.ts): ours by groupId, members' by `${groupId}:${deviceId}`
+  private ownKeys: Map<string, OwnSenderKey> = new Map()
+  private memberKeys: Map<string, SenderKey> = new Map()
+
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    const saved = store.get('groups', []) as Group[]
+    this.groups = new Map(saved.map((g) => [g.groupId, g]))
+    for (const { key } of this.ownKeys.values()) key.destroy()
+    for (const key of this.memberKeys.values()) key.destroy()
+    this.ownKeys.clear()
+    this.memberKeys.clear()
+  }
+
+  private async save(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('groups', [...this.groups.values()])
+  }
+
+  getGroups(): Group[] {
+    return [...this.groups.values()]
+  }
+
+  getGroup(groupId: string): Group | undefined {
+    return this.groups.get(groupId)
+  }
+
+  async createGroup(name: string, memberIds: string[]): Promise<Group> {
+    const localId = getDeviceInfo().deviceId
+    const group: Group = {
+      groupId: uuidv4(),
+      name,
+      members: [...new Set([localId, ...memberIds])],
+      createdBy: localId,
+      createdAt: Date.now()
+    }
+    this.groups.set(group.groupId, group)
+    await this.save()
+    await this.announce(group, group.members)
+    return group
+  }
+
+  async addMember(groupId: string, deviceId: string): Promise<Group> {
+    const group = this.requireGroup(groupId)
+    if (!group.members.includes(deviceId)) {
+      group.members = [...group.members, deviceId]
+      this.rekey(group)
+      await this.save()
+    }
+    await this.announce(group, group.members)
+    return group
+  }
+
+  async removeMember(groupId: string, deviceId: string): Promise<Group> {
+    const group = this.requireGroup(groupId)
+    const previousMembers = group.members
+    group.members = group.members.filter((id) => id !== deviceId)
+    if (deviceId === getDeviceInfo().deviceId) {
+      this.groups.delete(groupId)
+      group.members = []
+    }
+    this.rekey(group)
+    await this.save()
+    // The removed member is told too, so it drops the group on its side
+    await this.announce(group, previousMembers)
+    return group
+  }
+
+  /**
+   * Applies a GROUP_UPDATE received from another member. A group we already hold may only
+   * be changed by one of its members (or its creator); one we don't is only taken from a
+   * sender that lists itself in it, as an invitation.
+   */
+  async handleGroupUpdate(message: NetworkMessage): Promise<void> {
+    const update = message.payload as Partial<Group> | undefined
+    if (
+      typeof update?.groupId !== 'string' ||
+      !Array.isArray(update.members) ||
+      !update.members.every((id) => typeof id === 'string')
+    ) {
+      console.warn(`[Groups] Ignoring a malformed group update from ${message.deviceId}`)
+      return
+    }
+    const existing = this.groups.get(update.groupId)
+    const allowed = existing
+      ? existing.members.includes(message.deviceId) || existing.createdBy === message.deviceId
+      : update.members.includes(message.deviceId)
+    if (!allowed) {
+      console.warn(`[Groups] Ignoring an update to ${update.groupId} from ${message.deviceId}`)
+      return
+    }
+    const group: Group = {
+      groupId: update.groupId,
+      name: typeof update.name === 'string' ? update.name : (existing?.name ?? ''),
+      members: [...new Set(update.members)],
+      // Who made it and when don't change after the fact
+      createdBy: existing?.createdBy ?? message.deviceId,
+      createdAt: existing?.createdAt ?? (Number(update.createdAt) || Date.now())
+    }
+    if (group.members.includes(getDeviceInfo().deviceId)) {
+      const previous = this.groups.get(group.groupId)?.members ?? []
+      const changed =
+        previous.length !== group.members.length ||
+        previous.some((id) => !group.members.includes(id))
+      this.groups.set(group.groupId, group)
+      if (changed) this.rekey(group)
+    } else {
+      this.groups.delete(group.groupId)
+      this.rekey({ ...group, members: [] })
+    }
+    await this.save()
+    this.emit('groupUpdated', group)
+  }
+
+  /**
+   * Fans a message out to every other member over that member's own connection. Members
+   * that support sender keys all get the same ciphertext, after our current sender key if
+   * they haven't had it over their current session; older ones get the text.
+   */
+  async sendGroupMessage(
+    groupId: string,
+    payload: string,
+    replyTo?: string
+  ): Promise<{ message: NetworkMessage; state: GroupDeliveryState }> {
+    const group = this.requireGroup(groupId)
+    const message: NetworkMessage = {
+      type: 'GROUP_MESSAGE',
+      deviceId: getDeviceInfo().deviceId,
+      groupId,
+      id: uuidv4(),
+      payload,
+      timestamp: Date.now(),
+      replyTo
+    }
+
+    const state: GroupDeliveryState = {}
+    const reached: string[] = []
+    await Promise.all(
+      this.otherMembers(group).map(async (memberId) => {
+        if (await this.connect(memberId)) reached.push(memberId)
+        else state[memberId] = 'failed'
+      })
+    )
+    const sealing = reached.filter((id) => peerSupports(id, Capability.SENDER_KEYS))
+    let sealed: NetworkMessage | undefined
+    if (sealing.length > 0) {
+      const own = this.getOwnKey(group)
+      // The key has to go out as it stands before the message moves its chain on
+      for (const memberId of sealing) this.shareKey(own, memberId)
+      sealed = { ...message, payload: own.key.seal(payload) }
+    }
+    for (const memberId of reached) {
+      connectionManager.sendMessage(memberId, sealing.includes(memberId) ? sealed! : message)
+      state[memberId] = 'sent'
+    }
+    this.deliveries.set(message.id!, { groupId, state })
+    messageStore.add(groupId, message)
+    return { message, state }
+  }
+
+  /**
+   * Keeps a sender key a member sent us over our pairwise session.
+   */
+  handleSenderKey(message: NetworkMessage): void {
+    const key = SenderKey.fromPayload(message.payload)
+    const group = key && this.groups.get(key.groupId)
+    if (!key || !group?.members.includes(message.deviceId)) {
+      key?.destroy()
+      console.warn(`[Groups] Ignoring a sender key from ${message.deviceId}`)
+      return
+    }
+    const id = `${key.groupId}:${message.deviceId}`
+    this.memberKeys.get(id)?.destroy()
+    this.memberKeys.set(id, key)
+  }
+
+  /**
+   * A received GROUP_MESSAGE with its text decrypted, or null if it can't be: its sender
+   * isn't a member, we don't have the key it was sent with, or it doesn't authenticate.
+   * Text in the clear is only taken from members that don't support sender keys.
+   */
+  openGroupMessage(message: NetworkMessage): NetworkMessage | null {
+    const group = message.groupId ? this.groups.get(message.groupId) : undefined
+    if (!group?.members.includes(message.deviceId)) return null
+    if (typeof message.payload === 'string') {
+      return peerSupports(message.deviceId, Capability.SENDER_KEYS) ? null : message
+    }
+    const sealed = message.payload as SealedGroupPayload
+    const key = this.memberKeys.get(`${group.groupId}:${message.deviceId}`)
+    if (!key || key.keyId !== sealed?.keyId) return null
+    try {
+      return { ...message, payload: key.open(sealed, message.deviceId) }
+    } catch (e) {
+      console.error(`[Groups] Failed to decrypt a message from ${message.deviceId}:`, e)
+      return null
+    }
+  }
+
+  /**
+   * Records a delivery ack. Returns the updated state if the ack was for a group message.
+   */
+  recordDelivery(
+    messageId: string,
+    deviceId: string
+  ): { groupId: string; state: GroupDeliveryState } | undefined {
+    const entry = this.deliveries.get(messageId)
+    if (!entry || !(deviceId in entry.state)) return undefined
+    entry.state[deviceId] = 'delivered'
+    return entry
+  }
+
+  private async announce(group: Group, recipients: string[]): Promise<void> {
+    const message: NetworkMessage = {
+      type: 'GROUP_UPDATE',
+      deviceId: getDeviceInfo().deviceId,
+      id: uuidv4(),
+      payload: group,
+      timestamp: Date.now()
+    }
+    const localId = getDeviceInfo().deviceId
+    await Promise.all(
+      recipients.filter((id) => id !== localId).map((id) => this.sendTo(id, message))
+    )
+  }
+
+  private async sendTo(deviceId: string, message: NetworkMessage): Promise<boolean> {
+    if (!(await this.connect(deviceId))) return false
+    connectionManager.sendMessage(deviceId, message)
+    return true
+  }
+
+  private async connect(deviceId: string): Promise<boolean> {
+    const device = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
+    if (!device || !device.isOnline) return false
+    try {
+      await connectionManager.getConnection(device)
+      return true
+    } catch (e) {
+      console.error(`[Groups] Failed to reach member ${deviceId}:`, e)
+      return false
+    }
+  }
+
+  private getOwnKey(group: Group): OwnSenderKey {
+    let own = this.ownKeys.get(group.groupId)
+    if (!own) {
+      own = { key: SenderKey.generate(group.groupId), sharedWith: new Map() }
+      this.ownKeys.set(group.groupId, own)
+    }
+    return own
+  }
+
+  // A member that restarted or reconnected since it was given the key may no longer have it
+  private shareKey(own: OwnSenderKey, memberId: string): void {
+    const session = getSession(memberId)
+    if (!session || own.sharedWith.get(memberId) === session) return
+    const message: NetworkMessage = {
+      type: 'SENDER_KEY',
+      deviceId: getDeviceInfo().deviceId,
+      id: uuidv4(),
+      payload: own.key.toPayload() as SenderKeyPayload,
+      timestamp: Date.now()
+    }
+    connectionManager.sendMessage(memberId, message)
+    own.sharedWith.set(memberId, session)
+  }
+
+  /**
+   * Called whenever the members of `group` change: our key is dropped, so the next message
+   * goes out on a new one only the current members get, and keys of members who left are
+   * forgotten.
+   */
+  private rekey(group: Group): void {
+    this.ownKeys.get(group.groupId)?.key.destroy()
+    this.ownKeys.delete(group.groupId)
+    const prefix = `${group.groupId}:`
+    for (const [id, key] of this.memberKeys) {
+      if (id.startsWith(prefix) && !group.members.includes(id.slice(prefix.length))) {
+        key.destroy()
+        this.memberKeys.delete(id)
+      }
+    }
+  }
+
+  private otherMembers(group: Group): string[] {
+    const localId = getDeviceInfo().deviceId
+    return group.members.filter((id) => id !== localId)
+  }
+
+  private requireGroup(groupId: string): Group {
+    const group = this.groups.get(groupId)
+    if (!group) throw new Error('Group not found')
+    return group
+  }
+}
+
+export const groupManager = new GroupManager()
@@ -0,0 +1,138 @@
+import net from 'net'
+import { getDeviceInfo } from './identity'
+import { RelayReply, readReply, relayClient, send } from './relay'
+import { tcpServer } from './tcpServer'
+import { isProxyEnabled } from './proxy'
+
+/**
+ * Direct connections between peers that are both behind NAT, set up over the relay with
+ * TCP simultaneous open. Each end connects to the relay from a fresh port, and the relay
+ * tells each the public address and port it saw for the other. Both then connect from
+ * that same port to the other's: the outgoing SYNs open each NAT for the other's, and the
+ * two attempts meet as a single connection. If the NATs won't allow it, the connection
+ * goes through the relay as before.
+ *
+ * On the relay:
+ * - dialing: we send `{op: 'punch', from, to}` on a new connection, and the relay passes
+ *   `{op: 'punch', session}` to the peer on its control connection
+ * - answering: the peer sends `{op: 'punch-accept', session}` on a new connection
+ * Both get `{op: 'punch-peer', address, port}` (or `{op: 'error', message}`), after which
+ * the relay closes those connections.
+ */
+
+// How long the whole attempt may take before we fall back to the relay
+const PUNCH_TIMEOUT = 5000
+const RETRY_INTERVAL = 250
+// A peer we couldn't reach directly isn't tried again for this long (ms)
+const FAILURE_BACKOFF = 10 * 60 * 1000
+
+interface Endpoint {
+  address: string
+  port: number
+}
+
+// Ours (the port to connect from) and the other end's public one (to connect to)
+interface Endpoints {
+  local: Endpoint
+  remote: Endpoint
+}
+
+const failures: Map<string, number> = new Map()
+
+// Sends `message` to the relay from a new port and waits for the other end's endpoint
+function exchangeEndpoints(message: object): Promise<Endpoints | null> {
+  const target = relayClient.getTarget()
+  if (!target) return Promise.resolve(null)
+
+  return new Promise((resolve) => {
+    let result: Endpoints | null = null
+    const socket = net.connect(target.port, target.host, () => {
+      const local = { address: socket.localAddress!, port: socket.localPort! }
+      send(socket, message)
+      readReply(socket, (reply: RelayReply | null) => {
+        if (reply?.op === 'punch-peer' && reply.address && reply.port) {
+          result = { local, remote: { address: reply.address, port: reply.port } }
+        } else if (reply?.message) {
+          console.log('[Punch] Relay could not arrange a direct connection:', reply.message)
+        }
+        // Let the relay close first, so the port isn't left in TIME_WAIT on our side
+        socket.end()
+      })
+    })
+    socket.setTimeout(PUNCH_TIMEOUT, () => socket.destroy())
+    socket.on('error', (err) => console.warn('[Punch] Relay connection error:', err.message))
+    socket.on('close', () => resolve(result))
+  })
+}
+
+// Connects from `local` to `remote` until it works or the time is up
+function simultaneousOpen(local: Endpoint, remote: Endpoint): Promise<net.Socket | null> {
+  return new Promise((resolve) => {
+    let socket: net.Socket | undefined
+    let done = false
+    const finish = (result: net.Socket | null): void => {
+      if (done) return
+      done = true
+      clearTimeout(timer)
+      resolve(result)
+    }
+    const timer = setTimeout(() => {
+      socket?.destroy()
+      finish(null)
+    }, PUNCH_TIMEOUT)
+
+    const attempt = (): void => {
+      if (done) return
+      const current = net.connect({
+        host: remote.address,
+        port: remote.port,
+        localAddress: local.address,
+        localPort: local.port
+      })
+      socket = current
+      current.once('connect', () => {
+        current.removeAllListeners('error')
+        finish(current)
+      })
+      // Refused or reset until the other NAT has opened; try again shortly
+      current.once('error', () => {
+        current.destroy()
+        setTimeout(attempt, RETRY_INTERVAL)
+      })
+    }
+    attempt()
+  })
+}
+
+/**
+ * Tries to open a direct connection to a peer we know through the relay. Resolves with
+ * the connected socket, or null if the caller should go through the relay.
+ */
+export async function punch(deviceId: string): Promise<net.Socket | null> {
+  // Behind a proxy, connections can only go out through it
+  if (isProxyEnabled()) return null
+  if (Date.now() - (failures.get(deviceId) ?? 0) < FAILURE_BACKOFF) return null
+
+  const endpoints = await exchangeEndpoints({
+    op: 'punch',
+    from: getDeviceInfo().deviceId,
+    to: deviceId
+  })
+  const socket = endpoints && (await simultaneousOpen(endpoints.local, endpoints.remote))
+  if (!socket) {
+    console.log(`[Punch] No direct connection to ${deviceId}, using the relay`)
+    failures.set(deviceId, Date.now())
+    return null
+  }
+  console.log(`[Punch] Direct connection to ${deviceId} via ${socket.remoteAddress}`)
+  failures.delete(deviceId)
+  return socket
+}
+
+// The other end of punch(), on the relay's request; what connects is served like a LAN peer
+export async function answerPunch(session: string): Promise<void> {
+  if (isProxyEnabled()) return
+  const endpoints = await exchangeEndpoints({ op: 'punch-accept', session })
+  const socket = endpoints && (await simultaneousOpen(endpoints.local, endpoints.remote))
+  if (socket) tcpServer.handleConnection(socket)
+}
@@ -0,0 +1,178 @@
+import fs from 'fs'
+import os from 'os'
+import path from 'path'
+import { execFile } from 'child_process'
+import { promisify } from 'util'
+import { randomInt } from 'crypto'
+import { discoveryManager } from './discovery'
+import { getDeviceInfo } from './identity'
+
+/**
+ * Pairing where there is no shared network: one device turns its Wi-Fi into a hotspot and
+ * shows how to join it, the other joins, and from there discovery and transfers run over
+ * that link as on any LAN. The join parameters are a standard Wi-Fi QR payload
+ * (`WIFI:T:WPA;S:<ssid>;P:<password>;;`), so a phone's camera can join it as well.
+ *
+ * There is no cross-platform API for this, so it drives each OS's own tools: NetworkManager
+ * on Linux, netsh on Windows. macOS can join a hotspot but offers no way to create one.
+ */
+
+const run = promisify(execFile)
+
+const CONNECTION_NAME = 'HyperConnect-Hotspot'
+// Unambiguous characters only; the password may have to be typed in from the screen
+const PASSWORD_ALPHABET = 'abcdefghjkmnpqrstuvwxyzABCDEFGHJKLMNPQRSTUVWXYZ23456789'
+const PASSWORD_LENGTH = 12
+
+export interface HotspotInfo {
+  ssid: string
+  password: string
+  qrPayload: string
+}
+
+let active: HotspotInfo | null = null
+
+// Backslash-escapes the characters the Wi-Fi QR format reserves
+const escapeField = (value: string): string => value.replace(/([\\;,:"])/g, '\\$1')
+
+export const toQrPayload = (ssid: string, password: string): string =>
+  `WIFI:T:WPA;S:${escapeField(ssid)};P:${escapeField(password)};;`
+
+// Reads the SSID and password back out of a Wi-Fi QR payload
+export function parseQrPayload(payload: string): { ssid: string; password: string } | null {
+  if (!payload.startsWith('WIFI:')) return null
+  const fields: Record<string, string> = {}
+  for (const match of payload.slice(5).matchAll(/([A-Z]+):((?:\\.|[^;\\])*);/g)) {
+    fields[match[1]] = match[2].replace(/\\(.)/g, '$1')
+  }
+  if (!fields.S) return null
+  return { ssid: fields.S, password: fields.P ?? '' }
+}
+
+export function getHotspot(): HotspotInfo | null {
+  return active
+}
+
+export async function startHotspot(): Promise<HotspotInfo> {
+  if (active) return active
+  const ssid = `HyperConnect-${getDeviceInfo().deviceId.slice(0, 4).toUpperCase()}`
+  let password = ''
+  for (let i = 0; i < PASSWORD_LENGTH; i++) {
+    password += PASSWORD_ALPHABET[randomInt(PASSWORD_ALPHABET.length)]
+  }
+
+  switch (process.platform) {
+    case 'linux':
+      await run('nmcli', [
+        'device',
+        'wifi',
+        'hotspot',
+        'con-name',
+        CONNECTION_NAME,
+        'ssid',
+        ssid,
+        'password',
+        password
+      ])
+      break
+    case 'win32':
+      await run('netsh', [
+        'wlan',
+        'set',
+        'hostednetwork',
+        'mode=allow',
+        `ssid=${ssid}`,
+        `key=${password}`
+      ])
+      await run('netsh', ['wlan', 'start', 'hostednetwork'])
+      break
+    default:
+      throw new Error('Creating a hotspot is not supported here; join one from another device')
+  }
+
+  active = { ssid, password, qrPayload: toQrPayload(ssid, password) }
+  console.log(`[Hotspot] Started ${ssid}`)
+  // Announce ourselves on the new interface too
+  discoveryManager.updateAdvertisement(getDeviceInfo())
+  return active
+}
+
+export async function stopHotspot(): Promise<void> {
+  if (!active) return
+  active = null
+  try {
+    if (process.platform === 'linux') {
+      await run('nmcli', ['connection', 'delete', CONNECTION_NAME])
+    } else if (process.platform === 'win32') {
+      await run('netsh', ['wlan', 'stop', 'hostednetwork'])
+    }
+    console.log('[Hotspot] Stopped')
+  } catch (e) {
+    console.error('[Hotspot] Failed to stop hotspot:', e)
+  }
+}
+
+// The name of the Wi-Fi interface on macOS, usually but not always en0
+async function getMacWifiDevice(): Promise<string> {
+  const { stdout } = await run('networksetup', ['-listallhardwareports'])
+  const match = /Hardware Port: Wi-Fi\s*\nDevice: (\S+)/.exec(stdout)
+  return match?.[1] ?? 'en0'
+}
+
+/**
+ * Joins the hotspot described by a Wi-Fi QR payload, then looks for peers on it. Returns
+ * the SSID joined.
+ */
+export async function joinHotspot(payload: string): Promise<string> {
+  const network = parseQrPayload(payload.trim())
+  if (!network) throw new Error('Not a Wi-Fi QR payload')
+  const { ssid, password } = network
+
+  switch (process.platform) {
+    case 'linux':
+      await run('nmcli', ['device', 'wifi', 'connect', ssid, 'password', password])
+      break
+    case 'darwin':
+      await run('networksetup', ['-setairportnetwork', await getMacWifiDevice(), ssid, password])
+      break
+    case 'win32': {
+      // netsh only connects to networks it has a profile for
+      const escape = (value: string): string =>
+        value.replace(/&/g, '&amp;').replace(/</g, '&lt;').replace(/>/g, '&gt;')
+      const profile = `<?xml version="1.0"?>
+<WLANProfile xmlns="http://www.microsoft.com/networking/WLAN/profile/v1">
+  <name>${escape(ssid)}</name>
+  <SSIDConfig><SSID><name>${escape(ssid)}</name></SSID></SSIDConfig>
+  <connectionType>ESS</connectionType>
+  <connectionMode>manual</connectionMode>
+  <MSM><security>
+    <authEncryption>
+      <authentication>WPA2PSK</authentication>
+      <encryption>AES</encryption>
+      <useOneX>false</useOneX>
+    </authEncryption>
+    <sharedKey>
+      <keyType>passPhrase</keyType>
+      <protected>false</protected>
+      <keyMaterial>${escape(password)}</keyMaterial>
+    </sharedKey>
+  </security></MSM>
+</WLANProfile>`
+      const file = path.join(os.tmpdir(), `hyperconnect-wlan-${Date.now()}.xml`)
+      fs.writeFileSync(file, profile, { mode: 0o600 })
+      try {
+        await run('netsh', ['wlan', 'add', 'profile', `filename=${file}`])
+      } finally {
+        fs.rmSync(file, { force: true })
+      }
+      await run('netsh', ['wlan', 'connect', `name=${ssid}`])
+      break
+    }
+    default:
+      throw new Error(`Joining a hotspot is not supported on ${process.platform}`)
+  }
+
+  console.log(`[Hotspot] Joined ${ssid}`)
+  discoveryManager.rescan()
+  return ssid
+}
@@ -4,21 +4,63 @@ import fs from 'fs'
 import path from 'path'
 import os from 'os'
 import { DeviceInfo } from '@shared/messageTypes'
+import { getProfileDataPath } from './profiles'
 
-const CONFIG_PATH = path.join(app.getPath('userData'), 'device-config.json')
+// Each profile has its own identity, so the path follows the active profile
+const getConfigPath = (): string => path.join(getProfileDataPath(), 'device-config.json')
 
 interface DeviceConfig {
   deviceId?: string
   displayName?: string
   profileImage?: string
+  statusMessage?: string
+}
+
+// Guest identity lives only in memory. It is never written to disk and
+// is gone as soon as the process exits.
+let guestConfig: DeviceConfig | null = null
+
+export function isGuestMode(): boolean {
+  return guestConfig !== null
+}
+
+/**
+ * Switches to a temporary random identity for use on shared/public machines.
+ * Session keys are already ephemeral, so only the device ID and profile need swapping.
+ */
+export function enableGuestMode(displayName?: string): DeviceInfo {
+  guestConfig = {
+    deviceId: uuidv4(),
+    displayName: displayName || `Guest-${Math.floor(Math.random() * 10000)}`
+  }
+  console.log(`[Identity] Guest mode enabled as ${guestConfig.displayName}`)
+  return getDeviceInfo()
+}
+
+export function disableGuestMode(): DeviceInfo {
+  guestConfig = null
+  console.log('[Identity] Guest mode disabled')
+  return getDeviceInfo()
 }
 
 export function getDeviceInfo(): DeviceInfo {
+  if (guestConfig) {
+    return {
+      deviceId: guestConfig.deviceId!,
+      displayName: guestConfig.displayName!,
+      profileImage: guestConfig.profileImage,
+      statusMessage: guestConfig.statusMessage,
+      platform: process.platform,
+      appVersion: app.getVersion(),
+      isGuest: true
+    }
+  }
+
   let config: DeviceConfig = {}
 
-  if (fs.existsSync(CONFIG_PATH)) {
+  if (fs.existsSync(getConfigPath())) {
     try {
-      config = JSON.parse(fs.readFileSync(CONFIG_PATH, 'utf-8'))
+      config = JSON.parse(fs.readFileSync(getConfigPath(), 'utf-8'))
     } catch (e) {
       console.error('Failed to parse device config:', e)
     }
@@ -38,22 +80,24 @@ export function getDeviceInfo(): DeviceInfo {
     deviceId: config.deviceId,
     displayName: config.displayName,
     profileImage: config.profileImage,
+    statusMessage: config.statusMessage,
     platform: process.platform,
     appVersion: app.getVersion()
   }
 }
 
-export function updateProfile(name?: string, image?: string): void {
-  const config = loadConfig()
+export function updateProfile(name?: string, image?: string, statusMessage?: string): void {
+  const config = guestConfig ?? loadConfig()
   if (name !== undefined) config.displayName = name
   if (image !== undefined) config.profileImage = image
-  saveConfig(config)
+  if (statusMessage !== undefined) config.statusMessage = statusMessage
+  if (!guestConfig) saveConfig(config)
 }
 
 function loadConfig(): DeviceConfig {
-  if (fs.existsSync(CONFIG_PATH)) {
+  if (fs.existsSync(getConfigPath())) {
     try {
-      return JSON.parse(fs.readFileSync(CONFIG_PATH, 'utf-8'))
+      return JSON.parse(fs.readFileSync(getConfigPath(), 'utf-8'))
     } catch (e) {
       console.error('Failed to load config:', e)
     }
@@ -63,7 +107,7 @@ function loadConfig(): DeviceConfig {
 
 function saveConfig(config: DeviceConfig): void {
   try {
-    fs.writeFileSync(CONFIG_PATH, JSON.stringify(config, null, 2))
+    fs.writeFileSync(getConfigPath(), JSON.stringify(config, null, 2))
   } catch (e) {
     console.error('Failed to save config:', e)
   }
@@ -0,0 +1,59 @@
+import { FileMetadata, InboxThread, NetworkMessage } from '@shared/messageTypes'
+import { discoveryManager } from './discovery'
+import { getDeviceInfo } from './identity'
+import { groupManager } from './groups'
+import { messageStore } from './messageStore'
+
+const SNIPPET_LENGTH = 80
+
+function getSnippet(message: NetworkMessage): string {
+  if (message.deleted) return 'This message was deleted'
+  if (message.type === 'FILE_META') {
+    const metadata = message.payload as FileMetadata
+    return metadata?.sensitive ? '🔒 Sensitive file' : `📎 ${metadata?.name || 'File'}`
+  }
+  const text = typeof message.payload === 'string' ? message.payload : ''
+  const flat = text.replace(/\s+/g, ' ').trim()
+  return flat.length > SNIPPET_LENGTH ? `${flat.slice(0, SNIPPET_LENGTH - 1)}…` : flat
+}
+
+/**
+ * One entry per conversation (peers we've seen plus groups we belong to), newest
+ * activity first, so the conversation list can render from a single call.
+ */
+export function getInboxSummary(): InboxThread[] {
+  const localId = getDeviceInfo().deviceId
+  const devices = new Map(discoveryManager.getDiscoveredDevices().map((d) => [d.deviceId, d]))
+  const groups = new Map(groupManager.getGroups().map((g) => [g.groupId, g]))
+  const ids = new Set([...messageStore.getConversationIds(), ...devices.keys(), ...groups.keys()])
+
+  const threads: InboxThread[] = []
+  for (const conversationId of ids) {
+    const messages = messageStore.getConversation(conversationId)
+    const last = messages[messages.length - 1]
+    const unreadCount = messages.filter(
+      (m) => m.deviceId !== localId && m.status !== 'read' && !m.deleted
+    ).length
+    const group = groups.get(conversationId)
+    const device = devices.get(conversationId)
+
+    threads.push({
+      conversationId,
+      isGroup: !!group,
+      displayName: group?.name || device?.displayName || conversationId,
+      unreadCount,
+      // A group counts as online when any other member is reachable
+      isOnline: group
+        ? group.members.some((id) => id !== localId && devices.get(id)?.isOnline)
+        : !!device?.isOnline,
+      lastMessage: last && {
+        id: last.id,
+        snippet: getSnippet(last),
+        timestamp: last.timestamp || 0,
+        fromSelf: last.deviceId === localId
+      }
+    })
+  }
+
+  return threads.sort((a, b) => (b.lastMessage?.timestamp || 0) - (a.lastMessage?.timestamp || 0))
+}
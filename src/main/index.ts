@@ -2,10 +2,24 @@ import { app, shell, BrowserWindow } from 'electron'
 import { join } from 'path'
 import { electronApp, optimizer, is } from '@electron-toolkit/utils'
 import icon from '../../resources/icon.png?asset'
-import { getDeviceInfo } from './identity'
+import { getDeviceInfo, enableGuestMode } from './identity'
 import { tcpServer } from './tcpServer'
+import { tlsTransport } from './tlsTransport'
+import { relayClient } from './relay'
+import { stopHotspot } from './hotspot'
+import { webSocketBridge } from './webSocketBridge'
+import { listenerEndpoints } from './endpoints'
+import { firewall } from './firewall'
+import { connectionManager } from './protocol'
 import { discoveryManager } from './discovery'
+import { fileTransferManager } from './fileTransfer'
+import { networkMonitor } from './networkMonitor'
+import { reconnectManager } from './reconnect'
+import { overlayNetwork } from './overlay'
+import { udpChannel } from './udpChannel'
+import { bluetoothTransport } from './bluetooth'
 import { setupIpc } from './ipc'
+import { closeSecurityLog } from './securityLog'
 import { initAutoUpdater, checkForUpdates } from './autoUpdater'
 let mainWindow: BrowserWindow
 function createWindow(): void {
@@ -53,16 +67,39 @@ app.whenReady().then(async () => {
   setupIpc(mainWindow) // Set up IPC listeners first
 
   try {
+    // `--guest` starts with a temporary identity that is never persisted
+    if (app.commandLine.hasSwitch('guest')) {
+      enableGuestMode()
+    }
     const deviceInfo = getDeviceInfo()
+    // The listeners bind the configured address, so that has to be known before they start
+    await firewall.load().catch((err) => console.error('Failed to load firewall settings:', err))
     console.log('Got device info, starting TCP server...')
     const port = await tcpServer.start()
-    console.log(`TCP server started on port ${port}, executing startDiscovery...`)
+    // TLS is optional; peers can still reach us over plain TCP without it
+    const tlsPort = await tlsTransport.start().catch((err) => {
+      console.error('Failed to start TLS listener:', err)
+      return undefined
+    })
+    console.log(`TCP server started on port ${port} (TLS ${tlsPort}), executing startDiscovery...`)
 
-    discoveryManager.startDiscovery(deviceInfo, port) // Now safe to start discovery
+    discoveryManager.startDiscovery(deviceInfo, port, tlsPort) // Now safe to start discovery
     console.log('Discovery started.')
 
     discoveryManager.startHeartbeat() // Start the presence heartbeat check
     console.log('Heartbeat started.')
+    reconnectManager.start() // Retry peers whose connection drops while they're still around
+
+    // Moving between networks (Ethernet to Wi-Fi, say) strands connections on the old one
+    networkMonitor.on('change', (_added: string[], lost: string[]) => {
+      connectionManager.dropConnectionsOn(lost)
+      tcpServer.dropConnectionsOn(lost)
+      relayClient.dropConnectionsOn(lost)
+      fileTransferManager.migrate(lost)
+      // Announce ourselves on whatever interfaces we have now
+      discoveryManager.updateAdvertisement(getDeviceInfo())
+    })
+    networkMonitor.start()
 
     // Initialize auto-updater
     initAutoUpdater(mainWindow)
@@ -97,7 +134,19 @@ app.whenReady().then(async () => {
 app.on('will-quit', () => {
   console.log('App quitting, stopping services...')
   discoveryManager.stop()
+  networkMonitor.stop()
+  connectionManager.disconnectAll()
   tcpServer.stop()
+  tlsTransport.stop()
+  relayClient.stop()
+  overlayNetwork.stop()
+  udpChannel.stop()
+  bluetoothTransport.stop()
+  stopHotspot()
+  webSocketBridge.stop()
+  listenerEndpoints.stop()
+  // Last, as stopping the rest may have logged something
+  closeSecurityLog()
 })
 // Ensure cleanup on SIGINT/terminal close
 process.on('SIGINT', () => {
@@ -1,20 +1,108 @@
 import { ipcMain, BrowserWindow } from 'electron'
 import net from 'net'
-import { getDeviceInfo, updateProfile } from './identity'
+import fs from 'fs'
+import {
+  getDeviceInfo,
+  updateProfile,
+  enableGuestMode,
+  disableGuestMode,
+  isGuestMode
+} from './identity'
 import { discoveryManager } from './discovery'
-import { tcpServer } from './tcpServer'
-import { connectionManager } from './protocol'
+import { tcpServer, ConnectionRequest } from './tcpServer'
+import { connectionManager, MAX_BATCH_FRAMES } from './protocol'
 import { permissionManager, PermissionType } from './permissions'
+import { peerPermissionManager, PeerCapability } from './peerPermissions'
+import { frameGuard } from './frameGuard'
+import { downgradeGuard } from './downgradeGuard'
+import { getActiveProfile, listProfiles, switchProfile, getStoreOptions } from './profiles'
+import { getSecurityEvents, recordSecurityEvent } from './securityLog'
+import { runCryptoSelfTest } from './crypto/selfTest'
+import { isApprovalRequired, setApprovalRequired, untrustDevice } from './trustedDevices'
 
-import { NetworkMessage, Device } from '@shared/messageTypes'
+import {
+  NetworkMessage,
+  Device,
+  ProfileUpdatePayload,
+  MessageDeletion,
+  ScheduledMessage,
+  AutoAcceptRule,
+  ConflictPolicy,
+  Group,
+  ForwardedFrom,
+  FileMetadata,
+  PeerError,
+  TransferStatusQuery,
+  BatchPayload,
+  ConnectionInfo,
+  Timeouts
+} from '@shared/messageTypes'
 import { v4 as uuidv4 } from 'uuid'
 import { fileTransferManager } from './fileTransfer'
+import { groupManager } from './groups'
+import { messageStore } from './messageStore'
+import { clearGuestDrafts, getDraft, saveDraft } from './drafts'
+import { messageScheduler } from './scheduler'
+import { getInboxSummary } from './inbox'
+import { getAutoAcceptRules, setAutoAcceptRules } from './autoAcceptRules'
+import {
+  getDownloadDirectory,
+  setDownloadDirectory,
+  isPerPeerFoldersEnabled,
+  setPerPeerFolders,
+  getConflictPolicy,
+  setConflictPolicy,
+  getMaxIncomingSize,
+  setMaxIncomingSize
+} from './downloads'
+import {
+  isLinkPreviewEnabled,
+  setLinkPreviewEnabled,
+  extractFirstUrl,
+  fetchLinkPreview
+} from './linkPreview'
+import { isSendPreviewsEnabled, setSendPreviewsEnabled } from './thumbnails'
 import { isSensitiveMessageType } from './crypto/messageCrypto'
+import { createErrorFrame, parseErrorPayload } from './peerErrors'
+import { setPeerExtensions } from './extensions'
+import { storeTicket } from './crypto/resumption'
+import { isControlPaddingEnabled, loadControlPadding, setControlPadding } from './wire'
 import { NotificationManager } from './notifications'
+import { webSocketBridge } from './webSocketBridge'
+import { listenerEndpoints } from './endpoints'
+import { FirewallSettings, firewall } from './firewall'
+import { tlsTransport } from './tlsTransport'
+import { noiseIdentity } from './noiseHandshake'
+import { relayClient } from './relay'
+import { getHotspot, joinHotspot, startHotspot, stopHotspot } from './hotspot'
+import {
+  SocketTuning,
+  getPortRange,
+  getSocketTuning,
+  setPortRange,
+  setSocketTuning
+} from './listen'
+import { getProxy, loadProxy, setProxy } from './proxy'
+import { overlayNetwork } from './overlay'
+import { udpChannel } from './udpChannel'
+import { bluetoothTransport } from './bluetooth'
+import { recordPong } from './keepalive'
+import { timeoutSettings } from './timeouts'
+
+// Read receipts come in bursts as a conversation is read; each is held this long (ms) so
+// the rest of its burst can go out in the same BATCH
+const RECEIPT_BATCH_DELAY = 250
+
+// How often the renderer is sent the open connections with their metrics
+const METRICS_INTERVAL = 5000
+
+// Frames that only make sense on their own, never inside a BATCH
+const UNBATCHABLE_TYPES: NetworkMessage['type'][] = ['HELLO', 'HELLO_SECURE', 'FRAGMENT', 'BATCH']
 
 export function setupIpc(mainWindow: BrowserWindow): void {
   const notificationManager = new NotificationManager(mainWindow)
   fileTransferManager.setup(mainWindow)
+  messageStore.load()
 
   // Safe message sending helper - checks if window is destroyed before sending
   const sendToRenderer = (channel: string, ...args: unknown[]): void => {
@@ -31,17 +119,122 @@ export function setupIpc(mainWindow: BrowserWindow): void {
     permissionManager.requestPermission(type)
   )
 
+  // Per-device capabilities
+  peerPermissionManager.load().catch((e) => {
+    console.error('[IPC] Failed to load peer permissions:', e)
+  })
+  ipcMain.handle('get-peer-permissions', (_, deviceId: string) =>
+    peerPermissionManager.getPermissions(deviceId)
+  )
+  ipcMain.handle(
+    'set-peer-permission',
+    (_, deviceId: string, capability: PeerCapability, allowed: boolean) =>
+      peerPermissionManager.setPermission(deviceId, capability, allowed)
+  )
+
+  // Tell every connected peer about our new name/avatar so they don't wait for re-discovery
+  const broadcastProfileUpdate = (): void => {
+    const info = getDeviceInfo()
+    const message: NetworkMessage = {
+      type: 'PROFILE_UPDATE',
+      deviceId: info.deviceId,
+      id: uuidv4(),
+      timestamp: Date.now(),
+      payload: {
+        displayName: info.displayName,
+        profileImage: info.profileImage,
+        statusMessage: info.statusMessage
+      } as ProfileUpdatePayload
+    }
+    const outbound = connectionManager.getConnectedDeviceIds()
+    for (const deviceId of outbound) {
+      connectionManager.sendMessage(deviceId, message)
+    }
+    tcpServer.broadcast(message, outbound)
+  }
+
   // Device Info
   ipcMain.handle('get-device-info', () => getDeviceInfo())
   ipcMain.handle('update-display-name', (_, name: string) => {
     updateProfile(name)
+    broadcastProfileUpdate()
     return getDeviceInfo()
   })
-  ipcMain.handle('update-profile', (_, name?: string, image?: string) => {
-    updateProfile(name, image)
+  ipcMain.handle('update-profile', (_, name?: string, image?: string, statusMessage?: string) => {
+    updateProfile(name, image, statusMessage)
+    broadcastProfileUpdate()
     return getDeviceInfo()
   })
 
+  // Guest Mode - swaps to a throwaway identity and re-announces ourselves
+  ipcMain.handle('set-guest-mode', async (_, enabled: boolean, displayName?: string) => {
+    const info = enabled ? enableGuestMode(displayName) : disableGuestMode()
+    connectionManager.disconnectAll()
+    // A guest starts with no history; the real one comes back, untouched, afterwards
+    messageStore.load()
+    await messageScheduler.load()
+    clearGuestDrafts()
+    tlsTransport.refresh()
+    discoveryManager.updateAdvertisement(info)
+    relayClient.register()
+    // The renderer doesn't persist its state as a guest, and re-reads it for the real
+    // identity afterwards, on load
+    if (mainWindow && !mainWindow.isDestroyed()) {
+      mainWindow.webContents.reload()
+    }
+    return info
+  })
+  ipcMain.handle('is-guest-mode', () => isGuestMode())
+
+  // Incoming connection approval
+  ipcMain.handle('get-require-connection-approval', () => isApprovalRequired())
+  ipcMain.handle('set-require-connection-approval', (_, required: boolean) =>
+    setApprovalRequired(required)
+  )
+  ipcMain.handle('get-pending-connection-requests', () => tcpServer.getPendingConnectionRequests())
+  ipcMain.handle('accept-connection', (_, deviceId: string) => tcpServer.acceptConnection(deviceId))
+  ipcMain.handle('deny-connection', (_, deviceId: string) => tcpServer.denyConnection(deviceId))
+  ipcMain.handle('untrust-device', (_, deviceId: string) => untrustDevice(deviceId))
+
+  // Security audit log
+  ipcMain.handle('get-security-events', (_, limit?: number) => getSecurityEvents(limit))
+  // Known-answer tests for the ciphers and HKDF, and a benchmark that picks our cipher suite
+  ipcMain.handle('run-crypto-selftest', () => runCryptoSelfTest())
+
+  // Profiles - each has its own identity, settings and trusted devices
+  ipcMain.handle('get-active-profile', () => getActiveProfile())
+  ipcMain.handle('list-profiles', () => listProfiles())
+  ipcMain.handle('switch-profile', async (_, name: string) => {
+    if (name === getActiveProfile()) return getDeviceInfo()
+    switchProfile(name)
+    connectionManager.disconnectAll()
+    await peerPermissionManager.load()
+    await frameGuard.load()
+    await loadControlPadding()
+    await downgradeGuard.load()
+    await timeoutSettings.load()
+    await firewall.load()
+    await webSocketBridge.load()
+    await listenerEndpoints.load()
+    await tlsTransport.load()
+    await noiseIdentity.load()
+    await loadProxy()
+    await relayClient.load()
+    await overlayNetwork.load()
+    await udpChannel.load()
+    await bluetoothTransport.load()
+    await groupManager.load()
+    await messageScheduler.load()
+    messageStore.load()
+    const info = getDeviceInfo()
+    discoveryManager.updateAdvertisement(info)
+    // The renderer re-reads its persisted state for the new profile on load
+    if (mainWindow && !mainWindow.isDestroyed()) {
+      mainWindow.webContents.reload()
+    }
+    return info
+  })
+
   ipcMain.handle('clear-cache', async () => {
     if (mainWindow && !mainWindow.isDestroyed()) {
       const session = mainWindow.webContents.session
@@ -53,13 +246,7 @@ export function setupIpc(mainWindow: BrowserWindow): void {
   })
 
   // Download Directory Management
-  ipcMain.handle('get-download-path', async () => {
-    const { app } = await import('electron')
-    const Store = (await import('electron-store')).default
-    const store = new Store()
-    const customPath = store.get('downloadPath') as string | undefined
-    return customPath || app.getPath('downloads')
-  })
+  ipcMain.handle('get-download-path', () => getDownloadDirectory())
 
   ipcMain.handle('select-download-directory', async () => {
     const { dialog } = await import('electron')
@@ -73,27 +260,315 @@ export function setupIpc(mainWindow: BrowserWindow): void {
     return null
   })
 
-  ipcMain.handle('set-download-path', async (_, path: string) => {
-    const Store = (await import('electron-store')).default
-    const store = new Store()
-    store.set('downloadPath', path)
-    return path
-  })
+  ipcMain.handle('set-download-path', (_, path: string) => setDownloadDirectory(path))
+
+  ipcMain.handle('get-conflict-policy', () => getConflictPolicy())
+  ipcMain.handle('set-conflict-policy', (_, policy: ConflictPolicy) => setConflictPolicy(policy))
+
+  ipcMain.handle('get-per-peer-download-folders', () => isPerPeerFoldersEnabled())
+  ipcMain.handle('set-per-peer-download-folders', (_, enabled: boolean) =>
+    setPerPeerFolders(enabled)
+  )
 
   // Auto-accept Preference
   ipcMain.handle('get-auto-accept', async () => {
     const Store = (await import('electron-store')).default
-    const store = new Store()
+    const store = new Store(getStoreOptions())
     return store.get('autoAccept', false) as boolean
   })
 
   ipcMain.handle('set-auto-accept', async (_, autoAccept: boolean) => {
     const Store = (await import('electron-store')).default
-    const store = new Store()
+    const store = new Store(getStoreOptions())
     store.set('autoAccept', autoAccept)
     return autoAccept
   })
 
+  // Parallel streams for large files (1 = a single stream)
+  ipcMain.handle('get-parallel-streams', async () => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    return store.get('parallelStreams', 1) as number
+  })
+
+  ipcMain.handle('set-parallel-streams', async (_, count: number) => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    const clamped = Math.min(8, Math.max(1, Math.floor(count)))
+    store.set('parallelStreams', clamped)
+    return clamped
+  })
+
+  // Minutes an incoming file request may wait before it is rejected; 0 keeps it forever
+  ipcMain.handle('get-file-request-expiry', async () => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    return store.get('fileRequestExpiry', 60) as number
+  })
+
+  ipcMain.handle('set-file-request-expiry', async (_, minutes: number) => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    const value = Math.max(0, Math.floor(minutes))
+    store.set('fileRequestExpiry', value)
+    return value
+  })
+
+  ipcMain.handle('get-max-incoming-size', () => getMaxIncomingSize())
+  ipcMain.handle('set-max-incoming-size', (_, bytes: number) => setMaxIncomingSize(bytes))
+
+  // How many times a transfer cut off by a network error is resumed automatically
+  ipcMain.handle('get-transfer-retry-limit', async () => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    return store.get('transferRetryLimit', 5) as number
+  })
+
+  ipcMain.handle('set-transfer-retry-limit', async (_, limit: number) => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    const clamped = Math.min(20, Math.max(0, Math.floor(limit)))
+    store.set('transferRetryLimit', clamped)
+    return clamped
+  })
+
+  // Seconds a connection may stay idle before it is probed; 0 disables probing. Like the
+  // idle timeout below, this is what we offer in HELLO_SECURE (see keepalive.ts)
+  ipcMain.handle('get-heartbeat-interval', async () => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    return store.get('heartbeatInterval', 15) as number
+  })
+
+  ipcMain.handle('set-heartbeat-interval', async (_, seconds: number) => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    const value = Math.min(300, Math.max(0, Math.floor(seconds)))
+    store.set('heartbeatInterval', value)
+    return value
+  })
+
+  // Seconds of silence after which a connection is dropped; 0 never drops it
+  ipcMain.handle('get-idle-timeout', async () => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    return store.get('idleTimeout', 45) as number
+  })
+
+  ipcMain.handle('set-idle-timeout', async (_, seconds: number) => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    const value = Math.min(3600, Math.max(0, Math.floor(seconds)))
+    store.set('idleTimeout', value)
+    return value
+  })
+
+  // How long connecting, handshakes, control requests and stalled file streams may take
+  timeoutSettings.load().catch((e) => {
+    console.error('[IPC] Failed to load timeouts:', e)
+  })
+  ipcMain.handle('get-timeouts', () => timeoutSettings.getTimeouts())
+  ipcMain.handle('set-timeouts', (_, timeouts: Timeouts) => timeoutSettings.setTimeouts(timeouts))
+
+  // Strict mode drops malformed frames and disconnects, then bans, peers that keep sending them
+  frameGuard.load().catch((e) => {
+    console.error('[IPC] Failed to load frame parsing mode:', e)
+  })
+  ipcMain.handle('get-strict-frame-parsing', () => frameGuard.isStrict())
+  ipcMain.handle('set-strict-frame-parsing', (_, enabled: boolean) => frameGuard.setStrict(enabled))
+
+  // Pads acknowledgements, heartbeats and other control frames to hide their sizes
+  loadControlPadding().catch((e) => {
+    console.error('[IPC] Failed to load control frame padding:', e)
+  })
+  ipcMain.handle('get-pad-control-frames', () => isControlPaddingEnabled())
+  ipcMain.handle('set-pad-control-frames', (_, enabled: boolean) => setControlPadding(enabled))
+
+  // Refuses plaintext to and from peers that have had an encrypted session with us
+  downgradeGuard.load().catch((e) => {
+    console.error('[IPC] Failed to load downgrade protection:', e)
+  })
+  ipcMain.handle('get-refuse-plaintext', () => downgradeGuard.isPlaintextRefused())
+  ipcMain.handle('set-refuse-plaintext', (_, enabled: boolean) =>
+    downgradeGuard.setPlaintextRefused(enabled)
+  )
+
+  // WebSocket listener for peers without the app, e.g. a browser page
+  webSocketBridge.load().catch((e) => {
+    console.error('[IPC] Failed to start browser connections:', e)
+  })
+  ipcMain.handle('get-browser-connections', () => webSocketBridge.port)
+  ipcMain.handle('set-browser-connections', (_, enabled: boolean) =>
+    webSocketBridge.setEnabled(enabled)
+  )
+
+  // Extra listeners (TCP or WebSocket, on chosen addresses) next to the main one
+  listenerEndpoints.load().catch((e) => {
+    console.error('[IPC] Failed to start listener endpoints:', e)
+  })
+  ipcMain.handle('get-listener-endpoints', () => listenerEndpoints.getEndpoints())
+  ipcMain.handle('set-listener-endpoints', (_, urls: string[]) =>
+    listenerEndpoints.setEndpoints(urls)
+  )
+
+  // TLS with pinned device certificates, used for outgoing connections when the peer offers it
+  tlsTransport.load().catch((e) => {
+    console.error('[IPC] Failed to load TLS settings:', e)
+  })
+  ipcMain.handle('get-prefer-tls', () => tlsTransport.isPreferred())
+  ipcMain.handle('set-prefer-tls', (_, preferred: boolean) => tlsTransport.setPreferred(preferred))
+
+  // Static keys pinned by the Noise handshake
+  noiseIdentity.load().catch((e) => {
+    console.error('[IPC] Failed to load pinned static keys:', e)
+  })
+
+  // Relay server for peers off the LAN; setting an address registers us there. The relay
+  // is reached through the outbound proxy, if one is set, so that is loaded first
+  loadProxy()
+    .then(() => relayClient.load())
+    .catch((e) => {
+      console.error('[IPC] Failed to connect to relay server:', e)
+    })
+  ipcMain.handle('get-relay-address', () => relayClient.getAddress())
+  ipcMain.handle('set-relay-address', (_, address: string) => relayClient.setAddress(address))
+  ipcMain.handle('get-relay-status', () => relayClient.getStatus())
+  ipcMain.handle('get-outbound-proxy', () => getProxy())
+  ipcMain.handle('set-outbound-proxy', async (_, url: string) => {
+    const proxy = await setProxy(url)
+    await relayClient.load() // reconnect to the relay the new way
+    return proxy
+  })
+
+  // Ports the TCP server tries before taking any free one; used from the next start
+  ipcMain.handle('get-port-range', () => getPortRange())
+  ipcMain.handle('set-port-range', (_, start: number, end: number) => setPortRange(start, end))
+  // Listener backlog, SO_REUSEPORT and socket buffer sizes; also used from the next start
+  ipcMain.handle('get-socket-tuning', () => getSocketTuning())
+  ipcMain.handle('set-socket-tuning', (_, tuning: SocketTuning) => setSocketTuning(tuning))
+  // Rebinds the TCP server once open connections have drained, on `port` if one is given
+  ipcMain.handle('restart-server', async (_, port?: number) => {
+    if (port !== undefined) await setPortRange(port, port)
+    const listening = await tcpServer.restart()
+    discoveryManager.setPort(listening)
+    discoveryManager.updateAdvertisement(getDeviceInfo())
+    return listening
+  })
+
+  // Bind address (used from the next start) and which networks may connect to us
+  ipcMain.handle('get-firewall', () => firewall.getSettings())
+  ipcMain.handle('set-firewall', (_, settings: FirewallSettings) => firewall.setSettings(settings))
+
+  // Peers on Tailscale, WireGuard or ZeroTier, found from a peer list as mDNS can't reach them
+  overlayNetwork.load().catch((e) => {
+    console.error('[IPC] Failed to load overlay network settings:', e)
+  })
+  ipcMain.handle('get-overlay-status', () => overlayNetwork.getStatus())
+  ipcMain.handle('set-overlay-enabled', (_, enabled: boolean) => overlayNetwork.setEnabled(enabled))
+  ipcMain.handle('set-overlay-peers', (_, peers: string[]) => overlayNetwork.setPeers(peers))
+  ipcMain.handle('scan-overlay', async () => {
+    await overlayNetwork.scan()
+    return overlayNetwork.getStatus()
+  })
+
+  // File data over UDP, for Wi-Fi where TCP backs off on every dropped packet
+  udpChannel.load().catch((e) => {
+    console.error('[IPC] Failed to load UDP transfer settings:', e)
+  })
+  ipcMain.handle('get-udp-transfers', () => udpChannel.isEnabled())
+  ipcMain.handle('set-udp-transfers', (_, enabled: boolean) => udpChannel.setEnabled(enabled))
+
+  // Bluetooth serial links to paired devices, for when there is no IP network in common
+  bluetoothTransport.load().catch((e) => {
+    console.error('[IPC] Failed to load Bluetooth settings:', e)
+  })
+  ipcMain.handle('get-bluetooth-status', () => bluetoothTransport.getStatus())
+  ipcMain.handle('set-bluetooth-enabled', (_, enabled: boolean) =>
+    bluetoothTransport.setEnabled(enabled)
+  )
+  ipcMain.handle('set-bluetooth-ports', (_, ports: string[]) => bluetoothTransport.setPorts(ports))
+
+  // Hotspot pairing for devices with no network in common
+  ipcMain.handle('get-hotspot', () => getHotspot())
+  ipcMain.handle('start-hotspot', () => startHotspot())
+  ipcMain.handle('stop-hotspot', () => stopHotspot())
+  ipcMain.handle('join-hotspot', (_, qrPayload: string) => joinHotspot(qrPayload))
+
+  ipcMain.handle('get-send-folders-as-zip', async () => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    return store.get('sendFoldersAsZip', false) as boolean
+  })
+
+  ipcMain.handle('set-send-folders-as-zip', async (_, enabled: boolean) => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('sendFoldersAsZip', enabled)
+    return enabled
+  })
+
+  // Auto-accept rules
+  ipcMain.handle('get-auto-accept-rules', () => getAutoAcceptRules())
+  ipcMain.handle('set-auto-accept-rules', (_, rules: AutoAcceptRule[]) =>
+    setAutoAcceptRules(rules)
+  )
+
+  // Read receipts privacy toggle
+  ipcMain.handle('get-send-read-receipts', async () => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    return store.get('sendReadReceipts', true) as boolean
+  })
+
+  ipcMain.handle('set-send-read-receipts', async (_, enabled: boolean) => {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('sendReadReceipts', enabled)
+    return enabled
+  })
+
+  // Link previews privacy toggle
+  ipcMain.handle('get-link-previews', () => isLinkPreviewEnabled())
+  ipcMain.handle('set-link-previews', (_, enabled: boolean) => setLinkPreviewEnabled(enabled))
+  ipcMain.handle('get-send-file-previews', () => isSendPreviewsEnabled())
+  ipcMain.handle('set-send-file-previews', (_, enabled: boolean) =>
+    setSendPreviewsEnabled(enabled)
+  )
+
+  // Fetched in the background; the message is already stored and shown without it
+  const attachLinkPreview = async (
+    conversationId: string,
+    message: NetworkMessage
+  ): Promise<void> => {
+    const url = extractFirstUrl(message.payload)
+    if (!url || !message.id || !(await isLinkPreviewEnabled())) return
+    const preview = await fetchLinkPreview(url)
+    if (!preview) return
+    messageStore.update(conversationId, message.id, { linkPreview: preview })
+    sendToRenderer('link-preview', { conversationId, messageId: message.id, preview })
+  }
+
+  // Every open control connection, both ways, with its health
+  const listConnections = (): ConnectionInfo[] => {
+    const pooled = connectionManager.getConnections()
+    const adopted = new Set(pooled.filter((c) => c.direction === 'inbound').map((c) => c.deviceId))
+    return [...pooled, ...tcpServer.getConnections().filter((c) => !adopted.has(c.deviceId))]
+  }
+  ipcMain.handle('get-connections', () => listConnections())
+  // The same, pushed while the window is open, for the network quality shown in settings
+  const metricsTimer = setInterval(
+    () => sendToRenderer('connection-metrics', listConnections()),
+    METRICS_INTERVAL
+  )
+
+  // Closes every connection to and from the device. It stays offline, so the heartbeat
+  // doesn't dial it straight back, until it next announces itself or sends a message.
+  ipcMain.handle('disconnect-device', (_, deviceId: string) => {
+    const closed = [connectionManager.disconnect(deviceId), tcpServer.disconnect(deviceId)]
+    discoveryManager.markDeviceOffline(deviceId)
+    return closed.some(Boolean)
+  })
+
   // Network Info
   ipcMain.handle('get-network-info', async () => {
     const os = await import('os')
@@ -171,6 +646,8 @@ export function setupIpc(mainWindow: BrowserWindow): void {
       await connectionManager.getConnection(target)
       console.log(`[IPC] Connection established, sending payload`)
       connectionManager.sendMessage(deviceId, message)
+      messageStore.add(deviceId, message)
+      attachLinkPreview(deviceId, message)
       return message
     } catch {
       console.error(`[IPC] Failed to reach ${target.address}:${target.port}`)
@@ -178,26 +655,203 @@ export function setupIpc(mainWindow: BrowserWindow): void {
     }
   })
 
+  // Groups
+  groupManager.load().catch((e) => {
+    console.error('[IPC] Failed to load groups:', e)
+  })
+  ipcMain.handle('get-groups', () => groupManager.getGroups())
+  ipcMain.handle('create-group', (_, name: string, memberIds: string[]) =>
+    groupManager.createGroup(name, memberIds)
+  )
+  ipcMain.handle('add-group-member', (_, groupId: string, deviceId: string) =>
+    groupManager.addMember(groupId, deviceId)
+  )
+  ipcMain.handle('remove-group-member', (_, groupId: string, deviceId: string) =>
+    groupManager.removeMember(groupId, deviceId)
+  )
+  ipcMain.handle('send-group-message', (_, groupId: string, payload: string, replyTo?: string) =>
+    groupManager.sendGroupMessage(groupId, payload, replyTo)
+  )
+
+  // Forwarding - text is re-sent as a new MESSAGE, files start a fresh transfer
+  ipcMain.handle(
+    'forward-message',
+    async (_, original: NetworkMessage, toDeviceId: string, filePath?: string) => {
+      const originalSender = discoveryManager
+        .getDiscoveredDevices()
+        .find((d) => d.deviceId === original.deviceId)
+      const forwardedFrom: ForwardedFrom = original.forwardedFrom ?? {
+        deviceId: original.deviceId,
+        displayName:
+          original.deviceId === getDeviceInfo().deviceId
+            ? getDeviceInfo().displayName
+            : originalSender?.displayName,
+        messageId: original.id,
+        timestamp: original.timestamp
+      }
+
+      if (original.type === 'FILE_META') {
+        // Received files live at the local save path, sent files at their source path
+        const source = filePath || (original.payload as FileMetadata).path
+        if (!source || !fs.existsSync(source)) {
+          throw new Error('The original file is no longer available to forward')
+        }
+        return fileTransferManager.initiateSend(toDeviceId, source, undefined, forwardedFrom)
+      }
+
+      const target = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === toDeviceId)
+      if (!target) throw new Error('Device not found')
+
+      const message: NetworkMessage = {
+        type: 'MESSAGE',
+        deviceId: getDeviceInfo().deviceId,
+        id: uuidv4(),
+        payload: original.payload,
+        timestamp: Date.now(),
+        forwardedFrom
+      }
+      await connectionManager.getConnection(target)
+      connectionManager.sendMessage(toDeviceId, message)
+      messageStore.add(toDeviceId, message)
+      return message
+    }
+  )
+
+  // Search & history
+  ipcMain.handle('search-messages', (_, query: string, conversationId?: string, limit?: number) =>
+    messageStore.search(query, conversationId, limit)
+  )
+  ipcMain.handle('clear-messages', (_, conversationId?: string) => {
+    messageStore.clear(conversationId)
+  })
+
+  // Scheduled messages
+  messageScheduler
+    .load()
+    .then(() => messageScheduler.start())
+    .catch((e) => {
+      console.error('[IPC] Failed to load scheduled messages:', e)
+    })
+  ipcMain.handle('get-scheduled-messages', (_, deviceId?: string) =>
+    messageScheduler.list(deviceId)
+  )
+  ipcMain.handle(
+    'schedule-message',
+    (_, deviceId: string, payload: string, sendAt: number, replyTo?: string) =>
+      messageScheduler.schedule(deviceId, payload, sendAt, replyTo)
+  )
+  ipcMain.handle(
+    'update-scheduled-message',
+    (_, scheduleId: string, changes: { payload?: string; sendAt?: number }) =>
+      messageScheduler.update(scheduleId, changes)
+  )
+  ipcMain.handle('cancel-scheduled-message', (_, scheduleId: string) =>
+    messageScheduler.cancel(scheduleId)
+  )
+
+  // Drafts
+  ipcMain.handle('get-draft', (_, conversationId: string) => getDraft(conversationId))
+  ipcMain.handle('save-draft', (_, conversationId: string, text: string) =>
+    saveDraft(conversationId, text)
+  )
+
+  // Conversation list
+  ipcMain.handle('get-inbox-summary', () => getInboxSummary())
+  ipcMain.handle('mark-conversation-read', (_, conversationId: string) =>
+    messageStore.markRead(conversationId, getDeviceInfo().deviceId)
+  )
+
+  const pendingReceipts = new Map<string, NetworkMessage[]>()
+  const flushReceipts = async (deviceId: string): Promise<void> => {
+    const receipts = pendingReceipts.get(deviceId) ?? []
+    pendingReceipts.delete(deviceId)
+    const target = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
+    try {
+      if (target) await connectionManager.getConnection(target)
+      connectionManager.sendBatch(deviceId, receipts)
+    } catch {
+      console.warn(`[IPC] Could not deliver ${receipts.length} read receipt(s) to ${deviceId}`)
+    }
+  }
+
   ipcMain.handle('mark-as-read', async (_, deviceId: string, messageId: string) => {
-    const ack: NetworkMessage = {
-      type: 'MESSAGE_READ',
+    messageStore.markRead(deviceId, getDeviceInfo().deviceId, messageId)
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    if (!store.get('sendReadReceipts', true)) return
+
+    const receipt: NetworkMessage = {
+      type: 'READ_RECEIPT',
       deviceId: getDeviceInfo().deviceId,
       ackId: messageId,
       timestamp: Date.now(),
       status: 'read'
     }
-    connectionManager.sendMessage(deviceId, ack)
+    const queued = pendingReceipts.get(deviceId)
+    if (queued) {
+      queued.push(receipt)
+      return
+    }
+    pendingReceipts.set(deviceId, [receipt])
+    setTimeout(() => flushReceipts(deviceId), RECEIPT_BATCH_DELAY)
   })
 
-  ipcMain.handle('delete-remote-message', async (_, deviceId: string, messageId: string) => {
+  ipcMain.handle(
+    'edit-message',
+    async (_, deviceId: string, messageId: string, payload: string) => {
+      const edit: NetworkMessage = {
+        type: 'MESSAGE_EDIT',
+        deviceId: getDeviceInfo().deviceId,
+        ackId: messageId,
+        payload,
+        timestamp: Date.now()
+      }
+      const target = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
+      if (!target) throw new Error('Device not found')
+      await connectionManager.getConnection(target)
+      connectionManager.sendMessage(deviceId, edit)
+
+      const edited = {
+        conversationId: deviceId,
+        editorId: edit.deviceId,
+        messageId,
+        payload,
+        editedAt: edit.timestamp
+      }
+      messageStore.update(deviceId, messageId, { payload, edited: true, editedAt: edit.timestamp })
+      sendToRenderer('message-edited', edited)
+      return edited
+    }
+  )
+
+  const sendRetraction = async (deviceId: string, messageId: string): Promise<void> => {
     const message: NetworkMessage = {
       type: 'MESSAGE_DELETE',
       deviceId: getDeviceInfo().deviceId,
       ackId: messageId,
       timestamp: Date.now()
     }
+    const target = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
+    if (target) await connectionManager.getConnection(target)
     connectionManager.sendMessage(deviceId, message)
-  })
+  }
+
+  ipcMain.handle('delete-remote-message', (_, deviceId: string, messageId: string) =>
+    sendRetraction(deviceId, messageId)
+  )
+
+  ipcMain.handle(
+    'delete-message',
+    async (_, deviceId: string, messageId: string, forEveryone: boolean) => {
+      if (forEveryone) {
+        await sendRetraction(deviceId, messageId)
+      }
+      messageStore.remove(deviceId, messageId)
+      const deletion: MessageDeletion = { conversationId: deviceId, messageId, forEveryone }
+      sendToRenderer('message-deleted', deletion)
+      return deletion
+    }
+  )
 
   // Forward events to renderer with safe sending
   const onDeviceFound = (device: Device): void => {
@@ -211,10 +865,53 @@ export function setupIpc(mainWindow: BrowserWindow): void {
     sendToRenderer('device-lost', deviceId)
   }
 
+  const onDeviceUpdated = (device: Device): void => {
+    sendToRenderer('device-updated', device)
+  }
+
+  const onGroupUpdated = (group: Group): void => {
+    sendToRenderer('group-updated', group)
+  }
+
+  const onScheduledMessageSent = (scheduled: ScheduledMessage, message: NetworkMessage): void => {
+    sendToRenderer('scheduled-message-sent', {
+      scheduleId: scheduled.scheduleId,
+      deviceId: scheduled.deviceId,
+      message
+    })
+  }
+
+  const onConnectionRequest = (request: ConnectionRequest): void => {
+    sendToRenderer('connection-request', request)
+  }
+
+  const onConnectionRequestCancelled = (deviceId: string): void => {
+    sendToRenderer('connection-request-cancelled', deviceId)
+  }
+
+  // Profiles arrive both in HELLO_SECURE (handshake) and in PROFILE_UPDATE frames
+  const onPeerProfile = (deviceId: string, profile: ProfileUpdatePayload): void => {
+    if (typeof profile?.displayName === 'string' && profile.displayName) {
+      discoveryManager.updateDeviceProfile(deviceId, profile)
+    }
+  }
+
+  // Replies go back over our outbound connection if we have one, otherwise over the
+  // socket the frame arrived on (peers that only connected to us have no outbound link)
+  const sendReply = (socket: net.Socket, deviceId: string, reply: NetworkMessage): void => {
+    if (connectionManager.isConnected(deviceId)) {
+      connectionManager.sendMessage(deviceId, reply)
+    } else {
+      tcpServer.sendMessage(socket, reply)
+    }
+  }
+
+  // `senderId` is the device the connection authenticated as, null if it hasn't
   const handleIncomingMessage = (
     message: NetworkMessage,
     socket: net.Socket,
-    isEncrypted?: boolean
+    isEncrypted?: boolean,
+    senderId: string | null = null
   ): void => {
     // Mark device as online whenever we receive any traffic from it
     discoveryManager.markDeviceOnline(message.deviceId)
@@ -224,36 +921,218 @@ export function setupIpc(mainWindow: BrowserWindow): void {
       console.error(
         `[IPC] Rejecting unencrypted sensitive message ${message.type} from ${message.deviceId}`
       )
+      recordSecurityEvent(
+        'plaintext-fallback',
+        message.deviceId,
+        `Rejected unencrypted inbound ${message.type}`
+      )
+      return
+    }
+    // Nor anything else from a peer that has encrypted before, unless the user allows it
+    if (!isEncrypted && downgradeGuard.refusesPlaintext(message.deviceId)) {
+      console.error(`[IPC] Rejecting unencrypted ${message.type} from ${message.deviceId}`)
+      recordSecurityEvent(
+        'plaintext-fallback',
+        message.deviceId,
+        `Rejected unencrypted inbound ${message.type}`
+      )
+      return
+    }
+
+    // Central capability check for every inbound frame, regardless of connection direction
+    if (!peerPermissionManager.isFrameAllowed(message, senderId)) {
+      console.warn(`[IPC] Dropping ${message.type} from ${message.deviceId}: permission denied`)
+      recordSecurityEvent('peer-blocked', message.deviceId, `Blocked ${message.type}`)
+      // A refused file is referred to by its fileId, so the sender can fail the transfer
+      const ref =
+        message.type === 'FILE_META' ? (message.payload as FileMetadata).fileId : message.id
+      const error = createErrorFrame(message.deviceId, 'UNAUTHORIZED', ref)
+      if (error) sendReply(socket, message.deviceId, { ...error, correlationId: message.id })
       return
     }
 
-    if (message.type === 'HELLO') {
-      connectionManager.registerSocket(message.deviceId, socket)
-    } else if (message.type === 'PING') {
+    // Responses still go through the usual handling below
+    connectionManager.settleResponse(message)
+
+    // A bare HELLO proves nothing about who sent it, so its connection isn't pooled; only
+    // one that finished a secure handshake is (see the 'authenticated' handler below)
+    if (message.type === 'PING') {
       const pong: NetworkMessage = {
         type: 'PONG',
         deviceId: getDeviceInfo().deviceId,
         id: 'pong',
+        correlationId: message.id,
         timestamp: Date.now()
       }
       tcpServer.sendMessage(socket, pong)
       return // Don't forward to renderer
     } else if (message.type === 'PONG') {
+      recordPong(socket, message.correlationId)
       return // Don't forward to renderer
+    } else if (message.type === 'GOODBYE') {
+      // Closing the socket drops the session; the peer closes its other connections too
+      console.log(`[IPC] ${message.deviceId} disconnected`)
+      socket.destroy()
+      discoveryManager.markDeviceOffline(message.deviceId)
+      return
+    } else if (message.type === 'PROFILE_UPDATE') {
+      onPeerProfile(message.deviceId, message.payload as ProfileUpdatePayload)
+      return
     } else if (message.type === 'FILE_META') {
       fileTransferManager.handleIncomingMeta(message)
     } else if (message.type === 'FILE_ACCEPT') {
       fileTransferManager.handleAccept(message)
     } else if (message.type === 'FILE_REJECT') {
       fileTransferManager.handleReject(message)
-    } else if (message.type === 'MESSAGE_DELIVERED' || message.type === 'MESSAGE_READ') {
+    } else if (message.type === 'FILE_PAUSE') {
+      fileTransferManager.handlePause(message)
+    } else if (message.type === 'FILE_RESUME') {
+      fileTransferManager.handleResume(message)
+    } else if (message.type === 'FILE_CANCEL') {
+      fileTransferManager.handleCancel(message)
+    } else if (message.type === 'FILE_ACK') {
+      fileTransferManager.handleAck(message)
+      return // Don't forward to renderer
+    } else if (message.type === 'TRANSFER_STATUS_QUERY') {
+      const query = message.payload as TransferStatusQuery
+      fileTransferManager.handleStatusQuery(message.deviceId, query)
+      sendReply(socket, message.deviceId, {
+        type: 'TRANSFER_STATUS',
+        deviceId: getDeviceInfo().deviceId,
+        payload: fileTransferManager.getTransferStatus(message.deviceId, query.fileId),
+        id: uuidv4(),
+        correlationId: message.id,
+        timestamp: Date.now()
+      })
+      return
+    } else if (message.type === 'TRANSFER_STATUS') {
+      return // Taken by the request awaiting it
+    } else if (message.type === 'EXTENSIONS') {
+      setPeerExtensions(message.deviceId, message.payload)
+      return
+    } else if (message.type === 'SESSION_TICKET') {
+      // Only worth keeping if it came under the session it resumes
+      if (isEncrypted) storeTicket(message.deviceId, message.payload)
+      return
+    } else if (message.type === 'BATCH') {
+      // Each frame goes through the same checks as if it had arrived on its own, and can
+      // only speak for the device that sent the batch
+      const frames = (message.payload as BatchPayload | undefined)?.frames
+      if (!Array.isArray(frames) || frames.length > MAX_BATCH_FRAMES) return
+      for (const frame of frames) {
+        if (typeof frame !== 'object' || frame === null) continue
+        if (frame.deviceId !== message.deviceId || UNBATCHABLE_TYPES.includes(frame.type)) continue
+        if (frameGuard.check(frame, message.deviceId)) continue
+        handleIncomingMessage(frame, socket, isEncrypted, senderId)
+      }
+      return
+    } else if (message.type === 'ERROR') {
+      const error = parseErrorPayload(message.payload)
+      if (!error) return
+      console.warn(`[IPC] ${message.deviceId} reported ${error.code}`, error.ref ?? '')
+      fileTransferManager.handlePeerError(message.deviceId, error)
+      const peerError: PeerError = {
+        ...error,
+        deviceId: message.deviceId,
+        timestamp: message.timestamp ?? Date.now()
+      }
+      sendToRenderer('peer-error', peerError)
+      return
+    } else if (message.type === 'MESSAGE_ACK') {
+      // Older peers' MESSAGE_DELIVERED arrives renamed by the wire layer
+      const groupDelivery = groupManager.recordDelivery(message.ackId!, message.deviceId)
+      if (groupDelivery) {
+        sendToRenderer('group-message-delivered', {
+          groupId: groupDelivery.groupId,
+          messageId: message.ackId,
+          state: groupDelivery.state
+        })
+        return
+      }
+      // Only our own messages can be acknowledged by the peer
+      const ours = getDeviceInfo().deviceId
+      if (!messageStore.update(message.deviceId, message.ackId!, { status: 'delivered' }, ours)) {
+        return
+      }
+      sendToRenderer('message-delivered', {
+        deviceId: message.deviceId,
+        messageId: message.ackId
+      })
+      sendToRenderer('message-status-updated', {
+        deviceId: message.deviceId,
+        messageId: message.ackId,
+        status: 'delivered'
+      })
+      return
+    } else if (message.type === 'READ_RECEIPT') {
+      // Likewise MESSAGE_READ, and likewise only for our own messages
+      const ours = getDeviceInfo().deviceId
+      if (!messageStore.update(message.deviceId, message.ackId!, { status: 'read' }, ours)) {
+        return
+      }
+      sendToRenderer('message-read', {
+        deviceId: message.deviceId,
+        messageId: message.ackId
+      })
       sendToRenderer('message-status-updated', {
         deviceId: message.deviceId,
         messageId: message.ackId,
-        status: message.type === 'MESSAGE_DELIVERED' ? 'delivered' : 'read'
+        status: 'read'
+      })
+      return
+    } else if (message.type === 'GROUP_UPDATE') {
+      groupManager.handleGroupUpdate(message).catch((e) => {
+        console.error('[IPC] Failed to apply group update:', e)
+      })
+      return
+    } else if (message.type === 'SENDER_KEY') {
+      groupManager.handleSenderKey(message)
+      return
+    } else if (message.type === 'GROUP_MESSAGE') {
+      if (!message.groupId || !groupManager.getGroup(message.groupId)) {
+        console.warn(`[IPC] Dropping message for unknown group ${message.groupId}`)
+        return
+      }
+      // Left unacknowledged, so the sender doesn't show it delivered
+      const opened = groupManager.openGroupMessage(message)
+      if (!opened) {
+        console.warn(`[IPC] Dropping group message from ${message.deviceId} that can't be read`)
+        return
+      }
+      sendReply(socket, message.deviceId, {
+        type: 'MESSAGE_ACK',
+        deviceId: getDeviceInfo().deviceId,
+        ackId: message.id,
+        correlationId: message.id,
+        timestamp: Date.now(),
+        status: 'delivered'
+      })
+      messageStore.add(message.groupId, opened)
+      attachLinkPreview(message.groupId, opened)
+      sendToRenderer('group-message-received', opened)
+      notificationManager.showNewMessageNotification(opened)
+      return
+    } else if (message.type === 'MESSAGE_EDIT') {
+      // A peer may only edit what it sent itself
+      const patch = { payload: message.payload, edited: true, editedAt: message.timestamp }
+      if (!messageStore.update(message.deviceId, message.ackId!, patch, message.deviceId)) {
+        console.warn(`[IPC] Ignoring an edit from ${message.deviceId} to ${message.ackId}`)
+        return
+      }
+      sendToRenderer('message-edited', {
+        conversationId: message.deviceId,
+        editorId: message.deviceId,
+        messageId: message.ackId,
+        payload: message.payload,
+        editedAt: message.timestamp
       })
       return
     } else if (message.type === 'MESSAGE_DELETE') {
+      const tombstone = { payload: '', deleted: true }
+      if (!messageStore.update(message.deviceId, message.ackId!, tombstone, message.deviceId)) {
+        console.warn(`[IPC] Ignoring a deletion from ${message.deviceId} of ${message.ackId}`)
+        return
+      }
       sendToRenderer('remote-message-deleted', {
         deviceId: message.deviceId,
         messageId: message.ackId
@@ -261,16 +1140,19 @@ export function setupIpc(mainWindow: BrowserWindow): void {
       return
     }
 
-    if (message.type === 'MESSAGE') {
-      // Send delivery ack
+    if (message.type === 'MESSAGE' || message.type === 'FILE_META') {
+      // Confirm delivery so the sender can tell sent from delivered
       const ack: NetworkMessage = {
-        type: 'MESSAGE_DELIVERED',
+        type: 'MESSAGE_ACK',
         deviceId: getDeviceInfo().deviceId,
         ackId: message.id,
+        correlationId: message.id,
         timestamp: Date.now(),
         status: 'delivered'
       }
-      connectionManager.sendMessage(message.deviceId, ack)
+      sendReply(socket, message.deviceId, ack)
+      messageStore.add(message.deviceId, message)
+      if (message.type === 'MESSAGE') attachLinkPreview(message.deviceId, message)
     }
 
     sendToRenderer('message-received', message)
@@ -279,8 +1161,20 @@ export function setupIpc(mainWindow: BrowserWindow): void {
 
   discoveryManager.on('deviceFound', onDeviceFound)
   discoveryManager.on('deviceLost', onDeviceLost)
+  discoveryManager.on('deviceUpdated', onDeviceUpdated)
   tcpServer.on('message', handleIncomingMessage)
   connectionManager.on('message', handleIncomingMessage)
+  tcpServer.on('profile', onPeerProfile)
+  connectionManager.on('profile', onPeerProfile)
+  tcpServer.on('connection-request', onConnectionRequest)
+  groupManager.on('groupUpdated', onGroupUpdated)
+  messageScheduler.on('sent', onScheduledMessageSent)
+  tcpServer.on('connection-request-cancelled', onConnectionRequestCancelled)
+  tcpServer.on('listening', (port: number) => sendToRenderer('server-listening', port))
+  // A peer that connected to us is answered over that connection rather than a new one
+  tcpServer.on('authenticated', (deviceId: string, socket: net.Socket) =>
+    connectionManager.registerSocket(deviceId, socket)
+  )
 
   // Window Controls
   ipcMain.on('window-minimize', () => {
@@ -309,7 +1203,15 @@ export function setupIpc(mainWindow: BrowserWindow): void {
   mainWindow.on('closed', () => {
     discoveryManager.removeListener('deviceFound', onDeviceFound)
     discoveryManager.removeListener('deviceLost', onDeviceLost)
+    discoveryManager.removeListener('deviceUpdated', onDeviceUpdated)
     tcpServer.removeListener('message', handleIncomingMessage)
     connectionManager.removeListener('message', handleIncomingMessage)
+    tcpServer.removeListener('profile', onPeerProfile)
+    connectionManager.removeListener('profile', onPeerProfile)
+    tcpServer.removeListener('connection-request', onConnectionRequest)
+    groupManager.removeListener('groupUpdated', onGroupUpdated)
+    messageScheduler.removeListener('sent', onScheduledMessageSent)
+    tcpServer.removeListener('connection-request-cancelled', onConnectionRequestCancelled)
+    clearInterval(metricsTimer)
   })
 }
@@ -0,0 +1,179 @@
+import net from 'net'
+import { ConnectionInfo, NetworkMessage } from '@shared/messageTypes'
+import { getSession } from './crypto/sessionKey'
+import { getDeviceInfo } from './identity'
+import { getStoreOptions } from './profiles'
+import { writeFrame } from './wire'
+
+/**
+ * How a connection is kept alive, in seconds: once nothing has been received for
+ * `heartbeatInterval` a PING goes out, and after `idleTimeout` of silence the connection
+ * is given up on. Either may be 0 for off. Both ends send theirs in HELLO_SECURE and
+ * settle on the same values, so neither drops a peer the other expects to sit idle.
+ */
+export interface KeepaliveParams {
+  heartbeatInterval: number
+  idleTimeout: number
+}
+
+const DEFAULT_HEARTBEAT_INTERVAL = 15
+const DEFAULT_IDLE_TIMEOUT = 45
+// A peer's values are capped at this, so it can't make us hold a dead connection forever
+const MAX_SECONDS = 3600
+// The agreed idle timeout always leaves room for this many unanswered heartbeats
+const MISSED_HEARTBEATS = 3
+// Throughput is the rate over at least this long, taken afresh when it has passed
+const THROUGHPUT_WINDOW = 2000
+
+interface Liveness {
+  since: number // when the handshake finished
+  lastReceived: number
+  unanswered: number // heartbeats sent since
+  heartbeats: number // sent over the connection's lifetime, numbering them
+  lastPing?: { id: string; sentAt: number } // the newest heartbeat, until answered
+  rttMs?: number // how long the last answered heartbeat took
+  sample: { at: number; bytesIn: number; bytesOut: number } // where the window started
+  rateIn: number // bytes a second over the last window
+  rateOut: number
+}
+const liveness = new WeakMap<net.Socket, Liveness>()
+
+export async function getLocalKeepalive(): Promise<KeepaliveParams> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  return {
+    heartbeatInterval: store.get('heartbeatInterval', DEFAULT_HEARTBEAT_INTERVAL) as number,
+    idleTimeout: store.get('idleTimeout', DEFAULT_IDLE_TIMEOUT) as number
+  }
+}
+
+const toSeconds = (value: unknown): number | null => {
+  const seconds = Number(value)
+  if (!Number.isFinite(seconds) || seconds < 0) return null
+  return Math.min(MAX_SECONDS, Math.floor(seconds))
+}
+
+/**
+ * Settles on the values both ends will use: heartbeats as often as the more watchful end
+ * asks for, and the longer of the two idle timeouts (none if either end has it off). The
+ * result doesn't depend on which end is which. Peers that don't send values (older
+ * versions) leave ours in place.
+ */
+export function negotiateKeepalive(local: KeepaliveParams, remote: unknown): KeepaliveParams {
+  const params = remote as Partial<KeepaliveParams> | undefined
+  const remoteInterval = toSeconds(params?.heartbeatInterval)
+  const remoteTimeout = toSeconds(params?.idleTimeout)
+  if (remoteInterval === null || remoteTimeout === null) return local
+
+  const intervals = [local.heartbeatInterval, remoteInterval].filter((seconds) => seconds > 0)
+  const heartbeatInterval = intervals.length > 0 ? Math.min(...intervals) : 0
+  let idleTimeout =
+    local.idleTimeout && remoteTimeout ? Math.max(local.idleTimeout, remoteTimeout) : 0
+  if (idleTimeout && heartbeatInterval) {
+    idleTimeout = Math.max(idleTimeout, heartbeatInterval * MISSED_HEARTBEATS)
+  }
+  return { heartbeatInterval, idleTimeout }
+}
+
+/**
+ * Runs the agreed keepalive on an established connection. The peer's PONG, or any other
+ * data, counts as a sign of life. Destroying the socket runs the usual close handling, so
+ * the session is dropped and the device reported disconnected. `tag` prefixes log lines.
+ * How alive the connection looks is kept either way, for describeConnection, along with
+ * the round trip of each answered heartbeat (see recordPong).
+ */
+export function startKeepalive(
+  socket: net.Socket,
+  deviceId: string,
+  { heartbeatInterval, idleTimeout }: KeepaliveParams,
+  tag: string
+): void {
+  if (socket.destroyed) return
+  const now = Date.now()
+  const alive: Liveness = {
+    since: now,
+    lastReceived: now,
+    unanswered: 0,
+    heartbeats: 0,
+    sample: { at: now, bytesIn: socket.bytesRead ?? 0, bytesOut: socket.bytesWritten ?? 0 },
+    rateIn: 0,
+    rateOut: 0
+  }
+  liveness.set(socket, alive)
+  socket.on('data', () => {
+    alive.lastReceived = Date.now()
+    alive.unanswered = 0
+  })
+
+  const tick = (heartbeatInterval || idleTimeout) * 1000
+  if (!tick) return
+  const timer = setInterval(() => {
+    const idle = Date.now() - alive.lastReceived
+    if (idleTimeout && idle >= idleTimeout * 1000) {
+      console.warn(`[${tag}] ${deviceId} idle for ${idleTimeout}s, closing connection`)
+      socket.destroy()
+    } else if (heartbeatInterval && idle >= heartbeatInterval * 1000 && socket.writable) {
+      const id = `heartbeat-${++alive.heartbeats}`
+      const sentAt = Date.now()
+      const ping: NetworkMessage = {
+        type: 'PING',
+        deviceId: getDeviceInfo().deviceId,
+        id,
+        timestamp: sentAt
+      }
+      writeFrame(socket, deviceId, ping, tag)
+      alive.lastPing = { id, sentAt }
+      alive.unanswered++
+    }
+  }, tick)
+  socket.once('close', () => clearInterval(timer))
+}
+
+/**
+ * Takes the round trip from a PONG that answers the newest heartbeat on `socket`. Answers
+ * to older ones are ignored, so a late PONG doesn't count the wait for the ones after it.
+ */
+export function recordPong(socket: net.Socket, correlationId: string | undefined): void {
+  const alive = liveness.get(socket)
+  if (!alive?.lastPing || alive.lastPing.id !== correlationId) return
+  alive.rttMs = Date.now() - alive.lastPing.sentAt
+  alive.lastPing = undefined
+}
+
+// Bytes a second each way, rolled over to a new window once the current one is long enough
+const throughput = (socket: net.Socket, alive: Liveness, now: number): void => {
+  const elapsed = now - alive.sample.at
+  if (elapsed < THROUGHPUT_WINDOW) return
+  const bytesIn = socket.bytesRead ?? 0
+  const bytesOut = socket.bytesWritten ?? 0
+  alive.rateIn = Math.round(((bytesIn - alive.sample.bytesIn) * 1000) / elapsed)
+  alive.rateOut = Math.round(((bytesOut - alive.sample.bytesOut) * 1000) / elapsed)
+  alive.sample = { at: now, bytesIn, bytesOut }
+}
+
+// What get-connections and connection-metrics report for one connection
+export function describeConnection(
+  deviceId: string,
+  socket: net.Socket,
+  direction: ConnectionInfo['direction']
+): ConnectionInfo {
+  const alive = liveness.get(socket)
+  const unansweredHeartbeats = alive?.unanswered ?? 0
+  const now = Date.now()
+  if (alive) throughput(socket, alive, now)
+  return {
+    deviceId,
+    direction,
+    address: socket.remoteAddress,
+    encrypted: !!getSession(deviceId),
+    uptimeSeconds: alive ? Math.floor((now - alive.since) / 1000) : 0,
+    bytesIn: socket.bytesRead ?? 0,
+    bytesOut: socket.bytesWritten ?? 0,
+    rateIn: alive?.rateIn ?? 0,
+    rateOut: alive?.rateOut ?? 0,
+    rttMs: alive?.rttMs,
+    idleSeconds: alive ? Math.floor((now - alive.lastReceived) / 1000) : 0,
+    unansweredHeartbeats,
+    healthy: !socket.destroyed && socket.writable && unansweredHeartbeats === 0
+  }
+}
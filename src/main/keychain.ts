@@ -0,0 +1,79 @@
+import fs from 'fs'
+import { safeStorage } from 'electron'
+import { KeyStorage } from '@shared/messageTypes'
+import { isGuestMode } from './identity'
+
+/**
+ * Long-term secrets (the TLS and Noise private keys) sealed with a key the OS keychain
+ * holds, through Electron's safeStorage: Keychain on macOS, DPAPI on Windows, and the
+ * Secret Service (libsecret) or KWallet on Linux. What ends up in app data is `<file>.sealed`,
+ * which is useless without the user's login keychain.
+ *
+ * Where there is no keychain (a Linux desktop without a secret service, where safeStorage
+ * would fall back to a hard-coded key) secrets stay as plain files readable only by the
+ * user, as before. A plain file found where the keychain is available is sealed and then
+ * removed the first time it is read.
+ *
+ * Keys that never leave a TPM or the Secure Enclave are out of reach: Electron has no API
+ * for them, and Node's TLS and our Noise code need the private key in memory to use it. So
+ * the keychain is as far as this goes, and HELLO_SECURE tells peers which of these a device
+ * uses (keyStorage). That is the device's own word, since without hardware there is
+ * nothing to attest it.
+ */
+
+const sealedPath = (file: string): string => `${file}.sealed`
+
+export function isKeychainAvailable(): boolean {
+  if (!safeStorage.isEncryptionAvailable()) return false
+  return process.platform !== 'linux' || safeStorage.getSelectedStorageBackend() !== 'basic_text'
+}
+
+// How our private keys are kept, for HELLO_SECURE
+export function getKeyStorage(): KeyStorage {
+  if (isGuestMode()) return 'memory'
+  return isKeychainAvailable() ? 'keychain' : 'file'
+}
+
+// A peer's keyStorage, or undefined if it sent none we know
+export function parseKeyStorage(value: unknown): KeyStorage | undefined {
+  return value === 'keychain' || value === 'file' || value === 'memory' ? value : undefined
+}
+
+/**
+ * Stores `secret` as `file`, sealed if the keychain is available.
+ */
+export function writeSecret(file: string, secret: Buffer): void {
+  if (isKeychainAvailable()) {
+    fs.writeFileSync(sealedPath(file), safeStorage.encryptString(secret.toString('base64')), {
+      mode: 0o600
+    })
+    fs.rmSync(file, { force: true })
+  } else {
+    fs.writeFileSync(file, secret, { mode: 0o600 })
+    fs.rmSync(sealedPath(file), { force: true })
+  }
+}
+
+/**
+ * Reads a secret stored with writeSecret, or from a plain file written before the keychain
+ * was used, sealing it on the way. Returns null if there is none, or if the sealed copy
+ * can't be opened (another user's keychain, or one that was reset).
+ */
+export function readSecret(file: string): Buffer | null {
+  const sealed = sealedPath(file)
+  if (fs.existsSync(sealed)) {
+    try {
+      return Buffer.from(safeStorage.decryptString(fs.readFileSync(sealed)), 'base64')
+    } catch (err) {
+      console.error(`[Keychain] Could not unseal ${sealed}:`, err)
+      return null
+    }
+  }
+  if (!fs.existsSync(file)) return null
+  const secret = fs.readFileSync(file)
+  if (isKeychainAvailable()) {
+    console.log(`[Keychain] Moving ${file} into the keychain`)
+    writeSecret(file, secret)
+  }
+  return secret
+}
@@ -0,0 +1,226 @@
+import dns from 'dns'
+import http from 'http'
+import https from 'https'
+import { BlockList, LookupFunction, isIP, isIPv4 } from 'net'
+import { LinkPreview } from '@shared/messageTypes'
+import { getStoreOptions } from './profiles'
+
+const FETCH_TIMEOUT = 5000
+const MAX_HTML_BYTES = 512 * 1024
+// Thumbnails go to the renderer inline, as data: URLs, so they are kept small
+const MAX_IMAGE_BYTES = 256 * 1024
+const IMAGE_TYPES = ['image/png', 'image/jpeg', 'image/gif', 'image/webp']
+const MAX_REDIRECTS = 5
+const URL_PATTERN = /https?:\/\/[^\s<>"']+/i
+
+// Addresses a link from a message must not make us fetch from: this machine, the local
+// network and anything else that isn't on the public internet
+const NON_PUBLIC = new BlockList()
+for (const [network, prefix] of [
+  ['0.0.0.0', 8],
+  ['10.0.0.0', 8],
+  ['100.64.0.0', 10],
+  ['127.0.0.0', 8],
+  ['169.254.0.0', 16],
+  ['172.16.0.0', 12],
+  ['192.168.0.0', 16],
+  ['224.0.0.0', 3]
+] as const) {
+  NON_PUBLIC.addSubnet(network, prefix, 'ipv4')
+}
+for (const [network, prefix] of [
+  ['::', 127],
+  ['fc00::', 7],
+  ['fe80::', 10],
+  ['ff00::', 8]
+] as const) {
+  NON_PUBLIC.addSubnet(network, prefix, 'ipv6')
+}
+
+const isPublicAddress = (address: string, family: number): boolean => {
+  // An IPv4-mapped IPv6 address reaches the IPv4 one
+  const mapped = address.toLowerCase().match(/^::ffff:(.+)$/)?.[1]
+  if (mapped && isIPv4(mapped)) return !NON_PUBLIC.check(mapped, 'ipv4')
+  return !NON_PUBLIC.check(address, family === 6 ? 'ipv6' : 'ipv4')
+}
+
+/**
+ * Resolves like dns.lookup, but fails unless every address is a public one. Requests
+ * connect to the addresses it returns, so a host can't pass the check and then resolve
+ * somewhere else for the connection itself.
+ */
+const publicLookup: LookupFunction = (hostname, options, callback) => {
+  dns.lookup(hostname, { ...options, all: true }, (err, addresses) => {
+    if (err) return callback(err, '')
+    if (addresses.length === 0 || !addresses.every((a) => isPublicAddress(a.address, a.family))) {
+      return callback(new Error(`${hostname} is not a public address`), '')
+    }
+    if (options.all) callback(null, addresses)
+    else callback(null, addresses[0].address, addresses[0].family)
+  })
+}
+
+// Whether `url` may be fetched at all. Hosts are checked as they're resolved (see
+// publicLookup); an address given as the host is never looked up, so it's checked here
+const isFetchable = (url: URL): boolean => {
+  if (url.protocol !== 'http:' && url.protocol !== 'https:') return false
+  const host = url.hostname.replace(/^\[|\]$/g, '')
+  const family = isIP(host)
+  return family === 0 || isPublicAddress(host, family)
+}
+
+const get = (url: URL, accept: string, signal: AbortSignal): Promise<http.IncomingMessage> =>
+  new Promise((resolve, reject) => {
+    const client = url.protocol === 'https:' ? https : http
+    client
+      .get(url, { headers: { Accept: accept }, lookup: publicLookup, signal }, resolve)
+      .on('error', reject)
+  })
+
+// The body, reading no further than `limit` bytes of it
+async function readCapped(response: http.IncomingMessage, limit: number): Promise<Buffer> {
+  const chunks: Buffer[] = []
+  let length = 0
+  // Leaving the loop early destroys the response, which stops the download
+  for await (const chunk of response) {
+    chunks.push(chunk)
+    length += chunk.length
+    if (length >= limit) break
+  }
+  return Buffer.concat(chunks).subarray(0, limit)
+}
+
+/**
+ * Fetches `url` from a public host, following redirects by hand so each hop is checked
+ * the same way. Returns at most `limit` bytes of the body, or null for anything but a
+ * success.
+ */
+async function fetchPublic(
+  url: URL,
+  accept: string,
+  limit: number,
+  signal: AbortSignal
+): Promise<{ body: Buffer; type: string; url: URL } | null> {
+  let location = url
+  for (let redirects = 0; ; redirects++) {
+    if (!isFetchable(location)) {
+      console.warn(`[LinkPreview] Not fetching ${location.host}: not a public address`)
+      return null
+    }
+    const response = await get(location, accept, signal)
+    const status = response.statusCode ?? 0
+    const next = response.headers.location
+    if (status >= 300 && status < 400 && next) {
+      response.resume()
+      if (redirects >= MAX_REDIRECTS) return null
+      location = new URL(next, location)
+      continue
+    }
+    if (status < 200 || status >= 300) {
+      response.resume()
+      return null
+    }
+    const type = response.headers['content-type'] ?? ''
+    return { body: await readCapped(response, limit), type, url: location }
+  }
+}
+
+// A thumbnail as a data: URL, which the renderer can show; null if it is too big or
+// isn't an image type it shows
+async function fetchThumbnail(url: URL, signal: AbortSignal): Promise<string | null> {
+  const image = await fetchPublic(url, IMAGE_TYPES.join(', '), MAX_IMAGE_BYTES + 1, signal)
+  const type = image?.type.split(';')[0].trim().toLowerCase()
+  if (!image || !type || !IMAGE_TYPES.includes(type)) return null
+  if (image.body.length > MAX_IMAGE_BYTES) return null
+  return `data:${type};base64,${image.body.toString('base64')}`
+}
+
+/**
+ * Fetching a preview contacts the linked site from this machine, so it is off by default.
+ */
+export async function isLinkPreviewEnabled(): Promise<boolean> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  return store.get('linkPreviews', false) as boolean
+}
+
+export async function setLinkPreviewEnabled(enabled: boolean): Promise<boolean> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('linkPreviews', enabled)
+  return enabled
+}
+
+export function extractFirstUrl(text: unknown): string | null {
+  if (typeof text !== 'string') return null
+  const match = text.match(URL_PATTERN)
+  // Trailing punctuation usually belongs to the sentence, not the link
+  return match ? match[0].replace(/[.,;:!?)\]]+$/, '') : null
+}
+
+const decodeEntities = (value: string): string =>
+  value
+    .replace(/&amp;/g, '&')
+    .replace(/&lt;/g, '<')
+    .replace(/&gt;/g, '>')
+    .replace(/&quot;/g, '"')
+    .replace(/&#39;|&#x27;/g, "'")
+
+const readMeta = (html: string, key: string): string | undefined => {
+  const tags = html.match(/<meta\s[^>]*>/gi) || []
+  for (const tag of tags) {
+    const name = tag.match(/(?:property|name)\s*=\s*["']([^"']+)["']/i)?.[1]
+    if (name?.toLowerCase() !== key) continue
+    const content = tag.match(/content\s*=\s*["']([^"']*)["']/i)?.[1]
+    if (content) return decodeEntities(content.trim())
+  }
+  return undefined
+}
+
+/**
+ * Links come from peers too, so only hosts on the public internet are fetched from (see
+ * fetchPublic). The page's image, if any, is fetched the same way and inlined.
+ */
+export async function fetchLinkPreview(url: string): Promise<LinkPreview | null> {
+  const controller = new AbortController()
+  const timer = setTimeout(() => controller.abort(), FETCH_TIMEOUT)
+  try {
+    const page = await fetchPublic(new URL(url), 'text/html', MAX_HTML_BYTES, controller.signal)
+    if (!page?.type.includes('text/html')) return null
+
+    const html = page.body.toString('utf8')
+    const title =
+      readMeta(html, 'og:title') ||
+      readMeta(html, 'twitter:title') ||
+      html.match(/<title[^>]*>([^<]*)<\/title>/i)?.[1]?.trim()
+    const description =
+      readMeta(html, 'og:description') ||
+      readMeta(html, 'twitter:description') ||
+      readMeta(html, 'description')
+    if (!title && !description) return null
+
+    let image: string | undefined
+    const imageUrl = readMeta(html, 'og:image') || readMeta(html, 'twitter:image')
+    if (imageUrl) {
+      try {
+        const thumbnail = await fetchThumbnail(new URL(imageUrl, page.url), controller.signal)
+        image = thumbnail ?? undefined
+      } catch (e) {
+        // The preview is still worth showing without it
+        console.warn(`[LinkPreview] Failed to fetch the image for ${url}:`, e)
+      }
+    }
+    return {
+      url,
+      title: title ? decodeEntities(title) : undefined,
+      description,
+      image,
+      siteName: readMeta(html, 'og:site_name') || new URL(url).hostname
+    }
+  } catch (e) {
+    console.warn(`[LinkPreview] Failed to fetch preview for ${url}:`, e)
+    return null
+  } finally {
+    clearTimeout(timer)
+  }
+}
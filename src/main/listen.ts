@@ -0,0 +1,132 @@
+import net from 'net'
+import { getStoreOptions } from './profiles'
+
+export interface PortRange {
+  start: number
+  end: number
+}
+
+const DEFAULT_PORT_RANGE: PortRange = { start: 52900, end: 52900 }
+// However wide the configured range, only this many of its ports are tried
+const MAX_PORT_ATTEMPTS = 100
+
+// The ports the TCP server tries first, in order, before letting the OS pick one
+export async function getPortRange(): Promise<PortRange> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  return store.get('portRange', DEFAULT_PORT_RANGE) as PortRange
+}
+
+// Takes effect the next time the server starts
+export async function setPortRange(start: number, end: number): Promise<PortRange> {
+  const valid = (port: number): boolean => Number.isInteger(port) && port >= 1 && port <= 65535
+  if (!valid(start) || !valid(end) || end < start) {
+    throw new Error(`Invalid port range: ${start}-${end}`)
+  }
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  const range = { start, end }
+  store.set('portRange', range)
+  return range
+}
+
+/**
+ * Options for the sockets the TCP server accepts and the file streams we open, for those
+ * tuning fast links: whether other listeners may bind the same port (SO_REUSEPORT, where
+ * the platform has it), how many connections may wait to be accepted, and how many bytes
+ * a socket buffers before writes wait and reads pause. SO_REUSEADDR needs no setting:
+ * Node always sets it on listening sockets, except on Windows, where binding is exclusive.
+ */
+export interface SocketTuning {
+  reusePort: boolean
+  backlog: number
+  bufferSize: number
+}
+
+// Where Node can set SO_REUSEPORT on a TCP listener
+const REUSE_PORT_PLATFORMS = ['linux', 'freebsd']
+const DEFAULT_SOCKET_TUNING: SocketTuning = {
+  reusePort: false,
+  // macOS caps the backlog at kern.ipc.somaxconn, which is 128 out of the box
+  backlog: process.platform === 'darwin' ? 128 : 511,
+  bufferSize: 4 * 1024 * 1024
+}
+const MAX_BACKLOG = 65535
+const MIN_BUFFER_SIZE = 64 * 1024
+const MAX_BUFFER_SIZE = 64 * 1024 * 1024
+
+export async function getSocketTuning(): Promise<SocketTuning> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  const stored = store.get('socketTuning', {}) as Partial<SocketTuning>
+  return { ...DEFAULT_SOCKET_TUNING, ...stored }
+}
+
+// Takes effect the next time the server starts, and for file streams opened after that
+export async function setSocketTuning(tuning: SocketTuning): Promise<SocketTuning> {
+  const within = (value: number, min: number, max: number): boolean =>
+    Number.isInteger(value) && value >= min && value <= max
+  if (!within(tuning.backlog, 1, MAX_BACKLOG)) {
+    throw new Error(`Invalid backlog: ${tuning.backlog}`)
+  }
+  if (!within(tuning.bufferSize, MIN_BUFFER_SIZE, MAX_BUFFER_SIZE)) {
+    throw new Error(`Invalid buffer size: ${tuning.bufferSize}`)
+  }
+  if (tuning.reusePort && !REUSE_PORT_PLATFORMS.includes(process.platform)) {
+    throw new Error(`SO_REUSEPORT is not available on ${process.platform}`)
+  }
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  const cleaned = {
+    reusePort: !!tuning.reusePort,
+    backlog: tuning.backlog,
+    bufferSize: tuning.bufferSize
+  }
+  store.set('socketTuning', cleaned)
+  return cleaned
+}
+
+export function portsInRange({ start, end }: PortRange): number[] {
+  const ports: number[] = []
+  for (let port = start; port <= end && ports.length < MAX_PORT_ATTEMPTS; port++) {
+    ports.push(port)
+  }
+  return ports
+}
+
+/**
+ * Starts `server` on the first of `ports` that is free, or on a port the OS picks when none
+ * is, and resolves with the port it got. Any other error (no permission, no network)
+ * rejects, rather than leaving the caller waiting. `tag` prefixes log lines; `host`
+ * limits it to one address, and `tuning` sets the backlog and SO_REUSEPORT.
+ */
+export function listenOnFreePort(
+  server: net.Server,
+  ports: number[],
+  tag: string,
+  host?: string,
+  tuning?: Pick<SocketTuning, 'reusePort' | 'backlog'>
+): Promise<number> {
+  const candidates = [...ports, 0]
+  return new Promise((resolve, reject) => {
+    const tryListen = (index: number): void => {
+      const port = candidates[index]
+      server.once('error', (err: NodeJS.ErrnoException) => {
+        if (err.code === 'EADDRINUSE' && port !== 0) {
+          const next = candidates[index + 1] || 'a dynamic port'
+          console.log(`[${tag}] Port ${port} in use, trying ${next}...`)
+          tryListen(index + 1)
+        } else {
+          reject(err)
+        }
+      })
+      // Without a host: every address, IPv6 (including link-local) as well as IPv4
+      const { reusePort, backlog } = tuning ?? {}
+      server.listen({ port, host, reusePort, backlog }, () => {
+        server.removeAllListeners('error')
+        resolve((server.address() as net.AddressInfo).port)
+      })
+    }
+    tryListen(0)
+  })
+}
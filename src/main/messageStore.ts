@@ -0,0 +1,172 @@
+import fs from 'fs'
+import path from 'path'
+import { FileMetadata, MessageSearchResult, NetworkMessage } from '@shared/messageTypes'
+import { getProfileDataPath } from './profiles'
+import { isGuestMode } from './identity'
+
+const SAVE_DELAY = 1000
+
+/**
+ * Main-process copy of every conversation (1:1 threads keyed by peer deviceId,
+ * groups keyed by groupId). Lets backend features such as search work without
+ * pulling the whole history across IPC from the renderer. In guest mode it is kept only
+ * in memory, like the identity, and nothing is read from or written to disk.
+ */
+class MessageStore {
+  private conversations: Map<string, NetworkMessage[]> = new Map()
+  private saveTimer?: NodeJS.Timeout
+
+  private getStorePath(): string {
+    return path.join(getProfileDataPath(), 'messages.json')
+  }
+
+  load(): void {
+    this.conversations.clear()
+    if (isGuestMode()) return
+    const storePath = this.getStorePath()
+    if (!fs.existsSync(storePath)) return
+    try {
+      const saved: Record<string, NetworkMessage[]> = JSON.parse(
+        fs.readFileSync(storePath, 'utf-8')
+      )
+      this.conversations = new Map(Object.entries(saved))
+    } catch (e) {
+      console.error('[MessageStore] Failed to load messages:', e)
+    }
+  }
+
+  private scheduleSave(): void {
+    if (isGuestMode()) return
+    if (this.saveTimer) clearTimeout(this.saveTimer)
+    // Captured now so a profile switch before the timer fires can't misdirect the write
+    const storePath = this.getStorePath()
+    const snapshot = JSON.stringify(Object.fromEntries(this.conversations))
+    this.saveTimer = setTimeout(() => {
+      fs.promises.writeFile(storePath, snapshot).catch((e) => {
+        console.error('[MessageStore] Failed to save messages:', e)
+      })
+    }, SAVE_DELAY)
+  }
+
+  add(conversationId: string, message: NetworkMessage): void {
+    const messages = this.conversations.get(conversationId) || []
+    if (message.id && messages.some((m) => m.id === message.id)) return
+    messages.push({ ...message, status: message.status || 'sent' })
+    this.conversations.set(conversationId, messages)
+    this.scheduleSave()
+  }
+
+  /**
+   * Patches a stored message. With `authorId`, only a message that device wrote is patched,
+   * so a peer can't rewrite what someone else sent. Returns whether anything changed.
+   */
+  update(
+    conversationId: string,
+    messageId: string,
+    patch: Partial<NetworkMessage>,
+    authorId?: string
+  ): boolean {
+    const messages = this.conversations.get(conversationId)
+    const index = messages?.findIndex((m) => m.id === messageId) ?? -1
+    if (!messages || index === -1) return false
+    if (authorId !== undefined && messages[index].deviceId !== authorId) return false
+    messages[index] = { ...messages[index], ...patch }
+    this.scheduleSave()
+    return true
+  }
+
+  /**
+   * Marks incoming messages in a conversation as read, or just `messageId` when given.
+   */
+  markRead(conversationId: string, localDeviceId: string, messageId?: string): void {
+    const messages = this.conversations.get(conversationId)
+    if (!messages) return
+    let changed = false
+    for (const message of messages) {
+      if (message.deviceId === localDeviceId || message.status === 'read') continue
+      if (messageId && message.id !== messageId) continue
+      message.status = 'read'
+      changed = true
+    }
+    if (changed) this.scheduleSave()
+  }
+
+  remove(conversationId: string, messageId: string): void {
+    const messages = this.conversations.get(conversationId)
+    if (!messages) return
+    this.conversations.set(conversationId, messages.filter((m) => m.id !== messageId))
+    this.scheduleSave()
+  }
+
+  clear(conversationId?: string): void {
+    if (conversationId) {
+      this.conversations.delete(conversationId)
+    } else {
+      this.conversations.clear()
+    }
+    this.scheduleSave()
+  }
+
+  getConversation(conversationId: string): NetworkMessage[] {
+    return this.conversations.get(conversationId) || []
+  }
+
+  getConversationIds(): string[] {
+    return [...this.conversations.keys()]
+  }
+
+  /**
+   * Case-insensitive search over message text and file names. Messages matching more
+   * query terms rank higher, with a bonus for the exact phrase and a small recency bias.
+   */
+  search(query: string, conversationId?: string, limit = 50): MessageSearchResult[] {
+    const needle = query.trim().toLowerCase()
+    if (!needle) return []
+    const terms = [...new Set(needle.split(/\s+/))]
+
+    const results: MessageSearchResult[] = []
+    const conversations = conversationId
+      ? [[conversationId, this.getConversation(conversationId)] as const]
+      : [...this.conversations.entries()]
+
+    for (const [id, messages] of conversations) {
+      for (const message of messages) {
+        if (message.deleted) continue
+        const text = getSearchableText(message)
+        if (!text) continue
+
+        const haystack = text.toLowerCase()
+        const highlights: [number, number][] = []
+        let matchedTerms = 0
+        for (const term of terms) {
+          let from = haystack.indexOf(term)
+          if (from !== -1) matchedTerms++
+          while (from !== -1) {
+            highlights.push([from, from + term.length])
+            from = haystack.indexOf(term, from + term.length)
+          }
+        }
+        if (matchedTerms === 0) continue
+
+        const ageDays = (Date.now() - (message.timestamp || 0)) / (24 * 60 * 60 * 1000)
+        const score =
+          matchedTerms * 10 +
+          (terms.length > 1 && haystack.includes(needle) ? 5 : 0) +
+          Math.max(0, 3 - ageDays / 30)
+
+        highlights.sort((a, b) => a[0] - b[0])
+        results.push({ conversationId: id, message, text, score, highlights })
+      }
+    }
+
+    return results.sort((a, b) => b.score - a.score).slice(0, limit)
+  }
+}
+
+function getSearchableText(message: NetworkMessage): string | undefined {
+  if (typeof message.payload === 'string') return message.payload
+  if (message.type === 'FILE_META') return (message.payload as FileMetadata)?.name
+  return undefined
+}
+
+export const messageStore = new MessageStore()
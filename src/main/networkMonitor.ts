@@ -0,0 +1,49 @@
+import os from 'os'
+import EventEmitter from 'events'
+
+/**
+ * Notices the machine moving between networks, e.g. a laptop unplugged from Ethernet that
+ * carries on over Wi-Fi. Node has no event for this, so the interface addresses are
+ * polled. Emits 'change' with the addresses that appeared and those that went away.
+ */
+
+const POLL_INTERVAL = 3000
+
+// Every non-loopback address the machine has right now
+function getLocalAddresses(): Set<string> {
+  const addresses = new Set<string>()
+  for (const entries of Object.values(os.networkInterfaces())) {
+    for (const entry of entries || []) {
+      if (!entry.internal) addresses.add(entry.address)
+    }
+  }
+  return addresses
+}
+
+class NetworkMonitor extends EventEmitter {
+  private addresses: Set<string> = new Set()
+  private timer?: NodeJS.Timeout
+
+  start(): void {
+    if (this.timer) return
+    this.addresses = getLocalAddresses()
+    this.timer = setInterval(() => this.poll(), POLL_INTERVAL)
+  }
+
+  stop(): void {
+    clearInterval(this.timer)
+    this.timer = undefined
+  }
+
+  private poll(): void {
+    const current = getLocalAddresses()
+    const added = [...current].filter((addr) => !this.addresses.has(addr))
+    const lost = [...this.addresses].filter((addr) => !current.has(addr))
+    this.addresses = current
+    if (added.length === 0 && lost.length === 0) return
+    console.log(`[Network] Interfaces changed: +[${added.join(', ')}] -[${lost.join(', ')}]`)
+    this.emit('change', added, lost)
+  }
+}
+
+export const networkMonitor = new NetworkMonitor()
@@ -0,0 +1,121 @@
+import path from 'path'
+import { hkdfSync } from 'crypto'
+import { NoiseHandshake, StaticKey, generateStaticKey, importStaticKey } from './crypto/noise'
+import { deriveSessionKey, wipe } from './crypto/sessionKey'
+import { getDeviceInfo, isGuestMode } from './identity'
+import { getProfileDataPath, getStoreOptions } from './profiles'
+import { recordSecurityEvent } from './securityLog'
+import { readSecret, writeSecret } from './keychain'
+
+/**
+ * Noise XX (see crypto/noise.ts) in place of the plain ECDH handshake, between peers that
+ * both offer it. The dialer's HELLO_SECURE carries the first message next to its plain
+ * public key, so an older peer can still answer the old way; the answer carries the second
+ * message and a HANDSHAKE_FINISH frame the third. Both ends prove they hold a long-term
+ * static key, pinned the first time it is seen like a TLS certificate, and both device IDs
+ * are in the prologue, so the handshake is bound to who each end says it is. Once a peer's
+ * key is pinned, it can't fall back to the plain handshake.
+ */
+
+let staticKey: { key: StaticKey; deviceId: string } | null = null
+
+/**
+ * This device's static key, created on first use and kept in the OS keychain where there
+ * is one. A guest identity gets one that lives only in memory, like the identity itself.
+ */
+function getStaticKey(): StaticKey {
+  const { deviceId } = getDeviceInfo()
+  if (staticKey?.deviceId === deviceId) return staticKey.key
+
+  const file = path.join(getProfileDataPath(), 'noise-key.der')
+  const saved = isGuestMode() ? null : readSecret(file)
+  let key: StaticKey
+  if (saved) {
+    key = importStaticKey(saved)
+    wipe(saved)
+  } else {
+    key = generateStaticKey()
+    if (!isGuestMode()) {
+      const der = key.privateKey.export({ type: 'pkcs8', format: 'der' })
+      writeSecret(file, der)
+      wipe(der)
+    }
+  }
+  staticKey = { key, deviceId }
+  return key
+}
+
+/**
+ * A secret derived from this device's static key, for keys that have to belong to the same
+ * identity (the TLS certificate's, see tlsTransport.ts). Different `info` gives unrelated
+ * secrets, none of which reveal the static key.
+ */
+export function deriveIdentitySecret(info: string): Buffer {
+  const { d } = getStaticKey().privateKey.export({ format: 'jwk' })
+  const raw = Buffer.from(d!, 'base64url')
+  const secret = Buffer.from(hkdfSync('sha256', raw, Buffer.alloc(0), info, 32))
+  wipe(raw)
+  return secret
+}
+
+// The frame layer has one key for both directions, so it comes from both transport keys
+export function noiseSessionKey(handshake: NoiseHandshake): Buffer {
+  const keys = Buffer.concat(handshake.split())
+  const sessionKey = deriveSessionKey(keys)
+  wipe(keys)
+  return sessionKey
+}
+
+class NoiseIdentity {
+  private pins: Record<string, string> = {} // deviceId → static key (base64)
+
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    this.pins = store.get('pinnedStaticKeys', {}) as Record<string, string>
+  }
+
+  /**
+   * Our side of a handshake with `deviceId`; the side that dialed initiates. `binding`
+   * describes the connection underneath (see TlsTransport.channelBinding), so a handshake
+   * only completes if both ends see the same one.
+   */
+  begin(deviceId: string, initiator: boolean, binding = ''): NoiseHandshake {
+    const localId = getDeviceInfo().deviceId
+    const [first, second] = initiator ? [localId, deviceId] : [deviceId, localId]
+    const bound = binding ? `:${binding}` : ''
+    const prologue = Buffer.from(`hyperconnect-noise:${first}:${second}${bound}`)
+    return new NoiseHandshake(initiator, getStaticKey(), prologue)
+  }
+
+  // Whether `deviceId` has completed a Noise handshake with us before
+  isPinned(deviceId: string): boolean {
+    return !!this.pins[deviceId]
+  }
+
+  /**
+   * Checks the static key a finished handshake revealed against the one pinned for
+   * `deviceId`, pinning it if this is the first. Returns false (and logs a security event)
+   * for a mismatch.
+   */
+  verifyPeer(deviceId: string, handshake: NoiseHandshake): boolean {
+    const key = handshake.remoteStatic?.toString('base64')
+    if (!key) return false
+    const pinned = this.pins[deviceId]
+    if (pinned && pinned !== key) {
+      recordSecurityEvent('key-changed', deviceId, 'Static key does not match the pinned one')
+      return false
+    }
+    if (!pinned) this.pin(deviceId, key)
+    return true
+  }
+
+  private async pin(deviceId: string, key: string): Promise<void> {
+    this.pins[deviceId] = key
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('pinnedStaticKeys', this.pins)
+  }
+}
+
+export const noiseIdentity = new NoiseIdentity()
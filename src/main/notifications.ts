@@ -15,7 +15,11 @@ export class NotificationManager {
       return
     }
 
-    if (message.type !== 'MESSAGE' && message.type !== 'FILE_META') {
+    if (
+      message.type !== 'MESSAGE' &&
+      message.type !== 'FILE_META' &&
+      message.type !== 'GROUP_MESSAGE'
+    ) {
       return
     }
 
@@ -26,7 +30,9 @@ export class NotificationManager {
 
     const isFile = message.type === 'FILE_META'
     const title = isFile ? `File from ${deviceName}` : `New message from ${deviceName}`
-    const body = isFile ? (message.payload as FileMetadata).name : (message.payload as string)
+    const metadata = isFile ? (message.payload as FileMetadata) : undefined
+    const fileName = metadata?.sensitive ? 'Sensitive file' : metadata?.name
+    const body = isFile ? fileName : (message.payload as string)
 
     const notification = new Notification({
       title,
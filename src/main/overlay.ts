@@ -0,0 +1,214 @@
+import net from 'net'
+import os from 'os'
+import { execFile } from 'child_process'
+import { promisify } from 'util'
+import { Device } from '@shared/messageTypes'
+import { discoveryManager } from './discovery'
+import { getDeviceInfo } from './identity'
+import { getStoreOptions } from './profiles'
+import { getPortRange } from './listen'
+import { tcpServer } from './tcpServer'
+import { tlsTransport } from './tlsTransport'
+
+/**
+ * Peers on an overlay network (Tailscale, WireGuard, ZeroTier) rather than the LAN. These
+ * carry unicast fine but not mDNS multicast, so peers there are found from a list instead:
+ * Tailscale's own peer list, plus addresses the user enters. Each candidate is asked who it
+ * is with a one-line WHOIS on the TCP port, answered with what mDNS would have announced,
+ * and only while overlay networks are switched on, so we stay invisible there otherwise.
+ */
+
+const run = promisify(execFile)
+
+// Sent as the first line of a connection; the answer is one line of JSON
+export const WHOIS_REQUEST = 'HC_WHOIS\n'
+const WHOIS_TIMEOUT = 3000
+const MAX_WHOIS_REPLY = 4096
+const SCAN_INTERVAL = 60 * 1000
+
+export interface OverlayInterface {
+  name: string
+  kind: 'tailscale' | 'wireguard' | 'zerotier'
+  addresses: string[]
+}
+
+export interface OverlayStatus {
+  enabled: boolean
+  interfaces: OverlayInterface[]
+  peers: string[] // addresses entered by the user, "host" or "host:port"
+  found: number // devices currently known through an overlay
+}
+
+// Tailscale hands out 100.64.0.0/10 and fd7a:115c:a1e0::/48
+export const isOverlayAddress = (address: string): boolean => {
+  if (/^fd7a:115c:a1e0:/i.test(address)) return true
+  const match = /^100\.(\d+)\./.exec(address)
+  return !!match && Number(match[1]) >= 64 && Number(match[1]) < 128
+}
+
+export function getOverlayInterfaces(): OverlayInterface[] {
+  const found: OverlayInterface[] = []
+  for (const [name, entries] of Object.entries(os.networkInterfaces())) {
+    const addresses = (entries || []).filter((e) => !e.internal).map((e) => e.address)
+    let kind: OverlayInterface['kind'] | undefined
+    if (/^tailscale/i.test(name) || addresses.some(isOverlayAddress)) kind = 'tailscale'
+    else if (/^(wg|wireguard)/i.test(name)) kind = 'wireguard'
+    else if (/^(zt|zerotier)/i.test(name)) kind = 'zerotier'
+    if (kind && addresses.length > 0) found.push({ name, kind, addresses })
+  }
+  return found
+}
+
+// "host", "host:port" or "[v6 address]:port"; the port defaults to the one we'd listen on
+const parsePeer = (peer: string, port: number): { host: string; port: number } | null => {
+  const match = /^(?:\[([^\]]+)\]|([^:[\]]+))(?::(\d{1,5}))?$/.exec(peer.trim())
+  if (!match) return null
+  return { host: match[1] ?? match[2], port: match[3] ? Number(match[3]) : port }
+}
+
+// The peers `tailscale status` lists as online; none when Tailscale isn't installed
+async function getTailscalePeers(): Promise<string[]> {
+  try {
+    const { stdout } = await run('tailscale', ['status', '--json'], { timeout: WHOIS_TIMEOUT })
+    const status = JSON.parse(stdout) as {
+      Peer?: Record<string, { Online?: boolean; TailscaleIPs?: string[] }>
+    }
+    return Object.values(status.Peer || {})
+      .filter((peer) => peer.Online && peer.TailscaleIPs?.length)
+      .map((peer) => peer.TailscaleIPs!.find((ip) => net.isIPv4(ip)) ?? peer.TailscaleIPs![0])
+  } catch {
+    return []
+  }
+}
+
+// Asks whoever listens at `host`:`port` who it is
+function whois(host: string, port: number): Promise<Partial<Device> | null> {
+  return new Promise((resolve) => {
+    let buffer = ''
+    let result: Partial<Device> | null = null
+    const socket = net.connect({ host, port }, () => socket.write(WHOIS_REQUEST))
+    socket.setTimeout(WHOIS_TIMEOUT, () => socket.destroy())
+    socket.on('data', (chunk) => {
+      buffer += chunk.toString('utf-8')
+      const newline = buffer.indexOf('\n')
+      if (newline === -1 && buffer.length <= MAX_WHOIS_REPLY) return
+      try {
+        result = newline === -1 ? null : JSON.parse(buffer.slice(0, newline))
+      } catch {
+        result = null
+      }
+      socket.destroy()
+    })
+    socket.on('error', () => socket.destroy())
+    socket.on('close', () => resolve(result))
+  })
+}
+
+class OverlayNetwork {
+  private enabled = false
+  private peers: string[] = []
+  private found = new Set<string>()
+  private timer?: NodeJS.Timeout
+
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    this.peers = store.get('overlayPeers', []) as string[]
+    this.configure(store.get('overlayDiscovery', false) as boolean)
+  }
+
+  isEnabled(): boolean {
+    return this.enabled
+  }
+
+  getStatus(): OverlayStatus {
+    return {
+      enabled: this.enabled,
+      interfaces: getOverlayInterfaces(),
+      peers: this.peers,
+      found: this.found.size
+    }
+  }
+
+  async setEnabled(enabled: boolean): Promise<OverlayStatus> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('overlayDiscovery', enabled)
+    this.configure(enabled)
+    return this.getStatus()
+  }
+
+  async setPeers(peers: string[]): Promise<OverlayStatus> {
+    const cleaned = peers.map((peer) => peer.trim()).filter(Boolean)
+    const invalid = cleaned.find((peer) => !parsePeer(peer, 0))
+    if (invalid) throw new Error(`Invalid peer address: ${invalid}`)
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('overlayPeers', cleaned)
+    this.peers = cleaned
+    if (this.enabled) this.scan()
+    return this.getStatus()
+  }
+
+  private configure(enabled: boolean): void {
+    this.enabled = enabled
+    clearInterval(this.timer)
+    this.timer = undefined
+    if (!enabled) {
+      for (const deviceId of this.found) discoveryManager.removeRemoteDevice(deviceId, 'overlay')
+      this.found.clear()
+      return
+    }
+    this.scan()
+    this.timer = setInterval(() => this.scan(), SCAN_INTERVAL)
+  }
+
+  // Looks for peers at every address on the list; the heartbeat notices those that leave
+  async scan(): Promise<void> {
+    if (!this.enabled) return
+    const { start } = await getPortRange()
+    const candidates = [...(await getTailscalePeers()), ...this.peers]
+      .map((peer) => parsePeer(peer, start))
+      .filter((target): target is { host: string; port: number } => !!target)
+    const localDeviceId = getDeviceInfo().deviceId
+
+    await Promise.all(
+      candidates.map(async ({ host, port }) => {
+        const info = await whois(host, port)
+        if (typeof info?.deviceId !== 'string' || info.deviceId === localDeviceId) return
+        this.found.add(info.deviceId)
+        discoveryManager.addRemoteDevice({
+          deviceId: info.deviceId,
+          displayName: info.displayName || info.deviceId,
+          platform: info.platform || 'unknown',
+          appVersion: info.appVersion || '0.0.0',
+          address: host,
+          port: Number(info.port) || port,
+          tlsPort: Number(info.tlsPort) || undefined,
+          overlay: true,
+          lastSeen: Date.now(),
+          isOnline: true
+        })
+      })
+    )
+  }
+
+  // The server's side of WHOIS: what mDNS would announce, if overlays are switched on
+  answerWhois(socket: net.Socket): void {
+    if (!this.enabled) {
+      socket.destroy()
+      return
+    }
+    const { deviceId, displayName, platform, appVersion } = getDeviceInfo()
+    const tlsPort = tlsTransport.port || undefined
+    const reply = { deviceId, displayName, platform, appVersion, port: tcpServer.port, tlsPort }
+    socket.end(JSON.stringify(reply) + '\n')
+  }
+
+  stop(): void {
+    clearInterval(this.timer)
+    this.timer = undefined
+  }
+}
+
+export const overlayNetwork = new OverlayNetwork()
@@ -0,0 +1,44 @@
+import { v4 as uuidv4 } from 'uuid'
+import { ErrorCode, ErrorPayload, NetworkMessage } from '@shared/messageTypes'
+import { getDeviceInfo } from './identity'
+import { Capability, peerSupports } from './protocolVersion'
+
+const ERROR_CODES: readonly ErrorCode[] = [
+  'UNAUTHORIZED',
+  'UNSUPPORTED_VERSION',
+  'DISK_FULL',
+  'CHECKSUM_MISMATCH',
+  'RATE_LIMITED',
+  'INTERNAL'
+]
+
+/**
+ * The ERROR frame telling `deviceId` why something it sent failed, or null for a peer
+ * that predates ERROR frames and would show one as an unknown message.
+ */
+export function createErrorFrame(
+  deviceId: string,
+  code: ErrorCode,
+  ref?: string,
+  message?: string
+): NetworkMessage | null {
+  if (!peerSupports(deviceId, Capability.ERRORS)) return null
+  return {
+    type: 'ERROR',
+    deviceId: getDeviceInfo().deviceId,
+    payload: { code, ref, message } as ErrorPayload,
+    id: uuidv4(),
+    timestamp: Date.now()
+  }
+}
+
+// Checks an inbound ERROR payload; codes from newer peers that we don't know become INTERNAL
+export function parseErrorPayload(payload: unknown): ErrorPayload | null {
+  const { code, ref, message } = (payload ?? {}) as Partial<ErrorPayload>
+  if (typeof code !== 'string') return null
+  return {
+    code: ERROR_CODES.includes(code) ? code : 'INTERNAL',
+    ref: typeof ref === 'string' ? ref : undefined,
+    message: typeof message === 'string' ? message : undefined
+  }
+}
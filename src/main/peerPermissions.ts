@@ -0,0 +1,73 @@
+import { NetworkMessage } from '@shared/messageTypes'
+import { getStoreOptions } from './profiles'
+
+export type PeerCapability = 'send-files' | 'send-messages' | 'clipboard-sync' | 'remote-commands'
+
+export type PeerPermissions = Record<PeerCapability, boolean>
+
+const DEFAULT_PERMISSIONS: PeerPermissions = {
+  'send-files': true,
+  'send-messages': true,
+  'clipboard-sync': false,
+  'remote-commands': false
+}
+
+// Inbound frame types and the capability a peer needs for us to act on them.
+// Control frames (handshake, ping, acks) are always allowed.
+const FRAME_CAPABILITIES: Partial<Record<NetworkMessage['type'], PeerCapability>> = {
+  MESSAGE: 'send-messages',
+  MESSAGE_DELETE: 'send-messages',
+  MESSAGE_EDIT: 'send-messages',
+  GROUP_MESSAGE: 'send-messages',
+  GROUP_UPDATE: 'send-messages',
+  FILE_META: 'send-files'
+}
+
+class PeerPermissionManager {
+  private overrides: Record<string, Partial<PeerPermissions>> = {}
+
+  /**
+   * Loads saved per-device overrides. Frames are checked synchronously, so the
+   * overrides are kept in memory and only written back on change.
+   */
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    this.overrides = store.get('peerPermissions', {}) as Record<string, Partial<PeerPermissions>>
+  }
+
+  getPermissions(deviceId: string): PeerPermissions {
+    return { ...DEFAULT_PERMISSIONS, ...this.overrides[deviceId] }
+  }
+
+  isAllowed(deviceId: string, capability: PeerCapability): boolean {
+    return this.getPermissions(deviceId)[capability]
+  }
+
+  /**
+   * Returns false if the frame requires a capability that `senderId`, the device its
+   * connection authenticated as, lacks. The deviceId a frame carries is only its own claim,
+   * so such frames are refused outright from a connection that hasn't authenticated, or
+   * when they claim to be from another device.
+   */
+  isFrameAllowed(message: NetworkMessage, senderId: string | null): boolean {
+    const capability = FRAME_CAPABILITIES[message.type]
+    if (!capability) return true
+    if (!senderId || message.deviceId !== senderId) return false
+    return this.isAllowed(senderId, capability)
+  }
+
+  async setPermission(
+    deviceId: string,
+    capability: PeerCapability,
+    allowed: boolean
+  ): Promise<PeerPermissions> {
+    this.overrides[deviceId] = { ...this.overrides[deviceId], [capability]: allowed }
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('peerPermissions', this.overrides)
+    return this.getPermissions(deviceId)
+  }
+}
+
+export const peerPermissionManager = new PeerPermissionManager()
@@ -0,0 +1,81 @@
+import { app } from 'electron'
+import fs from 'fs'
+import path from 'path'
+
+export const DEFAULT_PROFILE = 'default'
+
+const PROFILES_PATH = path.join(app.getPath('userData'), 'profiles.json')
+const PROFILES_DIR = path.join(app.getPath('userData'), 'profiles')
+
+interface ProfilesConfig {
+  active: string
+  profiles: string[]
+}
+
+function loadProfilesConfig(): ProfilesConfig {
+  if (fs.existsSync(PROFILES_PATH)) {
+    try {
+      return JSON.parse(fs.readFileSync(PROFILES_PATH, 'utf-8'))
+    } catch (e) {
+      console.error('Failed to load profiles config:', e)
+    }
+  }
+  return { active: DEFAULT_PROFILE, profiles: [DEFAULT_PROFILE] }
+}
+
+function saveProfilesConfig(config: ProfilesConfig): void {
+  try {
+    fs.writeFileSync(PROFILES_PATH, JSON.stringify(config, null, 2))
+  } catch (e) {
+    console.error('Failed to save profiles config:', e)
+  }
+}
+
+let activeProfile = loadProfilesConfig().active
+
+export function getActiveProfile(): string {
+  return activeProfile
+}
+
+export function listProfiles(): string[] {
+  return loadProfilesConfig().profiles
+}
+
+/**
+ * Directory holding the active profile's identity, settings and trusted devices.
+ * The default profile keeps using the userData root so existing installs are unaffected.
+ */
+export function getProfileDataPath(): string {
+  if (activeProfile === DEFAULT_PROFILE) return app.getPath('userData')
+  return path.join(PROFILES_DIR, activeProfile)
+}
+
+/**
+ * Options for `new Store()` so every electron-store read/write is scoped to the active profile.
+ */
+export function getStoreOptions(): { cwd: string } {
+  return { cwd: getProfileDataPath() }
+}
+
+export function switchProfile(name: string): string {
+  const profile = name.trim()
+  if (!/^[a-zA-Z0-9 _-]{1,32}$/.test(profile)) {
+    throw new Error('Profile names may only contain letters, numbers, spaces, _ and -')
+  }
+
+  const config = loadProfilesConfig()
+  if (!config.profiles.includes(profile)) {
+    config.profiles.push(profile)
+  }
+  config.active = profile
+  saveProfilesConfig(config)
+
+  activeProfile = profile
+  const dataPath = getProfileDataPath()
+  if (!fs.existsSync(dataPath)) {
+    fs.mkdirSync(dataPath, { recursive: true })
+  }
+
+  console.log(`[Profiles] Switched to profile "${profile}"`)
+  return profile
+}
@@ -1,118 +1,298 @@
 import net from 'net'
-import { NetworkMessage, Device } from '@shared/messageTypes'
+import { BatchPayload, ConnectionInfo, NetworkMessage, Device } from '@shared/messageTypes'
 import EventEmitter from 'events'
-import { generateKeyPair, computeSharedSecret } from './crypto/ecdh'
-import { deriveSessionKey, storeSession, discardSession, getSession } from './crypto/sessionKey'
+import { v4 as uuidv4 } from 'uuid'
+import { KeyPair, generateKeyPair, computeSharedSecret } from './crypto/ecdh'
+import { DoubleRatchet } from './crypto/ratchet'
+import { completeResumption, takeResumption } from './crypto/resumption'
+import { noiseIdentity, noiseSessionKey } from './noiseHandshake'
 import {
-  encryptMessage,
-  decryptMessage,
-  isEncryptedMessage,
-  isSensitiveMessageType
-} from './crypto/messageCrypto'
+  combineSecrets,
+  decapsulate,
+  generateKemKeyPair,
+  isHybridKexSupported
+} from './crypto/hybridKex'
+import { deriveSessionKey, storeSession, discardSession, wipe } from './crypto/sessionKey'
 import { getDeviceInfo } from './identity'
+import { Capability, ProtocolHello, negotiateProtocol, peerSupports } from './protocolVersion'
+import { FrameDecoder, createHelloSecure, readFrame, sayGoodbye, writeFrame } from './wire'
+import { getFrameLimit } from './fragments'
+import { frameGuard } from './frameGuard'
+import {
+  describeConnection,
+  getLocalKeepalive,
+  negotiateKeepalive,
+  startKeepalive
+} from './keepalive'
+import { sendExtensions } from './extensions'
+import { tlsTransport } from './tlsTransport'
+import { relayClient } from './relay'
+import { punch } from './holePunch'
+import { bluetoothTransport } from './bluetooth'
+import { isBoundTo } from './addresses'
+import { recordSecurityEvent } from './securityLog'
+import { parseKeyStorage } from './keychain'
+import { Transcript, checkTranscriptMac, transcriptMac } from './crypto/transcript'
+import { downgradeGuard } from './downgradeGuard'
+import { timeoutSettings } from './timeouts'
+
+// How long ping() waits for its PONG; request() waits the control timeout by default
+const PING_TIMEOUT = 5 * 1000
+// How often connections that can no longer be written to are cleared from the pool
+const EVICT_INTERVAL = 30 * 1000
+// Most frames held for one peer while it is being reconnected to; the oldest go first
+const MAX_HELD_FRAMES = 500
+
+// Most frames one BATCH may carry; longer runs go out as several
+export const MAX_BATCH_FRAMES = 100
+
+type HelloPayload = ProtocolHello & {
+  keepalive?: unknown
+  publicKey?: string
+  resumed?: unknown // the peer's nonce, when it resumed the session our ticket describes
+  noise?: unknown // the second Noise message, when the peer took up our offer
+  kemCiphertext?: unknown // answers our ML-KEM key, with hybrid key exchange
+  transcriptMac?: unknown // binds both hellos to the session key
+  displayName?: string
+  profileImage?: string
+  statusMessage?: string
+  keyStorage?: unknown
+}
+
+interface PendingResponse {
+  deviceId: string
+  resolve: (response: NetworkMessage) => void
+  reject: (err: Error) => void
+  timer: NodeJS.Timeout
+}
 
+/**
+ * One long-lived control connection per peer carries every JSON frame: chat, receipts and
+ * file control (FILE_META, FILE_ACCEPT, FILE_ACK, ...). File bytes never share it; each
+ * stream opens its own FILE_STREAM socket (see FileTransferManager.startStreaming), so a
+ * large transfer can't hold up a chat message or a pause, and parallel ranges each get
+ * their own TCP flow.
+ *
+ * The connection is read as well as written: whatever the peer sends back on one we
+ * dialed (acks, errors, FILE_ACCEPT and FILE_REJECT, its own chat) goes through the same
+ * checks as on the server and out as 'message' (with the device the connection was made
+ * to as the sender), and responses settle request().
+ */
 export class ConnectionManager extends EventEmitter {
   private activeConnections: Map<string, net.Socket> = new Map()
+  private pendingResponses: Map<string, PendingResponse> = new Map()
+  private held = new Map<string, NetworkMessage[]>() // deviceId → frames awaiting a reconnect
+  private inbound = new WeakSet<net.Socket>() // pooled connections the peer opened
+
+  constructor() {
+    super()
+    setInterval(() => this.evictDead(), EVICT_INTERVAL).unref()
+  }
 
   async getConnection(device: Device): Promise<net.Socket> {
     if (this.activeConnections.has(device.deviceId)) {
       const socket = this.activeConnections.get(device.deviceId)!
       if (!socket.destroyed && socket.writable) return socket
       this.activeConnections.delete(device.deviceId)
-      discardSession(device.deviceId)
+      discardSession(device.deviceId, socket)
     }
 
+    if (frameGuard.isBanned(device.deviceId, device.address)) {
+      throw new Error(`${device.deviceId} is temporarily banned for sending malformed frames`)
+    }
+    const keepalive = await getLocalKeepalive()
+    // A peer known through the relay may still be reachable directly
+    const punched = device.relay ? await punch(device.deviceId) : null
+
     return new Promise((resolve, reject) => {
       console.log(`[Protocol] Attempting to connect to ${device.address}:${device.port}...`)
 
       let connected = false
-      const socket = net.connect(device.port, device.address, () => {
+      const onConnect = (): void => {
+        if (!tlsTransport.verifyPeer(socket, device.deviceId)) {
+          socket.destroy()
+          reject(new Error(`Connection to ${device.deviceId} failed its TLS certificate check`))
+          return
+        }
         connected = true
         console.log(`[Protocol] Successfully connected to ${device.address}:${device.port}`)
         socket.setNoDelay(true)
         socket.setKeepAlive(true, 1000)
 
-        // 1. Generate ephemeral key pair for this session
+        // 1. Generate ephemeral key pair for this session, and start a Noise handshake
         const { publicKey, privateKey } = generateKeyPair()
-
-        // 2. Send HELLO_SECURE with public key
-        const deviceInfo = getDeviceInfo()
-        const helloSecure: NetworkMessage = {
-          type: 'HELLO_SECURE',
-          deviceId: deviceInfo.deviceId,
-          id: 'hello-secure',
-          timestamp: Date.now(),
-          payload: {
-            publicKey,
-            displayName: deviceInfo.displayName,
-            platform: deviceInfo.platform,
-            profileImage: deviceInfo.profileImage
-          }
-        }
-
+        const binding = tlsTransport.channelBinding(socket, true)
+        const noise = noiseIdentity.begin(device.deviceId, true, binding)
+        const kemKeys = isHybridKexSupported() ? generateKemKeyPair() : undefined
+
+        // 2. Send HELLO_SECURE with public key and the first Noise message, offering to
+        // resume if we hold a ticket; the peer picks one. An ML-KEM key goes along too
+        const resumption = takeResumption(device.deviceId)
+        const helloSecure = createHelloSecure(publicKey, 'hello-secure', keepalive, {
+          resume: resumption?.request,
+          noise: noise.writeMessage().toString('base64'),
+          kem: kemKeys?.publicKey
+        })
         socket.write(JSON.stringify(helloSecure) + '\n')
 
-        // Internal handler for the handshake response with buffering
-        let handshakeBuffer = Buffer.alloc(0)
-        const onHandshakeData = (chunk: Buffer): void => {
-          handshakeBuffer = Buffer.concat([handshakeBuffer, chunk])
-
-          let offset: number
-          while ((offset = handshakeBuffer.indexOf('\n')) !== -1) {
-            const line = handshakeBuffer.slice(0, offset).toString().trim()
-            handshakeBuffer = handshakeBuffer.slice(offset + 1)
-
-            if (!line) continue
-
-            try {
-              const message: NetworkMessage = JSON.parse(line)
-
-              if (
-                message.type === 'HELLO_SECURE' &&
-                (message.payload as { publicKey: string })?.publicKey
-              ) {
-                const payload = message.payload as {
-                  publicKey: string
-                  displayName?: string
-                  profileImage?: string
-                }
-                // 3. Compute shared secret and derive session key
-                const sharedSecret = computeSharedSecret(privateKey, payload.publicKey)
-                const sessionKey = deriveSessionKey(sharedSecret)
+        // Everything the peer sends goes through one decoder; until its HELLO_SECURE
+        // arrives, nothing else is acted on
+        const decoder = new FrameDecoder('Protocol', () => getFrameLimit(device.deviceId))
+        let established = false
+        decoder.on('data', (raw: NetworkMessage) => {
+          if (established) {
+            this.handleFrame(socket, device.deviceId, raw)
+            return
+          }
+          const payload = raw.payload as HelloPayload | undefined
+          if (raw.type !== 'HELLO_SECURE') return
+          if (!(payload?.publicKey || payload?.resumed || payload?.noise)) return
+          const problem = frameGuard.check(raw, device.deviceId)
+          if (problem) {
+            frameGuard.reportMalformed(socket, device.deviceId, problem, 'Protocol', true)
+            return
+          }
 
-                storeSession(device.deviceId, { sessionKey, deviceId: device.deviceId })
-                this.activeConnections.set(device.deviceId, socket)
+          try {
+            // 3. Derive the session key the way the peer answered: from our ticket, by
+            // finishing the Noise handshake, or from the plain key exchange
+            const serverNonce = typeof payload.resumed === 'string' ? payload.resumed : undefined
+            let sessionKey: Buffer
+            let ephemeral: { ours: KeyPair; theirs: string } | undefined // a ratchet's start
+            let finishNoise: string | undefined // the last Noise message, for HANDSHAKE_FINISH
+            let how = 'established'
+            if (resumption && serverNonce) {
+              sessionKey = completeResumption(resumption, serverNonce)
+              how = 'resumed'
+            } else if (typeof payload.noise === 'string') {
+              noise.readMessage(Buffer.from(payload.noise, 'base64'))
+              if (!noiseIdentity.verifyPeer(device.deviceId, noise)) {
+                throw new Error(`Static key of ${device.deviceId} does not match the pinned one`)
+              }
+              // The handshake was bound to this connection's certificates, so they're its
+              tlsTransport.confirmPeer(socket, device.deviceId)
+              finishNoise = noise.writeMessage().toString('base64')
+              sessionKey = noiseSessionKey(noise)
+              ephemeral = noise.getEphemeralKeys()
+              how = 'established over Noise'
+            } else {
+              if (noiseIdentity.isPinned(device.deviceId)) {
+                throw new Error(`${device.deviceId} fell back to the plain handshake after Noise`)
+              }
+              const sharedSecret = computeSharedSecret(privateKey, payload.publicKey!)
+              sessionKey = deriveSessionKey(sharedSecret)
+              wipe(sharedSecret)
+              ephemeral = { ours: { publicKey, privateKey }, theirs: payload.publicKey! }
+            }
+            const protocol = negotiateProtocol(payload)
+            downgradeGuard.check(device.deviceId, protocol)
+            if (how !== 'resumed' && protocol.capabilities & Capability.HYBRID_KEX) {
+              if (!kemKeys || typeof payload.kemCiphertext !== 'string') {
+                throw new Error(`${device.deviceId} left out the ML-KEM ciphertext`)
+              }
+              const kemSecret = decapsulate(kemKeys.privateKey, payload.kemCiphertext)
+              sessionKey = combineSecrets(sessionKey, kemSecret)
+            }
 
-                // Update device info with received profile image
-                if (payload.profileImage) {
-                  device.profileImage = payload.profileImage
-                }
-                if (payload.displayName) {
-                  device.displayName = payload.displayName
+            // 4. Bind both hellos to the session key (see crypto/transcript.ts). After Noise we
+            // have it before the peer does, so our MAC goes with HANDSHAKE_FINISH; otherwise
+            // the peer's came with its HELLO_SECURE
+            const bound = (protocol.capabilities & Capability.TRANSCRIPT_MAC) !== 0
+            const transcript: Transcript = {
+              dialerId: getDeviceInfo().deviceId,
+              answererId: device.deviceId,
+              dialerHello: helloSecure.payload,
+              answererHello: payload
+            }
+            if (finishNoise) {
+              const finish: NetworkMessage = {
+                type: 'HANDSHAKE_FINISH',
+                deviceId: getDeviceInfo().deviceId,
+                timestamp: Date.now(),
+                payload: {
+                  noise: finishNoise,
+                  transcriptMac: bound ? transcriptMac(sessionKey, transcript) : undefined
                 }
-
-                console.log(`[Protocol] Secure session established with ${device.deviceId}`)
-
-                socket.removeListener('data', onHandshakeData)
-
-                // Pass any leftover data to the main listener
-                this.setupDataListener(socket, device.deviceId, handshakeBuffer)
-
-                this.emit('connected', device.deviceId, socket, device)
-                resolve(socket)
-                return
               }
-            } catch (e) {
-              console.error('[Protocol] Handshake chunk parse failed:', e)
+              socket.write(JSON.stringify(finish) + '\n')
+            } else if (
+              bound &&
+              !checkTranscriptMac(sessionKey, transcript, payload.transcriptMac)
+            ) {
+              throw new Error(`Handshake with ${device.deviceId} was altered on the way`)
+            }
+            const ratchet =
+              ephemeral && protocol.capabilities & Capability.RATCHET
+                ? new DoubleRatchet(
+                    sessionKey,
+                    ephemeral.ours,
+                    ephemeral.theirs,
+                    getDeviceInfo().deviceId,
+                    device.deviceId
+                  )
+                : undefined
+
+            storeSession(device.deviceId, {
+              sessionKey,
+              deviceId: device.deviceId,
+              protocol,
+              ratchet,
+              socket
+            })
+            wipe(privateKey, ephemeral?.ours.privateKey, resumption?.secret)
+            downgradeGuard.remember(device.deviceId, protocol)
+            this.activeConnections.set(device.deviceId, socket)
+
+            // The profile, image included, is checked before it's applied (see
+            // DiscoveryManager.updateDeviceProfile)
+            if (payload.displayName) {
+              device.displayName = payload.displayName
+              this.emit('profile', device.deviceId, {
+                displayName: payload.displayName,
+                profileImage: payload.profileImage,
+                statusMessage: payload.statusMessage,
+                keyStorage: parseKeyStorage(payload.keyStorage)
+              })
             }
-          }
-        }
 
-        socket.on('data', onHandshakeData)
-      })
+            console.log(`[Protocol] Secure session ${how} with ${device.deviceId}`)
+            established = true
+            const agreed = negotiateKeepalive(keepalive, payload.keepalive)
+            startKeepalive(socket, device.deviceId, agreed, 'Protocol')
+            sendExtensions(socket, device.deviceId, 'Protocol')
+
+            this.emit('connected', device.deviceId, socket, device)
+            this.sendHeldFrames(device.deviceId)
+            resolve(socket)
+          } catch (e) {
+            console.error('[Protocol] Handshake failed:', e)
+            recordSecurityEvent('handshake-rejected', device.deviceId, (e as Error).message)
+            socket.destroy()
+            reject(e as Error)
+          }
+        })
+        decoder.on('invalid', (err: Error) => {
+          frameGuard.reportMalformed(socket, device.deviceId, err.message, 'Protocol')
+        })
+        decoder.on('error', (err) => {
+          console.error(`[Protocol] Closing connection to ${device.deviceId}:`, err)
+          frameGuard.reportMalformed(socket, device.deviceId, err.message, 'Protocol', true)
+        })
+        socket.pipe(decoder)
+      }
+      let socket: net.Socket
+      if (punched) {
+        socket = punched
+        process.nextTick(onConnect)
+      } else if (device.relay) {
+        socket = relayClient.connect(new net.Socket(), device.deviceId, onConnect)
+      } else if (device.bluetooth) {
+        socket = bluetoothTransport.createStream().connect(device.port, device.address, onConnect)
+      } else {
+        socket = tlsTransport.connect(device, onConnect)
+      }
 
-      // Set a 5-second connection timeout
-      socket.setTimeout(5000)
+      socket.setTimeout(timeoutSettings.ms('connect'))
       socket.on('timeout', () => {
         if (!connected) {
           console.error(`[Protocol] Connection timeout to ${device.address}:${device.port}`)
@@ -126,110 +306,249 @@ export class ConnectionManager extends EventEmitter {
           `[Protocol] Connection error to ${device.address}:${device.port}:`,
           err.message
         )
-        this.activeConnections.delete(device.deviceId)
-        discardSession(device.deviceId)
+        // 'close' follows, and clears the connection out
         reject(err)
       })
 
       socket.on('close', () => {
         console.log(`[Protocol] Connection closed for device ${device.deviceId}`)
-        this.activeConnections.delete(device.deviceId)
-        discardSession(device.deviceId)
-        this.emit('disconnected', device.deviceId)
+        // Still pooled means neither we nor the pool let it go: it dropped
+        const dropped = this.activeConnections.get(device.deviceId) === socket
+        if (dropped) this.activeConnections.delete(device.deviceId)
+        discardSession(device.deviceId, socket)
+        this.rejectPending(device.deviceId)
+        // A handshake still waiting on the peer won't hear from it now
+        reject(new Error(`Connection to ${device.deviceId} closed during the handshake`))
+        this.emit('disconnected', device.deviceId, dropped)
       })
     })
   }
 
-  private setupDataListener(
-    socket: net.Socket,
-    deviceId: string,
-    initialBuffer: Buffer = Buffer.alloc(0)
-  ): void {
-    let buffer = initialBuffer
-
-    const processBufferedData = (): void => {
-      let offset: number
-      while ((offset = buffer.indexOf('\n')) !== -1) {
-        const line = buffer.slice(0, offset).toString().trim()
-        buffer = buffer.slice(offset + 1)
-        if (line) {
-          this.processLine(line, socket, deviceId)
-        }
-      }
-    }
-
-    // Process initial data
-    processBufferedData()
-
-    socket.on('data', (chunk) => {
-      buffer = Buffer.concat([buffer, chunk])
-      processBufferedData()
-    })
-  }
-
-  private processLine(line: string, socket: net.Socket, deviceId: string): void {
-    if (!line.trim()) return
+  private handleFrame(socket: net.Socket, deviceId: string, raw: NetworkMessage): void {
     try {
-      const rawMessage = JSON.parse(line)
-
-      if (isEncryptedMessage(rawMessage)) {
-        const session = getSession(deviceId)
-        if (session) {
-          const decrypted = decryptMessage(rawMessage, session.sessionKey)
-          this.emit('message', decrypted, socket, true)
-        } else {
-          console.error(`[Protocol] No session key for device ${deviceId}`)
-        }
-      } else {
-        // Unencrypted message
-        this.emit('message', rawMessage, socket, false)
+      const frame = readFrame(raw, deviceId, 'Protocol')
+      if (!frame) {
+        frameGuard.reportMalformed(socket, deviceId, 'frame could not be read', 'Protocol')
+        return
+      }
+      const problem = frameGuard.check(frame.message, deviceId)
+      if (problem) {
+        frameGuard.reportMalformed(socket, deviceId, problem, 'Protocol')
+        return
       }
+      this.emit('message', frame.message, socket, frame.encrypted, deviceId)
     } catch (e) {
       console.error('Failed to parse incoming message:', e)
+      frameGuard.reportMalformed(socket, deviceId, (e as Error).message, 'Protocol')
     }
   }
 
   sendMessage(deviceId: string, message: NetworkMessage): void {
     const socket = this.activeConnections.get(deviceId)
+    const held = this.held.get(deviceId)
     if (socket && !socket.destroyed && socket.writable) {
-      const session = getSession(deviceId)
-      const isSensitive = isSensitiveMessageType(message.type)
-
-      if (session) {
-        console.log(`[Protocol] Sending encrypted ${message.type} to ${deviceId}`)
-        const encrypted = encryptMessage(message, session.sessionKey)
-        socket.write(JSON.stringify(encrypted) + '\n')
-      } else if (isSensitive) {
-        console.error(
-          `[Protocol] Refusing to send sensitive message ${message.type} without encryption to ${deviceId}`
-        )
-      } else {
-        console.warn(`[Protocol] Sending unencrypted ${message.type} (no session for ${deviceId})`)
-        socket.write(JSON.stringify(message) + '\n')
-      }
+      writeFrame(socket, deviceId, message, 'Protocol')
+    } else if (held) {
+      if (held.length >= MAX_HELD_FRAMES) held.shift()
+      held.push(message)
     } else {
       console.warn(`[Protocol] No active connection for device ${deviceId}`)
     }
   }
 
+  /**
+   * Holds frames sent to `deviceId` while it has no connection, instead of dropping them,
+   * until a new one is established (when they go out in order) or dropHeldFrames. Used
+   * while reconnecting (see reconnect.ts).
+   */
+  holdFrames(deviceId: string): void {
+    if (!this.held.has(deviceId)) this.held.set(deviceId, [])
+  }
+
+  dropHeldFrames(deviceId: string): void {
+    const held = this.held.get(deviceId)
+    this.held.delete(deviceId)
+    if (held?.length) console.warn(`[Protocol] Dropping ${held.length} frame(s) for ${deviceId}`)
+  }
+
+  private sendHeldFrames(deviceId: string): void {
+    const held = this.held.get(deviceId)
+    this.held.delete(deviceId)
+    if (!held?.length) return
+    console.log(`[Protocol] Sending ${held.length} frame(s) held for ${deviceId}`)
+    this.sendBatch(deviceId, held)
+  }
+
+  /**
+   * Sends several frames to one peer, in as few BATCH frames as possible when it
+   * understands them and one by one otherwise. Saves framing and a write per frame when a
+   * queue is flushed or receipts pile up.
+   */
+  sendBatch(deviceId: string, messages: NetworkMessage[]): void {
+    if (messages.length < 2 || !peerSupports(deviceId, Capability.BATCH)) {
+      for (const message of messages) this.sendMessage(deviceId, message)
+      return
+    }
+    for (let i = 0; i < messages.length; i += MAX_BATCH_FRAMES) {
+      const payload: BatchPayload = { frames: messages.slice(i, i + MAX_BATCH_FRAMES) }
+      this.sendMessage(deviceId, {
+        type: 'BATCH',
+        deviceId: getDeviceInfo().deviceId,
+        timestamp: Date.now(),
+        payload
+      })
+    }
+  }
+
+  /**
+   * Sends `message` and resolves with the peer's response to it, the frame whose
+   * `correlationId` is the message's id: MESSAGE_ACK for a MESSAGE, FILE_ACCEPT or
+   * FILE_REJECT for a FILE_META, PONG for a PING, or ERROR when it was refused. Rejects
+   * after `timeout`, if the connection closes first, or at once for peers that don't
+   * correlate their responses.
+   */
+  async request(
+    device: Device,
+    message: NetworkMessage,
+    timeout = timeoutSettings.ms('control')
+  ): Promise<NetworkMessage> {
+    await this.getConnection(device)
+    if (!peerSupports(device.deviceId, Capability.CORRELATION)) {
+      throw new Error(`${device.deviceId} does not correlate responses`)
+    }
+
+    const id = message.id || uuidv4()
+    return new Promise((resolve, reject) => {
+      const timer = setTimeout(() => {
+        this.pendingResponses.delete(id)
+        reject(new Error(`No response to ${message.type} from ${device.deviceId}`))
+      }, timeout)
+      this.pendingResponses.set(id, { deviceId: device.deviceId, resolve, reject, timer })
+      this.sendMessage(device.deviceId, { ...message, id })
+    })
+  }
+
+  // Hands an inbound frame to the request() awaiting it, if it is a response to one
+  settleResponse(message: NetworkMessage): void {
+    const id = message.correlationId
+    const pending = id ? this.pendingResponses.get(id) : undefined
+    if (!pending || pending.deviceId !== message.deviceId) return
+    clearTimeout(pending.timer)
+    this.pendingResponses.delete(id!)
+    pending.resolve(message)
+  }
+
+  private rejectPending(deviceId: string): void {
+    for (const [id, pending] of this.pendingResponses) {
+      if (pending.deviceId !== deviceId) continue
+      clearTimeout(pending.timer)
+      this.pendingResponses.delete(id)
+      pending.reject(new Error(`Connection to ${deviceId} closed`))
+    }
+  }
+
+  // Peers that correlate responses must answer with a PONG; older ones only have to connect
   async ping(device: Device): Promise<void> {
     try {
       await this.getConnection(device)
       const pingMsg: NetworkMessage = {
         type: 'PING',
         deviceId: getDeviceInfo().deviceId,
-        id: 'ping',
+        id: uuidv4(),
         timestamp: Date.now()
       }
-      this.sendMessage(device.deviceId, pingMsg)
+      if (peerSupports(device.deviceId, Capability.CORRELATION)) {
+        await this.request(device, pingMsg, PING_TIMEOUT)
+      } else {
+        this.sendMessage(device.deviceId, pingMsg)
+      }
     } catch (e) {
       console.error(`[Protocol] Failed to ping ${device.deviceId}:`, e)
       throw e
     }
   }
 
+  /**
+   * Pools a connection the peer opened to us, once its secure handshake has authenticated
+   * it, so replies, receipts and file control go back over it instead of a second socket of
+   * ours. Frames held for the device go out over it too, so it must never be given a
+   * connection that has only claimed a deviceId. A live connection we dialed ourselves is
+   * kept in preference.
+   */
   registerSocket(deviceId: string, socket: net.Socket): void {
+    if (this.isConnected(deviceId) || socket.destroyed) return
     this.activeConnections.set(deviceId, socket)
+    this.inbound.add(socket)
+    // The server's own close handling drops the session; this takes it out of the pool and
+    // fails the requests sent over it, which won't be answered now
+    socket.once('close', () => {
+      if (this.activeConnections.get(deviceId) !== socket) return
+      this.activeConnections.delete(deviceId)
+      this.rejectPending(deviceId)
+    })
+    this.sendHeldFrames(deviceId)
+  }
+
+  isConnected(deviceId: string): boolean {
+    const socket = this.activeConnections.get(deviceId)
+    return !!socket && !socket.destroyed && socket.writable
+  }
+
+  // The pooled connections, with how alive each looks
+  getConnections(): ConnectionInfo[] {
+    return [...this.activeConnections].map(([deviceId, socket]) =>
+      describeConnection(deviceId, socket, this.inbound.has(socket) ? 'inbound' : 'outbound')
+    )
+  }
+
+  /**
+   * Clears connections that closed or were half-closed by the peer out of the pool, where
+   * otherwise they'd stay until the next send to that device found them.
+   */
+  private evictDead(): void {
+    for (const [deviceId, socket] of this.activeConnections) {
+      if (!socket.destroyed && socket.writable) continue
+      console.log(`[Protocol] Evicting dead connection to ${deviceId}`)
+      this.activeConnections.delete(deviceId)
+      socket.destroy()
+    }
+  }
+
+  /**
+   * Closes the pooled connection to `deviceId` with a GOODBYE. It is out of the pool before
+   * it closes, so it doesn't count as dropped and isn't reconnected. Returns false if there
+   * was none.
+   */
+  disconnect(deviceId: string): boolean {
+    const socket = this.activeConnections.get(deviceId)
+    if (!socket) return false
+    this.activeConnections.delete(deviceId)
+    sayGoodbye(socket, deviceId, 'Protocol')
+    return true
+  }
+
+  getConnectedDeviceIds(): string[] {
+    return [...this.activeConnections.entries()]
+      .filter(([, socket]) => !socket.destroyed && socket.writable)
+      .map(([deviceId]) => deviceId)
+  }
+
+  // Closes connections that left through an address the machine no longer has; they would
+  // otherwise hang until keepalive gave up. The next message reconnects over the new path.
+  dropConnectionsOn(addresses: string[]): void {
+    for (const [deviceId, socket] of this.activeConnections) {
+      if (!isBoundTo(socket, addresses)) continue
+      console.log(`[Protocol] Network changed, dropping connection to ${deviceId}`)
+      socket.destroy()
+    }
+  }
+
+  disconnectAll(): void {
+    for (const [deviceId, socket] of this.activeConnections) {
+      sayGoodbye(socket, deviceId, 'Protocol')
+      discardSession(deviceId)
+    }
+    this.activeConnections.clear()
   }
 }
 
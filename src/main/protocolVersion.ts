@@ -0,0 +1,145 @@
+import { NetworkMessage } from '@shared/messageTypes'
+import { getSession } from './crypto/sessionKey'
+import { isZstdSupported } from './compression'
+import { isHybridKexSupported } from './crypto/hybridKex'
+import {
+  CipherSuite,
+  DEFAULT_CIPHER_SUITE,
+  getCipherSuites,
+  negotiateCipherSuite
+} from './crypto/cipherSuite'
+
+/**
+ * Wire protocol version, exchanged in HELLO_SECURE. Peers that don't send one are
+ * version 1: the original release, before any of the capabilities below existed.
+ */
+export const PROTOCOL_VERSION = 2
+const LEGACY_VERSION = 1
+
+// Features that change what goes over the wire; both ends must have one to use it
+export const Capability = {
+  RECEIPTS: 1 << 0, // MESSAGE_ACK / READ_RECEIPT, named MESSAGE_DELIVERED / MESSAGE_READ before
+  FOLDERS: 1 << 1, // folder manifests in FILE_META
+  FILE_CONTROL: 1 << 2, // FILE_PAUSE / FILE_RESUME / FILE_CANCEL
+  PARALLEL_STREAMS: 1 << 3, // ranged FILE_STREAM headers
+  ZSTD: 1 << 4,
+  DELTA: 1 << 5,
+  FILE_ACK: 1 << 6, // receivers ack written offsets and senders pace themselves to them
+  MSGPACK: 1 << 7, // encrypted payloads may be MessagePack instead of JSON
+  GOODBYE: 1 << 8, // an intentional disconnect is announced before the socket closes
+  FRAGMENTS: 1 << 9, // oversized frames are split into FRAGMENT frames
+  ERRORS: 1 << 10, // failures are reported back in ERROR frames
+  CORRELATION: 1 << 11, // responses carry the id of the frame they answer
+  COMPRESSED_FRAMES: 1 << 12, // large encrypted payloads may be deflated (FrameFlag.DEFLATE)
+  REPLAY_PROTECTION: 1 << 13, // encrypted frames are numbered and a number is accepted once
+  TRANSFER_STATUS: 1 << 14, // TRANSFER_STATUS_QUERY is answered with TRANSFER_STATUS
+  EXTENSIONS: 1 << 15, // optional features are listed in an EXTENSIONS frame after the handshake
+  PADDING: 1 << 16, // encrypted payloads may be padded (FrameFlag.PADDED)
+  BATCH: 1 << 17, // several small frames may travel in one BATCH frame
+  SEALED_STREAMS: 1 << 18, // file streams are AES-GCM in authenticated chunks, not AES-CTR
+  RATCHET: 1 << 19, // frames are encrypted with Double Ratchet keys, not the session key
+  RESUMPTION: 1 << 20, // SESSION_TICKET after the handshake; HELLO_SECURE may resume with it
+  HYBRID_KEX: 1 << 21, // an ML-KEM-768 secret is mixed into the session key
+  SENDER_KEYS: 1 << 22, // GROUP_MESSAGE text is encrypted with a key from SENDER_KEY
+  TRANSCRIPT_MAC: 1 << 23 // both HELLO_SECUREs are MACed with the session key
+} as const
+export type CapabilityFlag = (typeof Capability)[keyof typeof Capability]
+
+export interface PeerProtocol {
+  version: number
+  capabilities: number // bitmap of Capability flags both ends support
+  cipherSuite: CipherSuite // encrypts frames and sealed streams; see crypto/cipherSuite.ts
+}
+
+// What a handshake carries; all absent from version 1 peers, and cipherSuites from older ones
+export interface ProtocolHello {
+  protocolVersion?: number
+  capabilities?: number
+  cipherSuites?: string[] // preferred first
+}
+
+const LEGACY_PROTOCOL: PeerProtocol = {
+  version: LEGACY_VERSION,
+  capabilities: 0,
+  cipherSuite: DEFAULT_CIPHER_SUITE
+}
+
+export function getLocalCapabilities(): number {
+  let capabilities =
+    Capability.RECEIPTS |
+    Capability.FOLDERS |
+    Capability.FILE_CONTROL |
+    Capability.PARALLEL_STREAMS |
+    Capability.DELTA |
+    Capability.FILE_ACK |
+    Capability.MSGPACK |
+    Capability.GOODBYE |
+    Capability.FRAGMENTS |
+    Capability.ERRORS |
+    Capability.CORRELATION |
+    Capability.COMPRESSED_FRAMES |
+    Capability.REPLAY_PROTECTION |
+    Capability.TRANSFER_STATUS |
+    Capability.EXTENSIONS |
+    Capability.PADDING |
+    Capability.BATCH |
+    Capability.SEALED_STREAMS |
+    Capability.RATCHET |
+    Capability.RESUMPTION |
+    Capability.SENDER_KEYS |
+    Capability.TRANSCRIPT_MAC
+  if (isZstdSupported()) capabilities |= Capability.ZSTD
+  if (isHybridKexSupported()) capabilities |= Capability.HYBRID_KEX
+  return capabilities
+}
+
+// Fields to add to our HELLO_SECURE payload
+export function getProtocolHello(): Required<ProtocolHello> {
+  return {
+    protocolVersion: PROTOCOL_VERSION,
+    capabilities: getLocalCapabilities(),
+    cipherSuites: getCipherSuites()
+  }
+}
+
+/**
+ * Agrees on the lower of the two versions, the capabilities both ends have and a cipher
+ * suite. Called by both sides with the other's HELLO_SECURE, so each arrives at the same
+ * result.
+ */
+export function negotiateProtocol(remote: ProtocolHello | undefined): PeerProtocol {
+  const version = Number(remote?.protocolVersion)
+  if (!Number.isInteger(version) || version <= LEGACY_VERSION) return LEGACY_PROTOCOL
+  return {
+    version: Math.min(PROTOCOL_VERSION, version),
+    capabilities: getLocalCapabilities() & (Number(remote?.capabilities) || 0),
+    cipherSuite: negotiateCipherSuite(remote?.cipherSuites)
+  }
+}
+
+// What was agreed with a connected peer; version 1 until a session exists
+export function getPeerProtocol(deviceId: string): PeerProtocol {
+  return getSession(deviceId)?.protocol ?? LEGACY_PROTOCOL
+}
+
+export function peerSupports(deviceId: string, capability: CapabilityFlag): boolean {
+  return (getPeerProtocol(deviceId).capabilities & capability) !== 0
+}
+
+/**
+ * Rewrites an outgoing frame into the form the agreed protocol expects, so an older peer
+ * never receives a frame type it would misread or drop.
+ */
+export function toWireMessage(deviceId: string, message: NetworkMessage): NetworkMessage {
+  if (peerSupports(deviceId, Capability.RECEIPTS)) return message
+  if (message.type === 'MESSAGE_ACK') return { ...message, type: 'MESSAGE_DELIVERED' }
+  if (message.type === 'READ_RECEIPT') return { ...message, type: 'MESSAGE_READ' }
+  return message
+}
+
+// The inbound half of the shim: legacy frame names become the current ones
+export function fromWireMessage(message: NetworkMessage): NetworkMessage {
+  if (message.type === 'MESSAGE_DELIVERED') return { ...message, type: 'MESSAGE_ACK' }
+  if (message.type === 'MESSAGE_READ') return { ...message, type: 'READ_RECEIPT' }
+  return message
+}
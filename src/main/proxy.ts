@@ -0,0 +1,229 @@
+import net from 'net'
+import { getStoreOptions } from './profiles'
+
+/**
+ * Outbound connections through a proxy, for networks that only let traffic out that way
+ * or to route through Tor. Only connections that leave the LAN use it, which today means
+ * those to the relay; peers on the LAN are always reached directly. The proxy is a URL:
+ * - socks5://[user:pass@]host:port (socks5h:// too); host names are passed to the proxy
+ *   unresolved, as Tor needs
+ * - http://[user:pass@]host:port, using CONNECT
+ */
+
+const DEFAULT_PORTS: Record<string, number> = { 'socks5:': 1080, 'socks5h:': 1080, 'http:': 8080 }
+// Longest HTTP response header we'll read from a proxy
+const MAX_HTTP_HEADER = 16 * 1024
+
+let proxy: URL | null = null
+
+const parseProxy = (value: string): URL | null => {
+  if (!value) return null
+  const url = new URL(value)
+  if (!(url.protocol in DEFAULT_PORTS) || !url.hostname) {
+    throw new Error(`Unsupported proxy: ${value} (use socks5:// or http://)`)
+  }
+  return url
+}
+
+export async function loadProxy(): Promise<void> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  proxy = parseProxy(store.get('outboundProxy', '') as string)
+}
+
+export function getProxy(): string {
+  return proxy?.href ?? ''
+}
+
+// An empty string connects directly again
+export async function setProxy(value: string): Promise<string> {
+  const url = parseProxy(value.trim())
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('outboundProxy', url?.href ?? '')
+  proxy = url
+  return getProxy()
+}
+
+export const isProxyEnabled = (): boolean => proxy !== null
+
+/**
+ * Reads a proxy's replies off the socket while a tunnel is set up, then hands anything
+ * that came after them back to the socket.
+ */
+class ReplyReader {
+  private buffer = Buffer.alloc(0)
+  private wake?: () => void
+  private closed = false
+
+  constructor(private socket: net.Socket) {
+    socket.on('data', this.onData)
+    socket.once('close', this.onClose)
+  }
+
+  private onData = (chunk: Buffer): void => {
+    this.buffer = Buffer.concat([this.buffer, chunk])
+    this.wake?.()
+  }
+
+  private onClose = (): void => {
+    this.closed = true
+    this.wake?.()
+  }
+
+  private async waitFor(ready: () => boolean): Promise<void> {
+    while (!ready()) {
+      if (this.closed) throw new Error('Proxy closed the connection')
+      await new Promise<void>((resolve) => (this.wake = resolve))
+    }
+  }
+
+  async read(length: number): Promise<Buffer> {
+    await this.waitFor(() => this.buffer.length >= length)
+    const bytes = this.buffer.subarray(0, length)
+    this.buffer = this.buffer.subarray(length)
+    return bytes
+  }
+
+  async readHttpHeader(): Promise<string> {
+    const end = (): number => this.buffer.indexOf('\r\n\r\n')
+    await this.waitFor(() => {
+      if (end() === -1 && this.buffer.length > MAX_HTTP_HEADER) {
+        throw new Error('Proxy response header too long')
+      }
+      return end() !== -1
+    })
+    const header = this.buffer.subarray(0, end()).toString('latin1')
+    this.buffer = this.buffer.subarray(end() + 4)
+    return header
+  }
+
+  release(): void {
+    this.socket.removeListener('data', this.onData)
+    this.socket.removeListener('close', this.onClose)
+    this.socket.pause()
+    if (this.buffer.length > 0) this.socket.unshift(this.buffer)
+  }
+}
+
+const credentials = (url: URL): { user: string; pass: string } | null =>
+  url.username
+    ? { user: decodeURIComponent(url.username), pass: decodeURIComponent(url.password) }
+    : null
+
+const SOCKS_ERRORS: Record<number, string> = {
+  1: 'general failure',
+  2: 'not allowed by ruleset',
+  3: 'network unreachable',
+  4: 'host unreachable',
+  5: 'connection refused',
+  6: 'TTL expired',
+  7: 'command not supported',
+  8: 'address type not supported'
+}
+
+// The 16 bytes of an IPv6 address, expanding '::'
+const ipv6Bytes = (address: string): Buffer => {
+  const [head, tail = ''] = address.split('%')[0].split('::')
+  const groups = (part: string): string[] => (part ? part.split(':') : [])
+  const zeros = address.includes('::') ? 8 - groups(head).length - groups(tail).length : 0
+  const all = [...groups(head), ...Array(zeros).fill('0'), ...groups(tail)]
+  const bytes = Buffer.alloc(16)
+  all.forEach((group, i) => bytes.writeUInt16BE(parseInt(group, 16), i * 2))
+  return bytes
+}
+
+// RFC 1928, with RFC 1929 username/password authentication
+async function socks5(socket: net.Socket, url: URL, host: string, port: number): Promise<void> {
+  const reader = new ReplyReader(socket)
+  try {
+    const auth = credentials(url)
+    socket.write(Buffer.from(auth ? [5, 2, 0x00, 0x02] : [5, 1, 0x00]))
+    const [version, method] = await reader.read(2)
+    if (version !== 5 || method === 0xff) throw new Error('SOCKS proxy accepted no auth method')
+    if (method === 0x02) {
+      if (!auth) throw new Error('SOCKS proxy requires a username and password')
+      const user = Buffer.from(auth.user)
+      const pass = Buffer.from(auth.pass)
+      socket.write(
+        Buffer.concat([Buffer.from([1, user.length]), user, Buffer.from([pass.length]), pass])
+      )
+      const [, status] = await reader.read(2)
+      if (status !== 0) throw new Error('SOCKS proxy rejected the username or password')
+    }
+
+    let address: Buffer
+    if (net.isIPv4(host)) {
+      address = Buffer.from([1, ...host.split('.').map(Number)])
+    } else if (net.isIPv6(host)) {
+      address = Buffer.concat([Buffer.from([4]), ipv6Bytes(host)])
+    } else {
+      address = Buffer.concat([Buffer.from([3, Buffer.byteLength(host)]), Buffer.from(host)])
+    }
+    const portBytes = Buffer.alloc(2)
+    portBytes.writeUInt16BE(port)
+    socket.write(Buffer.concat([Buffer.from([5, 1, 0]), address, portBytes]))
+
+    const [, reply, , type] = await reader.read(4)
+    if (reply !== 0) {
+      throw new Error(`SOCKS proxy could not connect: ${SOCKS_ERRORS[reply] ?? `error ${reply}`}`)
+    }
+    // The bound address that follows isn't needed, but has to be read past
+    const length = type === 1 ? 4 : type === 4 ? 16 : (await reader.read(1))[0]
+    await reader.read(length + 2)
+  } finally {
+    reader.release()
+  }
+}
+
+async function httpConnect(
+  socket: net.Socket,
+  url: URL,
+  host: string,
+  port: number
+): Promise<void> {
+  const reader = new ReplyReader(socket)
+  try {
+    const target = net.isIPv6(host) ? `[${host}]:${port}` : `${host}:${port}`
+    let request = `CONNECT ${target} HTTP/1.1\r\nHost: ${target}\r\n`
+    const auth = credentials(url)
+    if (auth) {
+      const token = Buffer.from(`${auth.user}:${auth.pass}`).toString('base64')
+      request += `Proxy-Authorization: Basic ${token}\r\n`
+    }
+    socket.write(request + '\r\n')
+
+    const status = (await reader.readHttpHeader()).split('\r\n')[0]
+    if (!/^HTTP\/1\.[01] 2\d\d/.test(status)) throw new Error(`Proxy refused CONNECT: ${status}`)
+  } finally {
+    reader.release()
+  }
+}
+
+/**
+ * Connects `socket` to `host`:`port`, through the proxy if one is set. `onConnect` runs once
+ * the tunnel is up, and must attach its listeners before it returns; a failure destroys
+ * the socket with an error.
+ */
+export function dial(
+  socket: net.Socket,
+  host: string,
+  port: number,
+  onConnect: () => void
+): net.Socket {
+  const url = proxy
+  if (!url) return socket.connect(port, host, onConnect)
+
+  const proxyPort = Number(url.port) || DEFAULT_PORTS[url.protocol]
+  socket.connect(proxyPort, url.hostname.replace(/^\[|\]$/g, ''), () => {
+    const tunnel = url.protocol === 'http:' ? httpConnect : socks5
+    tunnel(socket, url, host, port).then(
+      () => {
+        onConnect()
+        if (!socket.destroyed) socket.resume()
+      },
+      (err: Error) => socket.destroy(err)
+    )
+  })
+  return socket
+}
@@ -0,0 +1,77 @@
+import { connectionManager } from './protocol'
+import { discoveryManager } from './discovery'
+
+// The first retry waits about this long, each one after twice as long as the last, up to
+// MAX_DELAY; after MAX_ATTEMPTS the peer is left until something else connects to it
+const BASE_DELAY = 1000
+const MAX_DELAY = 60 * 1000
+const MAX_ATTEMPTS = 10
+// Each wait is spread by this much either way, so peers that dropped together don't
+// all come back at the same moment
+const JITTER = 0.2
+
+/**
+ * Reconnects to a peer whose connection dropped (rather than being closed by either end),
+ * for as long as discovery still sees it online. A new connection goes through the full
+ * handshake, or resumes the session with a ticket. Frames sent to the peer in the meantime
+ * are held and go out once it is back (see ConnectionManager.holdFrames).
+ */
+class ReconnectManager {
+  private attempts = new Map<string, number>() // deviceId → failed attempts so far
+  private timers = new Map<string, NodeJS.Timeout>()
+
+  start(): void {
+    connectionManager.on('disconnected', (deviceId: string, dropped: boolean) => {
+      // A failed attempt closes its socket too
+      if (!dropped || this.attempts.has(deviceId)) return
+      this.attempts.set(deviceId, 0)
+      connectionManager.holdFrames(deviceId)
+      this.schedule(deviceId)
+    })
+    // However it came about, a new connection ends the retries
+    connectionManager.on('connected', (deviceId: string) => this.finish(deviceId))
+  }
+
+  private schedule(deviceId: string): void {
+    const attempt = this.attempts.get(deviceId) ?? 0
+    const delay = Math.min(MAX_DELAY, BASE_DELAY * 2 ** attempt)
+    const jittered = delay * (1 - JITTER + Math.random() * 2 * JITTER)
+    console.log(`[Reconnect] Retrying ${deviceId} in ${Math.round(jittered / 1000)}s`)
+    this.timers.set(deviceId, setTimeout(() => this.attempt(deviceId), jittered))
+  }
+
+  private async attempt(deviceId: string): Promise<void> {
+    this.timers.delete(deviceId)
+    const device = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
+    if (!device?.isOnline) {
+      console.log(`[Reconnect] ${deviceId} is gone, giving up`)
+      this.finish(deviceId, true)
+      return
+    }
+    try {
+      // Resolves at once if the peer got through to us first
+      await connectionManager.getConnection(device)
+      this.finish(deviceId)
+    } catch (e) {
+      const failed = (this.attempts.get(deviceId) ?? 0) + 1
+      console.warn(`[Reconnect] Attempt ${failed} to reach ${deviceId} failed:`, e)
+      if (failed >= MAX_ATTEMPTS) {
+        this.finish(deviceId, true)
+        return
+      }
+      this.attempts.set(deviceId, failed)
+      this.schedule(deviceId)
+    }
+  }
+
+  // Stops retrying `deviceId`; held frames are dropped if it was given up on
+  private finish(deviceId: string, givenUp = false): void {
+    if (!this.attempts.has(deviceId)) return
+    clearTimeout(this.timers.get(deviceId))
+    this.timers.delete(deviceId)
+    this.attempts.delete(deviceId)
+    if (givenUp) connectionManager.dropHeldFrames(deviceId)
+  }
+}
+
+export const reconnectManager = new ReconnectManager()
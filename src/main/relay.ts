@@ -0,0 +1,296 @@
+import net from 'net'
+import { Device, DeviceInfo } from '@shared/messageTypes'
+import { discoveryManager } from './discovery'
+import { answerPunch } from './holePunch'
+import { getDeviceInfo } from './identity'
+import { getStoreOptions } from './profiles'
+import { dial } from './proxy'
+import { isBoundTo } from './addresses'
+import { tcpServer } from './tcpServer'
+
+/**
+ * Reaches peers that aren't on the same LAN through a relay the user runs somewhere both
+ * can connect to. The relay only forwards bytes: the two ends still do the usual
+ * HELLO_SECURE handshake through it, so it never sees a session key or a plaintext frame.
+ *
+ * The relay speaks newline-delimited JSON until a connection becomes a pipe:
+ * - control connection, kept open while a relay is configured:
+ *   we send `{op: 'register', deviceId, displayName, platform, appVersion}`; the relay
+ *   sends `{op: 'peers', peers: [...]}` whenever presence changes and
+ *   `{op: 'incoming', session}` when a peer asks for us
+ * - dialing: we send `{op: 'connect', from, to}` on a new connection
+ * - answering: we send `{op: 'accept', session}` on a new connection
+ * Either data connection gets `{op: 'ready'}` once both ends are joined, or
+ * `{op: 'error', message}`, and carries exactly what a direct connection would after that.
+ * The relay also sets up direct connections where the NATs allow (see holePunch.ts).
+ */
+
+const DEFAULT_RELAY_PORT = 52903
+const RECONNECT_DELAY = 10000
+// Longest line we'll wait for from the relay before giving up on it
+const MAX_LINE = 64 * 1024
+
+export interface RelayReply {
+  op: string
+  peers?: Partial<DeviceInfo>[]
+  session?: string
+  message?: string
+  address?: string // with port, the other end's public endpoint in a punch-peer reply
+  port?: number
+}
+
+// "host", "host:port" or "[v6 address]:port"
+export const parseAddress = (address: string): { host: string; port: number } | null => {
+  const match = /^(?:\[([^\]]+)\]|([^:[\]]+))(?::(\d{1,5}))?$/.exec(address.trim())
+  if (!match) return null
+  const port = match[3] ? Number(match[3]) : DEFAULT_RELAY_PORT
+  if (port < 1 || port > 65535) return null
+  return { host: match[1] ?? match[2], port }
+}
+
+/**
+ * Reads the relay's next line off a data connection. Whatever follows it is left on the
+ * socket for the listeners `onReply` attaches, which must be in place when it returns.
+ */
+export function readReply(
+  socket: net.Socket,
+  onReply: (reply: RelayReply | null) => void
+): void {
+  let buffer = Buffer.alloc(0)
+  const onData = (chunk: Buffer): void => {
+    buffer = Buffer.concat([buffer, chunk])
+    const newline = buffer.indexOf(0x0a)
+    if (newline === -1 && buffer.length <= MAX_LINE) return
+
+    socket.removeListener('data', onData)
+    socket.pause()
+    let reply: RelayReply | null = null
+    if (newline !== -1) {
+      const rest = buffer.subarray(newline + 1)
+      if (rest.length > 0) socket.unshift(rest)
+      try {
+        reply = JSON.parse(buffer.subarray(0, newline).toString('utf-8'))
+      } catch {
+        reply = null
+      }
+    }
+    onReply(reply)
+    if (!socket.destroyed) socket.resume()
+  }
+  socket.on('data', onData)
+}
+
+export const send = (socket: net.Socket, message: object): void => {
+  socket.write(JSON.stringify(message) + '\n')
+}
+
+class RelayClient {
+  private address = ''
+  private control?: net.Socket
+  private controlOpen = false // connected, through the proxy if there is one
+  private registered = false
+  private reconnectTimer?: NodeJS.Timeout
+  private peers = new Set<string>()
+
+  // Connects to the relay the profile has configured, if any
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    this.configure(store.get('relayAddress', '') as string)
+  }
+
+  getAddress(): string {
+    return this.address
+  }
+
+  // An empty address turns the relay off
+  async setAddress(address: string): Promise<string> {
+    const trimmed = address.trim()
+    if (trimmed && !parseAddress(trimmed)) throw new Error(`Invalid relay address: ${address}`)
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('relayAddress', trimmed)
+    this.configure(trimmed)
+    return trimmed
+  }
+
+  // Host and port of the configured relay, null when there is none
+  getTarget(): { host: string; port: number } | null {
+    return parseAddress(this.address)
+  }
+
+  getStatus(): { address: string; registered: boolean; peers: number } {
+    return { address: this.address, registered: this.registered, peers: this.peers.size }
+  }
+
+  // Announces this device to the relay; sent again when its identity changes
+  register(): void {
+    if (!this.control || !this.controlOpen) return
+    const { deviceId, displayName, platform, appVersion } = getDeviceInfo()
+    send(this.control, { op: 'register', deviceId, displayName, platform, appVersion })
+  }
+
+  stop(): void {
+    this.address = ''
+    this.disconnect()
+  }
+
+  // A control connection over a network we left is closed, and reconnects over the new one
+  dropConnectionsOn(addresses: string[]): void {
+    if (this.control && isBoundTo(this.control, addresses)) this.control.destroy()
+  }
+
+  /**
+   * Opens `socket` to the relay and asks it to join us to `deviceId`. `onReady` runs once
+   * the relay has done so; a refusal destroys the socket with an error.
+   */
+  connect(socket: net.Socket, deviceId: string, onReady: () => void): net.Socket {
+    const target = parseAddress(this.address)
+    if (!target) {
+      process.nextTick(() => socket.destroy(new Error('No relay server configured')))
+      return socket
+    }
+    dial(socket, target.host, target.port, () => {
+      send(socket, { op: 'connect', from: getDeviceInfo().deviceId, to: deviceId })
+      readReply(socket, (reply) => {
+        if (reply?.op === 'ready') {
+          onReady()
+        } else {
+          const reason = reply?.message || 'unexpected reply'
+          socket.destroy(new Error(`Relay could not reach ${deviceId}: ${reason}`))
+        }
+      })
+    })
+    return socket
+  }
+
+  private configure(address: string): void {
+    this.disconnect()
+    this.address = address
+    if (address) this.connectControl()
+  }
+
+  private disconnect(): void {
+    clearTimeout(this.reconnectTimer)
+    const control = this.control
+    this.control = undefined
+    this.controlOpen = false
+    control?.destroy()
+    this.dropPeers()
+  }
+
+  private connectControl(): void {
+    const target = parseAddress(this.address)
+    if (!target) return
+    console.log(`[Relay] Connecting to ${target.host}:${target.port}...`)
+    // Listeners for the relay's lines only go on once any proxy tunnel is up
+    const socket = dial(new net.Socket(), target.host, target.port, () => {
+      console.log(`[Relay] Connected to ${target.host}:${target.port}, registering`)
+      socket.setKeepAlive(true, 1000)
+      this.controlOpen = true
+      this.register()
+
+      let buffer = ''
+      socket.setEncoding('utf-8')
+      socket.on('data', (chunk: string) => {
+        buffer += chunk
+        let newline = buffer.indexOf('\n')
+        while (newline !== -1) {
+          this.handleControl(buffer.slice(0, newline))
+          buffer = buffer.slice(newline + 1)
+          newline = buffer.indexOf('\n')
+        }
+        if (buffer.length > MAX_LINE) socket.destroy()
+      })
+    })
+    this.control = socket
+
+    socket.on('error', (err) => {
+      console.warn('[Relay] Control connection error:', err.message)
+    })
+    socket.on('close', () => {
+      if (this.control !== socket) return
+      this.control = undefined
+      this.controlOpen = false
+      this.dropPeers()
+      if (this.address) {
+        this.reconnectTimer = setTimeout(() => this.connectControl(), RECONNECT_DELAY)
+      }
+    })
+  }
+
+  private handleControl(line: string): void {
+    let reply: RelayReply
+    try {
+      reply = JSON.parse(line)
+    } catch {
+      console.warn('[Relay] Ignoring unreadable line from relay')
+      return
+    }
+    if (reply.op === 'peers' && Array.isArray(reply.peers)) {
+      this.registered = true
+      this.updatePeers(reply.peers)
+    } else if (reply.op === 'incoming' && typeof reply.session === 'string') {
+      this.accept(reply.session)
+    } else if (reply.op === 'punch' && typeof reply.session === 'string') {
+      answerPunch(reply.session)
+    } else if (reply.op === 'error') {
+      console.warn('[Relay] Relay reported an error:', reply.message)
+    }
+  }
+
+  private updatePeers(peers: Partial<DeviceInfo>[]): void {
+    const target = parseAddress(this.address)
+    if (!target) return
+    const localDeviceId = getDeviceInfo().deviceId
+    const online = new Set<string>()
+    for (const peer of peers) {
+      if (typeof peer?.deviceId !== 'string' || peer.deviceId === localDeviceId) continue
+      online.add(peer.deviceId)
+      const device: Device = {
+        deviceId: peer.deviceId,
+        displayName: peer.displayName || peer.deviceId,
+        platform: peer.platform || 'unknown',
+        appVersion: peer.appVersion || '0.0.0',
+        address: target.host,
+        port: target.port,
+        relay: true,
+        lastSeen: Date.now(),
+        isOnline: true
+      }
+      discoveryManager.addRemoteDevice(device)
+    }
+    for (const deviceId of this.peers) {
+      if (!online.has(deviceId)) discoveryManager.removeRemoteDevice(deviceId, 'relay')
+    }
+    this.peers = online
+  }
+
+  private dropPeers(): void {
+    for (const deviceId of this.peers) discoveryManager.removeRemoteDevice(deviceId, 'relay')
+    this.peers.clear()
+    this.registered = false
+  }
+
+  // Answers a peer that asked the relay for us; the joined connection is served like a LAN one
+  private accept(session: string): void {
+    const target = parseAddress(this.address)
+    if (!target) return
+    const socket = dial(new net.Socket(), target.host, target.port, () => {
+      send(socket, { op: 'accept', session })
+      readReply(socket, (reply) => {
+        if (reply?.op === 'ready') {
+          tcpServer.handleConnection(socket)
+        } else {
+          console.warn('[Relay] Could not accept relayed connection:', reply?.message)
+          socket.destroy()
+        }
+      })
+    })
+    socket.on('error', (err) => {
+      console.warn('[Relay] Relayed connection error:', err.message)
+    })
+  }
+}
+
+export const relayClient = new RelayClient()
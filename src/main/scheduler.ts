@@ -0,0 +1,143 @@
+import EventEmitter from 'events'
+import { v4 as uuidv4 } from 'uuid'
+import { NetworkMessage, ScheduledMessage } from '@shared/messageTypes'
+import { connectionManager } from './protocol'
+import { discoveryManager } from './discovery'
+import { getDeviceInfo, isGuestMode } from './identity'
+import { getStoreOptions } from './profiles'
+import { messageStore } from './messageStore'
+
+const CHECK_INTERVAL = 5000
+
+class MessageScheduler extends EventEmitter {
+  private queue: Map<string, ScheduledMessage> = new Map()
+  private timer?: NodeJS.Timeout
+  private flushing = false
+
+  // A guest's queue is kept only in memory, like the rest of its conversations
+  async load(): Promise<void> {
+    if (isGuestMode()) {
+      this.queue = new Map()
+      return
+    }
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    const saved = store.get('scheduledMessages', []) as ScheduledMessage[]
+    this.queue = new Map(saved.map((m) => [m.scheduleId, m]))
+  }
+
+  private async save(): Promise<void> {
+    if (isGuestMode()) return
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('scheduledMessages', [...this.queue.values()])
+  }
+
+  start(): void {
+    if (this.timer) return
+    this.timer = setInterval(() => this.flush(), CHECK_INTERVAL)
+    // Messages that came due while the peer was away go out as soon as it reappears
+    discoveryManager.on('deviceFound', () => this.flush())
+  }
+
+  stop(): void {
+    if (this.timer) clearInterval(this.timer)
+    this.timer = undefined
+  }
+
+  list(deviceId?: string): ScheduledMessage[] {
+    const all = [...this.queue.values()].sort((a, b) => a.sendAt - b.sendAt)
+    return deviceId ? all.filter((m) => m.deviceId === deviceId) : all
+  }
+
+  async schedule(
+    deviceId: string,
+    payload: string,
+    sendAt: number,
+    replyTo?: string
+  ): Promise<ScheduledMessage> {
+    const scheduled: ScheduledMessage = {
+      scheduleId: uuidv4(),
+      deviceId,
+      payload,
+      sendAt,
+      replyTo,
+      createdAt: Date.now()
+    }
+    this.queue.set(scheduled.scheduleId, scheduled)
+    await this.save()
+    return scheduled
+  }
+
+  async update(
+    scheduleId: string,
+    changes: { payload?: string; sendAt?: number }
+  ): Promise<ScheduledMessage> {
+    const scheduled = this.queue.get(scheduleId)
+    if (!scheduled) throw new Error('Scheduled message not found')
+    if (changes.payload !== undefined) scheduled.payload = changes.payload
+    if (changes.sendAt !== undefined) scheduled.sendAt = changes.sendAt
+    await this.save()
+    return scheduled
+  }
+
+  async cancel(scheduleId: string): Promise<boolean> {
+    const removed = this.queue.delete(scheduleId)
+    if (removed) await this.save()
+    return removed
+  }
+
+  /**
+   * Sends everything that has come due to peers that are online. A peer's messages go out
+   * together (in BATCH frames where it supports them), so a queue that built up while it
+   * was away is delivered in one go.
+   */
+  private async flush(): Promise<void> {
+    if (this.flushing) return
+    this.flushing = true
+    try {
+      const now = Date.now()
+      const due = new Map<string, ScheduledMessage[]>()
+      for (const scheduled of this.list()) {
+        if (scheduled.sendAt > now) break
+        due.set(scheduled.deviceId, [...(due.get(scheduled.deviceId) ?? []), scheduled])
+      }
+
+      for (const [deviceId, batch] of due) {
+        const device = discoveryManager.getDiscoveredDevices().find((d) => d.deviceId === deviceId)
+        // Keep them queued until the peer is reachable again
+        if (!device || !device.isOnline) continue
+
+        const messages = batch.map(
+          (scheduled): NetworkMessage => ({
+            type: 'MESSAGE',
+            deviceId: getDeviceInfo().deviceId,
+            id: uuidv4(),
+            payload: scheduled.payload,
+            timestamp: Date.now(),
+            replyTo: scheduled.replyTo
+          })
+        )
+
+        try {
+          await connectionManager.getConnection(device)
+          connectionManager.sendBatch(deviceId, messages)
+        } catch (e) {
+          console.warn(`[Scheduler] Could not send ${batch.length} message(s), will retry:`, e)
+          continue
+        }
+
+        for (const scheduled of batch) this.queue.delete(scheduled.scheduleId)
+        await this.save()
+        batch.forEach((scheduled, i) => {
+          messageStore.add(deviceId, messages[i])
+          this.emit('sent', scheduled, messages[i])
+        })
+      }
+    } finally {
+      this.flushing = false
+    }
+  }
+}
+
+export const messageScheduler = new MessageScheduler()
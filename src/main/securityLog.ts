@@ -0,0 +1,251 @@
+import fs from 'fs'
+import path from 'path'
+import { createHmac, randomBytes, timingSafeEqual } from 'crypto'
+import { getProfileDataPath } from './profiles'
+import { readSecret, writeSecret } from './keychain'
+
+export type SecurityEventType =
+  | 'plaintext-fallback'
+  | 'decryption-failed'
+  | 'key-changed'
+  | 'handshake-rejected'
+  | 'peer-blocked'
+  | 'replay-rejected'
+  | 'rate-limited'
+
+export interface SecurityEvent {
+  type: SecurityEventType
+  timestamp: number
+  deviceId?: string
+  details?: string
+  prevHash: string
+  hash: string
+}
+
+const GENESIS_HASH = '0'.repeat(64)
+// Past this the log is rotated: it becomes security-events.1.log, replacing the one
+// before, and a new file carries on the chain
+const MAX_LOG_BYTES = 1024 * 1024
+// Events are written out together, at most this long after the first of them
+const FLUSH_DELAY = 1000
+
+/**
+ * Where the chain stands in the current file: the hash its first event follows on from,
+ * the last hash and how many events there are. It is kept next to the log with a MAC, so
+ * cutting events off the end is noticed as well as changing them.
+ */
+interface ChainHead {
+  first: string
+  hash: string
+  count: number
+  mac?: string
+}
+
+interface LogPaths {
+  log: string
+  rotated: string
+  head: string
+  key: string
+}
+
+interface LogState {
+  paths: LogPaths
+  key: Buffer // HMAC key for the chain, kept in the keychain where there is one
+  head: ChainHead // including events not written out yet
+  size: number // of the current file, likewise
+  pending: string[]
+  timer?: NodeJS.Timeout
+}
+
+const getLogPaths = (): LogPaths => {
+  const dir = getProfileDataPath()
+  return {
+    log: path.join(dir, 'security-events.log'),
+    rotated: path.join(dir, 'security-events.1.log'),
+    head: path.join(dir, 'security-events.head'),
+    key: path.join(dir, 'security-log.key')
+  }
+}
+
+function hashEvent(key: Buffer, event: Omit<SecurityEvent, 'hash'>): string {
+  return createHmac('sha256', key)
+    .update(event.prevHash)
+    .update(JSON.stringify([event.type, event.timestamp, event.deviceId, event.details]))
+    .digest('hex')
+}
+
+const headMac = (key: Buffer, head: ChainHead): string =>
+  createHmac('sha256', key).update(`${head.first}:${head.hash}:${head.count}`).digest('hex')
+
+const sameHex = (a: string, b: unknown): boolean =>
+  typeof b === 'string' && a.length === b.length && timingSafeEqual(Buffer.from(a), Buffer.from(b))
+
+function readHead(headPath: string): ChainHead | null {
+  if (!fs.existsSync(headPath)) return null
+  try {
+    return JSON.parse(fs.readFileSync(headPath, 'utf-8')) as ChainHead
+  } catch (e) {
+    console.error('[Security] Failed to read the security log head:', e)
+    return null
+  }
+}
+
+let state: LogState | null = null
+// Writes happen one after another, in the order they were queued
+let writing: Promise<void> = Promise.resolve()
+
+const enqueue = (write: () => Promise<void>): void => {
+  writing = writing.then(write).catch((e) => {
+    console.error('[Security] Failed to write the security log:', e)
+  })
+}
+
+// Queues whatever is pending for writing, along with the head as it then stands
+function flush(log: LogState): void {
+  clearTimeout(log.timer)
+  log.timer = undefined
+  if (log.pending.length === 0) return
+  const lines = log.pending.join('')
+  log.pending = []
+  const head = { ...log.head, mac: headMac(log.key, log.head) }
+  enqueue(async () => {
+    await fs.promises.appendFile(log.paths.log, lines)
+    await fs.promises.writeFile(log.paths.head, JSON.stringify(head))
+  })
+}
+
+/**
+ * The log of the active profile, opened on first use (and after a profile switch). A log
+ * that can't be checked, because it predates the keyed chain or its key is gone, is set
+ * aside as the rotated one and a new chain started.
+ */
+function openLog(): LogState {
+  const paths = getLogPaths()
+  if (state?.paths.log === paths.log) return state
+  if (state) flush(state)
+
+  let key = readSecret(paths.key)
+  let head = readHead(paths.head)
+  if (!key || !head) {
+    if (!key) {
+      key = randomBytes(32)
+      writeSecret(paths.key, key)
+    }
+    if (fs.existsSync(paths.log)) fs.renameSync(paths.log, paths.rotated)
+    head = { first: GENESIS_HASH, hash: GENESIS_HASH, count: 0 }
+    fs.writeFileSync(paths.head, JSON.stringify({ ...head, mac: headMac(key, head) }))
+  }
+  const size = fs.existsSync(paths.log) ? fs.statSync(paths.log).size : 0
+  state = {
+    paths,
+    key,
+    head: { first: head.first, hash: head.hash, count: head.count },
+    size,
+    pending: []
+  }
+  return state
+}
+
+/**
+ * Appends an event to the security log. Each entry is chained to the previous one by an
+ * HMAC under a key kept in the keychain, so edited, removed or truncated entries are
+ * detected by `verifySecurityLog`. Entries are written out in batches, off the caller's
+ * path, since peers can set them off.
+ */
+export function recordSecurityEvent(
+  type: SecurityEventType,
+  deviceId?: string,
+  details?: string
+): void {
+  let log: LogState
+  try {
+    log = openLog()
+  } catch (e) {
+    console.error('[Security] Failed to open the security log:', e)
+    return
+  }
+
+  const entry: Omit<SecurityEvent, 'hash'> = {
+    type,
+    timestamp: Date.now(),
+    deviceId,
+    details,
+    prevHash: log.head.hash
+  }
+  const event: SecurityEvent = { ...entry, hash: hashEvent(log.key, entry) }
+  const line = JSON.stringify(event) + '\n'
+
+  if (log.size > 0 && log.size + Buffer.byteLength(line) > MAX_LOG_BYTES) {
+    flush(log)
+    const { log: file, rotated } = log.paths
+    enqueue(() => fs.promises.rename(file, rotated))
+    log.size = 0
+    log.head = { first: log.head.hash, hash: log.head.hash, count: 0 }
+  }
+  log.pending.push(line)
+  log.size += Buffer.byteLength(line)
+  log.head = { first: log.head.first, hash: event.hash, count: log.head.count + 1 }
+  if (!log.timer) log.timer = setTimeout(() => flush(log), FLUSH_DELAY)
+}
+
+/**
+ * Writes out anything still pending, synchronously, for when the app quits.
+ */
+export function closeSecurityLog(): void {
+  const log = state
+  if (!log || log.pending.length === 0) return
+  clearTimeout(log.timer)
+  try {
+    fs.appendFileSync(log.paths.log, log.pending.join(''))
+    const head = { ...log.head, mac: headMac(log.key, log.head) }
+    fs.writeFileSync(log.paths.head, JSON.stringify(head))
+    log.pending = []
+  } catch (e) {
+    console.error('[Security] Failed to write the security log:', e)
+  }
+}
+
+async function readEvents(logPath: string): Promise<SecurityEvent[]> {
+  try {
+    return (await fs.promises.readFile(logPath, 'utf-8'))
+      .split('\n')
+      .filter((line) => line.trim())
+      .map((line) => JSON.parse(line) as SecurityEvent)
+  } catch (e) {
+    if ((e as NodeJS.ErrnoException).code !== 'ENOENT') {
+      console.error('[Security] Failed to read security log:', e)
+    }
+    return []
+  }
+}
+
+/**
+ * Returns true if every entry's MAC matches its contents and links to its predecessor,
+ * and the chain ends where its head (itself MACed) says, with as many entries.
+ */
+function verifySecurityLog(
+  events: SecurityEvent[],
+  key: Buffer,
+  head: ChainHead | null
+): boolean {
+  if (!head || !sameHex(headMac(key, head), head.mac)) return false
+  let prevHash = head.first
+  for (const event of events) {
+    if (event.prevHash !== prevHash || !sameHex(hashEvent(key, event), event.hash)) return false
+    prevHash = event.hash
+  }
+  return events.length === head.count && prevHash === head.hash
+}
+
+export async function getSecurityEvents(
+  limit?: number
+): Promise<{ events: SecurityEvent[]; intact: boolean }> {
+  const log = openLog()
+  flush(log)
+  await writing
+  const events = await readEvents(log.paths.log)
+  return {
+    events: limit ? events.slice(-limit) : events,
+    intact: verifySecurityLog(events, log.key, readHead(log.paths.head))
+  }
+}
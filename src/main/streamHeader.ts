@@ -0,0 +1,32 @@
+/**
+ * The line that opens a raw file stream and says what follows it:
+ * "FILE_STREAM:<fileId>[:<start>[-<end>][:zstd][:delta][:sealed]]\n". Without a range the
+ * stream carries the file from `start` to its end.
+ */
+export interface StreamHeader {
+  fileId: string
+  start: number
+  end?: number
+  compression?: 'zstd'
+  delta: boolean
+  sealed: boolean
+  length: number // of the line, newline included; the stream's bytes follow it
+}
+
+const STREAM_HEADER =
+  /^FILE_STREAM:([a-zA-Z0-9-]+)(?::(\d+)(?:-(\d+))?)?(?::(zstd))?(?::(delta))?(?::(sealed))?\n/
+
+// The header at the start of `head`, or null if it isn't a well-formed one
+export function parseStreamHeader(head: string): StreamHeader | null {
+  const match = head.match(STREAM_HEADER)
+  if (!match) return null
+  return {
+    fileId: match[1],
+    start: Number(match[2] || 0),
+    end: match[3] !== undefined ? Number(match[3]) : undefined,
+    compression: match[4] === 'zstd' ? 'zstd' : undefined,
+    delta: match[5] === 'delta',
+    sealed: match[6] === 'sealed',
+    length: match[0].length
+  }
+}
@@ -1,125 +1,264 @@
 import net from 'net'
 import EventEmitter from 'events'
-import { NetworkMessage } from '@shared/messageTypes'
+import { ConnectionInfo, NetworkMessage } from '@shared/messageTypes'
 import { generateKeyPair, computeSharedSecret } from './crypto/ecdh'
-import { deriveSessionKey, storeSession, discardSession, getSession } from './crypto/sessionKey'
+import { DoubleRatchet } from './crypto/ratchet'
 import {
-  encryptMessage,
-  decryptMessage,
-  isEncryptedMessage,
-  isSensitiveMessageType
-} from './crypto/messageCrypto'
+  deriveSessionKey,
+  discardSession,
+  getSession,
+  storeSession,
+  wipe
+} from './crypto/sessionKey'
+import { acceptResumption, issueTicket } from './crypto/resumption'
+import { combineSecrets, encapsulate } from './crypto/hybridKex'
+import { recordSecurityEvent } from './securityLog'
+import { isApprovalRequired, isTrustedDevice, trustDevice } from './trustedDevices'
+import { Capability, ProtocolHello, negotiateProtocol, peerSupports } from './protocolVersion'
 import { getDeviceInfo } from './identity'
+import { FrameDecoder, createHelloSecure, readFrame, sayGoodbye, writeFrame } from './wire'
+import { getFrameLimit } from './fragments'
+import { frameGuard } from './frameGuard'
+import {
+  describeConnection,
+  getLocalKeepalive,
+  negotiateKeepalive,
+  startKeepalive
+} from './keepalive'
+import { sendExtensions } from './extensions'
+import { tlsTransport } from './tlsTransport'
+import { isBoundTo, unmapAddress } from './addresses'
+import { getPortRange, getSocketTuning, listenOnFreePort, portsInRange } from './listen'
+import { WHOIS_REQUEST, overlayNetwork } from './overlay'
+import { firewall } from './firewall'
+import { noiseIdentity, noiseSessionKey } from './noiseHandshake'
+import { connectionLimits } from './connectionLimits'
+import { Transcript, checkTranscriptMac, transcriptMac } from './crypto/transcript'
+import { downgradeGuard } from './downgradeGuard'
+import { parseKeyStorage } from './keychain'
+import { timeoutSettings } from './timeouts'
+
+// Longest "FILE_STREAM:..." header line accepted before the connection is dropped
+const MAX_STREAM_HEADER = 256
+// How many frames may arrive while a Noise handshake waits for its last message (for as
+// long as the handshake timeout allows)
+const MAX_QUEUED_FRAMES = 100
+// How long stop() lets connections close by themselves before destroying them
+const DRAIN_TIMEOUT = 5 * 1000
+// How long a new connection has to say what it is (a hello, a file stream header or a
+// WHOIS) before it is taken for a port scan or a peer that crashed, and closed
+const HELLO_GRACE = 10 * 1000
+
+export interface ConnectionRequest {
+  deviceId: string
+  displayName?: string
+  platform?: string
+  address?: string
+}
+
+interface HandshakeInProgress {
+  queued: NetworkMessage[] // frames that arrived after HELLO_SECURE
+  finish?: (message: NetworkMessage) => void // takes HANDSHAKE_FINISH, when one is awaited
+}
+
+interface PendingHandshake {
+  request: ConnectionRequest
+  resolve: () => void
+  reject: (err: Error) => void
+}
 
 export class TCPServer extends EventEmitter {
-  private server: net.Server
+  private server?: net.Server // made on each start()
   private connections: Map<string, net.Socket> = new Map()
+  private sockets = new Set<net.Socket>() // every open connection, authenticated or not
+  private pendingHandshakes: Map<string, PendingHandshake> = new Map()
   public port: number = 0
 
-  constructor() {
-    super()
-    this.server = net.createServer((socket) => this.handleConnection(socket))
-  }
-
-  async start(preferredPort: number = 52900): Promise<number> {
-    return new Promise((resolve) => {
-      const tryListen = (currentPort: number): void => {
-        this.server.listen(currentPort, '0.0.0.0', () => {
-          const address = this.server.address() as net.AddressInfo
-          this.port = address.port
-          this.server.removeAllListeners('error')
-          console.log(`TCP Server listening on port ${this.port}`)
-          resolve(this.port)
-        })
-
-        this.server.once('error', (err: { code: string }) => {
-          if (err.code === 'EADDRINUSE' && currentPort !== 0) {
-            console.log(`Port ${currentPort} in use, trying dynamic port...`)
-            tryListen(0)
-          } else {
-            console.error('TCP Server error:', err)
-          }
-        })
-      }
-
-      tryListen(preferredPort)
-    })
+  // Emits 'listening' with the port it got, which needn't be one of the configured ones
+  async start(): Promise<number> {
+    const ports = portsInRange(await getPortRange())
+    const host = firewall.getBindAddress()
+    const tuning = await getSocketTuning()
+    // Accepted sockets take their buffer size from the server, so each start makes a new one
+    const server = net.createServer({ highWaterMark: tuning.bufferSize }, (socket) =>
+      this.handleConnection(socket)
+    )
+    this.server = server
+    this.port = await listenOnFreePort(server, ports, 'Server', host, tuning)
+    console.log(`TCP Server listening on port ${this.port}`)
+    this.emit('listening', this.port)
+    return this.port
   }
 
-  private handleConnection(socket: net.Socket): void {
+  // Also given connections bridged from other transports (see webSocketBridge.ts)
+  handleConnection(socket: net.Socket): void {
+    this.sockets.add(socket)
+    socket.once('close', () => this.sockets.delete(socket))
     socket.setNoDelay(true)
     socket.setKeepAlive(true, 1000)
 
-    // Performance optimization for high-speed transfers
-    // Note: highWaterMark is read-only on the socket itself, usually set at server level
-
-    let isRawStream = false
-    let buffer = Buffer.alloc(0)
     let authenticatedDeviceId: string | null = null
+    // Offences are held against the device once it is known, its address until then
+    const remoteAddress = unmapAddress(socket.remoteAddress)
+    const peerKey = (): string | undefined => authenticatedDeviceId ?? remoteAddress
 
-    socket.on('data', (chunk) => {
-      if (isRawStream) {
-        this.emit('raw-data', socket, chunk)
+    if (frameGuard.isBanned(remoteAddress)) {
+      console.warn(`[Server] Refusing connection from banned address ${remoteAddress}`)
+      socket.destroy()
+      return
+    }
+    if (!firewall.isAllowed(remoteAddress)) {
+      console.warn(`[Server] Refusing connection from ${remoteAddress}, outside allowed networks`)
+      socket.destroy()
+      return
+    }
+    if (!connectionLimits.acquire(remoteAddress)) {
+      socket.destroy()
+      return
+    }
+    socket.once('close', () => connectionLimits.release(remoteAddress))
+    const allowFrame = connectionLimits.frameLimiter(peerKey)
+    const helloTimer = setTimeout(() => {
+      console.warn(`[Server] Nothing from ${remoteAddress} in time, closing connection`)
+      socket.destroy()
+    }, HELLO_GRACE)
+    const greeted = (): void => clearTimeout(helloTimer)
+    socket.once('close', greeted)
+
+    // NDJSON frames, from every connection that isn't a file stream
+    const decoder = new FrameDecoder('Server', () => getFrameLimit(authenticatedDeviceId))
+    // Frames that arrive while a handshake is under way wait for it to finish; the last
+    // message of a Noise handshake goes to the handshake instead
+    let handshake: HandshakeInProgress | null = null
+    const handleFrame = (rawMessage: NetworkMessage): void => {
+      // Handle Handshake
+      if (rawMessage.type === 'HELLO_SECURE') {
+        const problem = frameGuard.check(rawMessage, null)
+        if (problem) {
+          frameGuard.reportMalformed(socket, peerKey(), problem, 'Server')
+          return
+        }
+        if (!connectionLimits.allowHandshake(remoteAddress)) {
+          socket.destroy()
+          return
+        }
+        greeted()
+        const current: HandshakeInProgress = { queued: [] }
+        handshake = current
+        const awaitFinish = (): Promise<NetworkMessage> =>
+          new Promise((resolve, reject) => {
+            const settle = (): void => {
+              clearTimeout(timer)
+              socket.removeListener('close', closed)
+            }
+            const timer = setTimeout(() => {
+              settle()
+              reject(new Error('Noise handshake was not finished in time'))
+            }, timeoutSettings.ms('handshake'))
+            // Nothing more is coming from a peer that has gone
+            const closed = (): void => {
+              settle()
+              reject(new Error('Connection closed mid-handshake'))
+            }
+            socket.once('close', closed)
+            current.finish = (message) => {
+              settle()
+              resolve(message)
+            }
+          })
+        this.handleSecureHandshake(socket, rawMessage, awaitFinish)
+          .then((deviceId) => {
+            authenticatedDeviceId = deviceId
+            handshake = null
+            this.emit('authenticated', deviceId, socket)
+            current.queued.forEach(handleFrame)
+          })
+          .catch((err) => {
+            console.error('[Server] Secure handshake rejected:', err.message)
+            recordSecurityEvent('handshake-rejected', rawMessage.deviceId, err.message)
+            socket.destroy()
+          })
         return
       }
 
-      buffer = Buffer.concat([buffer, chunk])
+      try {
+        const frame = readFrame(rawMessage, authenticatedDeviceId, 'Server')
+        if (!frame) {
+          frameGuard.reportMalformed(socket, peerKey(), 'frame could not be read', 'Server')
+          return
+        }
+        const problem = frameGuard.check(frame.message, authenticatedDeviceId)
+        if (problem) {
+          frameGuard.reportMalformed(socket, peerKey(), problem, 'Server')
+          return
+        }
+        if (frame.message.type === 'HELLO') greeted()
+        this.emit('message', frame.message, socket, frame.encrypted, authenticatedDeviceId)
+      } catch (e) {
+        console.error('Failed to parse incoming message:', e)
+        frameGuard.reportMalformed(socket, peerKey(), (e as Error).message, 'Server')
+      }
+    }
+    decoder.on('data', (rawMessage: NetworkMessage) => {
+      // Past its allowance, a connection's frames are dropped until it slows down
+      if (!allowFrame()) return
+      if (rawMessage.type === 'HANDSHAKE_FINISH') {
+        handshake?.finish?.(rawMessage)
+        if (handshake) handshake.finish = undefined
+      } else if (handshake) {
+        if (handshake.queued.length >= MAX_QUEUED_FRAMES) {
+          frameGuard.reportMalformed(socket, peerKey(), 'too many frames mid-handshake', 'Server')
+          return
+        }
+        handshake.queued.push(rawMessage)
+      } else {
+        handleFrame(rawMessage)
+      }
+    })
+    decoder.on('invalid', (err: Error) => {
+      frameGuard.reportMalformed(socket, peerKey(), err.message, 'Server')
+    })
+    decoder.on('error', (err) => {
+      console.error('[Server] Closing connection:', err)
+      frameGuard.reportMalformed(socket, peerKey(), err.message, 'Server', true)
+    })
 
-      // Check for raw stream header: "FILE_STREAM:"
-      if (buffer.length >= 12 && buffer.toString('utf8', 0, 12) === 'FILE_STREAM:') {
-        isRawStream = true
-        this.emit('raw-connection', socket, buffer)
-        buffer = Buffer.alloc(0)
+    // The first bytes tell a raw file stream ("FILE_STREAM:") from a frame connection. A
+    // stream is handed over once its header line is complete, along with whatever followed
+    // it; a WHOIS from an overlay peer is answered; anything else goes to the decoder.
+    let head = Buffer.alloc(0)
+    const sniff = (chunk: Buffer): void => {
+      head = Buffer.concat([head, chunk])
+      if (head.length < 12 && !head.includes('\n')) return
+      if (head.toString('utf8') === WHOIS_REQUEST) {
+        socket.removeListener('data', sniff)
+        greeted()
+        overlayNetwork.answerWhois(socket)
         return
       }
-
-      // NDJSON / Multi-line JSON support
-      let offset: number
-      while ((offset = buffer.indexOf('\n')) !== -1) {
-        const line = buffer.slice(0, offset).toString().trim()
-        buffer = buffer.slice(offset + 1)
-
-        if (!line) continue
-
-        try {
-          const rawMessage = JSON.parse(line)
-
-          // Handle Handshake
-          if (rawMessage.type === 'HELLO_SECURE') {
-            this.handleSecureHandshake(socket, rawMessage).then((deviceId) => {
-              authenticatedDeviceId = deviceId
-            })
-            continue
-          }
-
-          // Handle Encrypted Messages
-          if (isEncryptedMessage(rawMessage)) {
-            if (authenticatedDeviceId) {
-              const session = getSession(authenticatedDeviceId)
-              if (session) {
-                const decrypted = decryptMessage(rawMessage, session.sessionKey)
-                this.emit('message', decrypted, socket, true)
-              } else {
-                console.error(
-                  `[Server] No session key for authenticated device ${authenticatedDeviceId}`
-                )
-              }
-            } else {
-              console.warn('[Server] Received encrypted message before authentication')
-            }
-          } else {
-            this.emit('message', rawMessage, socket, false)
-          }
-        } catch (e) {
-          console.error('Failed to parse incoming message:', e)
+      if (head.toString('utf8', 0, 12) === 'FILE_STREAM:') {
+        if (!head.includes('\n')) {
+          if (head.length <= MAX_STREAM_HEADER) return
+          socket.removeListener('data', sniff)
+          frameGuard.reportMalformed(socket, peerKey(), 'stream header too long', 'Server', true)
+          return
         }
+        socket.removeListener('data', sniff)
+        // From here the stream's own stall timeout applies
+        greeted()
+        this.emit('raw-connection', socket, head)
+        return
       }
-    })
+      socket.removeListener('data', sniff)
+      decoder.write(head)
+      socket.pipe(decoder)
+    }
+    socket.on('data', sniff)
 
     socket.on('close', () => {
       if (authenticatedDeviceId) {
         this.connections.delete(authenticatedDeviceId)
-        discardSession(authenticatedDeviceId)
+        discardSession(authenticatedDeviceId, socket)
         this.emit('connectionClosed', authenticatedDeviceId)
       }
     })
@@ -131,85 +270,326 @@ export class TCPServer extends EventEmitter {
 
   private async handleSecureHandshake(
     socket: net.Socket,
-    message: NetworkMessage
+    message: NetworkMessage,
+    awaitFinish: () => Promise<NetworkMessage>
   ): Promise<string> {
-    // eslint-disable-next-line @typescript-eslint/no-explicit-any
-    const remotePublicKey = (message.payload as any)?.publicKey
+    const payload = message.payload as ProtocolHello & {
+      publicKey?: string
+      noise?: unknown
+      kem?: unknown
+      displayName?: string
+      platform?: string
+      profileImage?: string
+      statusMessage?: string
+      keyStorage?: unknown
+      keepalive?: unknown
+      resume?: unknown
+    }
+    const remotePublicKey = payload?.publicKey
     const remoteDeviceId = message.deviceId
 
     if (!remotePublicKey) {
       throw new Error('Missing public key in HELLO_SECURE')
     }
+    if (frameGuard.isBanned(remoteDeviceId)) {
+      throw new Error(`${remoteDeviceId} is temporarily banned for sending malformed frames`)
+    }
+    if (!tlsTransport.verifyPeer(socket, remoteDeviceId)) {
+      throw new Error(`Connection from ${remoteDeviceId} failed its TLS certificate check`)
+    }
 
     console.log(`[Server] Received HELLO_SECURE from ${remoteDeviceId}`)
 
-    // 1. Generate local ephemeral key pair
-    const { publicKey, privateKey } = generateKeyPair()
+    // Hold handshakes from unknown devices until the user decides; a ticket doesn't get
+    // around that, since the device may have been untrusted since it was issued
+    const approval = (await isApprovalRequired()) && !(await isTrustedDevice(remoteDeviceId))
+    const resumed = approval ? undefined : acceptResumption(remoteDeviceId, payload.resume)
+    if (approval) {
+      await this.waitForApproval(socket, {
+        deviceId: remoteDeviceId,
+        displayName: payload.displayName,
+        platform: payload.platform,
+        address: unmapAddress(socket.remoteAddress)
+      })
+    }
 
-    // 2. Compute shared secret and derive session key
-    const sharedSecret = computeSharedSecret(privateKey, remotePublicKey)
-    const sessionKey = deriveSessionKey(sharedSecret)
+    const keepalive = await getLocalKeepalive()
+    const protocol = negotiateProtocol(payload)
+    downgradeGuard.check(remoteDeviceId, protocol)
+    // When we both can, an ML-KEM secret goes into the new session key too
+    let hybrid: { secret: Buffer; ciphertext: string } | undefined
+    if (!resumed && protocol.capabilities & Capability.HYBRID_KEX) {
+      if (typeof payload.kem !== 'string') {
+        throw new Error(`${remoteDeviceId} advertised hybrid key exchange without a KEM key`)
+      }
+      hybrid = encapsulate(payload.kem)
+    }
+    const withKem = (key: Buffer): Buffer => (hybrid ? combineSecrets(key, hybrid.secret) : key)
+    // Both hellos are bound to the session key when we both can (see crypto/transcript.ts)
+    const bound = (protocol.capabilities & Capability.TRANSCRIPT_MAC) !== 0
+    const transcript = (response: NetworkMessage): Transcript => ({
+      dialerId: remoteDeviceId,
+      answererId: getDeviceInfo().deviceId,
+      dialerHello: payload,
+      answererHello: response.payload
+    })
+    // Sends our HELLO_SECURE, with the MAC if we have the session key already
+    const answer = (response: NetworkMessage, sessionKey?: Buffer): void => {
+      if (bound && sessionKey) {
+        const mac = transcriptMac(sessionKey, transcript(response))
+        response.payload = { ...(response.payload as object), transcriptMac: mac }
+      }
+      socket.write(JSON.stringify(response) + '\n')
+    }
 
-    // 3. Store session
-    storeSession(remoteDeviceId, { sessionKey, deviceId: remoteDeviceId })
-    this.connections.set(remoteDeviceId, socket)
+    if (resumed) {
+      // The ticket stands in for steps 1 and 2, and there is no ratchet to start
+      storeSession(remoteDeviceId, {
+        sessionKey: resumed.sessionKey,
+        deviceId: remoteDeviceId,
+        protocol,
+        socket
+      })
+      this.connections.set(remoteDeviceId, socket)
+      const response = createHelloSecure(undefined, 'hello-secure-resp', keepalive, {
+        resumed: resumed.nonce
+      })
+      answer(response, resumed.sessionKey)
+      console.log(`[Server] Resumed session with ${remoteDeviceId} (protocol v${protocol.version})`)
+    } else if (typeof payload.noise === 'string') {
+      // 1. Answer the first Noise message, then wait for the last
+      const binding = tlsTransport.channelBinding(socket, false)
+      const noise = noiseIdentity.begin(remoteDeviceId, false, binding)
+      noise.readMessage(Buffer.from(payload.noise, 'base64'))
+      const response = createHelloSecure(undefined, 'hello-secure-resp', keepalive, {
+        noise: noise.writeMessage().toString('base64'),
+        kemCiphertext: hybrid?.ciphertext
+      })
+      answer(response)
+      const finish = (await awaitFinish()).payload as
+        | { noise?: unknown; transcriptMac?: unknown }
+        | undefined
+      noise.readMessage(Buffer.from(String(finish?.noise ?? ''), 'base64'))
+      if (!noiseIdentity.verifyPeer(remoteDeviceId, noise)) {
+        throw new Error(`Static key of ${remoteDeviceId} does not match the pinned one`)
+      }
+      // The handshake was bound to this connection's certificates, so they're its
+      tlsTransport.confirmPeer(socket, remoteDeviceId)
+
+      // 2. The dialer had the session key first, so its MAC came with the last message
+      const sessionKey = withKem(noiseSessionKey(noise))
+      if (bound && !checkTranscriptMac(sessionKey, transcript(response), finish?.transcriptMac)) {
+        wipe(sessionKey)
+        throw new Error(`Handshake with ${remoteDeviceId} was altered on the way`)
+      }
 
-    // 4. Respond with our HELLO_SECURE
-    const deviceInfo = getDeviceInfo()
-    const response: NetworkMessage = {
-      type: 'HELLO_SECURE',
-      deviceId: deviceInfo.deviceId,
-      id: 'hello-secure-resp',
-      timestamp: Date.now(),
-      payload: {
-        publicKey,
-        displayName: deviceInfo.displayName,
-        platform: deviceInfo.platform,
-        profileImage: deviceInfo.profileImage
+      // 3. Store the session, with a ratchet starting from the Noise ephemeral keys
+      const { ours, theirs } = noise.getEphemeralKeys()
+      const ratchet =
+        protocol.capabilities & Capability.RATCHET
+          ? new DoubleRatchet(sessionKey, ours, theirs, getDeviceInfo().deviceId, remoteDeviceId)
+          : undefined
+      wipe(ours.privateKey)
+      storeSession(remoteDeviceId, {
+        sessionKey,
+        deviceId: remoteDeviceId,
+        protocol,
+        ratchet,
+        socket
+      })
+      this.connections.set(remoteDeviceId, socket)
+      console.log(
+        `[Server] Noise session established with ${remoteDeviceId} (protocol v${protocol.version})`
+      )
+    } else {
+      if (noiseIdentity.isPinned(remoteDeviceId)) {
+        throw new Error(`${remoteDeviceId} fell back to the plain handshake after using Noise`)
       }
-    }
 
-    socket.write(JSON.stringify(response) + '\n')
-    console.log(`[Server] Secure session established with ${remoteDeviceId}`)
+      // 1. Generate local ephemeral key pair
+      const { publicKey, privateKey } = generateKeyPair()
+
+      // 2. Compute shared secret and derive session key
+      const sharedSecret = computeSharedSecret(privateKey, remotePublicKey)
+      const sessionKey = withKem(deriveSessionKey(sharedSecret))
+      wipe(sharedSecret)
+
+      // 3. Store session, along with the protocol version and capabilities we share
+      const ratchet =
+        protocol.capabilities & Capability.RATCHET
+          ? new DoubleRatchet(
+              sessionKey,
+              { publicKey, privateKey },
+              remotePublicKey,
+              getDeviceInfo().deviceId,
+              remoteDeviceId
+            )
+          : undefined
+      storeSession(remoteDeviceId, {
+        sessionKey,
+        deviceId: remoteDeviceId,
+        protocol,
+        ratchet,
+        socket
+      })
+      this.connections.set(remoteDeviceId, socket)
+      wipe(privateKey)
+
+      // 4. Respond with our HELLO_SECURE
+      const response = createHelloSecure(publicKey, 'hello-secure-resp', keepalive, {
+        kemCiphertext: hybrid?.ciphertext
+      })
+      answer(response, sessionKey)
+      console.log(
+        `[Server] Secure session established with ${remoteDeviceId} (protocol v${protocol.version})`
+      )
+    }
+    downgradeGuard.remember(remoteDeviceId, protocol)
+
+    // Keep the connection alive as both ends agree, and give the peer a ticket for next time
+    const agreed = negotiateKeepalive(keepalive, payload.keepalive)
+    startKeepalive(socket, remoteDeviceId, agreed, 'Server')
+    sendExtensions(socket, remoteDeviceId, 'Server')
+    this.sendTicket(socket, remoteDeviceId)
+
+    if (payload.displayName) {
+      this.emit('profile', remoteDeviceId, {
+        displayName: payload.displayName,
+        profileImage: payload.profileImage,
+        statusMessage: payload.statusMessage,
+        keyStorage: parseKeyStorage(payload.keyStorage)
+      })
+    }
 
     return remoteDeviceId
   }
 
+  private sendTicket(socket: net.Socket, deviceId: string): void {
+    const session = getSession(deviceId)
+    if (!session || !peerSupports(deviceId, Capability.RESUMPTION)) return
+    const ticket: NetworkMessage = {
+      type: 'SESSION_TICKET',
+      deviceId: getDeviceInfo().deviceId,
+      timestamp: Date.now(),
+      payload: issueTicket(deviceId, session.sessionKey)
+    }
+    writeFrame(socket, deviceId, ticket, 'Server')
+  }
+
+  private waitForApproval(socket: net.Socket, request: ConnectionRequest): Promise<void> {
+    console.log(`[Server] Holding handshake from ${request.deviceId} for user approval`)
+    return new Promise((resolve, reject) => {
+      this.pendingHandshakes.get(request.deviceId)?.reject(new Error('Superseded by new request'))
+      this.pendingHandshakes.set(request.deviceId, { request, resolve, reject })
+
+      socket.once('close', () => {
+        if (this.pendingHandshakes.get(request.deviceId)?.request === request) {
+          this.pendingHandshakes.delete(request.deviceId)
+          this.emit('connection-request-cancelled', request.deviceId)
+          reject(new Error('Peer disconnected before approval'))
+        }
+      })
+
+      this.emit('connection-request', request)
+    })
+  }
+
+  getPendingConnectionRequests(): ConnectionRequest[] {
+    return [...this.pendingHandshakes.values()].map((p) => p.request)
+  }
+
+  async acceptConnection(deviceId: string): Promise<boolean> {
+    const pending = this.pendingHandshakes.get(deviceId)
+    if (!pending) return false
+    this.pendingHandshakes.delete(deviceId)
+    await trustDevice(deviceId)
+    pending.resolve()
+    return true
+  }
+
+  denyConnection(deviceId: string): boolean {
+    const pending = this.pendingHandshakes.get(deviceId)
+    if (!pending) return false
+    this.pendingHandshakes.delete(deviceId)
+    pending.reject(new Error('Connection denied by user'))
+    return true
+  }
+
+  // Authenticated inbound connections, with how alive each looks
+  getConnections(): ConnectionInfo[] {
+    return [...this.connections].map(([deviceId, socket]) =>
+      describeConnection(deviceId, socket, 'inbound')
+    )
+  }
+
+  // Closes the authenticated inbound connection from `deviceId`, if there is one
+  disconnect(deviceId: string): boolean {
+    const socket = this.connections.get(deviceId)
+    if (!socket) return false
+    sayGoodbye(socket, deviceId, 'Server')
+    return true
+  }
+
   registerConnection(deviceId: string, socket: net.Socket): void {
     this.connections.set(deviceId, socket)
   }
 
   sendMessage(socket: net.Socket, message: NetworkMessage): void {
     const deviceId = [...this.connections.entries()].find(([, s]) => s === socket)?.[0]
+    writeFrame(socket, deviceId, message, 'Server')
+  }
 
-    if (deviceId) {
-      const session = getSession(deviceId)
-      const isSensitive = isSensitiveMessageType(message.type)
-
-      if (session) {
-        console.log(`[Server] Sending encrypted ${message.type} to ${deviceId}`)
-        const encrypted = encryptMessage(message, session.sessionKey)
-        socket.write(JSON.stringify(encrypted) + '\n')
-        return
-      } else if (isSensitive) {
-        console.error(
-          `[Server] Refusing to send sensitive message ${message.type} without encryption to ${deviceId}`
-        )
-        return
-      }
+  /**
+   * Sends a message to every authenticated inbound connection, skipping devices
+   * that are already reachable through an outbound connection.
+   */
+  broadcast(message: NetworkMessage, skipDeviceIds: string[] = []): void {
+    for (const [deviceId, socket] of this.connections) {
+      if (skipDeviceIds.includes(deviceId) || socket.destroyed || !socket.writable) continue
+      this.sendMessage(socket, message)
     }
-
-    // Fallback for non-sensitive messages or before authentication
-    console.warn(`[Server] Sending unencrypted ${message.type}`)
-    socket.write(JSON.stringify(message) + '\n')
   }
 
-  stop(): void {
-    this.server.close()
-    for (const socket of this.connections.values()) {
+  // Inbound connections that came in on an address the machine no longer has
+  dropConnectionsOn(addresses: string[]): void {
+    for (const [deviceId, socket] of this.connections) {
+      if (!isBoundTo(socket, addresses)) continue
+      console.log(`[Server] Network changed, dropping connection from ${deviceId}`)
       socket.destroy()
     }
+  }
+
+  /**
+   * Stops accepting connections and drains the open ones: authenticated peers are told
+   * GOODBYE, and every socket (file streams and handshakes awaiting approval included) is
+   * ended. Resolves once all have closed, destroying any still open after DRAIN_TIMEOUT.
+   */
+  async stop(): Promise<void> {
+    if (this.server?.listening) this.server.close()
+    for (const [deviceId, socket] of this.connections) {
+      sayGoodbye(socket, deviceId, 'Server')
+    }
     this.connections.clear()
+
+    const closed = [...this.sockets].map(
+      (socket) =>
+        new Promise<void>((resolve) => {
+          socket.once('close', () => resolve())
+          socket.end()
+        })
+    )
+    const timer = setTimeout(() => {
+      for (const socket of this.sockets) socket.destroy()
+    }, DRAIN_TIMEOUT)
+    await Promise.all(closed)
+    clearTimeout(timer)
+  }
+
+  // Drains and rebinds, on the port range and bind address as they are configured now
+  async restart(): Promise<number> {
+    console.log('[Server] Restarting')
+    await this.stop()
+    return this.start()
   }
 }
 
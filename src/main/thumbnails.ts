@@ -0,0 +1,85 @@
+import fs from 'fs'
+import path from 'path'
+import { nativeImage, NativeImage } from 'electron'
+import { getProfileDataPath, getStoreOptions } from './profiles'
+
+const THUMBNAIL_SIZE = 256
+// Previews travel inside FILE_META, so they are kept small
+const PREVIEW_SIZE = 96
+const PREVIEW_MAX_BYTES = 24 * 1024
+
+const IMAGE_PATTERN = /\.(jpe?g|png|gif|webp|bmp)$/i
+const VIDEO_PATTERN = /\.(mp4|webm|mov|m4v|mkv|avi)$/i
+
+export const isPreviewable = (name: string): boolean =>
+  IMAGE_PATTERN.test(name) || VIDEO_PATTERN.test(name)
+
+const getThumbnailDir = (): string => path.join(getProfileDataPath(), 'thumbnails')
+
+async function loadImage(filePath: string, size: number): Promise<NativeImage | null> {
+  if (IMAGE_PATTERN.test(filePath)) {
+    const image = nativeImage.createFromPath(filePath)
+    if (image.isEmpty()) return null
+    const { width, height } = image.getSize()
+    const scale = Math.min(1, size / Math.max(width, height))
+    return image.resize({
+      width: Math.round(width * scale),
+      height: Math.round(height * scale),
+      quality: 'good'
+    })
+  }
+  // Video frames come from the OS thumbnailer, which Electron only exposes on macOS/Windows
+  if (VIDEO_PATTERN.test(filePath) && process.platform !== 'linux') {
+    const image = await nativeImage.createThumbnailFromPath(filePath, { width: size, height: size })
+    return image.isEmpty() ? null : image
+  }
+  return null
+}
+
+/**
+ * Renders a thumbnail for a received image or video and caches it under the profile,
+ * returning its path. Returns null for other files or if the file can't be decoded.
+ */
+export async function createThumbnail(fileId: string, filePath: string): Promise<string | null> {
+  if (!/^[a-zA-Z0-9-]+$/.test(fileId)) return null
+  try {
+    const image = await loadImage(filePath, THUMBNAIL_SIZE)
+    if (!image) return null
+    await fs.promises.mkdir(getThumbnailDir(), { recursive: true })
+    const thumbnailPath = path.join(getThumbnailDir(), `${fileId}.jpg`)
+    await fs.promises.writeFile(thumbnailPath, image.toJPEG(75))
+    return thumbnailPath
+  } catch (e) {
+    console.error(`[Thumbnails] Failed to create thumbnail for ${filePath}:`, e)
+    return null
+  }
+}
+
+/**
+ * A tiny JPEG data URL the sender attaches to FILE_META so the receiver can see what it
+ * is being offered before accepting. Off when the user disabled file previews.
+ */
+export async function createPreview(filePath: string): Promise<string | undefined> {
+  if (!isPreviewable(filePath) || !(await isSendPreviewsEnabled())) return undefined
+  try {
+    const image = await loadImage(filePath, PREVIEW_SIZE)
+    const jpeg = image?.toJPEG(60)
+    if (!jpeg || jpeg.length > PREVIEW_MAX_BYTES) return undefined
+    return `data:image/jpeg;base64,${jpeg.toString('base64')}`
+  } catch {
+    return undefined
+  }
+}
+
+export async function isSendPreviewsEnabled(): Promise<boolean> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  return store.get('sendFilePreviews', true) as boolean
+}
+
+export async function setSendPreviewsEnabled(enabled: boolean): Promise<boolean> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('sendFilePreviews', enabled)
+  return enabled
+}
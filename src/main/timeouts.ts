@@ -0,0 +1,64 @@
+import { Timeouts } from '@shared/messageTypes'
+import { getStoreOptions } from './profiles'
+
+const DEFAULT_TIMEOUTS: Timeouts = { connect: 5, handshake: 10, control: 10, bulk: 120 }
+// Longest any of them may be set to, in seconds
+const MAX_SECONDS = 600
+
+// Whole seconds within range; bulk may be 0 for never, the others need at least a second
+const clean = (kind: keyof Timeouts, value: unknown): number => {
+  const seconds = Number(value)
+  if (!Number.isFinite(seconds)) return DEFAULT_TIMEOUTS[kind]
+  return Math.min(MAX_SECONDS, Math.max(kind === 'bulk' ? 0 : 1, Math.floor(seconds)))
+}
+
+/**
+ * How long each kind of network operation may take, in seconds, before it is given up on:
+ * - connect: opening a connection to a peer
+ * - handshake: a peer finishing its handshake with us once it has started one (time spent
+ *   waiting for the user to approve it doesn't count)
+ * - control: a request over a control connection waiting for its response
+ * - bulk: a file stream going without any data either way; 0 never gives up
+ */
+class TimeoutSettings {
+  private timeouts: Timeouts = { ...DEFAULT_TIMEOUTS }
+
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    this.timeouts = {
+      connect: clean('connect', store.get('connectTimeout', DEFAULT_TIMEOUTS.connect)),
+      handshake: clean('handshake', store.get('handshakeTimeout', DEFAULT_TIMEOUTS.handshake)),
+      control: clean('control', store.get('controlTimeout', DEFAULT_TIMEOUTS.control)),
+      bulk: clean('bulk', store.get('bulkTimeout', DEFAULT_TIMEOUTS.bulk))
+    }
+  }
+
+  getTimeouts(): Timeouts {
+    return this.timeouts
+  }
+
+  // The timeout for `kind` in milliseconds, as timers and sockets take it
+  ms(kind: keyof Timeouts): number {
+    return this.timeouts[kind] * 1000
+  }
+
+  async setTimeouts(timeouts: Timeouts): Promise<Timeouts> {
+    const cleaned: Timeouts = {
+      connect: clean('connect', timeouts.connect),
+      handshake: clean('handshake', timeouts.handshake),
+      control: clean('control', timeouts.control),
+      bulk: clean('bulk', timeouts.bulk)
+    }
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('connectTimeout', cleaned.connect)
+    store.set('handshakeTimeout', cleaned.handshake)
+    store.set('controlTimeout', cleaned.control)
+    store.set('bulkTimeout', cleaned.bulk)
+    this.timeouts = cleaned
+    return cleaned
+  }
+}
+
+export const timeoutSettings = new TimeoutSettings()
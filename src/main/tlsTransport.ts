@@ -0,0 +1,227 @@
+import fs from 'fs'
+import net from 'net'
+import path from 'path'
+import tls from 'tls'
+import { X509Certificate, createPublicKey } from 'crypto'
+import { Device } from '@shared/messageTypes'
+import {
+  Certificate,
+  createSelfSignedCertificate,
+  deriveCertificateKey
+} from './crypto/certificate'
+import { wipe } from './crypto/sessionKey'
+import { getDeviceInfo, isGuestMode } from './identity'
+import { getProfileDataPath, getStoreOptions } from './profiles'
+import { recordSecurityEvent } from './securityLog'
+import { tcpServer } from './tcpServer'
+import { listenOnFreePort } from './listen'
+import { firewall } from './firewall'
+import { deriveIdentitySecret } from './noiseHandshake'
+
+/**
+ * Standard TLS as an alternative to plain TCP, for peers that prefer it. Every device
+ * has a self-signed certificate naming its device ID, whose key is derived from its
+ * identity (Noise static) key, and both ends present theirs. There is no CA: the Noise
+ * handshake inside is bound to both certificates (see channelBinding), so once it proves
+ * the peer holds its pinned static key, the certificate it presented is pinned too and
+ * must match from then on. A device with a pinned certificate is only accepted over TLS.
+ * Frames inside are still encrypted with the session key as on any other connection, so
+ * the two transports behave the same.
+ */
+
+// Certificates are checked against pins in verifyPeer rather than against a CA
+const TLS_OPTIONS = {
+  minVersion: 'TLSv1.3',
+  requestCert: true,
+  rejectUnauthorized: false
+} as const
+
+interface Credentials extends Certificate {
+  deviceId: string
+}
+
+let credentials: Credentials | null = null
+
+const getCertificatePath = (): string => path.join(getProfileDataPath(), 'tls-cert.pem')
+
+/**
+ * This device's certificate, made on first use with a key derived from the identity key,
+ * so nothing secret is stored for it. A guest identity gets one that lives only in
+ * memory, like the identity itself.
+ */
+function getCredentials(): Credentials {
+  const { deviceId } = getDeviceInfo()
+  if (credentials?.deviceId === deviceId) return credentials
+
+  const secret = deriveIdentitySecret('hyperconnect-tls')
+  const privateKey = deriveCertificateKey(secret)
+  wipe(secret)
+  const key = privateKey.export({ type: 'pkcs8', format: 'pem' }) as string
+  const spki = createPublicKey(privateKey).export({ type: 'spki', format: 'der' })
+
+  const certPath = getCertificatePath()
+  if (!isGuestMode() && fs.existsSync(certPath)) {
+    const cert = fs.readFileSync(certPath, 'utf-8')
+    const saved = new X509Certificate(cert)
+    // One made for an earlier identity, or with a key of its own, is replaced
+    const ownKey = saved.publicKey.export({ type: 'spki', format: 'der' }).equals(spki)
+    if (saved.subject === `CN=${deviceId}` && ownKey) {
+      credentials = { key, cert, deviceId }
+      return credentials
+    }
+  }
+
+  credentials = { ...createSelfSignedCertificate(deviceId, privateKey), deviceId }
+  if (!isGuestMode()) fs.writeFileSync(certPath, credentials.cert)
+  return credentials
+}
+
+class TlsTransport {
+  private server?: tls.Server
+  private pins: Record<string, string> = {} // deviceId → SHA-256 certificate fingerprint
+  // Pins made as a guest: kept in memory only, and forgotten with the guest identity
+  private guestPins: { owner: string; pins: Record<string, string> } | null = null
+  private preferred = false
+  public port = 0
+
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    this.pins = store.get('pinnedCertificates', {}) as Record<string, string>
+    this.preferred = store.get('preferTlsTransport', false) as boolean
+    this.refresh()
+  }
+
+  isPreferred(): boolean {
+    return this.preferred
+  }
+
+  async setPreferred(preferred: boolean): Promise<boolean> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('preferTlsTransport', preferred)
+    this.preferred = preferred
+    return preferred
+  }
+
+  async start(preferredPort: number = 52902): Promise<number> {
+    if (this.server) return this.port
+    const { key, cert } = getCredentials()
+    const server = tls.createServer({ ...TLS_OPTIONS, key, cert }, (socket) =>
+      tcpServer.handleConnection(socket)
+    )
+    server.on('tlsClientError', (err) => console.warn('[TLS] Handshake failed:', err.message))
+
+    const host = firewall.getBindAddress()
+    this.port = await listenOnFreePort(server, [preferredPort], 'TLS', host)
+    this.server = server
+    console.log(`[TLS] Listening on port ${this.port}`)
+    return this.port
+  }
+
+  stop(): void {
+    this.server?.close()
+    this.server = undefined
+    this.port = 0
+  }
+
+  // Picks up a new certificate after the identity changed (guest mode, another profile)
+  refresh(): void {
+    const { key, cert } = getCredentials()
+    this.server?.setSecureContext({ key, cert })
+  }
+
+  /**
+   * Opens a connection to `device`: over TLS when we prefer it (or have its certificate
+   * pinned) and the device advertises a TLS port, over plain TCP otherwise. `onConnect`
+   * runs once the connection (and the TLS handshake, if any) is up.
+   */
+  connect(device: Device, onConnect: () => void): net.Socket {
+    // A device whose certificate is pinned is only trusted over TLS, so it gets TLS anyway
+    const tlsWanted = this.preferred || this.isPinned(device.deviceId)
+    if (!tlsWanted || !device.tlsPort) {
+      return net.connect(device.port, device.address, onConnect)
+    }
+    const { key, cert } = getCredentials()
+    return tls.connect(
+      { ...TLS_OPTIONS, host: device.address, port: device.tlsPort, key, cert },
+      onConnect
+    )
+  }
+
+  // Whether `deviceId` has had its certificate pinned, here or as this guest
+  isPinned(deviceId: string): boolean {
+    return !!(this.pins[deviceId] ?? this.getGuestPins()?.[deviceId])
+  }
+
+  /**
+   * Describes the TLS connection under a handshake by both certificates' fingerprints,
+   * dialer's first, for the Noise prologue (see NoiseIdentity.begin). Someone in the
+   * middle of the TLS connection sees different ones at each end, so the handshake fails.
+   * Empty for plain TCP.
+   */
+  channelBinding(socket: net.Socket, dialer: boolean): string {
+    if (!(socket instanceof tls.TLSSocket)) return ''
+    const ours = socket.getX509Certificate()?.fingerprint256 ?? ''
+    const theirs = socket.getPeerX509Certificate()?.fingerprint256 ?? ''
+    return dialer ? `tls:${ours}:${theirs}` : `tls:${theirs}:${ours}`
+  }
+
+  /**
+   * Checks the certificate a TLS peer presented against the one pinned for `deviceId`.
+   * Returns false (and logs a security event) for a mismatch or a missing certificate, and
+   * for a plain TCP connection from a device whose certificate is pinned.
+   */
+  verifyPeer(socket: net.Socket, deviceId: string): boolean {
+    if (!(socket instanceof tls.TLSSocket)) {
+      if (!this.isPinned(deviceId)) return true
+      recordSecurityEvent('handshake-rejected', deviceId, 'Plain TCP from a device pinned to TLS')
+      return false
+    }
+    const certificate = socket.getPeerX509Certificate()
+    if (!certificate) {
+      recordSecurityEvent('handshake-rejected', deviceId, 'No TLS certificate presented')
+      return false
+    }
+    const pinned = this.pins[deviceId] ?? this.getGuestPins()?.[deviceId]
+    if (pinned && pinned !== certificate.fingerprint256) {
+      recordSecurityEvent('key-changed', deviceId, 'TLS certificate does not match the pinned one')
+      return false
+    }
+    return true
+  }
+
+  /**
+   * Pins the certificate `deviceId` presented on `socket`, if it has none pinned yet (in
+   * memory only, as a guest). Only called once a Noise handshake bound to this connection
+   * (see channelBinding) has proved the peer holds its pinned static key, so the pin ties
+   * the certificate to that identity.
+   */
+  confirmPeer(socket: net.Socket, deviceId: string): void {
+    if (!(socket instanceof tls.TLSSocket) || this.isPinned(deviceId)) return
+    const certificate = socket.getPeerX509Certificate()
+    if (certificate) this.pin(deviceId, certificate.fingerprint256)
+  }
+
+  // This guest identity's pins, or null outside guest mode
+  private getGuestPins(): Record<string, string> | null {
+    if (!isGuestMode()) return null
+    const owner = getDeviceInfo().deviceId
+    if (this.guestPins?.owner !== owner) this.guestPins = { owner, pins: {} }
+    return this.guestPins.pins
+  }
+
+  private async pin(deviceId: string, fingerprint: string): Promise<void> {
+    const guestPins = this.getGuestPins()
+    if (guestPins) {
+      guestPins[deviceId] = fingerprint
+      return
+    }
+    this.pins[deviceId] = fingerprint
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('pinnedCertificates', this.pins)
+  }
+}
+
+export const tlsTransport = new TlsTransport()
@@ -0,0 +1,121 @@
+import fs from 'fs'
+import path from 'path'
+import { CleanupResult } from '@shared/messageTypes'
+import { getStoreOptions } from './profiles'
+import { getDownloadDirectory } from './downloads'
+
+// A received file that was still being written. Kept in the store so files left behind
+// by a crash or a transfer that never finished can be found again on the next start.
+interface IncompleteRecord {
+  fileId: string
+  filePath: string
+  deviceId: string
+  startedAt: number
+}
+
+// Partial files left alone on startup; anything older is assumed abandoned
+export const STARTUP_CLEANUP_AGE = 24 * 60 * 60 * 1000
+
+// Delta transfers build the new file next to the destination under this suffix
+const DELTA_TEMP_PATTERN = /\.delta-[0-9a-fA-F-]{36}$/
+
+async function loadRecords(): Promise<Record<string, IncompleteRecord>> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  return { ...(store.get('incompleteTransfers', {}) as Record<string, IncompleteRecord>) }
+}
+
+async function saveRecords(records: Record<string, IncompleteRecord>): Promise<void> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('incompleteTransfers', records)
+}
+
+export async function trackIncomplete(
+  fileId: string,
+  filePath: string,
+  deviceId: string
+): Promise<void> {
+  const records = await loadRecords()
+  records[fileId] = { fileId, filePath, deviceId, startedAt: Date.now() }
+  await saveRecords(records)
+}
+
+export async function untrackIncomplete(fileId: string): Promise<void> {
+  const records = await loadRecords()
+  if (!records[fileId]) return
+  delete records[fileId]
+  await saveRecords(records)
+}
+
+// Removes `filePath` if it hasn't been written to for `maxAge`; returns the bytes freed
+async function removeIfStale(filePath: string, maxAge: number): Promise<number | null> {
+  const stats = await fs.promises.stat(filePath)
+  if (!stats.isFile() || Date.now() - stats.mtimeMs < maxAge) return null
+  await fs.promises.rm(filePath, { force: true })
+  return stats.size
+}
+
+// Delta temp files in the download directory and its per-peer subfolders
+async function findDeltaTempFiles(): Promise<string[]> {
+  const root = await getDownloadDirectory()
+  const found: string[] = []
+  const scan = async (dir: string, depth: number): Promise<void> => {
+    const entries = await fs.promises.readdir(dir, { withFileTypes: true }).catch(() => [])
+    for (const entry of entries) {
+      const fullPath = path.join(dir, entry.name)
+      if (entry.isFile() && DELTA_TEMP_PATTERN.test(entry.name)) found.push(fullPath)
+      else if (entry.isDirectory() && depth > 0) await scan(fullPath, depth - 1)
+    }
+  }
+  await scan(root, 1)
+  return found
+}
+
+/**
+ * Deletes partially received files that no transfer is using any more: tracked files older
+ * than `maxAge` (ms since last write) and stray delta temp files. Records whose file has
+ * already gone are dropped. `liveFileIds` are transfers that may still resume; their files
+ * are never touched.
+ */
+export async function cleanIncompleteTransfers(
+  maxAge: number,
+  liveFileIds: Set<string> = new Set()
+): Promise<CleanupResult> {
+  const result: CleanupResult = { removed: [], freedBytes: 0 }
+  const records = await loadRecords()
+  const livePaths = new Set<string>()
+
+  for (const record of Object.values(records)) {
+    if (liveFileIds.has(record.fileId)) {
+      livePaths.add(record.filePath)
+      continue
+    }
+    try {
+      const freed = await removeIfStale(record.filePath, maxAge)
+      if (freed === null) continue
+      result.removed.push(record.filePath)
+      result.freedBytes += freed
+    } catch (e) {
+      if ((e as NodeJS.ErrnoException).code !== 'ENOENT') {
+        console.error(`[Cleanup] Failed to remove ${record.filePath}:`, e)
+        continue
+      }
+    }
+    delete records[record.fileId]
+  }
+  await saveRecords(records)
+
+  for (const filePath of await findDeltaTempFiles()) {
+    if (livePaths.has(filePath) || result.removed.includes(filePath)) continue
+    const freed = await removeIfStale(filePath, maxAge).catch(() => null)
+    if (freed === null) continue
+    result.removed.push(filePath)
+    result.freedBytes += freed
+  }
+
+  if (result.removed.length > 0) {
+    console.log(`[Cleanup] Removed ${result.removed.length} incomplete file(s)`)
+  }
+  return result
+}
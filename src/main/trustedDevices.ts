@@ -0,0 +1,61 @@
+import { getStoreOptions } from './profiles'
+import { getDeviceInfo, isGuestMode } from './identity'
+
+// Devices approved as a guest, kept in memory only and forgotten with the guest identity
+let guestTrust: { owner: string; trusted: string[] } | null = null
+
+const getGuestTrust = (): { owner: string; trusted: string[] } => {
+  const owner = getDeviceInfo().deviceId
+  if (guestTrust?.owner !== owner) guestTrust = { owner, trusted: [] }
+  return guestTrust
+}
+
+/**
+ * Whether secure handshakes from devices that were never approved must wait for the user.
+ */
+export async function isApprovalRequired(): Promise<boolean> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  return store.get('requireConnectionApproval', false) as boolean
+}
+
+export async function setApprovalRequired(required: boolean): Promise<boolean> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('requireConnectionApproval', required)
+  return required
+}
+
+export async function getTrustedDevices(): Promise<string[]> {
+  if (isGuestMode()) return [...getGuestTrust().trusted]
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  return store.get('trustedDevices', []) as string[]
+}
+
+export async function isTrustedDevice(deviceId: string): Promise<boolean> {
+  return (await getTrustedDevices()).includes(deviceId)
+}
+
+export async function trustDevice(deviceId: string): Promise<void> {
+  const trusted = await getTrustedDevices()
+  if (trusted.includes(deviceId)) return
+  if (isGuestMode()) {
+    getGuestTrust().trusted.push(deviceId)
+    return
+  }
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('trustedDevices', [...trusted, deviceId])
+}
+
+export async function untrustDevice(deviceId: string): Promise<void> {
+  const trusted = await getTrustedDevices()
+  if (isGuestMode()) {
+    getGuestTrust().trusted = trusted.filter((id) => id !== deviceId)
+    return
+  }
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('trustedDevices', trusted.filter((id) => id !== deviceId))
+}
@@ -0,0 +1,553 @@
+import dgram from 'dgram'
+import net from 'net'
+import { Duplex } from 'stream'
+import { randomBytes } from 'crypto'
+import { Device } from '@shared/messageTypes'
+import { discoveryManager } from './discovery'
+import { getDeviceInfo } from './identity'
+import { getStoreOptions } from './profiles'
+import { tcpServer } from './tcpServer'
+import { unmapAddress } from './addresses'
+import { getPortRange } from './listen'
+import { firewall } from './firewall'
+
+/**
+ * An optional UDP channel for file payloads. On lossy Wi-Fi a single TCP stream takes every
+ * dropped packet as congestion and slows right down; here a loss is just resent (as soon as
+ * three later packets are acknowledged without it, as in TCP fast retransmit) and the
+ * window only eases off a little, much like KCP's fast mode. Control frames stay on TCP.
+ *
+ * A stream carries exactly the bytes a FILE_STREAM TCP connection would (header line, IV,
+ * encrypted data), so both ends of the transfer code are unchanged; received streams go to
+ * TCPServer like bridged WebSocket ones. One UDP socket, on the first port of the TCP
+ * server's range where it can, serves both directions. Every packet starts with a type
+ * byte and the stream's id (u32, picked by the dialing side):
+ * - SYN / SYN_ACK: open a stream
+ * - DATA seq payload, FIN seq: sequence numbers count packets, FIN ends the sender's half
+ * - ACK next window bitmap: everything below `next` arrived, plus the 256 packets after it
+ *   marked in `bitmap`; `window` is how many packets past `next` may be sent
+ * - RST: the stream is gone
+ */
+
+const PacketType = {
+  SYN: 1,
+  SYN_ACK: 2,
+  DATA: 3,
+  FIN: 4,
+  ACK: 5,
+  RST: 6
+} as const
+
+// Payload bytes per packet, small enough to get through without IP fragmentation
+const MSS = 1200
+const SACK_BITS = 256
+const ACK_SIZE = 11 + SACK_BITS / 8
+// Packets the receiver buffers ahead of the next one it needs
+const RECEIVE_WINDOW = 1024
+// Packets queued or unacknowledged before writes have to wait
+const SEND_BUFFER = 4096
+const INITIAL_CWND = 64
+const MIN_CWND = 32
+const MAX_CWND = 4 * RECEIVE_WINDOW
+// How far the window shrinks on a lost packet, and on a timeout. TCP halves it on every
+// loss, which is what makes it crawl on Wi-Fi, where most losses aren't congestion.
+const LOSS_BACKOFF = 0.8
+const TIMEOUT_BACKOFF = 0.5
+const INITIAL_RTO = 500
+const MIN_RTO = 30
+const MAX_RTO = 5000
+// Acks that pass over a packet before it is resent without waiting for its timeout
+const FAST_RESEND_SKIPS = 3
+const TICK_INTERVAL = 10
+// Each end acks at least this often, so an idle stream isn't taken for a dead one (ms)
+const KEEPALIVE_INTERVAL = 1000
+const IDLE_TIMEOUT = 15 * 1000
+const SYN_INTERVAL = 250
+const CONNECT_TIMEOUT = 5000
+// A device whose UDP channel didn't answer gets TCP streams for this long (ms)
+const FAILURE_BACKOFF = 10 * 60 * 1000
+
+interface InFlight {
+  packet: Buffer
+  sentAt: number
+  resends: number
+  skips: number
+  order: number // when it was last (re)sent, counting every packet sent on the stream
+}
+
+const socketError = (message: string, code: string): Error =>
+  Object.assign(new Error(message), { code })
+
+/**
+ * One end of a stream, with the parts of net.Socket's interface the transfer code uses.
+ */
+class UdpStream extends Duplex {
+  private host = ''
+  private port = 0
+  private connected = false
+  private synTimer?: NodeJS.Timeout
+  private tickTimer?: NodeJS.Timeout
+  private lastHeard = Date.now()
+  private lastSent = 0
+  private timeout = 0 // for setTimeout
+  private lastActive = Date.now() // data sent or received, as setTimeout counts it
+
+  // Sending half
+  private nextSeq = 0
+  private queue: Buffer[] = [] // packets with a sequence number, waiting for the window
+  private queuedSeq: number[] = []
+  private inFlight: Map<number, InFlight> = new Map()
+  private ackedThrough = 0 // every packet below this has been acknowledged
+  private peerWindow = RECEIVE_WINDOW
+  private cwnd = INITIAL_CWND
+  private ssthresh = RECEIVE_WINDOW
+  private srtt = 0
+  private rttvar = 0
+  private rto = INITIAL_RTO
+  private sendCount = 0
+  private recovery = 0 // losses among packets sent up to here were already backed off for
+  private pendingWrite?: () => void
+  private pendingFinal?: () => void
+  private finAcked = false
+
+  // Receiving half
+  private rcvNext = 0
+  private received: Map<number, Buffer | null> = new Map() // out of order; null is a FIN
+  private readPaused = false
+  private unacked = 0
+  private finReceived = false
+
+  constructor(
+    private channel: UdpChannel,
+    public readonly id: number
+  ) {
+    super({ allowHalfOpen: false })
+  }
+
+  get remoteAddress(): string {
+    return this.host
+  }
+
+  get localAddress(): undefined {
+    return undefined
+  }
+
+  get key(): string {
+    return streamKey(this.host, this.port, this.id)
+  }
+
+  setNoDelay(): this {
+    return this
+  }
+
+  setKeepAlive(): this {
+    return this
+  }
+
+  // Emits 'timeout' after `ms` without data either way, as net.Socket does; 0 turns it off.
+  // Acks and keepalives don't count, though they keep the stream itself open
+  setTimeout(ms: number, callback?: () => void): this {
+    this.timeout = ms
+    this.lastActive = Date.now()
+    if (callback) this.once('timeout', callback)
+    return this
+  }
+
+  // Dials `host`:`port`, as net.Socket.connect does
+  connect(port: number, host: string, onConnect?: () => void): this {
+    this.host = host
+    this.port = port
+    this.channel.register(this)
+    if (onConnect) this.once('connect', onConnect)
+    const started = Date.now()
+    const syn = (): void => {
+      if (Date.now() - started > CONNECT_TIMEOUT) {
+        this.channel.reportUnreachable(host)
+        this.destroy(socketError(`No UDP answer from ${host}:${port}`, 'ETIMEDOUT'))
+        return
+      }
+      this.sendControl(PacketType.SYN)
+      this.synTimer = setTimeout(syn, SYN_INTERVAL)
+    }
+    syn()
+    return this
+  }
+
+  // The listening end of a stream the other side opened
+  accept(host: string, port: number): void {
+    this.host = host
+    this.port = port
+    this.channel.register(this)
+    this.sendControl(PacketType.SYN_ACK)
+    this.establish()
+  }
+
+  private establish(): void {
+    if (this.connected) return
+    clearTimeout(this.synTimer)
+    this.connected = true
+    this.lastHeard = Date.now()
+    this.tickTimer = setInterval(() => this.tick(), TICK_INTERVAL)
+    this.emit('connect')
+    this.flush()
+  }
+
+  receive(type: number, packet: Buffer): void {
+    this.lastHeard = Date.now()
+    switch (type) {
+      case PacketType.SYN:
+        // Our SYN_ACK was lost
+        this.sendControl(PacketType.SYN_ACK)
+        break
+      case PacketType.SYN_ACK:
+        this.establish()
+        break
+      case PacketType.DATA:
+      case PacketType.FIN:
+        if (packet.length >= 9) {
+          const payload = type === PacketType.FIN ? null : packet.subarray(9)
+          this.receiveData(packet.readUInt32BE(5), payload)
+        }
+        break
+      case PacketType.ACK:
+        if (packet.length >= ACK_SIZE) this.receiveAck(packet)
+        break
+      case PacketType.RST: {
+        // Once the other end has everything, a reset only means it closed before acking
+        // our FIN
+        const clean =
+          this.finReceived &&
+          this.queue.length === 0 &&
+          [...this.inFlight.values()].every((entry) => entry.packet[0] === PacketType.FIN)
+        if (clean) this.pendingFinal?.()
+        this.destroy(clean ? undefined : socketError('Stream reset', 'ECONNRESET'))
+        break
+      }
+    }
+  }
+
+  private receiveData(seq: number, payload: Buffer | null): void {
+    this.lastActive = Date.now()
+    if (seq >= this.rcvNext && seq < this.rcvNext + RECEIVE_WINDOW && !this.finReceived) {
+      this.received.set(seq, payload)
+      let chunk: Buffer | null | undefined
+      while ((chunk = this.received.get(this.rcvNext)) !== undefined) {
+        this.received.delete(this.rcvNext)
+        this.rcvNext++
+        if (chunk === null) {
+          this.finReceived = true
+          this.push(null)
+          // Like a TCP socket, emit 'end' even when nothing reads from this side
+          this.read(0)
+          break
+        }
+        if (!this.push(chunk)) this.readPaused = true
+      }
+    }
+    // Gaps and duplicates are acked at once, so the sender learns of losses quickly
+    this.unacked++
+    if (this.unacked >= 2 || this.received.size > 0 || seq < this.rcvNext - 1 || !payload) {
+      this.sendAck()
+    }
+  }
+
+  private receiveAck(packet: Buffer): void {
+    const next = packet.readUInt32BE(5)
+    this.peerWindow = packet.readUInt16BE(9)
+    const now = Date.now()
+    // The latest-sent packet this ack covers; anything sent before it and still missing
+    // was passed over
+    let latest = -1
+    const acked = (seq: number): void => {
+      const entry = this.inFlight.get(seq)
+      if (!entry) return
+      latest = Math.max(latest, entry.order)
+      // Only first transmissions give an unambiguous round trip
+      if (entry.resends === 0) this.sampleRtt(now - entry.sentAt)
+      this.inFlight.delete(seq)
+      // Slow start up to the threshold, then a packet more per round trip
+      const growth = this.cwnd < this.ssthresh ? 1 : 1 / this.cwnd
+      this.cwnd = Math.min(MAX_CWND, this.cwnd + growth)
+      if (entry.packet[0] === PacketType.FIN) this.finAcked = true
+    }
+    for (const seq of this.inFlight.keys()) if (seq < next) acked(seq)
+    this.ackedThrough = Math.max(this.ackedThrough, next)
+
+    for (let i = 0; i < SACK_BITS; i++) {
+      if (packet[11 + (i >> 3)] & (0x80 >> (i & 7))) acked(next + 1 + i)
+    }
+    for (const entry of this.inFlight.values()) {
+      if (entry.order > latest || ++entry.skips < FAST_RESEND_SKIPS) continue
+      entry.skips = 0
+      this.backOff(entry, LOSS_BACKOFF)
+      this.resend(entry)
+    }
+
+    if (this.finAcked && this.inFlight.size === 0) {
+      const final = this.pendingFinal
+      this.pendingFinal = undefined
+      final?.()
+    }
+    this.flush()
+  }
+
+  // RFC 6298
+  private sampleRtt(rtt: number): void {
+    if (this.srtt === 0) {
+      this.srtt = rtt
+      this.rttvar = rtt / 2
+    } else {
+      this.rttvar = 0.75 * this.rttvar + 0.25 * Math.abs(this.srtt - rtt)
+      this.srtt = 0.875 * this.srtt + 0.125 * rtt
+    }
+    this.rto = Math.min(MAX_RTO, Math.max(MIN_RTO, this.srtt + 4 * this.rttvar))
+  }
+
+  // Shrinks the window once per window of packets, as losses found together are one event
+  private backOff(entry: InFlight, factor: number): void {
+    if (entry.order <= this.recovery) return
+    this.recovery = this.sendCount
+    this.cwnd = Math.max(MIN_CWND, this.cwnd * factor)
+    this.ssthresh = this.cwnd
+  }
+
+  private resend(entry: InFlight): void {
+    entry.resends++
+    entry.sentAt = Date.now()
+    entry.order = ++this.sendCount
+    this.send(entry.packet)
+  }
+
+  private tick(): void {
+    const now = Date.now()
+    if (now - this.lastHeard > IDLE_TIMEOUT) {
+      this.destroy(socketError('UDP stream timed out', 'ETIMEDOUT'))
+      return
+    }
+    if (this.timeout && now - this.lastActive >= this.timeout) {
+      this.lastActive = now
+      this.emit('timeout')
+    }
+    for (const entry of this.inFlight.values()) {
+      if (now - entry.sentAt < Math.min(MAX_RTO, this.rto * 2 ** entry.resends)) continue
+      this.backOff(entry, TIMEOUT_BACKOFF)
+      this.resend(entry)
+    }
+    if (this.unacked > 0 || now - this.lastSent > KEEPALIVE_INTERVAL) this.sendAck()
+    this.flush()
+  }
+
+  // Sends queued packets as far as both windows allow, and releases a waiting write
+  private flush(): void {
+    if (!this.connected) return
+    const limit = this.ackedThrough + Math.min(Math.floor(this.cwnd), this.peerWindow)
+    while (this.queue.length > 0 && this.queuedSeq[0] < limit) {
+      const packet = this.queue.shift()!
+      const seq = this.queuedSeq.shift()!
+      const order = ++this.sendCount
+      this.inFlight.set(seq, { packet, sentAt: Date.now(), resends: 0, skips: 0, order })
+      this.send(packet)
+    }
+    if (this.pendingWrite && this.queue.length + this.inFlight.size < SEND_BUFFER) {
+      const callback = this.pendingWrite
+      this.pendingWrite = undefined
+      callback()
+    }
+  }
+
+  private enqueue(type: number, payload?: Buffer): void {
+    const header = Buffer.alloc(9)
+    header[0] = type
+    header.writeUInt32BE(this.id, 1)
+    header.writeUInt32BE(this.nextSeq, 5)
+    this.queue.push(payload ? Buffer.concat([header, payload]) : header)
+    this.queuedSeq.push(this.nextSeq++)
+  }
+
+  private sendAck(): void {
+    this.unacked = 0
+    const packet = Buffer.alloc(ACK_SIZE)
+    packet[0] = PacketType.ACK
+    packet.writeUInt32BE(this.id, 1)
+    packet.writeUInt32BE(this.rcvNext, 5)
+    packet.writeUInt16BE(this.readPaused ? 0 : RECEIVE_WINDOW, 9)
+    for (const seq of this.received.keys()) {
+      const bit = seq - this.rcvNext - 1
+      if (bit >= 0 && bit < SACK_BITS) packet[11 + (bit >> 3)] |= 0x80 >> (bit & 7)
+    }
+    this.send(packet)
+  }
+
+  private sendControl(type: number): void {
+    const packet = Buffer.alloc(5)
+    packet[0] = type
+    packet.writeUInt32BE(this.id, 1)
+    this.send(packet)
+  }
+
+  private send(packet: Buffer): void {
+    this.lastSent = Date.now()
+    this.channel.send(packet, this.host, this.port)
+  }
+
+  _read(): void {
+    if (!this.readPaused) return
+    this.readPaused = false
+    // Tell the sender the window is open again
+    if (this.connected) this.sendAck()
+  }
+
+  _write(chunk: Buffer, _encoding: BufferEncoding, callback: (error?: Error) => void): void {
+    this.lastActive = Date.now()
+    for (let offset = 0; offset < chunk.length; offset += MSS) {
+      this.enqueue(PacketType.DATA, chunk.subarray(offset, offset + MSS))
+    }
+    this.pendingWrite = () => callback()
+    this.flush()
+  }
+
+  _final(callback: (error?: Error) => void): void {
+    this.pendingFinal = () => callback()
+    this.enqueue(PacketType.FIN)
+    this.flush()
+  }
+
+  _destroy(error: Error | null, callback: (error?: Error | null) => void): void {
+    clearTimeout(this.synTimer)
+    clearInterval(this.tickTimer)
+    if (this.connected && !(this.finAcked && this.finReceived)) {
+      this.sendControl(PacketType.RST)
+    }
+    this.channel.unregister(this)
+    callback(error)
+  }
+}
+
+const streamKey = (host: string, port: number, id: number): string => `${host}|${port}|${id}`
+
+class UdpChannel {
+  private socket?: dgram.Socket
+  private streams: Map<string, UdpStream> = new Map()
+  private failures: Map<string, number> = new Map()
+  private enabled = false
+  public port = 0
+
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    await this.configure(store.get('udpTransfers', false) as boolean)
+  }
+
+  isEnabled(): boolean {
+    return this.enabled
+  }
+
+  async setEnabled(enabled: boolean): Promise<boolean> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('udpTransfers', enabled)
+    await this.configure(enabled)
+    return enabled
+  }
+
+  private async configure(enabled: boolean): Promise<void> {
+    this.enabled = enabled
+    if (enabled && !this.socket) {
+      this.port = await this.start().catch((err) => {
+        console.error('[UDP] Failed to open the UDP channel:', err)
+        return 0
+      })
+    } else if (!enabled) {
+      this.stop()
+    }
+    // Peers only send over UDP to devices that announce a port for it
+    discoveryManager.setUdpPort(this.port || undefined)
+    discoveryManager.updateAdvertisement(getDeviceInfo())
+  }
+
+  // Binds the first port of the TCP server's range if it is free for UDP, any port otherwise
+  private async start(): Promise<number> {
+    // The socket is IPv6, so a bind address that is IPv4 is given in its mapped form
+    const address = firewall.getBindAddress()
+    const host = address && net.isIPv4(address) ? `::ffff:${address}` : address
+    const bind = (port: number): Promise<dgram.Socket> =>
+      new Promise((resolve, reject) => {
+        const socket = dgram.createSocket({ type: 'udp6', ipv6Only: false })
+        socket.once('error', reject)
+        socket.bind(port, host, () => {
+          socket.removeListener('error', reject)
+          resolve(socket)
+        })
+      })
+    const { start } = await getPortRange()
+    const socket = await bind(start).catch((err: NodeJS.ErrnoException) => {
+      if (err.code !== 'EADDRINUSE') throw err
+      return bind(0)
+    })
+    socket.on('message', (packet, rinfo) => this.receive(packet, rinfo))
+    socket.on('error', (err) => console.error('[UDP] Socket error:', err))
+    this.socket = socket
+    const { port } = socket.address()
+    console.log(`[UDP] Listening for file streams on port ${port}`)
+    return port
+  }
+
+  stop(): void {
+    for (const stream of this.streams.values()) stream.destroy()
+    this.socket?.close()
+    this.socket = undefined
+    this.port = 0
+  }
+
+  // Whether file streams to `device` should go over UDP
+  isUsable(device: Device): boolean {
+    if (!this.enabled || !this.socket || !device.udpPort || device.relay) return false
+    return Date.now() - (this.failures.get(device.address) ?? 0) >= FAILURE_BACKOFF
+  }
+
+  // A stream to be opened with connect(), as a net.Socket would be
+  createStream(): net.Socket {
+    return new UdpStream(this, randomBytes(4).readUInt32BE()) as unknown as net.Socket
+  }
+
+  reportUnreachable(host: string): void {
+    console.warn(`[UDP] No answer from ${host}, using TCP for its file streams`)
+    this.failures.set(host, Date.now())
+  }
+
+  register(stream: UdpStream): void {
+    this.streams.set(stream.key, stream)
+  }
+
+  unregister(stream: UdpStream): void {
+    if (this.streams.get(stream.key) === stream) this.streams.delete(stream.key)
+  }
+
+  send(packet: Buffer, host: string, port: number): void {
+    // The socket is IPv6; IPv4 peers are reached at their mapped address
+    const target = net.isIPv4(host) ? `::ffff:${host}` : host
+    this.socket?.send(packet, port, target)
+  }
+
+  private receive(packet: Buffer, rinfo: dgram.RemoteInfo): void {
+    if (packet.length < 5) return
+    const type = packet[0]
+    const id = packet.readUInt32BE(1)
+    const host = unmapAddress(rinfo.address)!
+    const stream = this.streams.get(streamKey(host, rinfo.port, id))
+    if (stream) {
+      stream.receive(type, packet)
+    } else if (type === PacketType.SYN) {
+      const accepted = new UdpStream(this, id)
+      accepted.accept(host, rinfo.port)
+      tcpServer.handleConnection(accepted as unknown as net.Socket)
+    } else if (type !== PacketType.RST) {
+      const reset = Buffer.alloc(5)
+      reset[0] = PacketType.RST
+      reset.writeUInt32BE(id, 1)
+      this.send(reset, host, rinfo.port)
+    }
+  }
+}
+
+export const udpChannel = new UdpChannel()
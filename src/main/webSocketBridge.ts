@@ -0,0 +1,245 @@
+import http from 'http'
+import net from 'net'
+import { createHash } from 'crypto'
+import { Duplex } from 'stream'
+import { MAX_MESSAGE_SIZE } from './fragments'
+import { getStoreOptions } from './profiles'
+import { tcpServer } from './tcpServer'
+import { listenOnFreePort } from './listen'
+import { firewall } from './firewall'
+
+/**
+ * Lets peers that can only speak WebSocket, such as a browser page, connect without the
+ * app. A WebSocket is only a pipe for the usual byte stream here: its messages are
+ * concatenated into exactly what a TCP connection would carry (newline-delimited frames,
+ * or a FILE_STREAM), and what we write goes back as binary messages. Each connection is
+ * then handed to TCPServer like any other, so the handshake, approval and routing are
+ * the same.
+ */
+
+const HANDSHAKE_GUID = '258EAFA5-E914-47DA-95CA-C5AB0DC85B11'
+
+const Opcode = {
+  CONTINUATION: 0x0,
+  TEXT: 0x1,
+  BINARY: 0x2,
+  CLOSE: 0x8,
+  PING: 0x9,
+  PONG: 0xa
+} as const
+
+const CLOSE_NORMAL = 1000
+const CLOSE_PROTOCOL_ERROR = 1002
+const CLOSE_TOO_BIG = 1009
+
+const encodeFrame = (opcode: number, payload: Buffer): Buffer => {
+  let header: Buffer
+  if (payload.length < 126) {
+    header = Buffer.from([0x80 | opcode, payload.length])
+  } else if (payload.length <= 0xffff) {
+    header = Buffer.alloc(4)
+    header[0] = 0x80 | opcode
+    header[1] = 126
+    header.writeUInt16BE(payload.length, 2)
+  } else {
+    header = Buffer.alloc(10)
+    header[0] = 0x80 | opcode
+    header[1] = 127
+    header.writeBigUInt64BE(BigInt(payload.length), 2)
+  }
+  return Buffer.concat([header, payload])
+}
+
+/**
+ * One accepted WebSocket as a byte stream, with the parts of net.Socket's interface that
+ * TCPServer and the transfer code use.
+ */
+class WebSocketStream extends Duplex {
+  private socket: net.Socket
+  private buffer = Buffer.alloc(0)
+  private closing = false
+
+  constructor(socket: net.Socket, head: Buffer) {
+    super()
+    this.socket = socket
+    socket.on('data', (chunk: Buffer) => this.receive(chunk))
+    socket.on('close', () => this.destroy())
+    socket.on('error', (err) => this.destroy(err))
+    socket.on('timeout', () => this.emit('timeout'))
+    if (head.length > 0) this.receive(head)
+  }
+
+  get remoteAddress(): string | undefined {
+    return this.socket.remoteAddress
+  }
+
+  setNoDelay(noDelay?: boolean): this {
+    this.socket.setNoDelay(noDelay)
+    return this
+  }
+
+  setKeepAlive(enable?: boolean, initialDelay?: number): this {
+    this.socket.setKeepAlive(enable, initialDelay)
+    return this
+  }
+
+  setTimeout(ms: number, callback?: () => void): this {
+    this.socket.setTimeout(ms)
+    if (callback) this.once('timeout', callback)
+    return this
+  }
+
+  _read(): void {
+    this.socket.resume()
+  }
+
+  _write(chunk: Buffer, _encoding: BufferEncoding, callback: (error?: Error) => void): void {
+    this.socket.write(encodeFrame(Opcode.BINARY, chunk), callback)
+  }
+
+  _final(callback: () => void): void {
+    this.close(CLOSE_NORMAL)
+    callback()
+  }
+
+  _destroy(error: Error | null, callback: (error?: Error | null) => void): void {
+    this.socket.destroy()
+    callback(error)
+  }
+
+  private close(code: number): void {
+    if (this.closing) return
+    this.closing = true
+    const payload = Buffer.alloc(2)
+    payload.writeUInt16BE(code)
+    this.socket.end(encodeFrame(Opcode.CLOSE, payload))
+  }
+
+  private fail(code: number): void {
+    this.close(code)
+    this.push(null)
+  }
+
+  // Takes whole frames off the buffer; a partial one waits for more data
+  private receive(chunk: Buffer): void {
+    this.buffer = Buffer.concat([this.buffer, chunk])
+    while (this.buffer.length >= 2 && !this.closing) {
+      const opcode = this.buffer[0] & 0x0f
+      const masked = (this.buffer[1] & 0x80) !== 0
+      let length = this.buffer[1] & 0x7f
+      let offset = 2
+      if (length === 126) {
+        if (this.buffer.length < 4) return
+        length = this.buffer.readUInt16BE(2)
+        offset = 4
+      } else if (length === 127) {
+        if (this.buffer.length < 10) return
+        const long = this.buffer.readBigUInt64BE(2)
+        if (long > BigInt(MAX_MESSAGE_SIZE)) return this.fail(CLOSE_TOO_BIG)
+        length = Number(long)
+        offset = 10
+      }
+      // Browsers always mask what they send; an unmasked frame isn't from one
+      if (!masked) return this.fail(CLOSE_PROTOCOL_ERROR)
+      if (length > MAX_MESSAGE_SIZE) return this.fail(CLOSE_TOO_BIG)
+      if (this.buffer.length < offset + 4 + length) return
+
+      const mask = this.buffer.subarray(offset, offset + 4)
+      const payload = Buffer.from(this.buffer.subarray(offset + 4, offset + 4 + length))
+      for (let i = 0; i < payload.length; i++) payload[i] ^= mask[i & 3]
+      this.buffer = this.buffer.subarray(offset + 4 + length)
+
+      switch (opcode) {
+        case Opcode.CONTINUATION:
+        case Opcode.TEXT:
+        case Opcode.BINARY:
+          if (!this.push(payload)) this.socket.pause()
+          break
+        case Opcode.PING:
+          this.socket.write(encodeFrame(Opcode.PONG, payload))
+          break
+        case Opcode.PONG:
+          break
+        case Opcode.CLOSE:
+          this.close(CLOSE_NORMAL)
+          this.push(null)
+          return
+        default:
+          return this.fail(CLOSE_PROTOCOL_ERROR)
+      }
+    }
+  }
+}
+
+// Completes the opening handshake and hands the connection to TCPServer
+function upgrade(req: http.IncomingMessage, socket: net.Socket, head: Buffer): void {
+  const key = req.headers['sec-websocket-key']
+  if (
+    req.headers.upgrade?.toLowerCase() !== 'websocket' ||
+    req.headers['sec-websocket-version'] !== '13' ||
+    typeof key !== 'string'
+  ) {
+    socket.end('HTTP/1.1 400 Bad Request\r\n\r\n')
+    return
+  }
+
+  const accept = createHash('sha1').update(key + HANDSHAKE_GUID).digest('base64')
+  socket.write(
+    'HTTP/1.1 101 Switching Protocols\r\n' +
+      'Upgrade: websocket\r\n' +
+      'Connection: Upgrade\r\n' +
+      `Sec-WebSocket-Accept: ${accept}\r\n\r\n`
+  )
+  console.log(`[WebSocket] Browser peer connected from ${socket.remoteAddress}`)
+  tcpServer.handleConnection(new WebSocketStream(socket, head) as unknown as net.Socket)
+}
+
+// An HTTP server that takes WebSocket upgrades as peer connections, not yet listening
+export function createWebSocketServer(): http.Server {
+  const server = http.createServer((_, res) => {
+    res.writeHead(426, { Upgrade: 'websocket' }).end()
+  })
+  server.on('upgrade', upgrade)
+  return server
+}
+
+class WebSocketBridge {
+  private server?: http.Server
+  public port = 0
+
+  // Off by default; starts the listener if the profile has it on
+  async load(): Promise<void> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    if (store.get('browserConnections', false)) await this.start()
+    else this.stop()
+  }
+
+  // Returns the port browsers can connect to, 0 when turned off
+  async setEnabled(enabled: boolean): Promise<number> {
+    const Store = (await import('electron-store')).default
+    const store = new Store(getStoreOptions())
+    store.set('browserConnections', enabled)
+    if (enabled) return this.start()
+    this.stop()
+    return 0
+  }
+
+  async start(preferredPort: number = 52901): Promise<number> {
+    if (this.server) return this.port
+    const server = createWebSocketServer()
+    const host = firewall.getBindAddress()
+    this.port = await listenOnFreePort(server, [preferredPort], 'WebSocket', host)
+    this.server = server
+    console.log(`[WebSocket] Listening for browser peers on port ${this.port}`)
+    return this.port
+  }
+
+  stop(): void {
+    this.server?.close()
+    this.server = undefined
+    this.port = 0
+  }
+}
+
+export const webSocketBridge = new WebSocketBridge()
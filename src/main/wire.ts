@@ -0,0 +1,298 @@
+import net from 'net'
+import { Transform, TransformCallback } from 'stream'
+import { NetworkMessage } from '@shared/messageTypes'
+import { getSession, wipe } from './crypto/sessionKey'
+import { ReplayWindow } from './crypto/replayWindow'
+import {
+  EncryptOptions,
+  decryptMessage,
+  encryptMessage,
+  isEncryptedMessage,
+  isSensitiveMessageType
+} from './crypto/messageCrypto'
+import { getDeviceInfo } from './identity'
+import { getStoreOptions } from './profiles'
+import { recordSecurityEvent } from './securityLog'
+import { downgradeGuard } from './downgradeGuard'
+import { getKeyStorage } from './keychain'
+import { FrameAssembler, MAX_MESSAGE_SIZE, splitFrame } from './fragments'
+import type { KeepaliveParams } from './keepalive'
+import type { ResumeRequest } from './crypto/resumption'
+import {
+  Capability,
+  fromWireMessage,
+  getPeerProtocol,
+  getProtocolHello,
+  peerSupports,
+  toWireMessage
+} from './protocolVersion'
+
+/**
+ * Framing shared by outbound connections (ConnectionManager) and inbound ones (TCPServer):
+ * newline-delimited JSON, encrypted with the session key once the handshake is done (with
+ * a MessagePack payload, deflated if large and padded if a control frame, when both ends
+ * support it and padding is on), split into fragments when too long (see fragments.ts),
+ * and translated to and from the legacy frame names for version 1 peers.
+ */
+
+/**
+ * Splits complete lines off `buffer`, calling `onLine` for each non-empty one, and
+ * returns the unfinished remainder. Throws once a line grows past `maxLength` bytes.
+ */
+function readLines(
+  buffer: Buffer,
+  onLine: (line: string) => void,
+  maxLength = MAX_MESSAGE_SIZE
+): Buffer {
+  let offset: number
+  while ((offset = buffer.indexOf('\n')) !== -1) {
+    if (offset > maxLength) throw new RangeError(`Frame exceeds ${maxLength} bytes`)
+    const line = buffer.subarray(0, offset).toString().trim()
+    buffer = buffer.subarray(offset + 1)
+    if (line) onLine(line)
+  }
+  if (buffer.length > maxLength) throw new RangeError(`Frame exceeds ${maxLength} bytes`)
+  return buffer
+}
+
+/**
+ * Turns a connection's bytes into raw frames: lines are split off, parsed and, if they are
+ * fragments, reassembled. What comes out may still be encrypted; see readFrame. A line over
+ * `maxLength()` bytes errors the stream, since the connection can't be resynchronised after
+ * that; lines that aren't JSON, or fragments that don't fit together, are skipped and
+ * reported with an 'invalid' event. `tag` prefixes log lines.
+ */
+export class FrameDecoder extends Transform {
+  private buffer = Buffer.alloc(0)
+  private assembler = new FrameAssembler()
+  private tag: string
+  private maxLength: () => number
+
+  constructor(tag: string, maxLength: () => number = () => MAX_MESSAGE_SIZE) {
+    super({ readableObjectMode: true })
+    this.tag = tag
+    this.maxLength = maxLength
+  }
+
+  _transform(chunk: Buffer, _encoding: BufferEncoding, callback: TransformCallback): void {
+    try {
+      const data = Buffer.concat([this.buffer, chunk])
+      this.buffer = readLines(data, (line) => this.decodeLine(line), this.maxLength())
+      callback()
+    } catch (e) {
+      callback(e as Error)
+    }
+  }
+
+  private decodeLine(line: string): void {
+    try {
+      const parsed = JSON.parse(line)
+      if (typeof parsed !== 'object' || parsed === null) {
+        throw new TypeError('Frame is not an object')
+      }
+      const frame = this.assembler.push(parsed)
+      if (frame) this.push(frame)
+    } catch (e) {
+      console.error(`[${this.tag}] Failed to parse incoming frame:`, e)
+      this.emit('invalid', e)
+    }
+  }
+}
+
+// Frames that only steer a conversation or transfer. Their sizes and timing say a lot
+// about what is going on, so they can be padded (see setControlPadding).
+const CONTROL_FRAME_TYPES = new Set<NetworkMessage['type']>([
+  'PING',
+  'PONG',
+  'MESSAGE_ACK',
+  'READ_RECEIPT',
+  'FILE_ACCEPT',
+  'FILE_REJECT',
+  'FILE_PAUSE',
+  'FILE_RESUME',
+  'FILE_CANCEL',
+  'FILE_ACK',
+  'GOODBYE',
+  'ERROR',
+  'TRANSFER_STATUS_QUERY',
+  'TRANSFER_STATUS',
+  'EXTENSIONS'
+])
+
+// Off by default: padding costs bandwidth on every acknowledgement
+let padControlFrames = false
+
+export async function loadControlPadding(): Promise<void> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  padControlFrames = store.get('padControlFrames', false) as boolean
+}
+
+export const isControlPaddingEnabled = (): boolean => padControlFrames
+
+export async function setControlPadding(enabled: boolean): Promise<boolean> {
+  const Store = (await import('electron-store')).default
+  const store = new Store(getStoreOptions())
+  store.set('padControlFrames', enabled)
+  padControlFrames = enabled
+  return enabled
+}
+
+const writeLine = (socket: net.Socket, deviceId: string, line: string): void => {
+  for (const part of splitFrame(line, deviceId)) socket.write(part + '\n')
+}
+
+// Our side of the handshake; both ends send the same payload, but for what resumption, Noise
+// and the hybrid exchange add (see resumption.ts, noiseHandshake.ts and hybridKex.ts). The
+// public key is left out of an answer that uses a ticket or Noise.
+export function createHelloSecure(
+  publicKey: string | undefined,
+  id: string,
+  keepalive: KeepaliveParams,
+  extra: {
+    resume?: ResumeRequest
+    resumed?: string
+    noise?: string
+    kem?: string
+    kemCiphertext?: string
+  } = {}
+): NetworkMessage {
+  const deviceInfo = getDeviceInfo()
+  return {
+    type: 'HELLO_SECURE',
+    deviceId: deviceInfo.deviceId,
+    id,
+    timestamp: Date.now(),
+    payload: {
+      ...(publicKey && { publicKey }),
+      ...extra,
+      displayName: deviceInfo.displayName,
+      platform: deviceInfo.platform,
+      profileImage: deviceInfo.profileImage,
+      statusMessage: deviceInfo.statusMessage,
+      keyStorage: getKeyStorage(),
+      keepalive,
+      ...getProtocolHello()
+    }
+  }
+}
+
+/**
+ * Writes one frame, encrypted when there is a session with `deviceId`. Sensitive frames
+ * are never sent in the clear; anything else falls back to plaintext (and is logged as a
+ * security event) before the handshake, unless the peer has encrypted with us before and
+ * the policy refuses that (see downgradeGuard.ts). `tag` prefixes log lines.
+ */
+export function writeFrame(
+  socket: net.Socket,
+  deviceId: string | undefined,
+  outgoing: NetworkMessage,
+  tag: string
+): void {
+  const message = deviceId ? toWireMessage(deviceId, outgoing) : outgoing
+  const session = deviceId ? getSession(deviceId) : undefined
+
+  if (session) {
+    console.log(`[${tag}] Sending encrypted ${message.type} to ${deviceId}`)
+    let seq: EncryptOptions['seq']
+    if (peerSupports(deviceId!, Capability.REPLAY_PROTECTION)) {
+      session.sentSeq = (session.sentSeq ?? 0) + 1
+      seq = { value: session.sentSeq, senderId: getDeviceInfo().deviceId }
+    }
+    const ratcheted = session.ratchet?.next()
+    const encrypted = encryptMessage(message, ratcheted?.key ?? session.sessionKey, {
+      encoding: peerSupports(deviceId!, Capability.MSGPACK) ? 'msgpack' : 'json',
+      compress: peerSupports(deviceId!, Capability.COMPRESSED_FRAMES),
+      pad:
+        padControlFrames &&
+        CONTROL_FRAME_TYPES.has(message.type) &&
+        peerSupports(deviceId!, Capability.PADDING),
+      seq,
+      ratchet: ratcheted?.header,
+      cipherSuite: getPeerProtocol(deviceId!).cipherSuite
+    })
+    wipe(ratcheted?.key)
+    writeLine(socket, deviceId!, JSON.stringify(encrypted))
+    return
+  }
+  if (isSensitiveMessageType(message.type)) {
+    console.error(
+      `[${tag}] Refusing to send sensitive message ${message.type} without encryption to ${deviceId}`
+    )
+    return
+  }
+  if (downgradeGuard.refusesPlaintext(deviceId)) {
+    console.error(`[${tag}] Refusing to send ${message.type} unencrypted to ${deviceId}`)
+    recordSecurityEvent(
+      'plaintext-fallback',
+      deviceId,
+      `Refused to send ${message.type} unencrypted`
+    )
+    return
+  }
+
+  console.warn(`[${tag}] Sending unencrypted ${message.type}`)
+  recordSecurityEvent('plaintext-fallback', deviceId, `Sent ${message.type} unencrypted`)
+  socket.write(JSON.stringify(message) + '\n')
+}
+
+/**
+ * Closes a connection on purpose, first telling the peer with GOODBYE so it can drop the
+ * session and show us offline straight away instead of waiting for the socket to time
+ * out. The socket is ended rather than destroyed so the frame is flushed first.
+ */
+export function sayGoodbye(socket: net.Socket, deviceId: string | undefined, tag: string): void {
+  if (socket.destroyed) return
+  if (deviceId && socket.writable && peerSupports(deviceId, Capability.GOODBYE)) {
+    const goodbye: NetworkMessage = {
+      type: 'GOODBYE',
+      deviceId: getDeviceInfo().deviceId,
+      timestamp: Date.now()
+    }
+    writeFrame(socket, deviceId, goodbye, tag)
+  }
+  socket.end()
+}
+
+/**
+ * Turns a parsed line into a message: decrypted with `deviceId`'s session if it is
+ * encrypted, and with legacy frame names mapped to current ones. Returns null for an
+ * encrypted frame that can't be decrypted yet, for one that is unnumbered or replayed
+ * when the session has replay protection, and for one without a ratchet header when the
+ * session has a ratchet.
+ */
+export function readFrame(
+  raw: unknown,
+  deviceId: string | null,
+  tag: string
+): { message: NetworkMessage; encrypted: boolean } | null {
+  if (!isEncryptedMessage(raw)) {
+    return { message: fromWireMessage(raw as NetworkMessage), encrypted: false }
+  }
+  const session = deviceId ? getSession(deviceId) : undefined
+  if (!session) {
+    console.error(`[${tag}] No session key for ${deviceId ?? 'unauthenticated connection'}`)
+    return null
+  }
+  // A ratcheted session only takes ratcheted frames, or a captured session key would do
+  if (session.ratchet && !raw.ratchet) {
+    console.error(`[${tag}] Dropping frame without a ratchet header from ${deviceId}`)
+    recordSecurityEvent('decryption-failed', deviceId!, 'Frame without a ratchet header')
+    return null
+  }
+  const { cipherSuite } = getPeerProtocol(deviceId!)
+  const decrypt = (key: Buffer): unknown => decryptMessage(raw, key, deviceId!, cipherSuite)
+  const message = (
+    session.ratchet ? session.ratchet.open(raw.ratchet!, decrypt) : decrypt(session.sessionKey)
+  ) as NetworkMessage
+  // Checked only now that the frame has authenticated, so its number can be trusted
+  if (peerSupports(deviceId!, Capability.REPLAY_PROTECTION)) {
+    if (!session.received) session.received = new ReplayWindow()
+    if (raw.seq === undefined || !session.received.accept(raw.seq)) {
+      console.error(`[${tag}] Dropping replayed frame #${raw.seq} from ${deviceId}`)
+      recordSecurityEvent('replay-rejected', deviceId!, `Frame #${raw.seq} was already received`)
+      return null
+    }
+  }
+  return { message: fromWireMessage(message), encrypted: true }
+}
@@ -0,0 +1,213 @@
+import fs from 'fs'
+import path from 'path'
+import zlib from 'zlib'
+import { Readable } from 'stream'
+
+// Entries are STOREd (no compression, the transfer stream already compresses) and their
+// CRCs go in a data descriptor after the data, so nothing has to be read twice
+const LOCAL_HEADER = 0x04034b50
+const DATA_DESCRIPTOR = 0x08074b50
+const CENTRAL_HEADER = 0x02014b50
+const END_OF_CENTRAL_DIR = 0x06054b50
+
+const LOCAL_HEADER_SIZE = 30
+const DATA_DESCRIPTOR_SIZE = 16
+const CENTRAL_HEADER_SIZE = 46
+const END_OF_CENTRAL_DIR_SIZE = 22
+
+const VERSION = 20
+const FLAG_DATA_DESCRIPTOR = 0x0008
+const FLAG_UTF8 = 0x0800
+const DIRECTORY_ATTRIBUTE = 0x10
+
+// Without ZIP64 every size and offset has to fit in 32 bits, and the entry count in 16
+export const ZIP_MAX_SIZE = 0xffffffff
+export const ZIP_MAX_ENTRIES = 0xffff
+
+export interface ZipEntry {
+  name: string // '/'-separated path inside the archive; directories end with '/'
+  sourcePath?: string // unset for directories
+  size: number
+  mtime: Date
+}
+
+interface WrittenEntry {
+  entry: ZipEntry
+  name: Buffer
+  crc: number
+  offset: number
+}
+
+// MS-DOS date and time, which is all the basic format can hold (2-second resolution)
+const dosDateTime = (date: Date): [number, number] => {
+  const year = Math.max(1980, date.getFullYear())
+  const time = (date.getHours() << 11) | (date.getMinutes() << 5) | (date.getSeconds() >> 1)
+  const day = ((year - 1980) << 9) | ((date.getMonth() + 1) << 5) | date.getDate()
+  return [time, day]
+}
+
+/**
+ * Exact size of the archive `createZipStream` produces for these entries, so it can be
+ * announced before any of it is generated.
+ */
+export function getZipSize(entries: ZipEntry[]): number {
+  let size = END_OF_CENTRAL_DIR_SIZE
+  for (const entry of entries) {
+    const nameLength = Buffer.byteLength(entry.name)
+    size += LOCAL_HEADER_SIZE + nameLength + entry.size + DATA_DESCRIPTOR_SIZE
+    size += CENTRAL_HEADER_SIZE + nameLength
+  }
+  return size
+}
+
+// Whether the entries fit in a plain (non-ZIP64) archive
+export function canZip(entries: ZipEntry[]): boolean {
+  return entries.length <= ZIP_MAX_ENTRIES && getZipSize(entries) <= ZIP_MAX_SIZE
+}
+
+const localHeader = (entry: ZipEntry, name: Buffer): Buffer => {
+  const [time, date] = dosDateTime(entry.mtime)
+  const header = Buffer.alloc(LOCAL_HEADER_SIZE)
+  header.writeUInt32LE(LOCAL_HEADER, 0)
+  header.writeUInt16LE(VERSION, 4)
+  header.writeUInt16LE(FLAG_DATA_DESCRIPTOR | FLAG_UTF8, 6)
+  header.writeUInt16LE(0, 8) // stored
+  header.writeUInt16LE(time, 10)
+  header.writeUInt16LE(date, 12)
+  // CRC and sizes (14-25) stay zero; they follow the data in the descriptor
+  header.writeUInt16LE(name.length, 26)
+  header.writeUInt16LE(0, 28)
+  return Buffer.concat([header, name])
+}
+
+const dataDescriptor = (crc: number, size: number): Buffer => {
+  const descriptor = Buffer.alloc(DATA_DESCRIPTOR_SIZE)
+  descriptor.writeUInt32LE(DATA_DESCRIPTOR, 0)
+  descriptor.writeUInt32LE(crc, 4)
+  descriptor.writeUInt32LE(size, 8)
+  descriptor.writeUInt32LE(size, 12)
+  return descriptor
+}
+
+const centralHeader = ({ entry, name, crc, offset }: WrittenEntry): Buffer => {
+  const [time, date] = dosDateTime(entry.mtime)
+  const header = Buffer.alloc(CENTRAL_HEADER_SIZE)
+  header.writeUInt32LE(CENTRAL_HEADER, 0)
+  header.writeUInt16LE(VERSION, 4)
+  header.writeUInt16LE(VERSION, 6)
+  header.writeUInt16LE(FLAG_DATA_DESCRIPTOR | FLAG_UTF8, 8)
+  header.writeUInt16LE(0, 10)
+  header.writeUInt16LE(time, 12)
+  header.writeUInt16LE(date, 14)
+  header.writeUInt32LE(crc, 16)
+  header.writeUInt32LE(entry.size, 20)
+  header.writeUInt32LE(entry.size, 24)
+  header.writeUInt16LE(name.length, 28)
+  // Extra field, comment, disk number and internal attributes (30-37) stay zero
+  header.writeUInt32LE(entry.sourcePath ? 0 : DIRECTORY_ATTRIBUTE, 38)
+  header.writeUInt32LE(offset, 42)
+  return Buffer.concat([header, name])
+}
+
+const endOfCentralDirectory = (count: number, size: number, offset: number): Buffer => {
+  const record = Buffer.alloc(END_OF_CENTRAL_DIR_SIZE)
+  record.writeUInt32LE(END_OF_CENTRAL_DIR, 0)
+  record.writeUInt16LE(count, 8)
+  record.writeUInt16LE(count, 10)
+  record.writeUInt32LE(size, 12)
+  record.writeUInt32LE(offset, 16)
+  return record
+}
+
+async function* generateZip(entries: ZipEntry[]): AsyncGenerator<Buffer> {
+  const written: WrittenEntry[] = []
+  let position = 0
+
+  for (const entry of entries) {
+    const name = Buffer.from(entry.name)
+    const header = localHeader(entry, name)
+    yield header
+    const offset = position
+    position += header.length
+
+    let crc = 0
+    let size = 0
+    if (entry.sourcePath) {
+      for await (const chunk of fs.createReadStream(entry.sourcePath, {
+        highWaterMark: 256 * 1024
+      })) {
+        size += chunk.length
+        // The announced archive size can't change, so neither can any file in it
+        if (size > entry.size) break
+        crc = zlib.crc32(chunk, crc)
+        yield chunk
+      }
+      if (size !== entry.size) {
+        throw new Error(`${entry.name} changed while it was being sent`)
+      }
+    }
+    position += size
+
+    const descriptor = dataDescriptor(crc, size)
+    yield descriptor
+    position += descriptor.length
+    written.push({ entry, name, crc, offset })
+  }
+
+  const centralStart = position
+  for (const entry of written) {
+    const header = centralHeader(entry)
+    yield header
+    position += header.length
+  }
+  yield endOfCentralDirectory(written.length, position - centralStart, centralStart)
+}
+
+async function* skipBytes(source: AsyncGenerator<Buffer>, count: number): AsyncGenerator<Buffer> {
+  let skipped = 0
+  for await (const chunk of source) {
+    if (skipped >= count) {
+      yield chunk
+    } else if (skipped + chunk.length > count) {
+      yield chunk.subarray(count - skipped)
+      skipped = count
+    } else {
+      skipped += chunk.length
+    }
+  }
+}
+
+/**
+ * Streams a zip archive of `entries`, generated as it is read; no temporary file is
+ * written. With `start`, the first `start` bytes are generated but not emitted, which is
+ * how an interrupted archive is resumed.
+ */
+export function createZipStream(entries: ZipEntry[], start = 0): Readable {
+  const source = generateZip(entries)
+  return Readable.from(start > 0 ? skipBytes(source, start) : source, { objectMode: false })
+}
+
+/**
+ * Lists a folder as zip entries, directories included so empty ones survive. Like folder
+ * manifests, symlinks and special files are skipped.
+ */
+export async function listZipEntries(folderPath: string): Promise<ZipEntry[]> {
+  const entries: ZipEntry[] = []
+  const walk = async (dir: string, prefix: string): Promise<void> => {
+    const items = await fs.promises.readdir(dir, { withFileTypes: true })
+    for (const item of items) {
+      const name = `${prefix}${item.name}`
+      const fullPath = path.join(dir, item.name)
+      if (item.isDirectory()) {
+        const { mtime } = await fs.promises.stat(fullPath)
+        entries.push({ name: `${name}/`, size: 0, mtime })
+        await walk(fullPath, `${name}/`)
+      } else if (item.isFile()) {
+        const { size, mtime } = await fs.promises.stat(fullPath)
+        entries.push({ name, sourcePath: fullPath, size, mtime })
+      }
+    }
+  }
+  await walk(folderPath, `${path.basename(folderPath)}/`)
+  return entries
+}
@@ -1,9 +1,111 @@
 import { ElectronAPI } from '@electron-toolkit/preload'
-import { Device, NetworkMessage, FileTransferProgress, DeviceInfo } from '@shared/messageTypes'
+import {
+  Device,
+  NetworkMessage,
+  FileTransferProgress,
+  DeviceInfo,
+  MessageEdit,
+  MessageDeletion,
+  Group,
+  GroupDeliveryState,
+  MessageSearchResult,
+  ScheduledMessage,
+  LinkPreview,
+  InboxThread,
+  AutoAcceptRule,
+  ConflictPolicy,
+  QueuedTransfer,
+  TransferPriority,
+  FanOutStatus,
+  CleanupResult,
+  ConnectionInfo,
+  CryptoSelfTestResult,
+  TransferStats,
+  PeerError,
+  Timeouts
+} from '@shared/messageTypes'
 import { UpdateInfo, DownloadProgress } from '@shared/updateTypes'
 
 export type PermissionType = 'notification' | 'camera' | 'microphone' | 'screen'
 export type PermissionStatus = 'granted' | 'denied' | 'not-determined' | 'unknown'
+export type PeerCapability = 'send-files' | 'send-messages' | 'clipboard-sync' | 'remote-commands'
+export type PeerPermissions = Record<PeerCapability, boolean>
+
+export interface ConnectionRequest {
+  deviceId: string
+  displayName?: string
+  platform?: string
+  address?: string
+}
+
+export interface SecurityEvent {
+  type:
+    | 'plaintext-fallback'
+    | 'decryption-failed'
+    | 'key-changed'
+    | 'handshake-rejected'
+    | 'peer-blocked'
+    | 'replay-rejected'
+    | 'rate-limited'
+  timestamp: number
+  deviceId?: string
+  details?: string
+  prevHash: string
+  hash: string
+}
+
+export interface HotspotInfo {
+  ssid: string
+  password: string
+  qrPayload: string // a Wi-Fi QR code payload, WIFI:T:WPA;S:...;P:...;;
+}
+
+export interface PortRange {
+  start: number
+  end: number
+}
+export interface SocketTuning {
+  reusePort: boolean
+  backlog: number
+  bufferSize: number // bytes
+}
+
+export interface FirewallSettings {
+  bindAddress: string // '' for every address; used from the next start
+  allowedSubnets: string[] // empty allows any
+  lanOnly: boolean
+}
+
+export interface ListenerEndpoint {
+  url: string
+  port: number // 0 when it couldn't start
+  error?: string
+}
+
+export interface BluetoothStatus {
+  enabled: boolean
+  ports: string[] // serial ports of paired devices, e.g. /dev/rfcomm0 or COM5
+  peers: string[] // display names of the devices on open links
+}
+
+export interface OverlayInterface {
+  name: string
+  kind: 'tailscale' | 'wireguard' | 'zerotier'
+  addresses: string[]
+}
+
+export interface OverlayStatus {
+  enabled: boolean
+  interfaces: OverlayInterface[]
+  peers: string[] // "host" or "host:port"
+  found: number
+}
+
+export interface RelayStatus {
+  address: string
+  registered: boolean
+  peers: number
+}
 
 export interface NetworkInfo {
   port: number
@@ -14,27 +116,180 @@ export interface NetworkInfo {
 export interface IApi {
   getDeviceInfo: () => Promise<DeviceInfo>
   updateDisplayName: (name: string) => Promise<DeviceInfo>
-  updateProfile: (name?: string, image?: string) => Promise<DeviceInfo>
+  updateProfile: (name?: string, image?: string, statusMessage?: string) => Promise<DeviceInfo>
+  setGuestMode: (enabled: boolean, displayName?: string) => Promise<DeviceInfo>
+  isGuestMode: () => Promise<boolean>
+  getRequireConnectionApproval: () => Promise<boolean>
+  setRequireConnectionApproval: (required: boolean) => Promise<boolean>
+  getPendingConnectionRequests: () => Promise<ConnectionRequest[]>
+  acceptConnection: (deviceId: string) => Promise<boolean>
+  denyConnection: (deviceId: string) => Promise<boolean>
+  untrustDevice: (deviceId: string) => Promise<void>
+  getSecurityEvents: (limit?: number) => Promise<{ events: SecurityEvent[]; intact: boolean }>
+  runCryptoSelfTest: () => Promise<CryptoSelfTestResult>
+  getActiveProfile: () => Promise<string>
+  listProfiles: () => Promise<string[]>
+  switchProfile: (name: string) => Promise<DeviceInfo>
   getNetworkInfo: () => Promise<NetworkInfo>
+  getConnections: () => Promise<ConnectionInfo[]>
+  disconnectDevice: (deviceId: string) => Promise<boolean>
   getDiscoveredDevices: () => Promise<Device[]>
   checkPermission: (type: PermissionType) => Promise<PermissionStatus>
   requestPermission: (type: PermissionType) => Promise<boolean>
+  getPeerPermissions: (deviceId: string) => Promise<PeerPermissions>
+  setPeerPermission: (
+    deviceId: string,
+    capability: PeerCapability,
+    allowed: boolean
+  ) => Promise<PeerPermissions>
   sendMessage: (deviceId: string, payload: string, replyTo?: string) => Promise<NetworkMessage>
 
-  sendFile: (deviceId: string, filePath: string, replyTo?: string) => Promise<NetworkMessage>
-  acceptFile: (fileId: string) => Promise<void>
+  sendFile: (
+    deviceId: string,
+    filePath: string,
+    replyTo?: string,
+    sensitive?: boolean,
+    passphrase?: string
+  ) => Promise<NetworkMessage>
+  // False if the file is passphrase-protected and `passphrase` is wrong
+  acceptFile: (fileId: string, passphrase?: string) => Promise<boolean>
   rejectFile: (fileId: string) => Promise<void>
+  pauseFile: (fileId: string) => Promise<boolean>
+  resumeFile: (fileId: string) => Promise<boolean>
+  cancelFile: (fileId: string) => Promise<boolean>
+  getTransferQueue: () => Promise<QueuedTransfer[]>
+  getTransferStats: (fileId: string) => Promise<TransferStats | null>
+  reorderTransferQueue: (fileIds: string[]) => Promise<QueuedTransfer[]>
+  setTransferPriority: (fileId: string, priority: TransferPriority) => Promise<boolean>
   selectFile: () => Promise<string | null>
+  sendFileToDevices: (paths: string[], deviceIds: string[]) => Promise<FanOutStatus>
+  getFanOutStatus: (batchId: string) => Promise<FanOutStatus | null>
+  sendFolder: (
+    deviceId: string,
+    folderPath: string,
+    replyTo?: string,
+    asZip?: boolean
+  ) => Promise<NetworkMessage>
+  selectFolder: () => Promise<string | null>
   openFileLocation: (filePath: string) => Promise<void>
   clearCache: () => Promise<boolean>
+  cleanIncompleteTransfers: () => Promise<CleanupResult>
   rescanDevices: () => Promise<void>
   getDownloadPath: () => Promise<string>
   selectDownloadDirectory: () => Promise<string | null>
   setDownloadPath: (path: string) => Promise<string>
+  getAutoAcceptRules: () => Promise<AutoAcceptRule[]>
+  setAutoAcceptRules: (rules: AutoAcceptRule[]) => Promise<AutoAcceptRule[]>
+  getParallelStreams: () => Promise<number>
+  getFileRequestExpiry: () => Promise<number>
+  getTransferRetryLimit: () => Promise<number>
+  getMaxIncomingSize: () => Promise<number>
+  setMaxIncomingSize: (bytes: number) => Promise<number>
+  setTransferRetryLimit: (limit: number) => Promise<number>
+  getHeartbeatInterval: () => Promise<number>
+  setHeartbeatInterval: (seconds: number) => Promise<number>
+  getIdleTimeout: () => Promise<number>
+  setIdleTimeout: (seconds: number) => Promise<number>
+  getTimeouts: () => Promise<Timeouts>
+  setTimeouts: (timeouts: Timeouts) => Promise<Timeouts>
+  getStrictFrameParsing: () => Promise<boolean>
+  setStrictFrameParsing: (enabled: boolean) => Promise<boolean>
+  getPadControlFrames: () => Promise<boolean>
+  setPadControlFrames: (enabled: boolean) => Promise<boolean>
+  getRefusePlaintext: () => Promise<boolean>
+  setRefusePlaintext: (enabled: boolean) => Promise<boolean>
+  // Port browser peers connect to over WebSocket; 0 when turned off
+  getBrowserConnections: () => Promise<number>
+  setBrowserConnections: (enabled: boolean) => Promise<number>
+  getPreferTls: () => Promise<boolean>
+  setPreferTls: (preferred: boolean) => Promise<boolean>
+  getRelayAddress: () => Promise<string>
+  setRelayAddress: (address: string) => Promise<string>
+  getRelayStatus: () => Promise<RelayStatus>
+  getOutboundProxy: () => Promise<string>
+  setOutboundProxy: (url: string) => Promise<string>
+  getHotspot: () => Promise<HotspotInfo | null>
+  startHotspot: () => Promise<HotspotInfo>
+  stopHotspot: () => Promise<void>
+  joinHotspot: (qrPayload: string) => Promise<string>
+  getListenerEndpoints: () => Promise<ListenerEndpoint[]>
+  setListenerEndpoints: (urls: string[]) => Promise<ListenerEndpoint[]>
+  getPortRange: () => Promise<PortRange>
+  getFirewall: () => Promise<FirewallSettings>
+  setFirewall: (settings: FirewallSettings) => Promise<FirewallSettings>
+  setPortRange: (start: number, end: number) => Promise<PortRange>
+  restartServer: (port?: number) => Promise<number>
+  getSocketTuning: () => Promise<SocketTuning>
+  setSocketTuning: (tuning: SocketTuning) => Promise<SocketTuning>
+  getOverlayStatus: () => Promise<OverlayStatus>
+  setOverlayEnabled: (enabled: boolean) => Promise<OverlayStatus>
+  setOverlayPeers: (peers: string[]) => Promise<OverlayStatus>
+  scanOverlay: () => Promise<OverlayStatus>
+  getUdpTransfers: () => Promise<boolean>
+  setUdpTransfers: (enabled: boolean) => Promise<boolean>
+  getBluetoothStatus: () => Promise<BluetoothStatus>
+  setBluetoothEnabled: (enabled: boolean) => Promise<BluetoothStatus>
+  setBluetoothPorts: (ports: string[]) => Promise<BluetoothStatus>
+  getSendFoldersAsZip: () => Promise<boolean>
+  setSendFoldersAsZip: (enabled: boolean) => Promise<boolean>
+  setFileRequestExpiry: (minutes: number) => Promise<number>
+  setParallelStreams: (count: number) => Promise<number>
+  getConflictPolicy: () => Promise<ConflictPolicy>
+  setConflictPolicy: (policy: ConflictPolicy) => Promise<ConflictPolicy>
+  getPerPeerDownloadFolders: () => Promise<boolean>
+  setPerPeerDownloadFolders: (enabled: boolean) => Promise<boolean>
   getAutoAccept: () => Promise<boolean>
   setAutoAccept: (autoAccept: boolean) => Promise<boolean>
+  getSendReadReceipts: () => Promise<boolean>
+  setSendReadReceipts: (enabled: boolean) => Promise<boolean>
+  editMessage: (deviceId: string, messageId: string, payload: string) => Promise<MessageEdit>
+  getGroups: () => Promise<Group[]>
+  createGroup: (name: string, memberIds: string[]) => Promise<Group>
+  addGroupMember: (groupId: string, deviceId: string) => Promise<Group>
+  removeGroupMember: (groupId: string, deviceId: string) => Promise<Group>
+  sendGroupMessage: (
+    groupId: string,
+    payload: string,
+    replyTo?: string
+  ) => Promise<{ message: NetworkMessage; state: GroupDeliveryState }>
+  forwardMessage: (
+    message: NetworkMessage,
+    toDeviceId: string,
+    filePath?: string
+  ) => Promise<NetworkMessage>
+  searchMessages: (
+    query: string,
+    conversationId?: string,
+    limit?: number
+  ) => Promise<MessageSearchResult[]>
+  clearMessages: (conversationId?: string) => Promise<void>
+  getScheduledMessages: (deviceId?: string) => Promise<ScheduledMessage[]>
+  scheduleMessage: (
+    deviceId: string,
+    payload: string,
+    sendAt: number,
+    replyTo?: string
+  ) => Promise<ScheduledMessage>
+  updateScheduledMessage: (
+    scheduleId: string,
+    changes: { payload?: string; sendAt?: number }
+  ) => Promise<ScheduledMessage>
+  cancelScheduledMessage: (scheduleId: string) => Promise<boolean>
+  getLinkPreviews: () => Promise<boolean>
+  getSendFilePreviews: () => Promise<boolean>
+  setSendFilePreviews: (enabled: boolean) => Promise<boolean>
+  setLinkPreviews: (enabled: boolean) => Promise<boolean>
+  getDraft: (conversationId: string) => Promise<string>
+  saveDraft: (conversationId: string, text: string) => Promise<void>
   markAsRead: (deviceId: string, messageId: string) => Promise<void>
+  markConversationRead: (conversationId: string) => Promise<void>
+  getInboxSummary: () => Promise<InboxThread[]>
   deleteRemoteMessage: (deviceId: string, messageId: string) => Promise<void>
+  deleteMessage: (
+    deviceId: string,
+    messageId: string,
+    forEveryone: boolean
+  ) => Promise<MessageDeletion>
   minimizeWindow: () => void
   maximizeWindow: () => void
   closeWindow: () => void
@@ -47,9 +302,39 @@ export interface IApi {
 
   onDeviceDiscovered: (callback: (device: Device) => void) => void
   onDeviceLost: (callback: (deviceId: string) => void) => void
+  onDeviceUpdated: (callback: (device: Device) => void) => () => void
+  onConnectionRequest: (callback: (request: ConnectionRequest) => void) => () => void
+  onConnectionRequestCancelled: (callback: (deviceId: string) => void) => () => void
+  onServerListening: (callback: (port: number) => void) => () => void
+  onConnectionMetrics: (callback: (connections: ConnectionInfo[]) => void) => () => void
   onMessageReceived: (callback: (message: NetworkMessage) => void) => void
   onFileReceived: (callback: (message: NetworkMessage) => void) => void
+  onFileFanOutProgress: (callback: (status: FanOutStatus) => void) => () => void
+  onFileVerificationProgress: (
+    callback: (data: { fileId: string; deviceId: string; progress: number }) => void
+  ) => () => void
   onFileTransferProgress: (callback: (progress: FileTransferProgress) => void) => void
+  onMessageDelivered: (
+    callback: (data: { deviceId: string; messageId: string }) => void
+  ) => () => void
+  onGroupUpdated: (callback: (group: Group) => void) => () => void
+  onGroupMessageReceived: (callback: (message: NetworkMessage) => void) => () => void
+  onGroupMessageDelivered: (
+    callback: (data: { groupId: string; messageId: string; state: GroupDeliveryState }) => void
+  ) => () => void
+  onLinkPreview: (
+    callback: (data: { conversationId: string; messageId: string; preview: LinkPreview }) => void
+  ) => () => void
+  onScheduledMessageSent: (
+    callback: (data: { scheduleId: string; deviceId: string; message: NetworkMessage }) => void
+  ) => () => void
+  onMessageDeleted: (callback: (deletion: MessageDeletion) => void) => () => void
+  onRemoteMessageDeleted: (
+    callback: (data: { deviceId: string; messageId: string }) => void
+  ) => () => void
+  onMessageEdited: (callback: (edit: MessageEdit) => void) => () => void
+  onPeerError: (callback: (error: PeerError) => void) => () => void
+  onMessageRead: (callback: (data: { deviceId: string; messageId: string }) => void) => () => void
 
   // Auto-Update Event Listeners
   onUpdateChecking: (callback: () => void) => () => void
@@ -1,9 +1,101 @@
 import { contextBridge, ipcRenderer } from 'electron'
 import { electronAPI } from '@electron-toolkit/preload'
-import { Device, NetworkMessage, FileTransferProgress, DeviceInfo } from '@shared/messageTypes'
+import {
+  Device,
+  NetworkMessage,
+  FileTransferProgress,
+  DeviceInfo,
+  MessageEdit,
+  MessageDeletion,
+  Group,
+  GroupDeliveryState,
+  MessageSearchResult,
+  ScheduledMessage,
+  LinkPreview,
+  InboxThread,
+  AutoAcceptRule,
+  ConflictPolicy,
+  QueuedTransfer,
+  TransferPriority,
+  FanOutStatus,
+  CleanupResult,
+  ConnectionInfo,
+  CryptoSelfTestResult,
+  TransferStats,
+  PeerError,
+  Timeouts
+} from '@shared/messageTypes'
 
 export type PermissionType = 'notification' | 'camera' | 'microphone' | 'screen'
 export type PermissionStatus = 'granted' | 'denied' | 'not-determined' | 'unknown'
+export type PeerCapability = 'send-files' | 'send-messages' | 'clipboard-sync' | 'remote-commands'
+export type PeerPermissions = Record<PeerCapability, boolean>
+export interface ConnectionRequest {
+  deviceId: string
+  displayName?: string
+  platform?: string
+  address?: string
+}
+export interface SecurityEvent {
+  type:
+    | 'plaintext-fallback'
+    | 'decryption-failed'
+    | 'key-changed'
+    | 'handshake-rejected'
+    | 'peer-blocked'
+    | 'replay-rejected'
+    | 'rate-limited'
+  timestamp: number
+  deviceId?: string
+  details?: string
+  prevHash: string
+  hash: string
+}
+export interface HotspotInfo {
+  ssid: string
+  password: string
+  qrPayload: string // a Wi-Fi QR code payload, WIFI:T:WPA;S:...;P:...;;
+}
+export interface PortRange {
+  start: number
+  end: number
+}
+export interface SocketTuning {
+  reusePort: boolean
+  backlog: number
+  bufferSize: number // bytes
+}
+export interface FirewallSettings {
+  bindAddress: string
+  allowedSubnets: string[]
+  lanOnly: boolean
+}
+export interface ListenerEndpoint {
+  url: string
+  port: number
+  error?: string
+}
+export interface BluetoothStatus {
+  enabled: boolean
+  ports: string[]
+  peers: string[]
+}
+export interface OverlayInterface {
+  name: string
+  kind: 'tailscale' | 'wireguard' | 'zerotier'
+  addresses: string[]
+}
+export interface OverlayStatus {
+  enabled: boolean
+  interfaces: OverlayInterface[]
+  peers: string[] // "host" or "host:port"
+  found: number
+}
+export interface RelayStatus {
+  address: string
+  registered: boolean
+  peers: number
+}
 
 console.log('[Preload] Script loading...')
 
@@ -11,37 +103,255 @@ const api = {
   getDeviceInfo: (): Promise<DeviceInfo> => ipcRenderer.invoke('get-device-info'),
   updateDisplayName: (name: string): Promise<DeviceInfo> =>
     ipcRenderer.invoke('update-display-name', name),
-  updateProfile: (name?: string, image?: string): Promise<DeviceInfo> =>
-    ipcRenderer.invoke('update-profile', name, image),
+  updateProfile: (name?: string, image?: string, statusMessage?: string): Promise<DeviceInfo> =>
+    ipcRenderer.invoke('update-profile', name, image, statusMessage),
+  setGuestMode: (enabled: boolean, displayName?: string): Promise<DeviceInfo> =>
+    ipcRenderer.invoke('set-guest-mode', enabled, displayName),
+  isGuestMode: (): Promise<boolean> => ipcRenderer.invoke('is-guest-mode'),
+  getRequireConnectionApproval: (): Promise<boolean> =>
+    ipcRenderer.invoke('get-require-connection-approval'),
+  setRequireConnectionApproval: (required: boolean): Promise<boolean> =>
+    ipcRenderer.invoke('set-require-connection-approval', required),
+  getPendingConnectionRequests: (): Promise<ConnectionRequest[]> =>
+    ipcRenderer.invoke('get-pending-connection-requests'),
+  acceptConnection: (deviceId: string): Promise<boolean> =>
+    ipcRenderer.invoke('accept-connection', deviceId),
+  denyConnection: (deviceId: string): Promise<boolean> =>
+    ipcRenderer.invoke('deny-connection', deviceId),
+  untrustDevice: (deviceId: string): Promise<void> =>
+    ipcRenderer.invoke('untrust-device', deviceId),
+  getSecurityEvents: (limit?: number): Promise<{ events: SecurityEvent[]; intact: boolean }> =>
+    ipcRenderer.invoke('get-security-events', limit),
+  runCryptoSelfTest: (): Promise<CryptoSelfTestResult> => ipcRenderer.invoke('run-crypto-selftest'),
+  getActiveProfile: (): Promise<string> => ipcRenderer.invoke('get-active-profile'),
+  listProfiles: (): Promise<string[]> => ipcRenderer.invoke('list-profiles'),
+  switchProfile: (name: string): Promise<DeviceInfo> => ipcRenderer.invoke('switch-profile', name),
   getNetworkInfo: () => ipcRenderer.invoke('get-network-info'),
+  getConnections: (): Promise<ConnectionInfo[]> => ipcRenderer.invoke('get-connections'),
+  disconnectDevice: (deviceId: string): Promise<boolean> =>
+    ipcRenderer.invoke('disconnect-device', deviceId),
   getDiscoveredDevices: (): Promise<Device[]> => ipcRenderer.invoke('get-discovered-devices'),
   checkPermission: (type: PermissionType): Promise<PermissionStatus> =>
     ipcRenderer.invoke('check-permission', type),
   requestPermission: (type: PermissionType): Promise<boolean> =>
     ipcRenderer.invoke('request-permission', type),
+  getPeerPermissions: (deviceId: string): Promise<PeerPermissions> =>
+    ipcRenderer.invoke('get-peer-permissions', deviceId),
+  setPeerPermission: (
+    deviceId: string,
+    capability: PeerCapability,
+    allowed: boolean
+  ): Promise<PeerPermissions> =>
+    ipcRenderer.invoke('set-peer-permission', deviceId, capability, allowed),
   sendMessage: (deviceId: string, payload: string, replyTo?: string): Promise<NetworkMessage> =>
     ipcRenderer.invoke('send-message', deviceId, payload, replyTo),
 
-  sendFile: (deviceId: string, filePath: string, replyTo?: string): Promise<NetworkMessage> =>
-    ipcRenderer.invoke('send-file', deviceId, filePath, replyTo),
-  acceptFile: (fileId: string): Promise<void> => ipcRenderer.invoke('accept-file', fileId),
+  sendFile: (
+    deviceId: string,
+    filePath: string,
+    replyTo?: string,
+    sensitive?: boolean,
+    passphrase?: string
+  ): Promise<NetworkMessage> =>
+    ipcRenderer.invoke('send-file', deviceId, filePath, replyTo, sensitive, passphrase),
+  acceptFile: (fileId: string, passphrase?: string): Promise<boolean> =>
+    ipcRenderer.invoke('accept-file', fileId, passphrase),
   rejectFile: (fileId: string): Promise<void> => ipcRenderer.invoke('reject-file', fileId),
+  pauseFile: (fileId: string): Promise<boolean> => ipcRenderer.invoke('pause-file', fileId),
+  resumeFile: (fileId: string): Promise<boolean> => ipcRenderer.invoke('resume-file', fileId),
+  cancelFile: (fileId: string): Promise<boolean> => ipcRenderer.invoke('cancel-file', fileId),
+  getTransferQueue: (): Promise<QueuedTransfer[]> => ipcRenderer.invoke('get-transfer-queue'),
+  getTransferStats: (fileId: string): Promise<TransferStats | null> =>
+    ipcRenderer.invoke('get-transfer-stats', fileId),
+  reorderTransferQueue: (fileIds: string[]): Promise<QueuedTransfer[]> =>
+    ipcRenderer.invoke('reorder-transfer-queue', fileIds),
+  setTransferPriority: (fileId: string, priority: TransferPriority): Promise<boolean> =>
+    ipcRenderer.invoke('set-transfer-priority', fileId, priority),
   selectFile: (): Promise<string | null> => ipcRenderer.invoke('select-file'),
+  sendFileToDevices: (paths: string[], deviceIds: string[]): Promise<FanOutStatus> =>
+    ipcRenderer.invoke('send-file-to-devices', paths, deviceIds),
+  getFanOutStatus: (batchId: string): Promise<FanOutStatus | null> =>
+    ipcRenderer.invoke('get-fanout-status', batchId),
+  sendFolder: (
+    deviceId: string,
+    folderPath: string,
+    replyTo?: string,
+    asZip?: boolean
+  ): Promise<NetworkMessage> =>
+    ipcRenderer.invoke('send-folder', deviceId, folderPath, replyTo, asZip),
+  selectFolder: (): Promise<string | null> => ipcRenderer.invoke('select-folder'),
   openFileLocation: (filePath: string): Promise<void> =>
     ipcRenderer.invoke('open-file-location', filePath),
   clearCache: (): Promise<boolean> => ipcRenderer.invoke('clear-cache'),
+  cleanIncompleteTransfers: (): Promise<CleanupResult> =>
+    ipcRenderer.invoke('clean-incomplete-transfers'),
   rescanDevices: (): Promise<void> => ipcRenderer.invoke('rescan-devices'),
   getDownloadPath: (): Promise<string> => ipcRenderer.invoke('get-download-path'),
   selectDownloadDirectory: (): Promise<string | null> =>
     ipcRenderer.invoke('select-download-directory'),
   setDownloadPath: (path: string): Promise<string> => ipcRenderer.invoke('set-download-path', path),
+  getAutoAcceptRules: (): Promise<AutoAcceptRule[]> => ipcRenderer.invoke('get-auto-accept-rules'),
+  setAutoAcceptRules: (rules: AutoAcceptRule[]): Promise<AutoAcceptRule[]> =>
+    ipcRenderer.invoke('set-auto-accept-rules', rules),
+  getParallelStreams: (): Promise<number> => ipcRenderer.invoke('get-parallel-streams'),
+  getFileRequestExpiry: (): Promise<number> => ipcRenderer.invoke('get-file-request-expiry'),
+  getTransferRetryLimit: (): Promise<number> => ipcRenderer.invoke('get-transfer-retry-limit'),
+  getMaxIncomingSize: (): Promise<number> => ipcRenderer.invoke('get-max-incoming-size'),
+  setMaxIncomingSize: (bytes: number): Promise<number> =>
+    ipcRenderer.invoke('set-max-incoming-size', bytes),
+  setTransferRetryLimit: (limit: number): Promise<number> =>
+    ipcRenderer.invoke('set-transfer-retry-limit', limit),
+  getHeartbeatInterval: (): Promise<number> => ipcRenderer.invoke('get-heartbeat-interval'),
+  setHeartbeatInterval: (seconds: number): Promise<number> =>
+    ipcRenderer.invoke('set-heartbeat-interval', seconds),
+  getIdleTimeout: (): Promise<number> => ipcRenderer.invoke('get-idle-timeout'),
+  setIdleTimeout: (seconds: number): Promise<number> =>
+    ipcRenderer.invoke('set-idle-timeout', seconds),
+  getTimeouts: (): Promise<Timeouts> => ipcRenderer.invoke('get-timeouts'),
+  setTimeouts: (timeouts: Timeouts): Promise<Timeouts> =>
+    ipcRenderer.invoke('set-timeouts', timeouts),
+  getStrictFrameParsing: (): Promise<boolean> => ipcRenderer.invoke('get-strict-frame-parsing'),
+  setStrictFrameParsing: (enabled: boolean): Promise<boolean> =>
+    ipcRenderer.invoke('set-strict-frame-parsing', enabled),
+  getPadControlFrames: (): Promise<boolean> => ipcRenderer.invoke('get-pad-control-frames'),
+  setPadControlFrames: (enabled: boolean): Promise<boolean> =>
+    ipcRenderer.invoke('set-pad-control-frames', enabled),
+  getRefusePlaintext: (): Promise<boolean> => ipcRenderer.invoke('get-refuse-plaintext'),
+  setRefusePlaintext: (enabled: boolean): Promise<boolean> =>
+    ipcRenderer.invoke('set-refuse-plaintext', enabled),
+  getBrowserConnections: (): Promise<number> => ipcRenderer.invoke('get-browser-connections'),
+  setBrowserConnections: (enabled: boolean): Promise<number> =>
+    ipcRenderer.invoke('set-browser-connections', enabled),
+  getPreferTls: (): Promise<boolean> => ipcRenderer.invoke('get-prefer-tls'),
+  setPreferTls: (preferred: boolean): Promise<boolean> =>
+    ipcRenderer.invoke('set-prefer-tls', preferred),
+  getRelayAddress: (): Promise<string> => ipcRenderer.invoke('get-relay-address'),
+  setRelayAddress: (address: string): Promise<string> =>
+    ipcRenderer.invoke('set-relay-address', address),
+  getRelayStatus: (): Promise<RelayStatus> => ipcRenderer.invoke('get-relay-status'),
+  getOutboundProxy: (): Promise<string> => ipcRenderer.invoke('get-outbound-proxy'),
+  setOutboundProxy: (url: string): Promise<string> => ipcRenderer.invoke('set-outbound-proxy', url),
+  getHotspot: (): Promise<HotspotInfo | null> => ipcRenderer.invoke('get-hotspot'),
+  startHotspot: (): Promise<HotspotInfo> => ipcRenderer.invoke('start-hotspot'),
+  stopHotspot: (): Promise<void> => ipcRenderer.invoke('stop-hotspot'),
+  joinHotspot: (qrPayload: string): Promise<string> =>
+    ipcRenderer.invoke('join-hotspot', qrPayload),
+  getListenerEndpoints: (): Promise<ListenerEndpoint[]> =>
+    ipcRenderer.invoke('get-listener-endpoints'),
+  setListenerEndpoints: (urls: string[]): Promise<ListenerEndpoint[]> =>
+    ipcRenderer.invoke('set-listener-endpoints', urls),
+  getPortRange: (): Promise<PortRange> => ipcRenderer.invoke('get-port-range'),
+  getFirewall: (): Promise<FirewallSettings> => ipcRenderer.invoke('get-firewall'),
+  setFirewall: (settings: FirewallSettings): Promise<FirewallSettings> =>
+    ipcRenderer.invoke('set-firewall', settings),
+  setPortRange: (start: number, end: number): Promise<PortRange> =>
+    ipcRenderer.invoke('set-port-range', start, end),
+  restartServer: (port?: number): Promise<number> => ipcRenderer.invoke('restart-server', port),
+  getSocketTuning: (): Promise<SocketTuning> => ipcRenderer.invoke('get-socket-tuning'),
+  setSocketTuning: (tuning: SocketTuning): Promise<SocketTuning> =>
+    ipcRenderer.invoke('set-socket-tuning', tuning),
+  getOverlayStatus: (): Promise<OverlayStatus> => ipcRenderer.invoke('get-overlay-status'),
+  setOverlayEnabled: (enabled: boolean): Promise<OverlayStatus> =>
+    ipcRenderer.invoke('set-overlay-enabled', enabled),
+  setOverlayPeers: (peers: string[]): Promise<OverlayStatus> =>
+    ipcRenderer.invoke('set-overlay-peers', peers),
+  scanOverlay: (): Promise<OverlayStatus> => ipcRenderer.invoke('scan-overlay'),
+  getUdpTransfers: (): Promise<boolean> => ipcRenderer.invoke('get-udp-transfers'),
+  setUdpTransfers: (enabled: boolean): Promise<boolean> =>
+    ipcRenderer.invoke('set-udp-transfers', enabled),
+  getBluetoothStatus: (): Promise<BluetoothStatus> => ipcRenderer.invoke('get-bluetooth-status'),
+  setBluetoothEnabled: (enabled: boolean): Promise<BluetoothStatus> =>
+    ipcRenderer.invoke('set-bluetooth-enabled', enabled),
+  setBluetoothPorts: (ports: string[]): Promise<BluetoothStatus> =>
+    ipcRenderer.invoke('set-bluetooth-ports', ports),
+  getSendFoldersAsZip: (): Promise<boolean> => ipcRenderer.invoke('get-send-folders-as-zip'),
+  setSendFoldersAsZip: (enabled: boolean): Promise<boolean> =>
+    ipcRenderer.invoke('set-send-folders-as-zip', enabled),
+  setFileRequestExpiry: (minutes: number): Promise<number> =>
+    ipcRenderer.invoke('set-file-request-expiry', minutes),
+  setParallelStreams: (count: number): Promise<number> =>
+    ipcRenderer.invoke('set-parallel-streams', count),
+  getConflictPolicy: (): Promise<ConflictPolicy> => ipcRenderer.invoke('get-conflict-policy'),
+  setConflictPolicy: (policy: ConflictPolicy): Promise<ConflictPolicy> =>
+    ipcRenderer.invoke('set-conflict-policy', policy),
+  getPerPeerDownloadFolders: (): Promise<boolean> =>
+    ipcRenderer.invoke('get-per-peer-download-folders'),
+  setPerPeerDownloadFolders: (enabled: boolean): Promise<boolean> =>
+    ipcRenderer.invoke('set-per-peer-download-folders', enabled),
   getAutoAccept: (): Promise<boolean> => ipcRenderer.invoke('get-auto-accept'),
   setAutoAccept: (autoAccept: boolean): Promise<boolean> =>
     ipcRenderer.invoke('set-auto-accept', autoAccept),
+  getSendReadReceipts: (): Promise<boolean> => ipcRenderer.invoke('get-send-read-receipts'),
+  setSendReadReceipts: (enabled: boolean): Promise<boolean> =>
+    ipcRenderer.invoke('set-send-read-receipts', enabled),
+  editMessage: (deviceId: string, messageId: string, payload: string): Promise<MessageEdit> =>
+    ipcRenderer.invoke('edit-message', deviceId, messageId, payload),
+  getGroups: (): Promise<Group[]> => ipcRenderer.invoke('get-groups'),
+  createGroup: (name: string, memberIds: string[]): Promise<Group> =>
+    ipcRenderer.invoke('create-group', name, memberIds),
+  addGroupMember: (groupId: string, deviceId: string): Promise<Group> =>
+    ipcRenderer.invoke('add-group-member', groupId, deviceId),
+  removeGroupMember: (groupId: string, deviceId: string): Promise<Group> =>
+    ipcRenderer.invoke('remove-group-member', groupId, deviceId),
+  sendGroupMessage: (
+    groupId: string,
+    payload: string,
+    replyTo?: string
+  ): Promise<{ message: NetworkMessage; state: GroupDeliveryState }> =>
+    ipcRenderer.invoke('send-group-message', groupId, payload, replyTo),
+  forwardMessage: (
+    message: NetworkMessage,
+    toDeviceId: string,
+    filePath?: string
+  ): Promise<NetworkMessage> =>
+    ipcRenderer.invoke('forward-message', message, toDeviceId, filePath),
+  searchMessages: (
+    query: string,
+    conversationId?: string,
+    limit?: number
+  ): Promise<MessageSearchResult[]> =>
+    ipcRenderer.invoke('search-messages', query, conversationId, limit),
+  clearMessages: (conversationId?: string): Promise<void> =>
+    ipcRenderer.invoke('clear-messages', conversationId),
+  getScheduledMessages: (deviceId?: string): Promise<ScheduledMessage[]> =>
+    ipcRenderer.invoke('get-scheduled-messages', deviceId),
+  scheduleMessage: (
+    deviceId: string,
+    payload: string,
+    sendAt: number,
+    replyTo?: string
+  ): Promise<ScheduledMessage> =>
+    ipcRenderer.invoke('schedule-message', deviceId, payload, sendAt, replyTo),
+  updateScheduledMessage: (
+    scheduleId: string,
+    changes: { payload?: string; sendAt?: number }
+  ): Promise<ScheduledMessage> =>
+    ipcRenderer.invoke('update-scheduled-message', scheduleId, changes),
+  cancelScheduledMessage: (scheduleId: string): Promise<boolean> =>
+    ipcRenderer.invoke('cancel-scheduled-message', scheduleId),
+  getLinkPreviews: (): Promise<boolean> => ipcRenderer.invoke('get-link-previews'),
+  getSendFilePreviews: (): Promise<boolean> => ipcRenderer.invoke('get-send-file-previews'),
+  setSendFilePreviews: (enabled: boolean): Promise<boolean> =>
+    ipcRenderer.invoke('set-send-file-previews', enabled),
+  setLinkPreviews: (enabled: boolean): Promise<boolean> =>
+    ipcRenderer.invoke('set-link-previews', enabled),
+  getDraft: (conversationId: string): Promise<string> =>
+    ipcRenderer.invoke('get-draft', conversationId),
+  saveDraft: (conversationId: string, text: string): Promise<void> =>
+    ipcRenderer.invoke('save-draft', conversationId, text),
   markAsRead: (deviceId: string, messageId: string): Promise<void> =>
     ipcRenderer.invoke('mark-as-read', deviceId, messageId),
+  markConversationRead: (conversationId: string): Promise<void> =>
+    ipcRenderer.invoke('mark-conversation-read', conversationId),
+  getInboxSummary: (): Promise<InboxThread[]> => ipcRenderer.invoke('get-inbox-summary'),
   deleteRemoteMessage: (deviceId: string, messageId: string): Promise<void> =>
     ipcRenderer.invoke('delete-remote-message', deviceId, messageId),
+  deleteMessage: (
+    deviceId: string,
+    messageId: string,
+    forEveryone: boolean
+  ): Promise<MessageDeletion> =>
+    ipcRenderer.invoke('delete-message', deviceId, messageId, forEveryone),
 
   // Auto-Update
   checkForUpdates: (): Promise<void> => ipcRenderer.invoke('check-for-updates'),
@@ -77,6 +387,42 @@ const api = {
       ipcRenderer.removeListener('device-lost', listener)
     }
   },
+  onDeviceUpdated: (callback: (device: Device) => void): (() => void) => {
+    const listener = (_: unknown, device: unknown): void => callback(device as Device)
+    ipcRenderer.on('device-updated', listener)
+    return (): void => {
+      ipcRenderer.removeListener('device-updated', listener)
+    }
+  },
+  onConnectionRequest: (callback: (request: ConnectionRequest) => void): (() => void) => {
+    const listener = (_: unknown, request: unknown): void => callback(request as ConnectionRequest)
+    ipcRenderer.on('connection-request', listener)
+    return (): void => {
+      ipcRenderer.removeListener('connection-request', listener)
+    }
+  },
+  onServerListening: (callback: (port: number) => void): (() => void) => {
+    const listener = (_: unknown, port: unknown): void => callback(port as number)
+    ipcRenderer.on('server-listening', listener)
+    return (): void => {
+      ipcRenderer.removeListener('server-listening', listener)
+    }
+  },
+  onConnectionMetrics: (callback: (connections: ConnectionInfo[]) => void): (() => void) => {
+    const listener = (_: unknown, connections: unknown): void =>
+      callback(connections as ConnectionInfo[])
+    ipcRenderer.on('connection-metrics', listener)
+    return (): void => {
+      ipcRenderer.removeListener('connection-metrics', listener)
+    }
+  },
+  onConnectionRequestCancelled: (callback: (deviceId: string) => void): (() => void) => {
+    const listener = (_: unknown, deviceId: unknown): void => callback(deviceId as string)
+    ipcRenderer.on('connection-request-cancelled', listener)
+    return (): void => {
+      ipcRenderer.removeListener('connection-request-cancelled', listener)
+    }
+  },
   onMessageReceived: (callback: (message: NetworkMessage) => void): (() => void) => {
     const listener = (_: unknown, message: unknown): void => callback(message as NetworkMessage)
     ipcRenderer.on('message-received', listener)
@@ -91,6 +437,23 @@ const api = {
       ipcRenderer.removeListener('file-received', listener)
     }
   },
+  onFileFanOutProgress: (callback: (status: FanOutStatus) => void): (() => void) => {
+    const listener = (_: unknown, status: unknown): void => callback(status as FanOutStatus)
+    ipcRenderer.on('file-fanout-progress', listener)
+    return (): void => {
+      ipcRenderer.removeListener('file-fanout-progress', listener)
+    }
+  },
+  onFileVerificationProgress: (
+    callback: (data: { fileId: string; deviceId: string; progress: number }) => void
+  ): (() => void) => {
+    const listener = (_: unknown, data: unknown): void =>
+      callback(data as { fileId: string; deviceId: string; progress: number })
+    ipcRenderer.on('file-verification-progress', listener)
+    return (): void => {
+      ipcRenderer.removeListener('file-verification-progress', listener)
+    }
+  },
   onFileTransferProgress: (callback: (progress: FileTransferProgress) => void): (() => void) => {
     const listener = (_: unknown, progress: unknown): void =>
       callback(progress as FileTransferProgress)
@@ -118,6 +481,90 @@ const api = {
       ipcRenderer.removeListener('message-status-updated', listener)
     }
   },
+  onMessageDelivered: (
+    callback: (data: { deviceId: string; messageId: string }) => void
+  ): (() => void) => {
+    const listener = (_: unknown, data: { deviceId: string; messageId: string }): void =>
+      callback(data)
+    ipcRenderer.on('message-delivered', listener)
+    return (): void => {
+      ipcRenderer.removeListener('message-delivered', listener)
+    }
+  },
+  onMessageRead: (
+    callback: (data: { deviceId: string; messageId: string }) => void
+  ): (() => void) => {
+    const listener = (_: unknown, data: { deviceId: string; messageId: string }): void =>
+      callback(data)
+    ipcRenderer.on('message-read', listener)
+    return (): void => {
+      ipcRenderer.removeListener('message-read', listener)
+    }
+  },
+  onMessageEdited: (callback: (edit: MessageEdit) => void): (() => void) => {
+    const listener = (_: unknown, edit: unknown): void => callback(edit as MessageEdit)
+    ipcRenderer.on('message-edited', listener)
+    return (): void => {
+      ipcRenderer.removeListener('message-edited', listener)
+    }
+  },
+  onMessageDeleted: (callback: (deletion: MessageDeletion) => void): (() => void) => {
+    const listener = (_: unknown, deletion: unknown): void => callback(deletion as MessageDeletion)
+    ipcRenderer.on('message-deleted', listener)
+    return (): void => {
+      ipcRenderer.removeListener('message-deleted', listener)
+    }
+  },
+  onGroupUpdated: (callback: (group: Group) => void): (() => void) => {
+    const listener = (_: unknown, group: unknown): void => callback(group as Group)
+    ipcRenderer.on('group-updated', listener)
+    return (): void => {
+      ipcRenderer.removeListener('group-updated', listener)
+    }
+  },
+  onGroupMessageReceived: (callback: (message: NetworkMessage) => void): (() => void) => {
+    const listener = (_: unknown, message: unknown): void => callback(message as NetworkMessage)
+    ipcRenderer.on('group-message-received', listener)
+    return (): void => {
+      ipcRenderer.removeListener('group-message-received', listener)
+    }
+  },
+  onGroupMessageDelivered: (
+    callback: (data: { groupId: string; messageId: string; state: GroupDeliveryState }) => void
+  ): (() => void) => {
+    const listener = (
+      _: unknown,
+      data: { groupId: string; messageId: string; state: GroupDeliveryState }
+    ): void => callback(data)
+    ipcRenderer.on('group-message-delivered', listener)
+    return (): void => {
+      ipcRenderer.removeListener('group-message-delivered', listener)
+    }
+  },
+  onLinkPreview: (
+    callback: (data: { conversationId: string; messageId: string; preview: LinkPreview }) => void
+  ): (() => void) => {
+    const listener = (
+      _: unknown,
+      data: { conversationId: string; messageId: string; preview: LinkPreview }
+    ): void => callback(data)
+    ipcRenderer.on('link-preview', listener)
+    return (): void => {
+      ipcRenderer.removeListener('link-preview', listener)
+    }
+  },
+  onScheduledMessageSent: (
+    callback: (data: { scheduleId: string; deviceId: string; message: NetworkMessage }) => void
+  ): (() => void) => {
+    const listener = (
+      _: unknown,
+      data: { scheduleId: string; deviceId: string; message: NetworkMessage }
+    ): void => callback(data)
+    ipcRenderer.on('scheduled-message-sent', listener)
+    return (): void => {
+      ipcRenderer.removeListener('scheduled-message-sent', listener)
+    }
+  },
   onRemoteMessageDeleted: (
     callback: (data: { deviceId: string; messageId: string }) => void
   ): (() => void) => {
@@ -128,6 +575,13 @@ const api = {
       ipcRenderer.removeListener('remote-message-deleted', listener)
     }
   },
+  onPeerError: (callback: (error: PeerError) => void): (() => void) => {
+    const listener = (_: unknown, error: PeerError): void => callback(error)
+    ipcRenderer.on('peer-error', listener)
+    return (): void => {
+      ipcRenderer.removeListener('peer-error', listener)
+    }
+  },
 
   // Auto-Update Event Listeners
   onUpdateChecking: (callback: () => void): (() => void) => {
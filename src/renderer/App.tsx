@@ -1,11 +1,11 @@
 import React, { useEffect, useRef } from 'react'
 import { HashRouter, Routes, Route, useNavigate } from 'react-router-dom'
-import { useStore } from './store/useStore'
+import { setGuestStorage, useStore } from './store/useStore'
 import { Onboarding } from './pages/Onboarding'
 import { Toaster } from './components/ui/sonner'
 import { toast } from 'sonner'
 import { ErrorBoundary } from './components/ErrorBoundary'
-import { NetworkMessage, FileMetadata } from '../shared/messageTypes'
+import { NetworkMessage, FileMetadata, ErrorCode } from '../shared/messageTypes'
 import { DashboardLayout } from './layouts/DashboardLayout'
 import { DevicePage } from './pages/DevicePage'
 import { SettingsPage } from './pages/SettingsPage'
@@ -13,6 +13,16 @@ import { WelcomePage } from './pages/WelcomePage'
 import { ConnectionToast } from './components/ui/ConnectionToast'
 import { MessageToast } from './components/ui/MessageToast'
 
+// What a peer's ERROR frame tells the user; transfer failures also show on the transfer
+const PEER_ERROR_TEXT: Record<ErrorCode, string> = {
+  UNAUTHORIZED: "isn't accepting this from you",
+  UNSUPPORTED_VERSION: 'is running a version that does not support this',
+  DISK_FULL: 'ran out of disk space',
+  CHECKSUM_MISMATCH: 'received a corrupt copy of a file',
+  RATE_LIMITED: 'is receiving too much at once, try again shortly',
+  INTERNAL: 'ran into an error'
+}
+
 const AppContent: React.FC = () => {
   const {
     onboardingComplete,
@@ -32,6 +42,19 @@ const AppContent: React.FC = () => {
   useEffect(() => {
     const init = async (): Promise<void> => {
       console.log('[App] Initializing...')
+      // Messages and unread counts are persisted per profile
+      const profile = await window.api.getActiveProfile()
+      if (profile !== 'default') {
+        useStore.persist.setOptions({ name: `hyper-connect-storage-${profile}` })
+        useStore.setState({ messages: {}, unreadCounts: {} })
+        await useStore.persist.rehydrate()
+      }
+      // A guest starts from nothing and leaves nothing behind
+      if (await window.api.isGuestMode()) {
+        setGuestStorage(true)
+        useStore.setState({ localDevice: null, messages: {}, unreadCounts: {} })
+      }
+
       const info = await window.api.getDeviceInfo()
       setLocalDevice(info)
 
@@ -66,6 +89,30 @@ const AppContent: React.FC = () => {
       }
     })
 
+    const unsubUpdated = window.api.onDeviceUpdated((device) => {
+      addDiscoveredDevice(device)
+    })
+
+    const unsubConnectionRequest = window.api.onConnectionRequest((request) => {
+      toast(`${request.displayName || 'An unknown device'} wants to connect`, {
+        id: `connection-request-${request.deviceId}`,
+        description: request.address,
+        duration: Infinity,
+        action: {
+          label: 'Accept',
+          onClick: () => window.api.acceptConnection(request.deviceId)
+        },
+        cancel: {
+          label: 'Deny',
+          onClick: () => window.api.denyConnection(request.deviceId)
+        }
+      })
+    })
+
+    const unsubConnectionCancelled = window.api.onConnectionRequestCancelled((deviceId) => {
+      toast.dismiss(`connection-request-${deviceId}`)
+    })
+
     const unsubMessage = window.api.onMessageReceived((message: NetworkMessage) => {
       const state = useStore.getState()
       if (message.type === 'MESSAGE' || message.type === 'FILE_META') {
@@ -107,7 +154,22 @@ const AppContent: React.FC = () => {
       // Logic handled in onMessageReceived
     })
 
-    const unsubProgress = window.api.onFileTransferProgress((progress) => updateTransfer(progress))
+    const unsubProgress = window.api.onFileTransferProgress((progress) => {
+      updateTransfer(progress)
+      // A finished upload means the file message has reached the peer
+      if (
+        progress.direction === 'outgoing' &&
+        progress.status === 'completed' &&
+        progress.messageId
+      ) {
+        updateMessageStatus(progress.deviceId, progress.messageId, 'delivered')
+      }
+    })
+
+    const unsubVerification = window.api.onFileVerificationProgress((data) => {
+      const transfer = useStore.getState().transfers[data.fileId]
+      if (transfer) updateTransfer({ ...transfer, verifyProgress: data.progress })
+    })
 
     const unsubNavigate = window.api.onNavigateToDevice((deviceId) => {
       const state = useStore.getState()
@@ -120,19 +182,59 @@ const AppContent: React.FC = () => {
       updateMessageStatus(data.deviceId, data.messageId, data.status)
     })
 
+    // Group conversations are stored under their groupId, alongside 1:1 threads
+    const unsubGroupMessage = window.api.onGroupMessageReceived((message) => {
+      if (message.groupId) addMessage(message.groupId, message)
+    })
+
+    const unsubGroupDelivered = window.api.onGroupMessageDelivered((data) => {
+      if (Object.values(data.state).every((s) => s === 'delivered')) {
+        updateMessageStatus(data.groupId, data.messageId, 'delivered')
+      }
+    })
+
+    const unsubLinkPreview = window.api.onLinkPreview(({ conversationId, messageId, preview }) => {
+      useStore.getState().setLinkPreview(conversationId, messageId, preview)
+    })
+
+    const unsubScheduled = window.api.onScheduledMessageSent(({ deviceId, message }) => {
+      addMessage(deviceId, message)
+    })
+
+    const unsubEdit = window.api.onMessageEdited((edit) => {
+      useStore.getState().editMessage(edit)
+    })
+
     const unsubDelete = window.api.onRemoteMessageDeleted((data) => {
-      useStore.getState().deleteMessage(data.deviceId, data.messageId)
+      // Keep a tombstone so the conversation shows that something was retracted
+      useStore.getState().retractMessage(data.deviceId, data.messageId)
+    })
+
+    const unsubPeerError = window.api.onPeerError((error) => {
+      const { discoveredDevices } = useStore.getState()
+      const device = discoveredDevices.find((d) => d.deviceId === error.deviceId)
+      toast.error(`${device?.displayName || 'A device'} ${PEER_ERROR_TEXT[error.code]}`)
     })
 
     return () => {
       unsubDiscovered()
       unsubLost()
+      unsubUpdated()
+      unsubConnectionRequest()
+      unsubConnectionCancelled()
       unsubMessage()
       unsubFile()
       unsubProgress()
+      unsubVerification()
       unsubNavigate()
       unsubStatus()
+      unsubGroupMessage()
+      unsubGroupDelivered()
+      unsubEdit()
+      unsubScheduled()
+      unsubLinkPreview()
       unsubDelete()
+      unsubPeerError()
     }
   }, [
     setLocalDevice,
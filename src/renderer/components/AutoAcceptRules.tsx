@@ -0,0 +1,135 @@
+import React, { useEffect, useState } from 'react'
+import { Plus, Trash2 } from 'lucide-react'
+import { AutoAcceptRule } from '../../shared/messageTypes'
+import { formatFileSize } from '../lib/utils'
+import { Button } from './ui/button'
+import { Input } from './ui/input'
+import { Label } from './ui/label'
+import { Switch } from './ui/switch'
+
+const selectClassName =
+  'h-10 rounded-md border border-input bg-background px-3 text-sm focus-visible:outline-none focus-visible:ring-2 focus-visible:ring-ring'
+
+const describeRule = (rule: AutoAcceptRule): string => {
+  const parts = [
+    rule.peer === 'trusted' ? 'trusted devices' : rule.peer === 'untrusted' ? 'unknown devices' : ''
+  ]
+  if (rule.extensions?.length) parts.push(rule.extensions.join(', '))
+  if (rule.maxSize !== undefined) parts.push(`up to ${formatFileSize(rule.maxSize)}`)
+  const scope = parts.filter(Boolean).join(' · ')
+  return scope || 'all files'
+}
+
+export const AutoAcceptRules: React.FC = () => {
+  const [rules, setRules] = useState<AutoAcceptRule[]>([])
+  const [action, setAction] = useState<AutoAcceptRule['action']>('accept')
+  const [peer, setPeer] = useState<NonNullable<AutoAcceptRule['peer']>>('trusted')
+  const [extensions, setExtensions] = useState('')
+  const [maxSizeMb, setMaxSizeMb] = useState('')
+
+  useEffect(() => {
+    window.api.getAutoAcceptRules().then(setRules)
+  }, [])
+
+  const saveRules = async (next: AutoAcceptRule[]): Promise<void> => {
+    const previous = rules
+    setRules(next)
+    try {
+      setRules(await window.api.setAutoAcceptRules(next))
+    } catch (error) {
+      console.error('Failed to save auto-accept rules:', error)
+      setRules(previous)
+    }
+  }
+
+  const handleAdd = (): void => {
+    const maxSize = parseFloat(maxSizeMb)
+    const rule: AutoAcceptRule = {
+      id: '',
+      action,
+      peer,
+      extensions: extensions.trim() ? extensions.split(/[\s,]+/) : undefined,
+      maxSize: Number.isFinite(maxSize) && maxSize > 0 ? maxSize * 1024 * 1024 : undefined
+    }
+    saveRules([...rules, rule])
+    setExtensions('')
+    setMaxSizeMb('')
+  }
+
+  return (
+    <div className="space-y-3">
+      <div className="space-y-0.5">
+        <Label className="text-base">Auto-accept Rules</Label>
+        <p className="text-sm text-muted-foreground">
+          Checked in order before asking. The first matching rule wins.
+        </p>
+      </div>
+
+      {rules.map((rule) => (
+        <div key={rule.id} className="flex items-center gap-3 rounded-md border px-3 py-2">
+          <span
+            className={
+              rule.action === 'accept'
+                ? 'text-xs font-bold uppercase text-green-500'
+                : 'text-xs font-bold uppercase text-destructive'
+            }
+          >
+            {rule.action}
+          </span>
+          <span className="flex-1 text-sm truncate">{describeRule(rule)}</span>
+          <Switch
+            checked={rule.enabled !== false}
+            onCheckedChange={(checked) =>
+              saveRules(rules.map((r) => (r.id === rule.id ? { ...r, enabled: checked } : r)))
+            }
+          />
+          <Button
+            variant="ghost"
+            size="icon"
+            onClick={() => saveRules(rules.filter((r) => r.id !== rule.id))}
+          >
+            <Trash2 className="w-4 h-4" />
+          </Button>
+        </div>
+      ))}
+
+      <div className="flex flex-wrap gap-2">
+        <select
+          className={selectClassName}
+          value={action}
+          onChange={(e) => setAction(e.target.value as AutoAcceptRule['action'])}
+        >
+          <option value="accept">Accept</option>
+          <option value="reject">Reject</option>
+        </select>
+        <select
+          className={selectClassName}
+          value={peer}
+          onChange={(e) => setPeer(e.target.value as NonNullable<AutoAcceptRule['peer']>)}
+        >
+          <option value="trusted">Trusted devices</option>
+          <option value="untrusted">Unknown devices</option>
+          <option value="any">Any device</option>
+        </select>
+        <Input
+          className="w-40"
+          placeholder="pdf, png (optional)"
+          value={extensions}
+          onChange={(e) => setExtensions(e.target.value)}
+        />
+        <Input
+          className="w-28"
+          type="number"
+          min="0"
+          placeholder="Max MB"
+          value={maxSizeMb}
+          onChange={(e) => setMaxSizeMb(e.target.value)}
+        />
+        <Button variant="secondary" onClick={handleAdd}>
+          <Plus className="w-4 h-4" />
+          Add Rule
+        </Button>
+      </div>
+    </div>
+  )
+}
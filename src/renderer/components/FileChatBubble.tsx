@@ -1,23 +1,58 @@
-import React from 'react'
-import { CheckCircle2, FileText } from 'lucide-react'
-import { FileMetadata, NetworkMessage } from '../../shared/messageTypes'
+import React, { useState } from 'react'
+import {
+  ArrowUpToLine,
+  CheckCircle2,
+  FileText,
+  Folder,
+  KeyRound,
+  Lock,
+  Pause,
+  Play,
+  X
+} from 'lucide-react'
+import { FileMetadata, FileRejection, NetworkMessage } from '../../shared/messageTypes'
 import { useStore } from '../store/useStore'
 import { cn, formatFileSize, getFileType } from '../lib/utils'
 import { Button } from './ui/button'
+import { Input } from './ui/input'
 import { Dialog, DialogContent, DialogTrigger } from './ui/dialog'
 
 const isImage = (name: string): boolean => /\.(jpg|jpeg|png|gif|webp|svg)$/i.test(name)
 const isVideo = (name: string): boolean => /\.(mp4|webm|mov|ogg)$/i.test(name)
 
-const AcceptRejectButtons: React.FC<{ fileId: string }> = ({ fileId }) => {
+const describeRejection = (rejection: FileRejection, isLocal: boolean): string | undefined => {
+  switch (rejection.reason) {
+    case 'timeout':
+      return isLocal ? 'Not accepted in time' : 'Request expired'
+    case 'too-large':
+      return `Over the ${formatFileSize(rejection.maxSize)} size limit`
+    case 'no-space':
+      return rejection.available !== undefined
+        ? `Not enough disk space (${formatFileSize(rejection.available)} free)`
+        : 'Not enough disk space'
+    default:
+      return undefined
+  }
+}
+
+// A passphrase-protected file asks for the sender's passphrase before it can be accepted
+const AcceptRejectButtons: React.FC<{ fileId: string; protectedFile?: boolean }> = ({
+  fileId,
+  protectedFile
+}) => {
   const { updateTransfer } = useStore()
   const transfer = useStore((state) => state.transfers[fileId])
   const status = transfer?.status || 'pending'
+  const [passphrase, setPassphrase] = useState('')
+  const [wrongPassphrase, setWrongPassphrase] = useState(false)
 
   if (status !== 'pending') return null
 
   const handleAccept = async (): Promise<void> => {
-    await window.api.acceptFile(fileId)
+    if (!(await window.api.acceptFile(fileId, protectedFile ? passphrase : undefined))) {
+      setWrongPassphrase(protectedFile === true)
+      return
+    }
     // eslint-disable-next-line @typescript-eslint/no-explicit-any
     updateTransfer({ fileId, status: 'active' } as any)
   }
@@ -29,22 +64,45 @@ const AcceptRejectButtons: React.FC<{ fileId: string }> = ({ fileId }) => {
   }
 
   return (
-    <div className="flex gap-2 pt-2 animate-in slide-in-from-top-2 duration-300">
-      <Button
-        size="sm"
-        className="flex-1 bg-green-500 hover:bg-green-600 text-white font-bold uppercase tracking-wider text-[10px] h-8 rounded-lg shadow-sm"
-        onClick={handleAccept}
-      >
-        Accept
-      </Button>
-      <Button
-        size="sm"
-        variant="secondary"
-        className="flex-1 font-bold uppercase tracking-wider text-[10px] h-8 rounded-lg"
-        onClick={handleReject}
-      >
-        Reject
-      </Button>
+    <div className="space-y-2 pt-2 animate-in slide-in-from-top-2 duration-300">
+      {protectedFile && (
+        <div className="space-y-1">
+          <Input
+            type="password"
+            placeholder="Passphrase from the sender"
+            className="h-8 text-xs"
+            value={passphrase}
+            onChange={(e) => {
+              setPassphrase(e.target.value)
+              setWrongPassphrase(false)
+            }}
+            onKeyDown={(e) => {
+              if (e.key === 'Enter' && passphrase) handleAccept()
+            }}
+          />
+          {wrongPassphrase && (
+            <p className="text-[10px] font-bold text-destructive">Wrong passphrase</p>
+          )}
+        </div>
+      )}
+      <div className="flex gap-2">
+        <Button
+          size="sm"
+          className="flex-1 bg-green-500 hover:bg-green-600 text-white font-bold uppercase tracking-wider text-[10px] h-8 rounded-lg shadow-sm"
+          disabled={protectedFile && !passphrase}
+          onClick={handleAccept}
+        >
+          Accept
+        </Button>
+        <Button
+          size="sm"
+          variant="secondary"
+          className="flex-1 font-bold uppercase tracking-wider text-[10px] h-8 rounded-lg"
+          onClick={handleReject}
+        >
+          Reject
+        </Button>
+      </div>
     </div>
   )
 }
@@ -59,9 +117,23 @@ export const FileChatBubble: React.FC<{ msg: NetworkMessage; isLocal: boolean }>
   const status = transfer?.status || (isLocal ? 'active' : 'pending')
   const isCompleted = status === 'completed'
   const isActive = status === 'active'
+  const isPaused = status === 'paused'
+  const isQueued = status === 'queued'
+  const isCancelled = status === 'cancelled'
+
+  const handleTogglePause = (): void => {
+    if (isPaused) {
+      window.api.resumeFile(metadata.fileId)
+    } else {
+      window.api.pauseFile(metadata.fileId)
+    }
+  }
 
   const filePath = transfer?.path || (isLocal ? metadata.path : undefined)
-  const canPreview = filePath && (isImage(metadata.name) || isVideo(metadata.name))
+  // Shown in place of the file icon until the file itself can be previewed
+  const thumbnailUrl = transfer?.thumbnail ? `file://${transfer.thumbnail}` : metadata.preview
+  const canPreview =
+    !metadata.isFolder && filePath && (isImage(metadata.name) || isVideo(metadata.name))
   const isReady = isLocal || isCompleted
 
   if (canPreview && isReady) {
@@ -100,7 +172,12 @@ export const FileChatBubble: React.FC<{ msg: NetworkMessage; isLocal: boolean }>
             isLocal ? 'border-primary-foreground/20' : 'border-border'
           )}
         >
-          <video src={fileUrl} controls className="max-w-full max-h-[300px]" />
+          <video
+            src={fileUrl}
+            poster={transfer?.thumbnail ? `file://${transfer.thumbnail}` : undefined}
+            controls
+            className="max-w-full max-h-[300px]"
+          />
         </div>
       )
     }
@@ -125,10 +202,16 @@ export const FileChatBubble: React.FC<{ msg: NetworkMessage; isLocal: boolean }>
         <div
           className={cn('p-2 rounded-lg', isLocal ? 'bg-primary-foreground/20' : 'bg-primary/10')}
         >
-          {isCompleted ? (
+          {thumbnailUrl ? (
+            <img src={thumbnailUrl} alt="" className="w-10 h-10 rounded object-cover" />
+          ) : isCompleted ? (
             <CheckCircle2
               className={cn('w-5 h-5', isLocal ? 'text-primary-foreground' : 'text-green-500')}
             />
+          ) : metadata.isFolder ? (
+            <Folder
+              className={cn('w-5 h-5', isLocal ? 'text-primary-foreground' : 'text-primary')}
+            />
           ) : (
             <FileText
               className={cn('w-5 h-5', isLocal ? 'text-primary-foreground' : 'text-primary')}
@@ -142,6 +225,15 @@ export const FileChatBubble: React.FC<{ msg: NetworkMessage; isLocal: boolean }>
               isLocal ? 'text-primary-foreground' : 'text-foreground'
             )}
           >
+            {metadata.sensitive && (
+              <Lock className="inline w-3 h-3 mr-1 -mt-0.5 opacity-70" aria-label="Sensitive" />
+            )}
+            {metadata.passphrase && (
+              <KeyRound
+                className="inline w-3 h-3 mr-1 -mt-0.5 opacity-70"
+                aria-label="Protected with a passphrase"
+              />
+            )}
             {metadata.name || 'File'}
           </p>
           <div className="flex items-center gap-2 mt-0.5">
@@ -151,7 +243,9 @@ export const FileChatBubble: React.FC<{ msg: NetworkMessage; isLocal: boolean }>
                 isLocal ? 'text-primary-foreground' : 'text-muted-foreground'
               )}
             >
-              {getFileType(metadata.name)}
+              {metadata.isFolder
+                ? `Folder · ${metadata.entries?.length ?? 0} files`
+                : getFileType(metadata.name)}
             </p>
             <span className="w-1 h-1 rounded-full bg-current opacity-30" />
             <p
@@ -171,7 +265,7 @@ export const FileChatBubble: React.FC<{ msg: NetworkMessage; isLocal: boolean }>
             >
               {isLocal ? 'Sent' : 'Received'}
             </p>
-            {isCompleted && (
+            {(isCompleted || isCancelled) && (
               <>
                 <span className="w-1 h-1 rounded-full bg-current opacity-30" />
                 <p
@@ -180,7 +274,7 @@ export const FileChatBubble: React.FC<{ msg: NetworkMessage; isLocal: boolean }>
                     isLocal ? 'text-primary-foreground' : 'text-primary'
                   )}
                 >
-                  Done
+                  {isCompleted ? 'Done' : 'Cancelled'}
                 </p>
               </>
             )}
@@ -188,7 +282,7 @@ export const FileChatBubble: React.FC<{ msg: NetworkMessage; isLocal: boolean }>
         </div>
       </div>
 
-      {isActive && transfer && (
+      {(isActive || isPaused) && transfer && (
         <div className="px-1 space-y-1.5 animate-in fade-in duration-300">
           <div
             className={cn(
@@ -196,8 +290,32 @@ export const FileChatBubble: React.FC<{ msg: NetworkMessage; isLocal: boolean }>
               isLocal ? 'text-primary-foreground/70' : 'text-muted-foreground'
             )}
           >
-            <span>{(transfer.progress * 100).toFixed(0)}%</span>
-            <span>{formatFileSize(transfer.speed)}/s</span>
+            <span>
+              {(transfer.progress * 100).toFixed(0)}%
+              {transfer.fileCount ? ` · ${transfer.filesCompleted ?? 0}/${transfer.fileCount}` : ''}
+              {transfer.compressionRatio && transfer.compressionRatio > 1.05
+                ? ` · zstd ${transfer.compressionRatio.toFixed(1)}×`
+                : ''}
+            </span>
+            <span className="flex items-center gap-2">
+              {isPaused ? 'Paused' : `${formatFileSize(transfer.speed)}/s`}
+              <button
+                type="button"
+                onClick={handleTogglePause}
+                className="opacity-70 hover:opacity-100 transition-opacity"
+                title={isPaused ? 'Resume' : 'Pause'}
+              >
+                {isPaused ? <Play className="w-3 h-3" /> : <Pause className="w-3 h-3" />}
+              </button>
+              <button
+                type="button"
+                onClick={() => window.api.cancelFile(metadata.fileId)}
+                className="opacity-70 hover:opacity-100 transition-opacity"
+                title="Cancel"
+              >
+                <X className="w-3 h-3" />
+              </button>
+            </span>
           </div>
           <div
             className={cn(
@@ -216,7 +334,66 @@ export const FileChatBubble: React.FC<{ msg: NetworkMessage; isLocal: boolean }>
         </div>
       )}
 
-      {!isLocal && status === 'pending' && <AcceptRejectButtons fileId={metadata.fileId} />}
+      {status === 'retrying' && transfer && (
+        <p
+          className={cn(
+            'px-1 text-[9px] font-bold uppercase tracking-tighter',
+            isLocal ? 'text-primary-foreground/70' : 'text-muted-foreground'
+          )}
+        >
+          {transfer.error || 'Retrying'}
+        </p>
+      )}
+
+      {status === 'verifying' && transfer && (
+        <div
+          className={cn(
+            'px-1 flex justify-between text-[9px] font-bold uppercase tracking-tighter',
+            isLocal ? 'text-primary-foreground/70' : 'text-muted-foreground'
+          )}
+        >
+          <span>Verifying</span>
+          <span>{((transfer.verifyProgress ?? 0) * 100).toFixed(0)}%</span>
+        </div>
+      )}
+
+      {isQueued && transfer && (
+        <div
+          className={cn(
+            'px-1 flex justify-between text-[9px] font-bold uppercase tracking-tighter',
+            isLocal ? 'text-primary-foreground/70' : 'text-muted-foreground'
+          )}
+        >
+          <span>
+            Queued{transfer.queuePosition ? ` · #${transfer.queuePosition}` : ''}
+            {transfer.priority && transfer.priority !== 'normal' ? ` · ${transfer.priority}` : ''}
+          </span>
+          {transfer.priority !== 'high' && (
+            <button
+              type="button"
+              onClick={() => window.api.setTransferPriority(metadata.fileId, 'high')}
+              className="opacity-70 hover:opacity-100 transition-opacity"
+              title="Send next"
+            >
+              <ArrowUpToLine className="w-3 h-3" />
+            </button>
+          )}
+        </div>
+      )}
+
+      {status === 'failed' && transfer?.error && (
+        <p className="px-1 text-[10px] font-medium text-destructive">{transfer.error}</p>
+      )}
+
+      {status === 'rejected' && transfer?.rejection && (
+        <p className="px-1 text-[10px] font-medium text-destructive">
+          {describeRejection(transfer.rejection, isLocal)}
+        </p>
+      )}
+
+      {!isLocal && status === 'pending' && (
+        <AcceptRejectButtons fileId={metadata.fileId} protectedFile={!!metadata.passphrase} />
+      )}
     </div>
   )
 }
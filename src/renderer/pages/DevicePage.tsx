@@ -3,6 +3,9 @@ import { useParams, Navigate } from 'react-router-dom'
 import { useShallow } from 'zustand/react/shallow'
 import {
   FileUp,
+  FolderUp,
+  KeyRound,
+  Lock,
   Paperclip,
   Send,
   User,
@@ -39,6 +42,7 @@ import { ThemeToggle } from '../components/ui/theme-toggle'
 import { Card, CardContent } from '../components/ui/card'
 import { FileChatBubble } from '../components/FileChatBubble'
 import { Textarea } from '../components/ui/textarea'
+import { Input } from '../components/ui/input'
 import { Tabs, TabsContent, TabsList, TabsTrigger } from '../components/ui/tabs'
 import { Avatar, AvatarFallback, AvatarImage } from '../components/ui/avatar'
 const EMPTY_MESSAGES: NetworkMessage[] = []
@@ -82,6 +86,29 @@ export const DevicePage: React.FC = () => {
     deviceId ? state.messages[deviceId] || EMPTY_MESSAGES : EMPTY_MESSAGES
   )
   const [input, setInput] = useState('')
+  const [passphrase, setPassphrase] = useState('')
+  const [passphraseOpen, setPassphraseOpen] = useState(false)
+  const draftLoadedFor = useRef<string | null>(null)
+  // Restore the saved draft when switching conversations
+  useEffect(() => {
+    if (!deviceId) return
+    let cancelled = false
+    draftLoadedFor.current = null
+    window.api.getDraft(deviceId).then((draft) => {
+      if (cancelled) return
+      setInput(draft)
+      draftLoadedFor.current = deviceId
+    })
+    return () => {
+      cancelled = true
+    }
+  }, [deviceId])
+  // Persist the draft shortly after typing stops
+  useEffect(() => {
+    if (!deviceId || draftLoadedFor.current !== deviceId) return
+    const timer = setTimeout(() => window.api.saveDraft(deviceId, input), 500)
+    return () => clearTimeout(timer)
+  }, [deviceId, input])
   const scrollRef = useRef<HTMLDivElement>(null)
   const scrollToBottom = useCallback((): void => {
     if (scrollRef.current) {
@@ -118,8 +145,12 @@ export const DevicePage: React.FC = () => {
   const handleForward = async (targetDeviceId: string): Promise<void> => {
     if (!forwardingMessage) return
     try {
-      const payload = forwardingMessage.payload as string
-      const sentMsg = await window.api.sendMessage(targetDeviceId, payload)
+      const fileId =
+        forwardingMessage.type === 'FILE_META'
+          ? (forwardingMessage.payload as FileMetadata).fileId
+          : undefined
+      const filePath = fileId ? useStore.getState().transfers[fileId]?.path : undefined
+      const sentMsg = await window.api.forwardMessage(forwardingMessage, targetDeviceId, filePath)
       addMessage(targetDeviceId, sentMsg)
       setForwardingMessage(null)
       setForwardingMessage(null)
@@ -129,11 +160,39 @@ export const DevicePage: React.FC = () => {
       toast.custom((id) => <StatusToast message="Failed to forward message" type="error" id={id} />)
     }
   }
-  const handleFileSelect = async (): Promise<void> => {
+  const handleFileSelect = async (sensitive = false, passphrase?: string): Promise<void> => {
     if (!device) return
     const path = await window.api.selectFile()
     if (path) {
-      const sentMsg = await window.api.sendFile(device.deviceId, path, replyingTo?.id)
+      const sentMsg = await window.api.sendFile(
+        device.deviceId,
+        path,
+        replyingTo?.id,
+        sensitive,
+        passphrase
+      )
+      addMessage(device.deviceId, sentMsg)
+      setReplyingTo(null)
+    }
+  }
+  // The receiver has to type the same passphrase to accept; it's shared some other way
+  const handleProtectedFileSelect = async (): Promise<void> => {
+    setPassphraseOpen(false)
+    try {
+      await handleFileSelect(false, passphrase)
+      setPassphrase('')
+    } catch (e) {
+      console.error('[DevicePage] Protected file error:', e)
+      toast.custom((id) => (
+        <StatusToast message="Could not send a passphrase-protected file" type="error" id={id} />
+      ))
+    }
+  }
+  const handleFolderSelect = async (): Promise<void> => {
+    if (!device) return
+    const path = await window.api.selectFolder()
+    if (path) {
+      const sentMsg = await window.api.sendFolder(device.deviceId, path, replyingTo?.id)
       addMessage(device.deviceId, sentMsg)
       setReplyingTo(null)
     }
@@ -155,12 +214,10 @@ export const DevicePage: React.FC = () => {
       // Remote delete logic
       const isLocal = msg.deviceId === localDevice?.deviceId
       const isWithinTime = Date.now() - (msg.timestamp || 0) < REMOTE_DELETE_LIMIT
-      if (isLocal && isWithinTime) {
-        try {
-          await window.api.deleteRemoteMessage(deviceId, msg.id)
-        } catch (e) {
-          console.error('[DevicePage] Remote delete failed:', e)
-        }
+      try {
+        await window.api.deleteMessage(deviceId, msg.id, isLocal && isWithinTime)
+      } catch (e) {
+        console.error('[DevicePage] Remote delete failed:', e)
       }
     },
     [deviceId, deleteMessage, localDevice?.deviceId]
@@ -330,7 +387,11 @@ export const DevicePage: React.FC = () => {
                                 })()}
                               </div>
                             )}
-                            {!isFile ? (
+                            {msg.deleted ? (
+                              <p className="text-sm italic opacity-60">
+                                This message was deleted
+                              </p>
+                            ) : !isFile ? (
                               <div className="text-sm leading-relaxed whitespace-pre-wrap wrap-break-word select-text prose prose-invert max-w-none [&>p]:mb-0 [&>a]:text-blue-400 [&>a]:underline hover:[&>a]:text-blue-300 [&>ul]:list-disc [&>ul]:ml-4 [&>ol]:list-decimal [&>ol]:ml-4">
                                 <ReactMarkdown
                                   remarkPlugins={[remarkGfm]}
@@ -353,6 +414,37 @@ export const DevicePage: React.FC = () => {
                                     ? msg.payload
                                     : JSON.stringify(msg.payload)}
                                 </ReactMarkdown>
+                                {msg.linkPreview && (
+                                  <a
+                                    href={msg.linkPreview.url}
+                                    target="_blank"
+                                    rel="noopener noreferrer"
+                                    className="not-prose mt-2 flex gap-3 rounded-lg border border-white/10 bg-black/10 p-2 no-underline hover:bg-black/20 transition-colors"
+                                  >
+                                    {msg.linkPreview.image && (
+                                      <img
+                                        src={msg.linkPreview.image}
+                                        alt=""
+                                        className="w-16 h-16 rounded object-cover shrink-0"
+                                      />
+                                    )}
+                                    <div className="min-w-0">
+                                      <p className="text-[10px] uppercase opacity-60 truncate">
+                                        {msg.linkPreview.siteName}
+                                      </p>
+                                      {msg.linkPreview.title && (
+                                        <p className="text-sm font-semibold truncate">
+                                          {msg.linkPreview.title}
+                                        </p>
+                                      )}
+                                      {msg.linkPreview.description && (
+                                        <p className="text-xs opacity-70 line-clamp-2">
+                                          {msg.linkPreview.description}
+                                        </p>
+                                      )}
+                                    </div>
+                                  </a>
+                                )}
                               </div>
                             ) : (
                               <FileChatBubble msg={msg} isLocal={isLocal} />
@@ -448,11 +540,61 @@ export const DevicePage: React.FC = () => {
           <div className="flex gap-2 max-w-4xl mx-auto items-center">
             <button
               type="button"
-              onClick={handleFileSelect}
+              onClick={() => handleFileSelect()}
               className="p-2.5 hover:bg-secondary rounded-xl text-muted-foreground hover:text-primary transition-all hover:scale-105 active:scale-95"
             >
               <Paperclip className="w-5 h-5" />
             </button>
+            <button
+              type="button"
+              onClick={() => handleFileSelect(true)}
+              title="Send sensitive file (always needs approval, no previews)"
+              className="p-2.5 hover:bg-secondary rounded-xl text-muted-foreground hover:text-primary transition-all hover:scale-105 active:scale-95"
+            >
+              <Lock className="w-5 h-5" />
+            </button>
+            <button
+              type="button"
+              onClick={handleFolderSelect}
+              title="Send folder"
+              className="p-2.5 hover:bg-secondary rounded-xl text-muted-foreground hover:text-primary transition-all hover:scale-105 active:scale-95"
+            >
+              <FolderUp className="w-5 h-5" />
+            </button>
+            <Popover open={passphraseOpen} onOpenChange={setPassphraseOpen}>
+              <PopoverTrigger asChild>
+                <button
+                  type="button"
+                  title="Send file protected with a passphrase"
+                  className="p-2.5 hover:bg-secondary rounded-xl text-muted-foreground hover:text-primary transition-all hover:scale-105 active:scale-95"
+                >
+                  <KeyRound className="w-5 h-5" />
+                </button>
+              </PopoverTrigger>
+              <PopoverContent className="w-72 space-y-2" side="top" align="start">
+                <p className="text-xs text-muted-foreground">
+                  The receiver will need this passphrase to accept the file. Tell them some
+                  other way.
+                </p>
+                <Input
+                  type="password"
+                  placeholder="Passphrase"
+                  value={passphrase}
+                  onChange={(e) => setPassphrase(e.target.value)}
+                  onKeyDown={(e) => {
+                    if (e.key === 'Enter' && passphrase) handleProtectedFileSelect()
+                  }}
+                />
+                <Button
+                  size="sm"
+                  className="w-full"
+                  disabled={!passphrase}
+                  onClick={handleProtectedFileSelect}
+                >
+                  Choose file
+                </Button>
+              </PopoverContent>
+            </Popover>
             <Popover>
               <PopoverTrigger asChild>
                 <button
@@ -599,20 +741,28 @@ const TransferView: React.FC<{ device: Device }> = ({ device }) => {
                   )}
                 >
                   <CardContent className="p-5 flex items-center gap-5">
-                    <div
-                      className={cn(
-                        'p-3.5 rounded-2xl border transition-colors',
-                        remainsCompleted
-                          ? 'bg-green-500/10 border-green-500/20 text-green-600'
-                          : 'bg-muted/10 border-border/50 text-muted-foreground/50'
-                      )}
-                    >
-                      {remainsCompleted ? (
-                        <FileUp className="w-6 h-6" />
-                      ) : (
-                        <Paperclip className="w-6 h-6" />
-                      )}
-                    </div>
+                    {transfer.thumbnail ? (
+                      <img
+                        src={`file://${transfer.thumbnail}`}
+                        alt=""
+                        className="w-14 h-14 rounded-2xl border border-border/50 object-cover"
+                      />
+                    ) : (
+                      <div
+                        className={cn(
+                          'p-3.5 rounded-2xl border transition-colors',
+                          remainsCompleted
+                            ? 'bg-green-500/10 border-green-500/20 text-green-600'
+                            : 'bg-muted/10 border-border/50 text-muted-foreground/50'
+                        )}
+                      >
+                        {remainsCompleted ? (
+                          <FileUp className="w-6 h-6" />
+                        ) : (
+                          <Paperclip className="w-6 h-6" />
+                        )}
+                      </div>
+                    )}
                     <div className="flex-1 min-w-0">
                       <p className="text-sm text-foreground truncate max-w-60">
                         {transfer.name || 'Unknown File'}
@@ -11,7 +11,8 @@ import {
   Camera,
   Network,
   Server,
-  Wifi
+  Wifi,
+  FileX
 } from 'lucide-react'
 import { useStore } from '@/renderer/store/useStore'
 import {
@@ -38,18 +39,92 @@ import {
 } from '@/renderer/components/ui/alert-dialog'
 import { Separator } from '@/renderer/components/ui/separator'
 import { Switch } from '@/renderer/components/ui/switch'
+import { AutoAcceptRules } from '@/renderer/components/AutoAcceptRules'
 import { processProfileImage } from '../lib/image'
-import type { NetworkInfo } from '@/preload/index.d'
+import { formatFileSize } from '../lib/utils'
+import type {
+  BluetoothStatus,
+  FirewallSettings,
+  HotspotInfo,
+  ListenerEndpoint,
+  NetworkInfo,
+  OverlayStatus,
+  RelayStatus,
+  SocketTuning
+} from '@/preload/index.d'
+import type { ConflictPolicy, ConnectionInfo, Timeouts } from '@shared/messageTypes'
+const formatUptime = (seconds: number): string => {
+  if (seconds < 60) return `${seconds}s`
+  if (seconds < 3600) return `${Math.floor(seconds / 60)}m`
+  return `${Math.floor(seconds / 3600)}h ${Math.floor((seconds % 3600) / 60)}m`
+}
+// The choices offered for each timeout, in seconds
+const TIMEOUT_OPTIONS: { key: keyof Timeouts; label: string; options: number[] }[] = [
+  { key: 'connect', label: 'Connect', options: [3, 5, 10, 30] },
+  { key: 'handshake', label: 'Handshake', options: [5, 10, 30, 60] },
+  { key: 'control', label: 'Requests', options: [5, 10, 30, 60] },
+  { key: 'bulk', label: 'File stalls', options: [0, 30, 120, 300, 600] }
+]
+// Network quality from the last heartbeat round trip: green, amber or red
+const qualityColor = (connection: ConnectionInfo): string => {
+  if (!connection.healthy) return 'bg-red-500'
+  if (connection.rttMs === undefined || connection.rttMs < 100) return 'bg-green-500'
+  return connection.rttMs < 500 ? 'bg-amber-500' : 'bg-red-500'
+}
 export const SettingsPage: React.FC = () => {
-  const { localDevice, setLocalDevice, clearMessages, clearTransfers } = useStore()
+  const { localDevice, setLocalDevice, clearMessages, clearTransfers, discoveredDevices } =
+    useStore()
   const [name, setName] = useState(localDevice?.displayName || '')
   const [saving, setSaving] = useState(false)
   const [clearingCache, setClearingCache] = useState(false)
+  const [cleaningPartials, setCleaningPartials] = useState(false)
+  const [partialsResult, setPartialsResult] = useState<string | null>(null)
   const [downloadPath, setDownloadPath] = useState<string>('')
   const [loadingPath, setLoadingPath] = useState(true)
   const [autoAccept, setAutoAccept] = useState(false)
+  const [sendReadReceipts, setSendReadReceipts] = useState(true)
+  const [linkPreviews, setLinkPreviews] = useState(false)
+  const [filePreviews, setFilePreviews] = useState(true)
+  const [foldersAsZip, setFoldersAsZip] = useState(false)
+  const [perPeerFolders, setPerPeerFolders] = useState(false)
+  const [conflictPolicy, setConflictPolicy] = useState<ConflictPolicy>('rename')
+  const [parallelStreams, setParallelStreams] = useState(1)
+  const [requestExpiry, setRequestExpiry] = useState(60)
+  const [retryLimit, setRetryLimit] = useState(5)
+  const [heartbeatInterval, setHeartbeatInterval] = useState(15)
+  const [idleTimeout, setIdleTimeout] = useState(45)
+  const [timeouts, setTimeouts] = useState<Timeouts>({
+    connect: 5,
+    handshake: 10,
+    control: 10,
+    bulk: 120
+  })
+  const [strictFrameParsing, setStrictFrameParsing] = useState(true)
+  const [padControlFrames, setPadControlFrames] = useState(false)
+  const [refusePlaintext, setRefusePlaintext] = useState(true)
+  const [runningSelfTest, setRunningSelfTest] = useState(false)
+  const [selfTestResult, setSelfTestResult] = useState<string | null>(null)
+  const [browserPort, setBrowserPort] = useState(0)
+  const [endpoints, setEndpoints] = useState<ListenerEndpoint[]>([])
+  const [endpointUrls, setEndpointUrls] = useState('')
+  const [firewall, setFirewall] = useState<FirewallSettings | null>(null)
+  const [bindAddress, setBindAddress] = useState('')
+  const [socketTuning, setSocketTuning] = useState<SocketTuning | null>(null)
+  const [allowedSubnets, setAllowedSubnets] = useState('')
+  const [preferTls, setPreferTls] = useState(false)
+  const [relayAddress, setRelayAddress] = useState('')
+  const [relayStatus, setRelayStatus] = useState<RelayStatus | null>(null)
+  const [hotspot, setHotspot] = useState<HotspotInfo | null>(null)
+  const [outboundProxy, setOutboundProxy] = useState('')
+  const [overlayStatus, setOverlayStatus] = useState<OverlayStatus | null>(null)
+  const [overlayPeers, setOverlayPeers] = useState('')
+  const [udpTransfers, setUdpTransfers] = useState(false)
+  const [bluetoothStatus, setBluetoothStatus] = useState<BluetoothStatus | null>(null)
+  const [bluetoothPorts, setBluetoothPorts] = useState('')
+  const [maxIncomingSize, setMaxIncomingSize] = useState(0)
   const [profileImage, setProfileImage] = useState<string | null>(localDevice?.profileImage || null)
   const [networkInfo, setNetworkInfo] = useState<NetworkInfo | null>(null)
+  const [connections, setConnections] = useState<ConnectionInfo[]>([])
   const [appVersion, setAppVersion] = useState<string>('')
   const [updateStatus, setUpdateStatus] = useState<
     'idle' | 'checking' | 'available' | 'not-available' | 'downloading' | 'downloaded' | 'error'
@@ -60,13 +135,54 @@ export const SettingsPage: React.FC = () => {
   useEffect(() => {
     const loadSettings = async (): Promise<void> => {
       try {
-        const [path, auto, netInfo] = await Promise.all([
+        const [path, perPeer, policy, auto, readReceipts, previews, netInfo] = await Promise.all([
           window.api.getDownloadPath(),
+          window.api.getPerPeerDownloadFolders(),
+          window.api.getConflictPolicy(),
           window.api.getAutoAccept(),
+          window.api.getSendReadReceipts(),
+          window.api.getLinkPreviews(),
           window.api.getNetworkInfo()
         ])
         setDownloadPath(path)
+        setPerPeerFolders(perPeer)
+        setConflictPolicy(policy)
+        setParallelStreams(await window.api.getParallelStreams())
+        setRequestExpiry(await window.api.getFileRequestExpiry())
+        setRetryLimit(await window.api.getTransferRetryLimit())
+        setHeartbeatInterval(await window.api.getHeartbeatInterval())
+        setIdleTimeout(await window.api.getIdleTimeout())
+        setTimeouts(await window.api.getTimeouts())
+        setStrictFrameParsing(await window.api.getStrictFrameParsing())
+        setPadControlFrames(await window.api.getPadControlFrames())
+        setRefusePlaintext(await window.api.getRefusePlaintext())
+        setBrowserPort(await window.api.getBrowserConnections())
+        setPreferTls(await window.api.getPreferTls())
+        setRelayAddress(await window.api.getRelayAddress())
+        setRelayStatus(await window.api.getRelayStatus())
+        setHotspot(await window.api.getHotspot())
+        setOutboundProxy(await window.api.getOutboundProxy())
+        const listeners = await window.api.getListenerEndpoints()
+        setEndpoints(listeners)
+        setEndpointUrls(listeners.map((endpoint) => endpoint.url).join(', '))
+        const rules = await window.api.getFirewall()
+        setFirewall(rules)
+        setBindAddress(rules.bindAddress)
+        setAllowedSubnets(rules.allowedSubnets.join(', '))
+        setSocketTuning(await window.api.getSocketTuning())
+        const overlay = await window.api.getOverlayStatus()
+        setOverlayStatus(overlay)
+        setOverlayPeers(overlay.peers.join(', '))
+        setUdpTransfers(await window.api.getUdpTransfers())
+        const bluetooth = await window.api.getBluetoothStatus()
+        setBluetoothStatus(bluetooth)
+        setBluetoothPorts(bluetooth.ports.join(', '))
+        setMaxIncomingSize(await window.api.getMaxIncomingSize())
+        setFilePreviews(await window.api.getSendFilePreviews())
+        setFoldersAsZip(await window.api.getSendFoldersAsZip())
         setAutoAccept(auto)
+        setSendReadReceipts(readReceipts)
+        setLinkPreviews(previews)
         setNetworkInfo(netInfo)
       } finally {
         setLoadingPath(false)
@@ -74,6 +190,17 @@ export const SettingsPage: React.FC = () => {
     }
     loadSettings()
   }, [])
+  useEffect(() => {
+    // The server may end up on another port than the one it last reported
+    return window.api.onServerListening((port) =>
+      setNetworkInfo((info) => (info ? { ...info, port } : info))
+    )
+  }, [])
+  useEffect(() => {
+    window.api.getConnections().then(setConnections).catch(console.error)
+    // Refreshed every few seconds from then on
+    return window.api.onConnectionMetrics(setConnections)
+  }, [])
   useEffect(() => {
     // Load app version
     window.api.getAppVersion().then(setAppVersion)
@@ -141,11 +268,289 @@ export const SettingsPage: React.FC = () => {
     try {
       const selectedPath = await window.api.selectDownloadDirectory()
       if (selectedPath) {
-        await window.api.setDownloadPath(selectedPath)
-        setDownloadPath(selectedPath)
+        setDownloadPath(await window.api.setDownloadPath(selectedPath))
       }
     } catch (error) {
       console.error('Failed to select download directory:', error)
+      alert('That folder cannot be used for downloads')
+    }
+  }
+  const handleConflictPolicyChange = async (policy: ConflictPolicy): Promise<void> => {
+    const previous = conflictPolicy
+    setConflictPolicy(policy)
+    try {
+      await window.api.setConflictPolicy(policy)
+    } catch (error) {
+      console.error('Failed to update conflict policy:', error)
+      setConflictPolicy(previous)
+    }
+  }
+  const handleParallelStreamsChange = async (count: number): Promise<void> => {
+    const previous = parallelStreams
+    setParallelStreams(count)
+    try {
+      setParallelStreams(await window.api.setParallelStreams(count))
+    } catch (error) {
+      console.error('Failed to update parallel streams setting:', error)
+      setParallelStreams(previous)
+    }
+  }
+  const handleRequestExpiryChange = async (minutes: number): Promise<void> => {
+    const previous = requestExpiry
+    setRequestExpiry(minutes)
+    try {
+      setRequestExpiry(await window.api.setFileRequestExpiry(minutes))
+    } catch (error) {
+      console.error('Failed to update file request expiry:', error)
+      setRequestExpiry(previous)
+    }
+  }
+  const handleRetryLimitChange = async (limit: number): Promise<void> => {
+    const previous = retryLimit
+    setRetryLimit(limit)
+    try {
+      setRetryLimit(await window.api.setTransferRetryLimit(limit))
+    } catch (error) {
+      console.error('Failed to update transfer retry limit:', error)
+      setRetryLimit(previous)
+    }
+  }
+  const handleHeartbeatIntervalChange = async (seconds: number): Promise<void> => {
+    const previous = heartbeatInterval
+    setHeartbeatInterval(seconds)
+    try {
+      setHeartbeatInterval(await window.api.setHeartbeatInterval(seconds))
+    } catch (error) {
+      console.error('Failed to update heartbeat interval:', error)
+      setHeartbeatInterval(previous)
+    }
+  }
+  const handleTimeoutChange = async (key: keyof Timeouts, seconds: number): Promise<void> => {
+    const previous = timeouts
+    const updated = { ...timeouts, [key]: seconds }
+    setTimeouts(updated)
+    try {
+      setTimeouts(await window.api.setTimeouts(updated))
+    } catch (error) {
+      console.error('Failed to update timeouts:', error)
+      setTimeouts(previous)
+    }
+  }
+  const handleIdleTimeoutChange = async (seconds: number): Promise<void> => {
+    const previous = idleTimeout
+    setIdleTimeout(seconds)
+    try {
+      setIdleTimeout(await window.api.setIdleTimeout(seconds))
+    } catch (error) {
+      console.error('Failed to update idle timeout:', error)
+      setIdleTimeout(previous)
+    }
+  }
+  const handleToggleStrictFrameParsing = async (checked: boolean): Promise<void> => {
+    const previous = strictFrameParsing
+    setStrictFrameParsing(checked)
+    try {
+      await window.api.setStrictFrameParsing(checked)
+    } catch (error) {
+      console.error('Failed to update strict frame parsing:', error)
+      setStrictFrameParsing(previous)
+    }
+  }
+  const handleTogglePadControlFrames = async (checked: boolean): Promise<void> => {
+    const previous = padControlFrames
+    setPadControlFrames(checked)
+    try {
+      await window.api.setPadControlFrames(checked)
+    } catch (error) {
+      console.error('Failed to update control frame padding:', error)
+      setPadControlFrames(previous)
+    }
+  }
+  const handleToggleRefusePlaintext = async (checked: boolean): Promise<void> => {
+    const previous = refusePlaintext
+    setRefusePlaintext(checked)
+    try {
+      await window.api.setRefusePlaintext(checked)
+    } catch (error) {
+      console.error('Failed to update plaintext policy:', error)
+      setRefusePlaintext(previous)
+    }
+  }
+  const handleDisconnectDevice = async (deviceId: string): Promise<void> => {
+    try {
+      await window.api.disconnectDevice(deviceId)
+      setConnections(await window.api.getConnections())
+    } catch (error) {
+      console.error('Failed to disconnect device:', error)
+    }
+  }
+  const handleRunSelfTest = async (): Promise<void> => {
+    setRunningSelfTest(true)
+    try {
+      const { checks, throughput, preferredSuite } = await window.api.runCryptoSelfTest()
+      const failed = checks.filter((check) => !check.passed).map((check) => check.name)
+      const speeds = Object.entries(throughput)
+        .map(([suite, speed]) => `${suite} ${Math.round(speed)} MB/s`)
+        .join(', ')
+      setSelfTestResult(
+        failed.length > 0
+          ? `Failed: ${failed.join(', ')}. ${speeds}`
+          : `All ${checks.length} checks passed. ${speeds}, using ${preferredSuite}`
+      )
+    } catch (error) {
+      console.error('Failed to run the crypto self-test:', error)
+    } finally {
+      setRunningSelfTest(false)
+    }
+  }
+  const handleToggleBrowserConnections = async (checked: boolean): Promise<void> => {
+    try {
+      setBrowserPort(await window.api.setBrowserConnections(checked))
+    } catch (error) {
+      console.error('Failed to update browser connections:', error)
+    }
+  }
+  const handleTogglePreferTls = async (checked: boolean): Promise<void> => {
+    const previous = preferTls
+    setPreferTls(checked)
+    try {
+      await window.api.setPreferTls(checked)
+    } catch (error) {
+      console.error('Failed to update TLS preference:', error)
+      setPreferTls(previous)
+    }
+  }
+  const handleToggleHotspot = async (checked: boolean): Promise<void> => {
+    try {
+      if (checked) {
+        setHotspot(await window.api.startHotspot())
+      } else {
+        await window.api.stopHotspot()
+        setHotspot(null)
+      }
+    } catch (error) {
+      console.error('Failed to update hotspot:', error)
+    }
+  }
+  const handleSaveEndpoints = async (): Promise<void> => {
+    try {
+      const listeners = await window.api.setListenerEndpoints(endpointUrls.split(','))
+      setEndpoints(listeners)
+      setEndpointUrls(listeners.map((endpoint) => endpoint.url).join(', '))
+    } catch (error) {
+      console.error('Failed to update listener endpoints:', error)
+    }
+  }
+  const saveFirewall = async (changes: Partial<FirewallSettings>): Promise<void> => {
+    if (!firewall) return
+    const previous = firewall
+    setFirewall({ ...previous, ...changes })
+    try {
+      const rules = await window.api.setFirewall({ ...previous, ...changes })
+      setFirewall(rules)
+      setBindAddress(rules.bindAddress)
+      setAllowedSubnets(rules.allowedSubnets.join(', '))
+    } catch (error) {
+      console.error('Failed to update firewall settings:', error)
+      setFirewall(previous)
+    }
+  }
+  const saveSocketTuning = async (changes: Partial<SocketTuning>): Promise<void> => {
+    if (!socketTuning) return
+    const previous = socketTuning
+    setSocketTuning({ ...previous, ...changes })
+    try {
+      setSocketTuning(await window.api.setSocketTuning({ ...previous, ...changes }))
+    } catch (error) {
+      console.error('Failed to update socket options:', error)
+      setSocketTuning(previous)
+    }
+  }
+  const handleSaveOutboundProxy = async (): Promise<void> => {
+    try {
+      setOutboundProxy(await window.api.setOutboundProxy(outboundProxy))
+    } catch (error) {
+      console.error('Failed to update outbound proxy:', error)
+    }
+  }
+  const handleToggleOverlay = async (checked: boolean): Promise<void> => {
+    const previous = overlayStatus
+    if (previous) setOverlayStatus({ ...previous, enabled: checked })
+    try {
+      setOverlayStatus(await window.api.setOverlayEnabled(checked))
+    } catch (error) {
+      console.error('Failed to update overlay networks:', error)
+      setOverlayStatus(previous)
+    }
+  }
+  const handleSaveOverlayPeers = async (): Promise<void> => {
+    try {
+      const status = await window.api.setOverlayPeers(overlayPeers.split(','))
+      setOverlayStatus(status)
+      setOverlayPeers(status.peers.join(', '))
+      // Peers answer in the background; show how many were found a moment later
+      setTimeout(async () => setOverlayStatus(await window.api.getOverlayStatus()), 4000)
+    } catch (error) {
+      console.error('Failed to update overlay peers:', error)
+    }
+  }
+  const handleToggleUdpTransfers = async (checked: boolean): Promise<void> => {
+    setUdpTransfers(checked)
+    try {
+      setUdpTransfers(await window.api.setUdpTransfers(checked))
+    } catch (error) {
+      console.error('Failed to update UDP transfers:', error)
+      setUdpTransfers(!checked)
+    }
+  }
+  const handleToggleBluetooth = async (checked: boolean): Promise<void> => {
+    const previous = bluetoothStatus
+    if (previous) setBluetoothStatus({ ...previous, enabled: checked })
+    try {
+      setBluetoothStatus(await window.api.setBluetoothEnabled(checked))
+    } catch (error) {
+      console.error('Failed to update Bluetooth fallback:', error)
+      setBluetoothStatus(previous)
+    }
+  }
+  const handleSaveBluetoothPorts = async (): Promise<void> => {
+    try {
+      const status = await window.api.setBluetoothPorts(bluetoothPorts.split(','))
+      setBluetoothStatus(status)
+      setBluetoothPorts(status.ports.join(', '))
+      // Links open in the background; show who answered a moment later
+      setTimeout(async () => setBluetoothStatus(await window.api.getBluetoothStatus()), 4000)
+    } catch (error) {
+      console.error('Failed to update Bluetooth ports:', error)
+    }
+  }
+  const handleSaveRelayAddress = async (): Promise<void> => {
+    try {
+      setRelayAddress(await window.api.setRelayAddress(relayAddress))
+      // Registration finishes in the background; show how far it got a moment later
+      setTimeout(async () => setRelayStatus(await window.api.getRelayStatus()), 2000)
+    } catch (error) {
+      console.error('Failed to update relay server:', error)
+    }
+  }
+  const handleMaxIncomingSizeChange = async (bytes: number): Promise<void> => {
+    const previous = maxIncomingSize
+    setMaxIncomingSize(bytes)
+    try {
+      setMaxIncomingSize(await window.api.setMaxIncomingSize(bytes))
+    } catch (error) {
+      console.error('Failed to update max incoming file size:', error)
+      setMaxIncomingSize(previous)
+    }
+  }
+  const handleTogglePerPeerFolders = async (checked: boolean): Promise<void> => {
+    const previous = perPeerFolders
+    setPerPeerFolders(checked)
+    try {
+      await window.api.setPerPeerDownloadFolders(checked)
+    } catch (error) {
+      console.error('Failed to update per-device folder setting:', error)
+      setPerPeerFolders(previous)
     }
   }
   const handleClearCache = async (): Promise<void> => {
@@ -157,6 +562,21 @@ export const SettingsPage: React.FC = () => {
       setClearingCache(false)
     }
   }
+  const handleCleanPartials = async (): Promise<void> => {
+    setCleaningPartials(true)
+    try {
+      const { removed, freedBytes } = await window.api.cleanIncompleteTransfers()
+      setPartialsResult(
+        removed.length > 0
+          ? `Removed ${removed.length} incomplete file(s), freeing ${formatFileSize(freedBytes)}`
+          : 'No incomplete files to remove'
+      )
+    } catch (error) {
+      console.error('Failed to clean incomplete transfers:', error)
+    } finally {
+      setCleaningPartials(false)
+    }
+  }
   const handleToggleAutoAccept = async (checked: boolean): Promise<void> => {
     // Optimistic update
     const previous = autoAccept
@@ -168,6 +588,46 @@ export const SettingsPage: React.FC = () => {
       setAutoAccept(previous) // Rollback on error
     }
   }
+  const handleToggleReadReceipts = async (checked: boolean): Promise<void> => {
+    const previous = sendReadReceipts
+    setSendReadReceipts(checked)
+    try {
+      await window.api.setSendReadReceipts(checked)
+    } catch (error) {
+      console.error('Failed to update read receipts setting:', error)
+      setSendReadReceipts(previous)
+    }
+  }
+  const handleToggleLinkPreviews = async (checked: boolean): Promise<void> => {
+    const previous = linkPreviews
+    setLinkPreviews(checked)
+    try {
+      await window.api.setLinkPreviews(checked)
+    } catch (error) {
+      console.error('Failed to update link preview setting:', error)
+      setLinkPreviews(previous)
+    }
+  }
+  const handleToggleFilePreviews = async (checked: boolean): Promise<void> => {
+    const previous = filePreviews
+    setFilePreviews(checked)
+    try {
+      await window.api.setSendFilePreviews(checked)
+    } catch (error) {
+      console.error('Failed to update file preview setting:', error)
+      setFilePreviews(previous)
+    }
+  }
+  const handleToggleFoldersAsZip = async (checked: boolean): Promise<void> => {
+    const previous = foldersAsZip
+    setFoldersAsZip(checked)
+    try {
+      await window.api.setSendFoldersAsZip(checked)
+    } catch (error) {
+      console.error('Failed to update folder zip setting:', error)
+      setFoldersAsZip(previous)
+    }
+  }
   const handleCheckForUpdates = async (): Promise<void> => {
     try {
       await window.api.checkForUpdates()
@@ -299,6 +759,536 @@ export const SettingsPage: React.FC = () => {
               </p>
             </div>
             <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Per-device Folders</Label>
+                <p className="text-sm text-muted-foreground">
+                  Save files from each device into its own subfolder
+                </p>
+              </div>
+              <Switch checked={perPeerFolders} onCheckedChange={handleTogglePerPeerFolders} />
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">When a File Already Exists</Label>
+                <p className="text-sm text-muted-foreground">
+                  How to name a received file that clashes with an existing one
+                </p>
+              </div>
+              <select
+                className="h-10 rounded-md border border-input bg-background px-3 text-sm"
+                value={conflictPolicy}
+                onChange={(e) => handleConflictPolicyChange(e.target.value as ConflictPolicy)}
+              >
+                <option value="rename">Keep both</option>
+                <option value="overwrite">Replace</option>
+                <option value="ask">Ask me</option>
+              </select>
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Parallel Streams</Label>
+                <p className="text-sm text-muted-foreground">
+                  Split files over 64 MB across several connections on fast networks
+                </p>
+              </div>
+              <select
+                className="h-10 rounded-md border border-input bg-background px-3 text-sm"
+                value={parallelStreams}
+                onChange={(e) => handleParallelStreamsChange(Number(e.target.value))}
+              >
+                <option value={1}>Off</option>
+                <option value={2}>2 streams</option>
+                <option value={4}>4 streams</option>
+                <option value={8}>8 streams</option>
+              </select>
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Max Incoming Size</Label>
+                <p className="text-sm text-muted-foreground">
+                  Decline files and folders larger than this without asking
+                </p>
+              </div>
+              <select
+                className="h-10 rounded-md border border-input bg-background px-3 text-sm"
+                value={maxIncomingSize}
+                onChange={(e) => handleMaxIncomingSizeChange(Number(e.target.value))}
+              >
+                <option value={0}>No limit</option>
+                <option value={100 * 1024 ** 2}>100 MB</option>
+                <option value={1024 ** 3}>1 GB</option>
+                <option value={4 * 1024 ** 3}>4 GB</option>
+                <option value={20 * 1024 ** 3}>20 GB</option>
+              </select>
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Expire File Requests</Label>
+                <p className="text-sm text-muted-foreground">
+                  Decline incoming files that haven&apos;t been accepted in time
+                </p>
+              </div>
+              <select
+                className="h-10 rounded-md border border-input bg-background px-3 text-sm"
+                value={requestExpiry}
+                onChange={(e) => handleRequestExpiryChange(Number(e.target.value))}
+              >
+                <option value={0}>Never</option>
+                <option value={10}>After 10 minutes</option>
+                <option value={60}>After 1 hour</option>
+                <option value={1440}>After 1 day</option>
+              </select>
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Automatic Retries</Label>
+                <p className="text-sm text-muted-foreground">
+                  Resume transfers interrupted by a network error, waiting longer each time
+                </p>
+              </div>
+              <select
+                className="h-10 rounded-md border border-input bg-background px-3 text-sm"
+                value={retryLimit}
+                onChange={(e) => handleRetryLimitChange(Number(e.target.value))}
+              >
+                <option value={0}>Off</option>
+                <option value={3}>3 times</option>
+                <option value={5}>5 times</option>
+                <option value={10}>10 times</option>
+              </select>
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Connection Heartbeat</Label>
+                <p className="text-sm text-muted-foreground">
+                  Check idle connections and drop ones that stop answering (new connections)
+                </p>
+              </div>
+              <select
+                className="h-10 rounded-md border border-input bg-background px-3 text-sm"
+                value={heartbeatInterval}
+                onChange={(e) => handleHeartbeatIntervalChange(Number(e.target.value))}
+              >
+                <option value={0}>Off</option>
+                <option value={10}>Every 10 seconds</option>
+                <option value={15}>Every 15 seconds</option>
+                <option value={30}>Every 30 seconds</option>
+                <option value={60}>Every minute</option>
+              </select>
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Idle Timeout</Label>
+                <p className="text-sm text-muted-foreground">
+                  Drop connections that stay silent this long (the longer of yours and the
+                  other device&apos;s applies)
+                </p>
+              </div>
+              <select
+                className="h-10 rounded-md border border-input bg-background px-3 text-sm"
+                value={idleTimeout}
+                onChange={(e) => handleIdleTimeoutChange(Number(e.target.value))}
+              >
+                <option value={0}>Never</option>
+                <option value={45}>After 45 seconds</option>
+                <option value={120}>After 2 minutes</option>
+                <option value={300}>After 5 minutes</option>
+                <option value={1800}>After 30 minutes</option>
+              </select>
+            </div>
+            <Separator />
+            <div className="space-y-2">
+              <div className="space-y-0.5">
+                <Label className="text-base">Timeouts</Label>
+                <p className="text-sm text-muted-foreground">
+                  How long connecting, handshakes and requests may take, and how long a file
+                  transfer may stall, before they are given up on
+                </p>
+              </div>
+              <div className="grid grid-cols-2 sm:grid-cols-4 gap-2">
+                {TIMEOUT_OPTIONS.map(({ key, label, options }) => (
+                  <div key={key} className="space-y-1">
+                    <Label className="text-sm text-muted-foreground">{label}</Label>
+                    <select
+                      className="h-10 w-full rounded-md border border-input bg-background px-3 text-sm"
+                      value={timeouts[key]}
+                      onChange={(e) => handleTimeoutChange(key, Number(e.target.value))}
+                    >
+                      {options.map((seconds) => (
+                        <option key={seconds} value={seconds}>
+                          {seconds === 0 ? 'Never' : `${seconds} seconds`}
+                        </option>
+                      ))}
+                    </select>
+                  </div>
+                ))}
+              </div>
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Strict Frame Checking</Label>
+                <p className="text-sm text-muted-foreground">
+                  Drop malformed data and temporarily block devices that keep sending it
+                </p>
+              </div>
+              <Switch
+                checked={strictFrameParsing}
+                onCheckedChange={handleToggleStrictFrameParsing}
+              />
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Pad Control Traffic</Label>
+                <p className="text-sm text-muted-foreground">
+                  Make receipts and heartbeats look alike on the network (uses more data)
+                </p>
+              </div>
+              <Switch checked={padControlFrames} onCheckedChange={handleTogglePadControlFrames} />
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Refuse Unencrypted Traffic</Label>
+                <p className="text-sm text-muted-foreground">
+                  Never fall back to plaintext with devices that have connected securely before
+                </p>
+              </div>
+              <Switch checked={refusePlaintext} onCheckedChange={handleToggleRefusePlaintext} />
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Crypto Self-Test</Label>
+                <p className="text-sm text-muted-foreground">
+                  {selfTestResult ??
+                    'Check the ciphers against known answers and time them on this device'}
+                </p>
+              </div>
+              <Button
+                onClick={handleRunSelfTest}
+                disabled={runningSelfTest}
+                variant="outline"
+                size="default"
+              >
+                {runningSelfTest ? '...' : 'Run'}
+              </Button>
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Browser Connections</Label>
+                <p className="text-sm text-muted-foreground">
+                  {browserPort
+                    ? `Browsers can connect over WebSocket on port ${browserPort}`
+                    : 'Let devices without the app connect from a browser'}
+                </p>
+              </div>
+              <Switch checked={browserPort > 0} onCheckedChange={handleToggleBrowserConnections} />
+            </div>
+            <Separator />
+            <div className="space-y-2">
+              <Label htmlFor="listener-endpoints" className="text-base">
+                Extra Listeners
+              </Label>
+              <p className="text-sm text-muted-foreground">
+                {endpoints.some((endpoint) => endpoint.error)
+                  ? `Not listening on ${endpoints
+                      .filter((endpoint) => endpoint.error)
+                      .map((endpoint) => `${endpoint.url} (${endpoint.error})`)
+                      .join(', ')}`
+                  : 'Also accept connections here, besides the main port'}
+              </p>
+              <div className="flex gap-2">
+                <Input
+                  id="listener-endpoints"
+                  value={endpointUrls}
+                  onChange={(e) => setEndpointUrls(e.target.value)}
+                  placeholder="ws://localhost:52910, tcp://tailscale0:52900"
+                  className="flex-1"
+                />
+                <Button onClick={handleSaveEndpoints} variant="default" size="default">
+                  <Save className="w-4 h-4" />
+                  Save
+                </Button>
+              </div>
+            </div>
+            <Separator />
+            <div className="space-y-2">
+              <Label htmlFor="bind-address" className="text-base">
+                Bind Address
+              </Label>
+              <p className="text-sm text-muted-foreground">
+                Listen on this address only; empty for all of them (applies after a restart)
+              </p>
+              <div className="flex gap-2">
+                <Input
+                  id="bind-address"
+                  value={bindAddress}
+                  onChange={(e) => setBindAddress(e.target.value)}
+                  placeholder="192.168.1.20"
+                  className="flex-1"
+                />
+                <Button
+                  onClick={() => saveFirewall({ bindAddress })}
+                  variant="default"
+                  size="default"
+                >
+                  <Save className="w-4 h-4" />
+                  Save
+                </Button>
+              </div>
+            </div>
+            <Separator />
+            <div className="space-y-2">
+              <div className="space-y-0.5">
+                <Label className="text-base">Socket Tuning</Label>
+                <p className="text-sm text-muted-foreground">
+                  For fast networks: how many connections may queue, how much each buffers,
+                  and whether other listeners may share the port (applies after a restart)
+                </p>
+              </div>
+              <div className="grid grid-cols-1 sm:grid-cols-3 gap-2">
+                <div className="space-y-1">
+                  <Label className="text-sm text-muted-foreground">Accept backlog</Label>
+                  <select
+                    className="h-10 w-full rounded-md border border-input bg-background px-3 text-sm"
+                    value={socketTuning?.backlog ?? 511}
+                    onChange={(e) => saveSocketTuning({ backlog: Number(e.target.value) })}
+                  >
+                    {[128, 511, 1024, 4096].map((backlog) => (
+                      <option key={backlog} value={backlog}>
+                        {backlog}
+                      </option>
+                    ))}
+                  </select>
+                </div>
+                <div className="space-y-1">
+                  <Label className="text-sm text-muted-foreground">Buffer size</Label>
+                  <select
+                    className="h-10 w-full rounded-md border border-input bg-background px-3 text-sm"
+                    value={socketTuning?.bufferSize ?? 4 * 1024 * 1024}
+                    onChange={(e) => saveSocketTuning({ bufferSize: Number(e.target.value) })}
+                  >
+                    {[1, 4, 16, 64].map((mb) => (
+                      <option key={mb} value={mb * 1024 * 1024}>
+                        {mb} MB
+                      </option>
+                    ))}
+                  </select>
+                </div>
+                <div className="flex items-center justify-between gap-2">
+                  <Label className="text-sm text-muted-foreground">Reuse port</Label>
+                  <Switch
+                    checked={!!socketTuning?.reusePort}
+                    onCheckedChange={(checked) => saveSocketTuning({ reusePort: checked })}
+                  />
+                </div>
+              </div>
+            </div>
+            <Separator />
+            <div className="space-y-2">
+              <Label htmlFor="allowed-subnets" className="text-base">
+                Allowed Networks
+              </Label>
+              <p className="text-sm text-muted-foreground">
+                Only accept connections from these subnets; empty allows any
+              </p>
+              <div className="flex gap-2">
+                <Input
+                  id="allowed-subnets"
+                  value={allowedSubnets}
+                  onChange={(e) => setAllowedSubnets(e.target.value)}
+                  placeholder="192.168.1.0/24, fd00::/8"
+                  className="flex-1"
+                />
+                <Button
+                  onClick={() => saveFirewall({ allowedSubnets: allowedSubnets.split(',') })}
+                  variant="default"
+                  size="default"
+                >
+                  <Save className="w-4 h-4" />
+                  Save
+                </Button>
+              </div>
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">LAN Only</Label>
+                <p className="text-sm text-muted-foreground">
+                  Refuse connections from outside private networks, including the relay
+                </p>
+              </div>
+              <Switch
+                checked={!!firewall?.lanOnly}
+                onCheckedChange={(checked) => saveFirewall({ lanOnly: checked })}
+              />
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Prefer TLS Connections</Label>
+                <p className="text-sm text-muted-foreground">
+                  Connect over TLS when the other device offers it, pinning its certificate
+                </p>
+              </div>
+              <Switch checked={preferTls} onCheckedChange={handleTogglePreferTls} />
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Hotspot Pairing</Label>
+                <p className="text-sm text-muted-foreground">
+                  {hotspot
+                    ? `Join "${hotspot.ssid}" with password ${hotspot.password}`
+                    : 'Create a Wi-Fi hotspot for devices with no network in common'}
+                </p>
+              </div>
+              <Switch checked={hotspot !== null} onCheckedChange={handleToggleHotspot} />
+            </div>
+            <Separator />
+            <div className="space-y-2">
+              <Label htmlFor="relay-address" className="text-base">
+                Relay Server
+              </Label>
+              <p className="text-sm text-muted-foreground">
+                {relayStatus?.registered
+                  ? `Registered with ${relayStatus.address}, ${relayStatus.peers} peer(s) online`
+                  : 'Reach devices on other networks through a relay (host:port)'}
+              </p>
+              <div className="flex gap-2">
+                <Input
+                  id="relay-address"
+                  value={relayAddress}
+                  onChange={(e) => setRelayAddress(e.target.value)}
+                  placeholder="relay.example.com:52903"
+                  className="flex-1"
+                />
+                <Button onClick={handleSaveRelayAddress} variant="default" size="default">
+                  <Save className="w-4 h-4" />
+                  Save
+                </Button>
+              </div>
+            </div>
+            <Separator />
+            <div className="space-y-2">
+              <Label htmlFor="outbound-proxy" className="text-base">
+                Outbound Proxy
+              </Label>
+              <p className="text-sm text-muted-foreground">
+                Reach the relay through a SOCKS5 or HTTP proxy, e.g. Tor at socks5://127.0.0.1:9050
+              </p>
+              <div className="flex gap-2">
+                <Input
+                  id="outbound-proxy"
+                  value={outboundProxy}
+                  onChange={(e) => setOutboundProxy(e.target.value)}
+                  placeholder="socks5://host:port or http://host:port"
+                  className="flex-1"
+                />
+                <Button onClick={handleSaveOutboundProxy} variant="default" size="default">
+                  <Save className="w-4 h-4" />
+                  Save
+                </Button>
+              </div>
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Overlay Networks</Label>
+                <p className="text-sm text-muted-foreground">
+                  {overlayStatus?.interfaces.length
+                    ? `Find devices over ${overlayStatus.interfaces
+                        .map((iface) => `${iface.name} (${iface.kind})`)
+                        .join(', ')}; ${overlayStatus.found} found`
+                    : 'Find devices over Tailscale, WireGuard or ZeroTier'}
+                </p>
+              </div>
+              <Switch checked={!!overlayStatus?.enabled} onCheckedChange={handleToggleOverlay} />
+            </div>
+            {overlayStatus?.enabled && (
+              <div className="space-y-2">
+                <Label htmlFor="overlay-peers" className="text-base">
+                  Overlay Peers
+                </Label>
+                <p className="text-sm text-muted-foreground">
+                  Addresses to look for devices at, besides the Tailscale peer list
+                </p>
+                <div className="flex gap-2">
+                  <Input
+                    id="overlay-peers"
+                    value={overlayPeers}
+                    onChange={(e) => setOverlayPeers(e.target.value)}
+                    placeholder="10.8.0.2, 10.8.0.3:52900"
+                    className="flex-1"
+                  />
+                  <Button onClick={handleSaveOverlayPeers} variant="default" size="default">
+                    <Save className="w-4 h-4" />
+                    Save
+                  </Button>
+                </div>
+              </div>
+            )}
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">UDP File Transfers</Label>
+                <p className="text-sm text-muted-foreground">
+                  Send file data over UDP to devices that also have this on; faster on lossy
+                  Wi-Fi
+                </p>
+              </div>
+              <Switch checked={udpTransfers} onCheckedChange={handleToggleUdpTransfers} />
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Bluetooth Fallback</Label>
+                <p className="text-sm text-muted-foreground">
+                  {bluetoothStatus?.peers.length
+                    ? `Connected over Bluetooth to ${bluetoothStatus.peers.join(', ')}`
+                    : 'Reach paired devices over Bluetooth when there is no network in common'}
+                </p>
+              </div>
+              <Switch
+                checked={!!bluetoothStatus?.enabled}
+                onCheckedChange={handleToggleBluetooth}
+              />
+            </div>
+            {bluetoothStatus?.enabled && (
+              <div className="space-y-2">
+                <Label htmlFor="bluetooth-ports" className="text-base">
+                  Bluetooth Ports
+                </Label>
+                <p className="text-sm text-muted-foreground">
+                  Serial ports of paired devices; messages and files up to 20 MB go this way
+                </p>
+                <div className="flex gap-2">
+                  <Input
+                    id="bluetooth-ports"
+                    value={bluetoothPorts}
+                    onChange={(e) => setBluetoothPorts(e.target.value)}
+                    placeholder="/dev/rfcomm0, COM5"
+                    className="flex-1"
+                  />
+                  <Button onClick={handleSaveBluetoothPorts} variant="default" size="default">
+                    <Save className="w-4 h-4" />
+                    Save
+                  </Button>
+                </div>
+              </div>
+            )}
+            <Separator />
             <div className="flex items-center justify-between">
               <div className="space-y-0.5">
                 <Label className="text-base">Auto-accept Files</Label>
@@ -308,6 +1298,48 @@ export const SettingsPage: React.FC = () => {
               </div>
               <Switch checked={autoAccept} onCheckedChange={handleToggleAutoAccept} />
             </div>
+            <Separator />
+            <AutoAcceptRules />
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Send Read Receipts</Label>
+                <p className="text-sm text-muted-foreground">
+                  Let senders know when you have read their messages
+                </p>
+              </div>
+              <Switch checked={sendReadReceipts} onCheckedChange={handleToggleReadReceipts} />
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Link Previews</Label>
+                <p className="text-sm text-muted-foreground">
+                  Fetch titles and thumbnails for links (contacts the linked site)
+                </p>
+              </div>
+              <Switch checked={linkPreviews} onCheckedChange={handleToggleLinkPreviews} />
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Send File Previews</Label>
+                <p className="text-sm text-muted-foreground">
+                  Include a small thumbnail of images and videos with file requests
+                </p>
+              </div>
+              <Switch checked={filePreviews} onCheckedChange={handleToggleFilePreviews} />
+            </div>
+            <Separator />
+            <div className="flex items-center justify-between">
+              <div className="space-y-0.5">
+                <Label className="text-base">Send Folders as ZIP</Label>
+                <p className="text-sm text-muted-foreground">
+                  Send folders as a single zip archive, for devices running older versions
+                </p>
+              </div>
+              <Switch checked={foldersAsZip} onCheckedChange={handleToggleFoldersAsZip} />
+            </div>
           </CardContent>
         </Card>
         {/* Network Section */}
@@ -359,6 +1391,53 @@ export const SettingsPage: React.FC = () => {
                     )}
                   </div>
                 </div>
+                <Separator />
+                <div className="space-y-2">
+                  <Label className="text-sm text-muted-foreground">Connected Devices</Label>
+                  <div className="space-y-1">
+                    {connections.length > 0 ? (
+                      connections.map((connection) => (
+                        <div
+                          key={`${connection.direction}-${connection.deviceId}`}
+                          className="flex items-center justify-between gap-2 px-3 py-2 bg-muted rounded-md text-sm"
+                        >
+                          <div className="min-w-0">
+                            <p className="flex items-center gap-2 font-medium truncate">
+                              <span
+                                className={`w-2 h-2 rounded-full ${qualityColor(connection)}`}
+                              />
+                              {discoveredDevices.find((d) => d.deviceId === connection.deviceId)
+                                ?.displayName ?? connection.deviceId}
+                              {connection.rttMs !== undefined && (
+                                <span className="text-xs text-muted-foreground font-mono">
+                                  {connection.rttMs} ms
+                                </span>
+                              )}
+                            </p>
+                            <p className="text-xs text-muted-foreground font-mono truncate">
+                              {connection.address ?? 'unknown address'} ·{' '}
+                              {connection.encrypted ? 'encrypted' : 'unencrypted'} · up{' '}
+                              {formatUptime(connection.uptimeSeconds)} · ↓{' '}
+                              {formatFileSize(connection.bytesIn)} (
+                              {formatFileSize(connection.rateIn)}/s) ↑{' '}
+                              {formatFileSize(connection.bytesOut)} (
+                              {formatFileSize(connection.rateOut)}/s)
+                            </p>
+                          </div>
+                          <Button
+                            variant="outline"
+                            size="sm"
+                            onClick={() => handleDisconnectDevice(connection.deviceId)}
+                          >
+                            Disconnect
+                          </Button>
+                        </div>
+                      ))
+                    ) : (
+                      <p className="text-sm text-muted-foreground">No devices connected</p>
+                    )}
+                  </div>
+                </div>
               </>
             ) : (
               <p className="text-sm text-muted-foreground">Loading network information...</p>
@@ -396,7 +1475,10 @@ export const SettingsPage: React.FC = () => {
                 <AlertDialogFooter>
                   <AlertDialogCancel>Cancel</AlertDialogCancel>
                   <AlertDialogAction
-                    onClick={() => clearMessages()}
+                    onClick={() => {
+                      clearMessages()
+                      window.api.clearMessages()
+                    }}
                     className="bg-destructive hover:bg-destructive/90"
                   >
                     Clear History
@@ -433,6 +1515,36 @@ export const SettingsPage: React.FC = () => {
                 </AlertDialogFooter>
               </AlertDialogContent>
             </AlertDialog>
+            <AlertDialog>
+              <AlertDialogTrigger asChild>
+                <Button
+                  variant="outline"
+                  disabled={cleaningPartials}
+                  className="flex-1 justify-center text-destructive hover:text-destructive hover:bg-destructive/10"
+                >
+                  <FileX className="w-4 h-4" />
+                  {cleaningPartials ? '...' : 'Partial Files'}
+                </Button>
+              </AlertDialogTrigger>
+              <AlertDialogContent>
+                <AlertDialogHeader>
+                  <AlertDialogTitle>Remove Incomplete Downloads?</AlertDialogTitle>
+                  <AlertDialogDescription>
+                    This deletes partially received files from failed or abandoned transfers.
+                    Transfers that are still running or can be resumed are left alone.
+                  </AlertDialogDescription>
+                </AlertDialogHeader>
+                <AlertDialogFooter>
+                  <AlertDialogCancel>Cancel</AlertDialogCancel>
+                  <AlertDialogAction
+                    onClick={handleCleanPartials}
+                    className="bg-destructive hover:bg-destructive/90"
+                  >
+                    Remove Files
+                  </AlertDialogAction>
+                </AlertDialogFooter>
+              </AlertDialogContent>
+            </AlertDialog>
             <AlertDialog>
               <AlertDialogTrigger asChild>
                 <Button
@@ -464,6 +1576,11 @@ export const SettingsPage: React.FC = () => {
               </AlertDialogContent>
             </AlertDialog>
           </CardContent>
+          {partialsResult && (
+            <CardContent className="pt-0">
+              <p className="text-xs text-muted-foreground">{partialsResult}</p>
+            </CardContent>
+          )}
         </Card>
         {/* About Section */}
         <Card>
@@ -1,6 +1,13 @@
 import { create } from 'zustand'
-import { persist, createJSONStorage } from 'zustand/middleware'
-import { Device, NetworkMessage, FileTransferProgress, DeviceInfo } from '@shared/messageTypes'
+import { persist, createJSONStorage, StateStorage } from 'zustand/middleware'
+import {
+  Device,
+  NetworkMessage,
+  FileTransferProgress,
+  DeviceInfo,
+  MessageEdit,
+  LinkPreview
+} from '@shared/messageTypes'
 
 interface AppState {
   localDevice: DeviceInfo | null
@@ -48,6 +55,9 @@ interface AppState {
   setDiscoveredDevices: (devices: Device[]) => void
   clearMessages: (deviceId?: string) => void
   deleteMessage: (deviceId: string, messageId: string) => void
+  editMessage: (edit: MessageEdit) => void
+  retractMessage: (deviceId: string, messageId: string) => void
+  setLinkPreview: (deviceId: string, messageId: string, preview: LinkPreview) => void
   clearTransfers: () => void
   updateMessageStatus: (
     deviceId: string,
@@ -58,6 +68,27 @@ interface AppState {
   setTheme: (theme: 'light' | 'dark') => void
 }
 
+// Nothing is written to (or read from) localStorage as a guest; see setGuestStorage
+let guestStorage = false
+
+const profileStorage: StateStorage = {
+  getItem: (name) => (guestStorage ? null : localStorage.getItem(name)),
+  setItem: (name, value) => {
+    if (!guestStorage) localStorage.setItem(name, value)
+  },
+  removeItem: (name) => {
+    if (!guestStorage) localStorage.removeItem(name)
+  }
+}
+
+/**
+ * Stops persisting the store while the app runs as a guest, so the guest identity and its
+ * conversations are never written to disk.
+ */
+export function setGuestStorage(guest: boolean): void {
+  guestStorage = guest
+}
+
 export const useStore = create<AppState>()(
   persist(
     (set) => ({
@@ -218,11 +249,53 @@ export const useStore = create<AppState>()(
             }
           }
         }),
+      retractMessage: (deviceId, messageId) =>
+        set((state) => {
+          const deviceMessages = state.messages[deviceId] || []
+          // A peer can only retract what it sent itself
+          const updatedMessages = deviceMessages.map((m) =>
+            m.id === messageId && m.deviceId === deviceId ? { ...m, payload: '', deleted: true } : m
+          )
+          return {
+            messages: {
+              ...state.messages,
+              [deviceId]: updatedMessages
+            }
+          }
+        }),
+      editMessage: (edit) =>
+        set((state) => {
+          const deviceMessages = state.messages[edit.conversationId] || []
+          const updatedMessages = deviceMessages.map((m) =>
+            m.id === edit.messageId && m.deviceId === edit.editorId
+              ? { ...m, payload: edit.payload, edited: true, editedAt: edit.editedAt }
+              : m
+          )
+          return {
+            messages: {
+              ...state.messages,
+              [edit.conversationId]: updatedMessages
+            }
+          }
+        }),
+      setLinkPreview: (deviceId, messageId, preview) =>
+        set((state) => {
+          const deviceMessages = state.messages[deviceId] || []
+          const updatedMessages = deviceMessages.map((m) =>
+            m.id === messageId ? { ...m, linkPreview: preview } : m
+          )
+          return {
+            messages: {
+              ...state.messages,
+              [deviceId]: updatedMessages
+            }
+          }
+        }),
       clearTransfers: () => set({ transfers: {} })
     }),
     {
       name: 'hyper-connect-storage',
-      storage: createJSONStorage(() => localStorage),
+      storage: createJSONStorage(() => profileStorage),
       partialize: (state) => ({
         onboardingComplete: state.onboardingComplete,
         localDevice: state.localDevice,
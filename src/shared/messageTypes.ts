@@ -4,6 +4,8 @@ export interface DeviceInfo {
   platform: string
   appVersion: string
   profileImage?: string
+  statusMessage?: string
+  isGuest?: boolean
 }
 
 export interface Device {
@@ -13,52 +15,408 @@ export interface Device {
   appVersion: string
   address: string
   port: number
+  tlsPort?: number // advertised when the device also accepts TLS connections
+  udpPort?: number // advertised when the device takes file streams over UDP
+  relay?: boolean // reached through the relay server (address and port are the relay's)
+  overlay?: boolean // found on an overlay network such as Tailscale, where mDNS doesn't reach
+  bluetooth?: boolean // reached over a Bluetooth serial link (address is the port's path)
   lastSeen: number
   isOnline: boolean
   profileImage?: string
+  avatarPath?: string
+  statusMessage?: string
+  keyStorage?: KeyStorage // as the device itself reports it; nothing proves it
 }
 
+// Where a device keeps its private keys: sealed by the OS keychain, in a plain file only
+// its user can read, or (for a guest identity) nowhere but memory
+export type KeyStorage = 'keychain' | 'file' | 'memory'
+
 export interface NetworkMessage {
   type:
     | 'HELLO'
     | 'HELLO_SECURE'
     | 'ENCRYPTED_MESSAGE'
     | 'MESSAGE'
-    | 'MESSAGE_DELIVERED'
+    | 'MESSAGE_ACK'
+    | 'MESSAGE_DELIVERED' // legacy names, only seen on the wire to and from version 1 peers
     | 'MESSAGE_READ'
+    | 'READ_RECEIPT'
     | 'MESSAGE_DELETE'
+    | 'MESSAGE_EDIT'
     | 'FILE_META'
     | 'FILE_ACCEPT'
     | 'FILE_REJECT'
+    | 'FILE_PAUSE'
+    | 'FILE_RESUME'
+    | 'FILE_CANCEL'
+    | 'FILE_ACK'
     | 'FILE_PROGRESS'
     | 'FILE_COMPLETE'
     | 'PING'
     | 'PONG'
+    | 'GOODBYE'
+    | 'ERROR'
+    | 'TRANSFER_STATUS_QUERY'
+    | 'TRANSFER_STATUS'
+    | 'EXTENSIONS'
+    | 'SESSION_TICKET'
+    | 'HANDSHAKE_FINISH' // wire only: the last message of a Noise handshake
+    | 'BATCH'
+    | 'FRAGMENT' // wire only: a slice of an oversized frame, reassembled before dispatch
+    | 'PROFILE_UPDATE'
+    | 'GROUP_MESSAGE'
+    | 'GROUP_UPDATE'
+    | 'SENDER_KEY'
   deviceId: string
   payload?: unknown
   id?: string
   ackId?: string
+  correlationId?: string // on a response: the id of the frame it answers
   timestamp?: number
   replyTo?: string
   status?: 'sending' | 'sent' | 'delivered' | 'read'
+  edited?: boolean
+  editedAt?: number
+  deleted?: boolean // tombstone left behind when the sender retracts a message
+  groupId?: string
+  forwardedFrom?: ForwardedFrom
+  linkPreview?: LinkPreview
+}
+
+export interface LinkPreview {
+  url: string
+  title?: string
+  description?: string
+  image?: string // a data: URL, fetched by the main process
+  siteName?: string
+}
+
+export interface ForwardedFrom {
+  deviceId: string // original sender
+  displayName?: string
+  messageId?: string
+  timestamp?: number
+}
+
+export interface ProfileUpdatePayload {
+  displayName: string
+  profileImage?: string
+  statusMessage?: string
+  keyStorage?: KeyStorage
+}
+
+export interface MessageEdit {
+  conversationId: string // deviceId of the peer the conversation is with
+  editorId: string // deviceId of whoever made the edit; only the original sender may edit
+  messageId: string
+  payload: string
+  editedAt: number
+}
+
+export interface MessageDeletion {
+  conversationId: string
+  messageId: string
+  forEveryone: boolean
+}
+
+export interface Group {
+  groupId: string
+  name: string
+  members: string[] // deviceIds, including our own
+  createdBy: string
+  createdAt: number
+}
+
+// memberId -> delivery state of one group message
+export type GroupDeliveryState = Record<string, 'sent' | 'delivered' | 'failed'>
+
+export interface MessageSearchResult {
+  conversationId: string
+  message: NetworkMessage
+  text: string // the text that was searched (message body or file name)
+  score: number
+  highlights: [number, number][] // [start, end) offsets into `text`
+}
+
+export interface InboxThread {
+  conversationId: string // peer deviceId or groupId
+  isGroup: boolean
+  displayName: string
+  unreadCount: number
+  isOnline: boolean
+  lastMessage?: {
+    id?: string
+    snippet: string
+    timestamp: number
+    fromSelf: boolean
+  }
+}
+
+export interface ScheduledMessage {
+  scheduleId: string
+  deviceId: string
+  payload: string
+  sendAt: number
+  replyTo?: string
+  createdAt: number
 }
 
 export interface FileMetadata {
   fileId: string
   name: string
-  size: number
+  size: number // total of all entries for a folder
   path?: string
+  isFolder?: boolean
+  compression?: 'zstd' // offered by the sender; used only if the receiver's FILE_ACCEPT agrees
+  delta?: boolean // sender can send a delta against a DeltaSignature returned in FILE_ACCEPT
+  checksum?: string // SHA-256 (hex) of the whole file; the receiver verifies it when complete
+  preview?: string // small JPEG data URL of an image or video, shown before accepting
+  // Never auto-accepted, previewed or thumbnailed, and named only in the chat itself
+  sensitive?: boolean
+  entries?: FolderEntry[] // folder manifest, streamed one file at a time
+  directories?: string[] // relative paths, so empty directories survive the transfer
+  passphrase?: PassphraseProtection // the receiver needs the sender's passphrase to accept
+}
+
+// How the key of a passphrase-protected file is derived from the phrase (see passphrase.ts)
+export interface PassphraseProtection {
+  kdf: 'argon2id' | 'scrypt'
+  salt: string // Base64
+  check: string // Base64; tells the receiver whether the phrase it was given is right
+}
+
+// Block signatures of the receiver's existing copy of a file, sent back in FILE_ACCEPT.
+// `weak` are rolling checksums and `strong` truncated SHA-256 hashes, one per block.
+export interface DeltaSignature {
+  blockSize: number
+  weak: number[]
+  strong: string[]
+}
+
+// Why a file was refused. `reason` is unset when the user declined it; the sizes let
+// the sender show what the receiver's limit or free space was.
+export interface FileRejection {
+  reason?: 'timeout' | 'no-space' | 'too-large'
+  maxSize?: number // for 'too-large'
+  available?: number // for 'no-space'
+}
+
+// Payload of FILE_REJECT
+export interface FileRejectPayload extends FileRejection {
+  fileId: string
+}
+
+// Payload of FILE_PAUSE / FILE_RESUME / FILE_CANCEL. `offset` is only ever set by the
+// receiver: it is the number of bytes it has verifiably written, and the sender resumes
+// from there.
+export interface FileControlPayload {
+  fileId: string
+  offset?: number
+}
+
+// Payload of BATCH: frames handled one after another as if each had arrived on its own
+export interface BatchPayload {
+  frames: NetworkMessage[]
+}
+
+// Payload of EXTENSIONS: the optional features the sender can use (see extensions.ts)
+export interface ExtensionsPayload {
+  extensions: string[]
+}
+
+// Payload of SESSION_TICKET: lets the receiver resume the session later (see resumption.ts)
+export interface SessionTicketPayload {
+  ticket: string // Base64, opaque to the receiver
+  lifetime: number // ms it can be used for
+}
+
+// Payload of SENDER_KEY: the sender's current chain for a group (see senderKey.ts)
+export interface SenderKeyPayload {
+  groupId: string
+  keyId: string
+  chainKey: string // Base64
+  iteration: number // the next message the sender will encrypt on it
+}
+
+// Payload of a GROUP_MESSAGE encrypted with the sender's key, in place of the text
+export interface SealedGroupPayload {
+  keyId: string
+  iteration: number
+  iv: string // Base64
+  tag: string // Base64
+  ciphertext: string // Base64
+}
+
+// Payload of TRANSFER_STATUS_QUERY. `status` is how the asking side sees the transfer.
+export interface TransferStatusQuery {
+  fileId: string
+  status?: FileTransferProgress['status']
+}
+
+// Payload of TRANSFER_STATUS: how the answering side sees a transfer. 'unknown' means it
+// has no such transfer with us, e.g. because it restarted since.
+export interface TransferStatusPayload {
+  fileId: string
+  status: FileTransferProgress['status'] | 'unknown'
+  bytes: number // receiver: gap-free bytes written; sender: bytes sent
+}
+
+// Why a peer couldn't act on something we sent, carried by an ERROR frame
+export type ErrorCode =
+  | 'UNAUTHORIZED' // we lack the permission the frame needs
+  | 'UNSUPPORTED_VERSION' // the frame needs a protocol feature the peer doesn't have
+  | 'DISK_FULL'
+  | 'CHECKSUM_MISMATCH'
+  | 'RATE_LIMITED'
+  | 'INTERNAL'
+
+export interface ErrorPayload {
+  code: ErrorCode
+  ref?: string // fileId or message id the error is about
+  message?: string // detail for logs
+}
+
+// An ERROR frame as passed to the renderer
+export interface PeerError extends ErrorPayload {
+  deviceId: string
+  timestamp: number
+}
+
+// What to do when a received file's name is already taken in the download directory
+export type ConflictPolicy = 'rename' | 'overwrite' | 'ask'
+
+export interface AutoAcceptRule {
+  id: string
+  action: 'accept' | 'reject'
+  enabled?: boolean
+  peer?: 'any' | 'trusted' | 'untrusted' // trust = approved via connection requests
+  deviceId?: string // limit the rule to one peer
+  extensions?: string[] // e.g. ['.pdf', '.png']; omitted = any type
+  maxSize?: number // bytes; larger files don't match
+}
+
+export interface FolderEntry {
+  fileId: string
+  relativePath: string // '/'-separated, relative to the folder root
+  size: number
 }
 
 export interface FileTransferProgress {
   fileId: string
   deviceId: string
+  messageId?: string // id of the FILE_META chat message this transfer belongs to
   progress: number
   speed: number
   eta: number
-  status: 'pending' | 'active' | 'completed' | 'failed' | 'rejected'
+  status:
+    | 'pending'
+    | 'queued'
+    | 'active'
+    | 'paused'
+    | 'retrying'
+    | 'verifying'
+    | 'completed'
+    | 'failed'
+    | 'rejected'
+    | 'cancelled'
   path?: string
   name?: string
   size?: number
   direction?: 'incoming' | 'outgoing'
+  fileCount?: number // folders only
+  filesCompleted?: number
+  compressionRatio?: number // original bytes / bytes on the wire, when compressed
+  priority?: TransferPriority // outgoing only
+  queuePosition?: number // 1-based, while waiting for a free transfer slot
+  error?: string // why a transfer failed, when known
+  rejection?: FileRejection // set on 'rejected' transfers that weren't simply declined
+  verifyProgress?: number // 0-1 while the received file's checksum is being checked
+  thumbnail?: string // local path of a thumbnail generated for a received image or video
+}
+
+export type TransferPriority = 'high' | 'normal' | 'low'
+
+// An open control connection to a peer and how alive it looks
+export interface ConnectionInfo {
+  deviceId: string
+  direction: 'outbound' | 'inbound' // whether we dialed it or the peer did
+  address?: string
+  encrypted: boolean // whether there is a session key for the device
+  uptimeSeconds: number // since the handshake finished
+  bytesIn: number // read from the socket, framing and encryption included
+  bytesOut: number
+  rateIn: number // bytes a second, over the last few seconds
+  rateOut: number
+  rttMs?: number // round trip of the last answered heartbeat, if any has been
+  idleSeconds: number // since anything was last received on it
+  unansweredHeartbeats: number // PINGs sent since then
+  healthy: boolean // writable, and not waiting on a heartbeat
+}
+
+// How long each kind of network operation may take, in seconds (see main/timeouts.ts)
+export interface Timeouts {
+  connect: number
+  handshake: number
+  control: number
+  bulk: number // 0 for never
+}
+
+// Result of the crypto self-test in settings
+export interface CryptoSelfTestResult {
+  checks: { name: string; passed: boolean }[] // known-answer tests
+  throughput: Record<string, number> // cipher suite → MB/s on this device
+  preferredSuite: string // offered first in new handshakes
+}
+
+// Result of removing abandoned partial downloads
+export interface CleanupResult {
+  removed: string[] // file paths
+  freedBytes: number
+}
+
+// One file to one device within a send-to-several-devices batch
+export interface FanOutTarget {
+  deviceId: string
+  filePath: string
+  fileId?: string // unset if the transfer could not be started
+  messageId?: string
+  status: FileTransferProgress['status']
+  progress: number
+  error?: string
+}
+
+// Combined state of a batch: 'partial' once everything has finished but only some succeeded
+export interface FanOutStatus {
+  batchId: string
+  status: 'active' | 'completed' | 'partial' | 'failed'
+  progress: number
+  targets: FanOutTarget[]
+}
+
+// An accepted outgoing transfer waiting for a slot, in the order it will start
+export interface QueuedTransfer {
+  fileId: string
+  deviceId: string
+  name?: string
+  size?: number
+  priority: TransferPriority
+}
+
+// Throughput over the interval ending at `time`, in bytes of the file per second
+export interface SpeedSample {
+  time: number
+  speed: number
+}
+
+// Sampled history of one transfer (a folder counts as one), for drawing speed graphs
+export interface TransferStats {
+  fileId: string
+  status: FileTransferProgress['status']
+  progress: number
+  speed: number
+  eta: number
+  samples: SpeedSample[] // oldest first, about one a second while data is flowing
+  retries: number // automatic retries after network errors
+  resumes: number // times the transfer restarted from the receiver's verified offset
 }